@@ -0,0 +1,100 @@
+//! Compares the tree-walking evaluator with the bytecode VM: `cargo bench --bench engines`.
+//!
+//! The language has no loops, so the workloads call functions that call themselves twice over
+//! a number of levels, which runs their bodies an exponential number of times.
+
+use std::time::{Duration, Instant};
+
+use stat_script::lexer::tokenizer::Tokenizer;
+use stat_script::parse::parser::{Node, StatParser};
+use stat_script::runtime::program::{Evaluator, Program};
+
+const RUNS: u32 = 5;
+
+/// Arithmetic on local variables, e.g. the update step of a simulation.
+fn arithmetic(levels: usize) -> String {
+    let mut source = String::from("func level0(x @f64) @f64 {
+    set<f64> a <- x * 1.0001 + 0.5
+    set<f64> b <- a * a - x / 3.0
+    set<f64> c <- (a + b) % 7.0
+    set<bool> small <- c < 3.5 & b > 0.0 | a == x
+    return c - a / (b + 1.0)
+}
+");
+
+    for level in 1..=levels {
+        let below = level - 1;
+        source.push_str(&format!("func level{level}(x @f64) @f64 {{
+    set<f64> y <- level{below}(x = x + 1.0)
+    return level{below}(x = y * 0.5) + y
+}}
+"));
+    }
+
+    source.push_str(&format!("func main() @i32 {{
+    set<f64> result <- level{levels}(x = 1.5)
+    return 0
+}}
+"));
+
+    source
+}
+
+/// Draws from a distribution and aggregates, dominated by builtin calls.
+fn sampling(levels: usize) -> String {
+    let mut source = String::from("func level0(n @int) @f64 {
+    set<list> draws <- builtin normal_sample(n = n, mean = 0.0, sd = 1.0)
+    return builtin mean(values = draws) + builtin sd(values = draws)
+}
+");
+
+    for level in 1..=levels {
+        let below = level - 1;
+        source.push_str(&format!("func level{level}(n @int) @f64 {{
+    return level{below}(n = n) + level{below}(n = n)
+}}
+"));
+    }
+
+    source.push_str(&format!("func main() @i32 {{
+    builtin set_seed(seed = 42)
+    set<f64> total <- level{levels}(n = 8)
+    return 0
+}}
+"));
+
+    source
+}
+
+fn time(ast: &Node, engine: Evaluator) -> Duration {
+    (0..RUNS)
+        .map(|_| {
+            let mut program = Program::new(ast.clone()).with_output(Box::new(std::io::sink())).with_evaluator(engine);
+
+            let started = Instant::now();
+            program.execute().expect("the benchmark runs");
+            started.elapsed()
+        })
+        .min()
+        .expect("there is at least one run")
+}
+
+fn main() {
+    let workloads = [("arithmetic", arithmetic(14)), ("sampling", sampling(12))];
+
+    println!("{:<12} {:>14} {:>14} {:>8}", "workload", "tree-walker", "bytecode", "speedup");
+
+    for (name, source) in workloads {
+        let ast = StatParser::new(Tokenizer::new(source)).parse().expect("the benchmark parses");
+
+        let tree_walker = time(&ast, Evaluator::TreeWalker);
+        let bytecode = time(&ast, Evaluator::Bytecode);
+
+        println!(
+            "{name:<12} {:>12.2?} {:>12.2?} {:>7.2}x",
+            tree_walker,
+            bytecode,
+            tree_walker.as_secs_f64() / bytecode.as_secs_f64()
+        );
+    }
+}
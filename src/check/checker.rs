@@ -0,0 +1,523 @@
+use std::collections::HashMap;
+
+use crate::error::checker::{CheckError, CheckErrorKind};
+use crate::lexer::tokenizer::Span;
+use crate::parse::parser::{BinaryType, FunctionCall, FunctionDefinition, Node, NodeType, UnaryType, VariableDeclaration};
+use crate::runtime::builtins::find_builtin;
+use crate::runtime::host::Host;
+use crate::runtime::native::NativeSignature;
+use crate::runtime::value::Value;
+
+/// The type of an expression as far as it is known before running the program. `Any` is used
+/// where the type depends on runtime values, e.g. the result of a builtin or `NA`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum StaticType {
+    Any,
+    Nothing,
+    Boolean,
+    Integer,
+    Double,
+    String,
+    Character,
+    List,
+    Table,
+    Record,
+}
+
+impl StaticType {
+    /// Resolves a declared type name, accepting the same names as `Value::coerce`.
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "any" => Some(Self::Any),
+            "Nothing" => Some(Self::Nothing),
+            "i8" | "i16" | "i32" | "i64" | "i128" | "u8" | "u16" | "u32" | "u64" | "u128" | "int" => Some(Self::Integer),
+            "f32" | "f64" | "double" => Some(Self::Double),
+            "bool" => Some(Self::Boolean),
+            "string" => Some(Self::String),
+            "char" => Some(Self::Character),
+            "list" => Some(Self::List),
+            "table" => Some(Self::Table),
+            "record" => Some(Self::Record),
+            _ => None
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Any => "any",
+            Self::Nothing => "Nothing",
+            Self::Boolean => "bool",
+            Self::Integer => "int",
+            Self::Double => "f64",
+            Self::String => "string",
+            Self::Character => "char",
+            Self::List => "list",
+            Self::Table => "table",
+            Self::Record => "record",
+        }
+    }
+
+    /// Whether a value of type `other` can be stored where `self` is declared.
+    pub fn accepts(&self, other: StaticType) -> bool {
+        match (self, other) {
+            (Self::Any, _) | (_, Self::Any) => true,
+            (Self::Double, Self::Integer) => true,
+            (declared, actual) => *declared == actual
+        }
+    }
+
+    fn is_numeric(&self) -> bool {
+        matches!(self, Self::Any | Self::Integer | Self::Double)
+    }
+}
+
+/// Checks a parsed program without running it: declared types must exist, variables must be
+/// declared before they are used, calls must match the called function and values must fit
+/// the types they are assigned to. All problems are collected instead of stopping at the first.
+pub fn check(program: &Node) -> Vec<CheckError> {
+    check_with(program, &Host::default())
+}
+
+/// Checks a program that runs with the functions and globals of an embedding application.
+pub fn check_with(program: &Node, host: &Host) -> Vec<CheckError> {
+    let mut checker = Checker {
+        native_functions: host.functions.iter()
+            .map(|(name, function)| (name.clone(), function.signature()))
+            .collect(),
+        globals: host.globals.iter()
+            .map(|(name, value)| (name.clone(), static_type_of(value)))
+            .collect(),
+        ..Checker::default()
+    };
+    checker.check_program(program);
+
+    checker.errors
+}
+
+/// The type an interactive entry ends in, inferred without running it, with the functions and
+/// variables earlier entries defined. Returns the problems found instead if there are any.
+pub fn infer_entry(
+    nodes: &[Node],
+    functions: &HashMap<String, FunctionDefinition>,
+    variables: &[(String, Value)],
+    host: &Host,
+) -> Result<StaticType, Vec<CheckError>> {
+    let mut checker = Checker {
+        functions: functions.clone(),
+        native_functions: host.functions.iter()
+            .map(|(name, function)| (name.clone(), function.signature()))
+            .collect(),
+        ..Checker::default()
+    };
+    checker.scopes = vec![variables.iter().map(|(name, value)| (name.clone(), static_type_of(value))).collect()];
+
+    let mut entry_type = StaticType::Nothing;
+    for node in nodes {
+        entry_type = match node.node_type.as_ref() {
+            NodeType::FunctionDefinition(definition) => {
+                checker.functions.insert(definition.name.clone(), definition.clone());
+                StaticType::Nothing
+            }
+            _ => checker.infer(node),
+        };
+    }
+
+    match checker.errors.is_empty() {
+        true => Ok(entry_type),
+        false => Err(checker.errors),
+    }
+}
+
+fn static_type_of(value: &Value) -> StaticType {
+    StaticType::from_name(value.type_name()).unwrap_or(StaticType::Any)
+}
+
+#[derive(Default)]
+struct Checker {
+    functions: HashMap<String, FunctionDefinition>,
+    native_functions: HashMap<String, NativeSignature>,
+    globals: HashMap<String, StaticType>,
+    scopes: Vec<HashMap<String, StaticType>>,
+    function: String,
+    return_type: Option<StaticType>,
+    errors: Vec<CheckError>,
+}
+
+impl Checker {
+    fn check_program(&mut self, program: &Node) {
+        let nodes = match program.node_type.as_ref() {
+            NodeType::Program(nodes) => nodes,
+            _ => return
+        };
+
+        for node in nodes {
+            if let NodeType::FunctionDefinition(definition) = node.node_type.as_ref() {
+                if self.functions.insert(definition.name.clone(), definition.clone()).is_some() {
+                    self.function.clone_from(&definition.name);
+                    self.error(definition.name_span, CheckErrorKind::DuplicateDefinition, format!("Function '{}' is defined more than once.", definition.name));
+                }
+            }
+        }
+
+        for node in nodes {
+            if let NodeType::FunctionDefinition(definition) = node.node_type.as_ref() {
+                self.check_function(definition);
+            }
+        }
+    }
+
+    fn check_function(&mut self, definition: &FunctionDefinition) {
+        self.function.clone_from(&definition.name);
+        self.return_type = self.resolve_type(&definition.return_type, definition.name_span);
+
+        let mut scope = HashMap::new();
+        for parameter in definition.signature.iter() {
+            let param_type = self.resolve_type(&parameter.param_type, parameter.span).unwrap_or(StaticType::Any);
+
+            if scope.insert(parameter.param_name.clone(), param_type).is_some() {
+                self.error(parameter.span, CheckErrorKind::DuplicateDefinition, format!("Parameter '{}' is declared more than once.", parameter.param_name));
+            }
+        }
+
+        self.scopes = vec![self.globals.clone(), scope];
+
+        if let NodeType::Block(nodes) = definition.body.node_type.as_ref() {
+            for node in nodes {
+                self.check_statement(node);
+            }
+        }
+    }
+
+    fn check_statement(&mut self, node: &Node) {
+        match node.node_type.as_ref() {
+            NodeType::VariableDeclaration(declaration) => self.check_variable_declaration(declaration),
+            NodeType::ReturnExpression(value) => {
+                let actual = self.infer(value);
+
+                if let Some(expected) = self.return_type {
+                    if !expected.accepts(actual) {
+                        self.error(node.span, CheckErrorKind::TypeMismatch, format!(
+                            "Function '{}' returns '{}' but the returned value is '{}'.",
+                            self.function, expected.name(), actual.name()
+                        ));
+                    }
+                }
+            }
+            NodeType::TryCatch(try_catch) => {
+                self.infer(&try_catch.body);
+
+                self.scopes.push(HashMap::from([(try_catch.variable.clone(), StaticType::Record)]));
+                self.infer(&try_catch.handler);
+                self.scopes.pop();
+            }
+            _ => {
+                self.infer(node);
+            }
+        }
+    }
+
+    fn check_variable_declaration(&mut self, declaration: &VariableDeclaration) {
+        let actual = self.infer(&declaration.value);
+
+        let variable_type = match &declaration.variable_type {
+            None => actual,
+            Some(name) => match self.resolve_type(name, declaration.name_span) {
+                Some(declared) => {
+                    if !declared.accepts(actual) {
+                        self.error(declaration.value.span, CheckErrorKind::TypeMismatch, format!(
+                            "Cannot assign a value of type '{}' to '{}' declared as '{}'.",
+                            actual.name(), declaration.name, declared.name()
+                        ));
+                    }
+
+                    declared
+                }
+                None => StaticType::Any
+            }
+        };
+
+        if let Some(scope) = self.scopes.last_mut() {
+            scope.insert(declaration.name.clone(), variable_type);
+        }
+    }
+
+    fn infer(&mut self, node: &Node) -> StaticType {
+        match node.node_type.as_ref() {
+            NodeType::StringLiteral(_) => StaticType::String,
+            NodeType::Int8Literal(_) | NodeType::Int16Literal(_) | NodeType::Int32Literal(_)
+            | NodeType::Int64Literal(_) | NodeType::Int128Literal(_) => StaticType::Integer,
+            NodeType::DoubleLiteral(_) => StaticType::Double,
+            NodeType::BooleanLiteral(_) => StaticType::Boolean,
+            NodeType::NaLiteral => StaticType::Any,
+            NodeType::ListLiteral(elements) => {
+                for element in elements {
+                    self.infer(element);
+                }
+
+                StaticType::List
+            }
+            NodeType::VariableReference(name) => match self.lookup(name) {
+                Some(variable_type) => variable_type,
+                None => {
+                    self.error(node.span, CheckErrorKind::UndefinedVariable, format!("Variable '{name}' is not defined in this scope."));
+                    StaticType::Any
+                }
+            },
+            NodeType::FieldAccess(target, field) => match self.infer(target) {
+                StaticType::Table => StaticType::List,
+                StaticType::Any | StaticType::Record => StaticType::Any,
+                other => {
+                    self.error(node.span, CheckErrorKind::UnknownField, format!("Cannot access field '{field}' on '{}'.", other.name()));
+                    StaticType::Any
+                }
+            },
+            NodeType::UnaryNode(unary_type, operand) => self.infer_unary(node.span, unary_type, operand),
+            NodeType::BinaryNode(binary_type, left, right) => self.infer_binary(node.span, *binary_type, left, right),
+            NodeType::FunctionCall(call) => self.infer_function_call(call),
+            NodeType::Block(nodes) => {
+                self.scopes.push(HashMap::new());
+                for node in nodes {
+                    self.check_statement(node);
+                }
+                self.scopes.pop();
+
+                StaticType::Any
+            }
+            NodeType::VariableDeclaration(_) | NodeType::ReturnExpression(_) | NodeType::TryCatch(_) => {
+                self.check_statement(node);
+                StaticType::Nothing
+            }
+            NodeType::Program(_) | NodeType::FunctionDefinition(_) => {
+                self.error(node.span, CheckErrorKind::MisplacedDefinition, "Functions can only be defined at the top level.".into());
+                StaticType::Nothing
+            }
+            NodeType::Import(_) => {
+                self.error(node.span, CheckErrorKind::MisplacedDefinition, "Modules can only be imported at the top level.".into());
+                StaticType::Nothing
+            }
+        }
+    }
+
+    fn infer_unary(&mut self, span: Span, unary_type: &UnaryType, operand: &Node) -> StaticType {
+        let operand = self.infer(operand);
+
+        match (unary_type, operand) {
+            (_, StaticType::Any | StaticType::List) => operand,
+            (UnaryType::Not, StaticType::Boolean) => StaticType::Boolean,
+            (UnaryType::Negate, StaticType::Integer | StaticType::Double) => operand,
+            (UnaryType::Not, other) => {
+                self.error(span, CheckErrorKind::TypeMismatch, format!("Operator '!' cannot be applied to '{}'.", other.name()));
+                StaticType::Boolean
+            }
+            (UnaryType::Negate, other) => {
+                self.error(span, CheckErrorKind::TypeMismatch, format!("Operator '-' cannot be applied to '{}'.", other.name()));
+                StaticType::Any
+            }
+        }
+    }
+
+    fn infer_binary(&mut self, span: Span, binary_type: BinaryType, left: &Node, right: &Node) -> StaticType {
+        let left = self.infer(left);
+        let right = self.infer(right);
+
+        // lists are combined element-wise, their element types are not tracked
+        if left == StaticType::List || right == StaticType::List {
+            return StaticType::List;
+        }
+
+        let mismatch = |checker: &mut Self, result: StaticType| {
+            checker.error(span, CheckErrorKind::TypeMismatch, format!(
+                "Operator '{}' cannot be applied to '{}' and '{}'.",
+                binary_type.symbol(), left.name(), right.name()
+            ));
+            result
+        };
+
+        match binary_type {
+            BinaryType::Equal | BinaryType::NotEqual => StaticType::Boolean,
+            BinaryType::And | BinaryType::Or => {
+                let is_logical = |t: StaticType| matches!(t, StaticType::Any | StaticType::Boolean);
+
+                if is_logical(left) && is_logical(right) {
+                    StaticType::Boolean
+                } else {
+                    mismatch(self, StaticType::Boolean)
+                }
+            }
+            BinaryType::Less | BinaryType::LessEqual | BinaryType::Greater | BinaryType::GreaterEqual => {
+                let comparable = match (left, right) {
+                    (StaticType::Any, _) | (_, StaticType::Any) => true,
+                    (StaticType::String, StaticType::String) | (StaticType::Character, StaticType::Character) => true,
+                    (l, r) => l.is_numeric() && r.is_numeric()
+                };
+
+                if comparable {
+                    StaticType::Boolean
+                } else {
+                    mismatch(self, StaticType::Boolean)
+                }
+            }
+            _ => match (left, right) {
+                (StaticType::String, StaticType::String) if binary_type == BinaryType::Add => StaticType::String,
+                (StaticType::Integer, StaticType::Integer) => match binary_type {
+                    BinaryType::Divide => StaticType::Double,
+                    // negative exponents produce doubles
+                    BinaryType::Power => StaticType::Any,
+                    _ => StaticType::Integer
+                },
+                (StaticType::Any, _) | (_, StaticType::Any) if left.is_numeric() && right.is_numeric() => StaticType::Any,
+                (StaticType::Any, StaticType::String) | (StaticType::String, StaticType::Any) if binary_type == BinaryType::Add => {
+                    StaticType::Any
+                }
+                (l, r) if l.is_numeric() && r.is_numeric() => StaticType::Double,
+                _ => mismatch(self, StaticType::Any)
+            }
+        }
+    }
+
+    fn infer_function_call(&mut self, call: &FunctionCall) -> StaticType {
+        let arguments: Vec<(String, StaticType)> = call.parameters.iter()
+            .map(|(name, node)| (name.clone(), self.infer(node)))
+            .collect();
+
+        if call.builtin {
+            if find_builtin(&call.name).is_some() {
+                return StaticType::Any;
+            }
+
+            return match self.native_functions.get(&call.name).cloned() {
+                Some(signature) => self.check_native_call(call, &signature, &arguments),
+                None => {
+                    self.error(call.name_span, CheckErrorKind::UnknownFunction, format!("Unknown builtin '{}'.", call.name));
+                    StaticType::Any
+                }
+            };
+        }
+
+        let definition = match self.functions.get(&call.name) {
+            Some(definition) => definition.clone(),
+            None => {
+                self.error(call.name_span, CheckErrorKind::UnknownFunction, format!("Unknown function '{}'.", call.name));
+                return StaticType::Any;
+            }
+        };
+
+        for (name, actual) in arguments.iter() {
+            match definition.signature.iter().find(|p| &p.param_name == name) {
+                None => self.error(call.name_span, CheckErrorKind::ArgumentMismatch, format!("Function '{}' has no parameter '{name}'.", call.name)),
+                Some(parameter) => {
+                    let expected = StaticType::from_name(&parameter.param_type).unwrap_or(StaticType::Any);
+
+                    if !expected.accepts(*actual) {
+                        self.error(call.name_span, CheckErrorKind::TypeMismatch, format!(
+                            "Argument '{name}' of function '{}' expects '{}' but got '{}'.",
+                            call.name, expected.name(), actual.name()
+                        ));
+                    }
+                }
+            }
+        }
+
+        for parameter in definition.signature.iter() {
+            if !arguments.iter().any(|(name, _)| name == &parameter.param_name) {
+                self.error(call.name_span, CheckErrorKind::ArgumentMismatch, format!("Missing argument '{}' for function '{}'.", parameter.param_name, call.name));
+            }
+        }
+
+        StaticType::from_name(&definition.return_type).unwrap_or(StaticType::Any)
+    }
+
+    /// Checks a call of a native function of the host against its declared signature.
+    fn check_native_call(&mut self, call: &FunctionCall, signature: &NativeSignature, arguments: &[(String, StaticType)]) -> StaticType {
+        for (name, actual) in arguments.iter() {
+            let param_type = match signature.parameter(name) {
+                Some(parameter) => &parameter.param_type,
+                None => match &signature.variadic {
+                    Some(param_type) => param_type,
+                    None => {
+                        self.error(call.name_span, CheckErrorKind::ArgumentMismatch, format!("Builtin '{}' has no parameter '{name}'.", call.name));
+                        continue;
+                    }
+                }
+            };
+            let expected = StaticType::from_name(param_type).unwrap_or(StaticType::Any);
+
+            if !expected.accepts(*actual) {
+                self.error(call.name_span, CheckErrorKind::TypeMismatch, format!(
+                    "Argument '{name}' of builtin '{}' expects '{}' but got '{}'.",
+                    call.name, expected.name(), actual.name()
+                ));
+            }
+        }
+
+        for parameter in signature.parameters.iter().filter(|parameter| !parameter.optional) {
+            if !arguments.iter().any(|(name, _)| name == &parameter.name) {
+                self.error(call.name_span, CheckErrorKind::ArgumentMismatch, format!("Builtin '{}' is missing the required argument '{}'.", call.name, parameter.name));
+            }
+        }
+
+        StaticType::from_name(&signature.return_type).unwrap_or(StaticType::Any)
+    }
+
+    fn resolve_type(&mut self, name: &str, span: Span) -> Option<StaticType> {
+        let resolved = StaticType::from_name(name);
+
+        if resolved.is_none() {
+            self.error(span, CheckErrorKind::UnknownType, format!("Unknown type '{name}'."));
+        }
+
+        resolved
+    }
+
+    fn lookup(&self, name: &str) -> Option<StaticType> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    fn error(&mut self, span: Span, kind: CheckErrorKind, message: String) {
+        self.errors.push(CheckError::new(&self.function, span, kind, message));
+    }
+}
+
+#[cfg(test)]
+mod checker_tests {
+    use crate::check::checker::check;
+    use crate::error::checker::CheckErrorKind;
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::parse::parser::StatParser;
+
+    /// The kinds of the errors with their lines.
+    fn kinds(source: &str) -> Vec<(CheckErrorKind, i128)> {
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+
+        check(&ast).into_iter().map(|e| (e.kind, e.span.line)).collect()
+    }
+
+    #[test]
+    fn test_valid_program() {
+        let source = "func half(x @f64) @f64 { return x / 2 }
+            func main() @i32 {
+                set<f64> h <- half(x = 3)
+                set<inherit> label <- \"half: \" + \"value\"
+                builtin println(template = label)
+                return 0
+            }";
+
+        assert_eq!(kinds(source), Vec::new());
+    }
+
+    #[test]
+    fn test_reports_all_errors() {
+        let source = "func main() @i32 {
+                set<int> n <- \"text\"
+                set<unknown> m <- 1
+                return missing + true
+            }";
+
+        assert_eq!(kinds(source), vec![
+            (CheckErrorKind::TypeMismatch, 2),
+            (CheckErrorKind::UnknownType, 3),
+            (CheckErrorKind::UndefinedVariable, 4),
+            (CheckErrorKind::TypeMismatch, 4),
+        ]);
+    }
+}
@@ -0,0 +1,2 @@
+mod protocol;
+pub mod server;
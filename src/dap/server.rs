@@ -0,0 +1,789 @@
+//! A Debug Adapter Protocol server, which lets editors debug a script: it launches the script
+//! with a [`Debugger`], reports where it pauses and answers the editor's requests about the
+//! paused program.
+//!
+//! A thread reads the requests. Those that must work while the script runs, like
+//! `setBreakpoints` and `pause`, it answers itself through the [`DebugControl`], and it forwards
+//! the others to the thread running the script.
+
+use std::cell::RefCell;
+use std::fs;
+use std::io::{BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex, OnceLock};
+use std::thread;
+
+use serde_json::{json, Value as Json};
+
+use crate::check::checker::check;
+use crate::dap::protocol::{read_message, write_message, Request};
+use crate::error::engine::EngineError;
+use crate::error::runtime::RuntimeErrorKind;
+use crate::lexer::tokenizer::Tokenizer;
+use crate::module::loader::Loader;
+use crate::parse::parser::StatParser;
+use crate::runtime::cancellation::CancellationToken;
+use crate::runtime::debug::{DebugControl, DebugHook, Debugger, PauseReason, Paused, Resume};
+use crate::runtime::program::Program;
+use crate::runtime::value::Value;
+
+/// Scripts run on a single thread, which is all the client is told about.
+const THREAD_ID: i64 = 1;
+
+/// The exit codes of a script that failed, as `stat_script run` exits with.
+const EXIT_RUNTIME_ERROR: i32 = 1;
+const EXIT_INTERRUPTED: i32 = 130;
+
+/// Serves the debug adapter protocol on standard input and output until the client disconnects.
+pub fn run_stdio(search_path: Vec<PathBuf>) -> anyhow::Result<()> {
+    run(BufReader::new(std::io::stdin()), std::io::stdout(), search_path)
+}
+
+/// Serves the debug adapter protocol until the client disconnects, resolving the imports of the
+/// launched script with the search path.
+pub fn run(input: impl BufRead + Send + 'static, output: impl Write + Send + 'static, search_path: Vec<PathBuf>) -> anyhow::Result<()> {
+    let outgoing = Outgoing(Arc::new(Mutex::new(Writer { output: Box::new(output), seq: 0 })));
+    let control = DebugControl::new();
+    let cancellation = CancellationToken::new();
+    let files = Arc::new(OnceLock::new());
+    let (sender, receiver) = mpsc::channel();
+
+    let reader = thread::spawn({
+        let reader = Reader { outgoing: outgoing.clone(), control: control.clone(), cancellation: cancellation.clone(), files: Arc::clone(&files) };
+        move || reader.run(input, sender)
+    });
+
+    let mut session = Session {
+        outgoing,
+        requests: Rc::new(RefCell::new(Requests { receiver, pending: None })),
+        control,
+        cancellation,
+        files,
+        search_path,
+        launched: None,
+    };
+    // the reader stops too, at the disconnect or the end of the input that ended the session
+    session.serve();
+
+    reader.join().map_err(|_| anyhow::anyhow!("the thread reading requests panicked"))?
+}
+
+fn capabilities() -> Json {
+    json!({
+        "supportsConfigurationDoneRequest": true,
+        "supportsTerminateRequest": true,
+        "supportsEvaluateForHovers": true,
+    })
+}
+
+struct Writer {
+    output: Box<dyn Write + Send>,
+    seq: i64,
+}
+
+/// Sends responses and events to the client, from both threads.
+#[derive(Clone)]
+struct Outgoing(Arc<Mutex<Writer>>);
+
+impl Outgoing {
+    fn send(&self, mut message: Json) {
+        let mut writer = self.0.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        writer.seq += 1;
+        message["seq"] = json!(writer.seq);
+
+        // a client that is gone has nowhere left to be told
+        let _ = write_message(&mut writer.output, &message);
+    }
+
+    fn respond(&self, request: &Request, body: Json) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": true,
+            "command": request.command,
+            "body": body,
+        }));
+    }
+
+    fn fail(&self, request: &Request, message: &str) {
+        self.send(json!({
+            "type": "response",
+            "request_seq": request.seq,
+            "success": false,
+            "command": request.command,
+            "message": message,
+        }));
+    }
+
+    fn event(&self, event: &str, body: Json) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+}
+
+/// The files of the launched script and of the modules it imports, which breakpoints can be set
+/// in.
+struct Files {
+    script: PathBuf,
+    modules: Vec<PathBuf>,
+}
+
+/// Reads the requests, answering those that must work while the script runs.
+struct Reader {
+    outgoing: Outgoing,
+    control: DebugControl,
+    cancellation: CancellationToken,
+    files: Arc<OnceLock<Files>>,
+}
+
+impl Reader {
+    fn run(self, mut input: impl BufRead, sender: Sender<Request>) -> anyhow::Result<()> {
+        while let Some(message) = read_message(&mut input)? {
+            let Ok(request) = serde_json::from_value::<Request>(message) else {
+                continue;
+            };
+            if request.kind != "request" {
+                continue;
+            }
+
+            match request.command.as_str() {
+                "setBreakpoints" => self.set_breakpoints(&request),
+                "setExceptionBreakpoints" => self.outgoing.respond(&request, json!({})),
+                "threads" => self.outgoing.respond(&request, json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+                "pause" => {
+                    self.control.pause();
+                    self.outgoing.respond(&request, json!({}));
+                }
+                command => {
+                    let disconnect = command == "disconnect";
+                    if disconnect || command == "terminate" {
+                        // a running script stops at its next statement, a paused one when told
+                        self.cancellation.cancel();
+                    }
+
+                    if sender.send(request).is_err() || disconnect {
+                        break;
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Replaces the breakpoints in the file of the request by those of the request. Breakpoints
+    /// in a file that is neither the launched script nor a module it imports are not verified,
+    /// and never pause it.
+    fn set_breakpoints(&self, request: &Request) {
+        let source = request.arguments["source"]["path"].as_str().map(canonical);
+        let module = match (self.files.get(), source) {
+            (Some(files), Some(source)) if files.script == source => Ok(None),
+            (Some(files), Some(source)) if files.modules.contains(&source) => Ok(Some(source)),
+            (Some(_), _) => Err("Breakpoints can only be set in the launched script and the modules it imports."),
+            (None, _) => Err("Breakpoints can only be set once a script is launched."),
+        };
+
+        let lines: Vec<i128> = match request.arguments["breakpoints"].as_array() {
+            Some(breakpoints) => breakpoints.iter().filter_map(|breakpoint| breakpoint["line"].as_i64()).map(i128::from).collect(),
+            None => Vec::new(),
+        };
+
+        let breakpoints: Vec<Json> = lines.iter().map(|line| match module {
+            Ok(_) => json!({ "verified": true, "line": line }),
+            Err(message) => json!({ "verified": false, "line": line, "message": message }),
+        }).collect();
+
+        if let Ok(module) = module {
+            self.control.set_breakpoints(module.as_deref(), lines);
+        }
+        self.outgoing.respond(request, json!({ "breakpoints": breakpoints }));
+    }
+}
+
+/// The path that tells whether two paths are the same file.
+fn canonical(path: &str) -> PathBuf {
+    fs::canonicalize(path).unwrap_or_else(|_| PathBuf::from(path))
+}
+
+/// The requests for the thread running the script.
+struct Requests {
+    receiver: Receiver<Request>,
+    /// A request handed back to the session, i.e. a `disconnect` that stopped the paused script.
+    pending: Option<Request>,
+}
+
+impl Requests {
+    fn next(&mut self) -> Option<Request> {
+        self.pending.take().or_else(|| self.receiver.recv().ok())
+    }
+}
+
+/// A script that was launched, and runs once the client is done configuring it.
+struct Launch {
+    program: Program,
+    path: String,
+    stop_on_entry: bool,
+}
+
+/// Handles the requests while no script is paused.
+struct Session {
+    outgoing: Outgoing,
+    requests: Rc<RefCell<Requests>>,
+    control: DebugControl,
+    cancellation: CancellationToken,
+    files: Arc<OnceLock<Files>>,
+    search_path: Vec<PathBuf>,
+    launched: Option<Launch>,
+}
+
+impl Session {
+    fn serve(&mut self) {
+        loop {
+            let Some(request) = self.requests.borrow_mut().next() else {
+                return;
+            };
+
+            match request.command.as_str() {
+                "initialize" => self.outgoing.respond(&request, capabilities()),
+                "launch" => match self.launch(&request.arguments) {
+                    Ok(launch) => {
+                        self.launched = Some(launch);
+                        self.outgoing.respond(&request, json!({}));
+                        self.outgoing.event("initialized", json!({}));
+                    }
+                    Err(message) => self.outgoing.fail(&request, &message),
+                },
+                "configurationDone" => {
+                    self.outgoing.respond(&request, json!({}));
+                    if let Some(launch) = self.launched.take() {
+                        self.start(launch);
+                    }
+                }
+                "terminate" => self.outgoing.respond(&request, json!({})),
+                "disconnect" => {
+                    self.outgoing.respond(&request, json!({}));
+                    return;
+                }
+                command => self.outgoing.fail(&request, &format!("Cannot handle '{command}' while no script is paused.")),
+            }
+        }
+    }
+
+    /// Parses, links and checks the script of a `launch` request, with the arguments for its main
+    /// method.
+    fn launch(&self, arguments: &Json) -> Result<Launch, String> {
+        let Some(path) = arguments["program"].as_str() else {
+            return Err("The launch request needs the path of the script in 'program'.".to_string());
+        };
+
+        let source = fs::read_to_string(path).map_err(|e| format!("Failed to read file: \"{path}\": {e}"))?;
+        let ast = StatParser::new(Tokenizer::new(source)).parse().map_err(|e| EngineError::Parse(e).message())?;
+        let mut loader = Loader::new(self.search_path.clone());
+        let linked = loader.link(&ast, Some(Path::new(path))).map_err(|e| EngineError::Module(e).message())?;
+
+        let errors = check(&linked);
+        if !errors.is_empty() {
+            return Err(EngineError::Check(errors).message());
+        }
+
+        let main_arguments = match arguments["args"].as_array() {
+            Some(values) => values.iter().map(|value| value.as_str().map_or_else(|| value.to_string(), str::to_string)).collect(),
+            None => Vec::new(),
+        };
+        let program = Program::new(linked).with_arguments(main_arguments).with_cancellation(self.cancellation.clone());
+        program.main_arguments().map_err(|e| e.message)?;
+
+        let modules = loader.modules().map(|module| module.path.clone()).collect();
+        let _ = self.files.set(Files { script: canonical(path), modules });
+
+        Ok(Launch {
+            program,
+            path: path.to_string(),
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        })
+    }
+
+    /// Runs the launched script to its end, then tells the client how it exited.
+    fn start(&mut self, launch: Launch) {
+        let hook = Inspector {
+            outgoing: self.outgoing.clone(),
+            requests: Rc::clone(&self.requests),
+            path: launch.path,
+            references: Vec::new(),
+        };
+        let mut debugger = Debugger::new(hook).with_control(self.control.clone());
+        if launch.stop_on_entry {
+            debugger = debugger.stop_on_entry();
+        }
+
+        let output = OutputEvents { outgoing: self.outgoing.clone(), buffer: Vec::new() };
+        let mut program = launch.program.with_output(Box::new(output)).with_debugger(debugger);
+
+        let exit_code = match program.execute() {
+            Ok(code) => code,
+            Err(err) => {
+                let (message, code) = match err.kind {
+                    RuntimeErrorKind::Interrupted => (err.message.clone(), EXIT_INTERRUPTED),
+                    _ => (format!("A runtime error occurred: {}", err.message), EXIT_RUNTIME_ERROR),
+                };
+
+                let mut text = format!("{message}\n");
+                if !err.stack.is_empty() {
+                    text.push_str("Traceback, innermost call first:\n");
+                    for frame in &err.stack {
+                        text.push_str(&format!("  {frame}\n"));
+                    }
+                }
+                self.outgoing.event("output", json!({ "category": "stderr", "output": text }));
+
+                code
+            }
+        };
+        // the output still buffered goes out before the script is reported as exited
+        drop(program);
+
+        self.outgoing.event("exited", json!({ "exitCode": exit_code }));
+        self.outgoing.event("terminated", json!({}));
+    }
+}
+
+/// Sends what the script prints to the client, a line at a time.
+struct OutputEvents {
+    outgoing: Outgoing,
+    buffer: Vec<u8>,
+}
+
+impl OutputEvents {
+    fn send(&mut self, length: usize) {
+        let bytes: Vec<u8> = self.buffer.drain(..length).collect();
+        self.outgoing.event("output", json!({ "category": "stdout", "output": String::from_utf8_lossy(&bytes) }));
+    }
+}
+
+impl Write for OutputEvents {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if let Some(last) = self.buffer.iter().rposition(|byte| *byte == b'\n') {
+            self.send(last + 1);
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        if !self.buffer.is_empty() {
+            self.send(self.buffer.len());
+        }
+        Ok(())
+    }
+}
+
+impl Drop for OutputEvents {
+    fn drop(&mut self) {
+        let _ = self.flush();
+    }
+}
+
+/// What a `variablesReference` of the client refers to while the script is paused.
+enum Reference {
+    /// The variables of the call at a depth of the stack.
+    Locals(usize),
+    Globals,
+    /// The elements of a list, the fields of a record or the columns of a table.
+    Value(Value),
+}
+
+/// Answers the client's requests about the paused script, until it resumes it.
+struct Inspector {
+    outgoing: Outgoing,
+    requests: Rc<RefCell<Requests>>,
+    path: String,
+    /// The references handed out since the script paused, by `variablesReference` minus one.
+    references: Vec<Reference>,
+}
+
+impl DebugHook for Inspector {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        self.references.clear();
+
+        let reason = match paused.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+            PauseReason::Pause => "pause",
+        };
+        self.outgoing.event("stopped", json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }));
+
+        loop {
+            let Some(request) = self.requests.borrow_mut().next() else {
+                return Resume::Stop;
+            };
+
+            let resume = match request.command.as_str() {
+                "continue" => Resume::Continue,
+                "next" => Resume::StepOver,
+                "stepIn" => Resume::StepInto,
+                "stepOut" => Resume::StepOut,
+                "terminate" => Resume::Stop,
+                "disconnect" => {
+                    // the session answers once the script has stopped
+                    self.requests.borrow_mut().pending = Some(request);
+                    return Resume::Stop;
+                }
+                _ => {
+                    match self.inspect(paused, &request) {
+                        Ok(body) => self.outgoing.respond(&request, body),
+                        Err(message) => self.outgoing.fail(&request, &message),
+                    }
+                    continue;
+                }
+            };
+
+            let body = match resume {
+                Resume::Continue => json!({ "allThreadsContinued": true }),
+                _ => json!({}),
+            };
+            self.outgoing.respond(&request, body);
+            return resume;
+        }
+    }
+}
+
+impl Inspector {
+    fn inspect(&mut self, paused: &mut Paused, request: &Request) -> Result<Json, String> {
+        let arguments = &request.arguments;
+
+        match request.command.as_str() {
+            "stackTrace" => {
+                let frames: Vec<Json> = paused.stack().iter().enumerate().map(|(depth, call)| {
+                    // a function imported from a module is in the file of the module
+                    let path = call.module.as_deref().unwrap_or(Path::new(&self.path));
+                    let source = json!({ "name": path.file_name().map(|name| name.to_string_lossy()), "path": path });
+
+                    json!({ "id": depth, "name": call.function, "line": call.line, "column": 1, "source": source })
+                }).collect();
+
+                Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+            }
+            "scopes" => {
+                let depth = frame_depth(paused, arguments)?;
+                let scopes = json!([
+                    { "name": "Locals", "presentationHint": "locals", "variablesReference": self.reference(Reference::Locals(depth)), "expensive": false },
+                    { "name": "Globals", "variablesReference": self.reference(Reference::Globals), "expensive": false },
+                ]);
+
+                Ok(json!({ "scopes": scopes }))
+            }
+            "variables" => {
+                let index = arguments["variablesReference"].as_u64().and_then(|reference| usize::try_from(reference).ok()?.checked_sub(1));
+                let variables = match index.and_then(|index| self.references.get(index)) {
+                    Some(Reference::Locals(depth)) => paused.stack()[*depth].frame.borrow().locals(),
+                    Some(Reference::Globals) => paused.globals(),
+                    Some(Reference::Value(value)) => children(value),
+                    None => return Err("Unknown variables reference.".to_string()),
+                };
+
+                let variables: Vec<Json> = variables.into_iter().map(|(name, value)| {
+                    let type_name = value.type_name();
+                    let (summary, reference) = self.describe(value);
+                    json!({ "name": name, "value": summary, "type": type_name, "variablesReference": reference })
+                }).collect();
+
+                Ok(json!({ "variables": variables }))
+            }
+            "evaluate" => {
+                let expression = arguments["expression"].as_str().unwrap_or_default();
+                let depth = match arguments["frameId"].is_null() {
+                    true => 0,
+                    false => frame_depth(paused, arguments)?,
+                };
+
+                match paused.evaluate(expression, depth) {
+                    Ok(Some(value)) if value != Value::Nothing => {
+                        let type_name = value.type_name();
+                        let (summary, reference) = self.describe(value);
+                        Ok(json!({ "result": summary, "type": type_name, "variablesReference": reference }))
+                    }
+                    Ok(_) => Ok(json!({ "result": "", "variablesReference": 0 })),
+                    Err(e) => Err(e.message()),
+                }
+            }
+            command => Err(format!("Unknown request '{command}'.")),
+        }
+    }
+
+    fn reference(&mut self, reference: Reference) -> usize {
+        self.references.push(reference);
+        self.references.len()
+    }
+
+    /// A value on one line, and the reference to its parts if it has any.
+    fn describe(&mut self, value: Value) -> (String, usize) {
+        let summary = match &value {
+            Value::Table(table) => format!("table ({} rows)", table.row_count()),
+            Value::Record(record) => record.name.clone(),
+            value => value.to_string(),
+        };
+
+        let reference = match children(&value).is_empty() {
+            true => 0,
+            false => self.reference(Reference::Value(value)),
+        };
+
+        (summary, reference)
+    }
+}
+
+/// The depth of the call the request's `frameId` refers to, the id of a frame of `stackTrace`.
+fn frame_depth(paused: &Paused, arguments: &Json) -> Result<usize, String> {
+    arguments["frameId"].as_u64()
+        .and_then(|id| usize::try_from(id).ok())
+        .filter(|depth| *depth < paused.stack().len())
+        .ok_or_else(|| "Unknown frame.".to_string())
+}
+
+/// The parts of a value the client can expand: the elements of a list, the fields of a record
+/// and the columns of a table.
+fn children(value: &Value) -> Vec<(String, Value)> {
+    match value {
+        Value::List(values) => values.iter().enumerate().map(|(i, value)| (format!("[{}]", i + 1), value.clone())).collect(),
+        Value::Record(record) => record.fields.clone(),
+        Value::Table(table) => table.columns.iter().map(|column| (column.name.clone(), Value::List(column.values.clone()))).collect(),
+        _ => Vec::new(),
+    }
+}
+
+#[cfg(test)]
+mod server_tests {
+    use std::collections::VecDeque;
+    use std::fs;
+    use std::io::{BufReader, PipeWriter};
+    use std::path::PathBuf;
+    use std::sync::mpsc::{self, Receiver};
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    use serde_json::{json, Value as Json};
+
+    use crate::dap::protocol::{read_message, write_message};
+    use crate::dap::server::run;
+    use crate::test_support::TempDir;
+
+    const SOURCE: &str = "func scale(x @int) @int {
+    set<list> values <- [x, x * 2]
+    return x * 10
+}
+func main() {
+    set<int> n <- 4
+    builtin println(template = scale(x = n))
+    builtin println(template = n)
+}";
+
+    /// A client scripting the requests an editor sends, with the server on another thread.
+    struct Client {
+        input: PipeWriter,
+        messages: Receiver<Json>,
+        seq: i64,
+        events: VecDeque<Json>,
+        /// What the script printed, from the output events seen so far.
+        output: String,
+        server: Option<JoinHandle<()>>,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (server_input, input) = std::io::pipe().unwrap();
+            let (client_input, server_output) = std::io::pipe().unwrap();
+            let server = thread::spawn(move || run(BufReader::new(server_input), server_output, Vec::new()).unwrap());
+
+            let (sender, messages) = mpsc::channel();
+            thread::spawn(move || {
+                let mut client_input = BufReader::new(client_input);
+                while let Ok(Some(message)) = read_message(&mut client_input) {
+                    if sender.send(message).is_err() {
+                        break;
+                    }
+                }
+            });
+
+            Self { input, messages, seq: 0, events: VecDeque::new(), output: String::new(), server: Some(server) }
+        }
+
+        fn receive(&self) -> Json {
+            self.messages.recv_timeout(Duration::from_secs(10)).expect("the server did not answer")
+        }
+
+        /// Sends a request and returns the response, keeping the events that come before it.
+        fn send(&mut self, command: &str, arguments: Json) -> Json {
+            self.seq += 1;
+            write_message(&mut self.input, &json!({ "seq": self.seq, "type": "request", "command": command, "arguments": arguments })).unwrap();
+
+            loop {
+                let message = self.receive();
+                match message["type"].as_str() {
+                    Some("response") if message["request_seq"] == self.seq => return message,
+                    Some("event") => self.events.push_back(message),
+                    _ => panic!("unexpected message {message}"),
+                }
+            }
+        }
+
+        fn request(&mut self, command: &str, arguments: Json) -> Json {
+            let response = self.send(command, arguments);
+            assert_eq!(response["success"], true, "{response}");
+            response["body"].clone()
+        }
+
+        /// Waits for the event, skipping others and collecting the output of the script.
+        fn event(&mut self, name: &str) -> Json {
+            loop {
+                let event = self.events.pop_front().unwrap_or_else(|| self.receive());
+                if event["event"] == "output" {
+                    self.output.push_str(event["body"]["output"].as_str().unwrap());
+                }
+                if event["event"] == name {
+                    return event["body"].clone();
+                }
+            }
+        }
+
+        /// Launches the script, stopping at the breakpoints before it runs.
+        fn launch(&mut self, path: &PathBuf, breakpoints: &[i64]) {
+            let capabilities = self.request("initialize", json!({ "adapterID": "stat_script", "linesStartAt1": true }));
+            assert_eq!(capabilities["supportsConfigurationDoneRequest"], true);
+
+            self.request("launch", json!({ "program": path }));
+            self.event("initialized");
+
+            let breakpoints: Vec<Json> = breakpoints.iter().map(|line| json!({ "line": line })).collect();
+            let response = self.request("setBreakpoints", json!({ "source": { "path": path }, "breakpoints": breakpoints }));
+            assert!(response["breakpoints"].as_array().unwrap().iter().all(|breakpoint| breakpoint["verified"] == true));
+        }
+
+        fn variables(&mut self, reference: &Json) -> Vec<(String, String)> {
+            let body = self.request("variables", json!({ "variablesReference": reference }));
+            body["variables"].as_array().unwrap().iter()
+                .map(|variable| (variable["name"].as_str().unwrap().to_string(), variable["value"].as_str().unwrap().to_string()))
+                .collect()
+        }
+    }
+
+    impl Drop for Client {
+        fn drop(&mut self) {
+            if thread::panicking() {
+                return;
+            }
+
+            self.request("disconnect", json!({}));
+            self.server.take().unwrap().join().unwrap();
+        }
+    }
+
+    /// Writes the script into a fresh directory and returns it with the path of the script.
+    fn script(test: &str) -> (TempDir, PathBuf) {
+        let directory = TempDir::new(&format!("dap_{test}"));
+        let path = directory.write("script.stsc", SOURCE);
+
+        (directory, path)
+    }
+
+    fn lines(stack: &Json) -> Vec<(String, i64)> {
+        stack["stackFrames"].as_array().unwrap().iter()
+            .map(|frame| (frame["name"].as_str().unwrap().to_string(), frame["line"].as_i64().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn test_breakpoints_variables_and_steps() {
+        let (_directory, path) = script("breakpoints");
+        let mut client = Client::start();
+        client.launch(&path, &[3]);
+        client.request("configurationDone", json!({}));
+
+        assert_eq!(client.event("stopped")["reason"], "breakpoint");
+        let stack = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(lines(&stack), [("scale".to_string(), 3), ("main".to_string(), 7)]);
+        assert_eq!(stack["stackFrames"][0]["source"]["path"], json!(path));
+
+        let scopes = client.request("scopes", json!({ "frameId": 0 }));
+        let locals = &scopes["scopes"][0];
+        assert_eq!(locals["name"], "Locals");
+        let body = client.request("variables", json!({ "variablesReference": locals["variablesReference"] }));
+        let values = &body["variables"][0];
+        assert_eq!((&values["name"], &values["value"], &values["type"]), (&json!("values"), &json!("[4, 8]"), &json!("list")));
+        assert_eq!(client.variables(&values["variablesReference"]), [("[1]".to_string(), "4".to_string()), ("[2]".to_string(), "8".to_string())]);
+
+        let scopes = client.request("scopes", json!({ "frameId": 1 }));
+        assert_eq!(client.variables(&scopes["scopes"][0]["variablesReference"]), [("n".to_string(), "4".to_string())]);
+
+        let result = client.request("evaluate", json!({ "expression": "x * 100", "frameId": 0 }));
+        assert_eq!(result["result"], "400");
+        client.request("evaluate", json!({ "expression": "set<int> n <- 5", "frameId": 1 }));
+        assert_eq!(client.send("evaluate", json!({ "expression": "nope", "frameId": 0 }))["success"], false);
+
+        client.request("next", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "step");
+        assert_eq!(lines(&client.request("stackTrace", json!({ "threadId": 1 }))), [("main".to_string(), 8)]);
+
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("exited")["exitCode"], 0);
+        client.event("terminated");
+        assert_eq!(client.output, "40\n5\n");
+    }
+
+    #[test]
+    fn test_pause_and_disconnect() {
+        let (_directory, path) = script("pause");
+        let mut client = Client::start();
+        client.launch(&path, &[]);
+
+        // the script pauses at its first statement once it runs
+        client.request("pause", json!({ "threadId": 1 }));
+        client.request("configurationDone", json!({}));
+        assert_eq!(client.event("stopped")["reason"], "pause");
+        assert_eq!(lines(&client.request("stackTrace", json!({ "threadId": 1 }))), [("main".to_string(), 6)]);
+
+        client.request("stepIn", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "step");
+        client.request("stepIn", json!({ "threadId": 1 }));
+        client.event("stopped");
+        assert_eq!(lines(&client.request("stackTrace", json!({ "threadId": 1 }))), [("scale".to_string(), 2), ("main".to_string(), 7)]);
+
+        client.request("stepOut", json!({ "threadId": 1 }));
+        client.event("stopped");
+        assert_eq!(lines(&client.request("stackTrace", json!({ "threadId": 1 }))), [("main".to_string(), 8)]);
+        assert_eq!(client.output, "40\n");
+    }
+
+    #[test]
+    fn test_breakpoints_in_modules() {
+        let directory = TempDir::new("dap_modules");
+        let main = directory.write("main.stsc", "import lib\nfunc main() {\n    set<int> n <- helper(x = 2)\n    builtin println(template = n)\n}");
+        let lib = directory.write("lib.stsc", "pub func helper(x @int) @int {\n    set<int> y <- x * 10\n    return y\n}");
+        let other = directory.write("other.stsc", "pub func other() {\n    builtin println(template = 1)\n}");
+        let lib = fs::canonicalize(lib).unwrap();
+
+        let mut client = Client::start();
+        // line 3 of the script is a breakpoint, line 3 of the module is not
+        client.launch(&main, &[3]);
+        let response = client.request("setBreakpoints", json!({ "source": { "path": lib }, "breakpoints": [{ "line": 2 }] }));
+        assert_eq!(response["breakpoints"][0]["verified"], true);
+        let response = client.request("setBreakpoints", json!({ "source": { "path": other }, "breakpoints": [{ "line": 2 }] }));
+        assert_eq!(response["breakpoints"][0]["verified"], false);
+        client.request("configurationDone", json!({}));
+
+        assert_eq!(client.event("stopped")["reason"], "breakpoint");
+        assert_eq!(lines(&client.request("stackTrace", json!({ "threadId": 1 }))), [("main".to_string(), 3)]);
+
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("stopped")["reason"], "breakpoint");
+        let stack = client.request("stackTrace", json!({ "threadId": 1 }));
+        assert_eq!(lines(&stack), [("lib.helper".to_string(), 2), ("main".to_string(), 3)]);
+        assert_eq!(stack["stackFrames"][0]["source"], json!({ "name": "lib.stsc", "path": lib }));
+        assert_eq!(stack["stackFrames"][1]["source"]["path"], json!(main));
+
+        client.request("continue", json!({ "threadId": 1 }));
+        assert_eq!(client.event("exited")["exitCode"], 0);
+        assert_eq!(client.output, "20\n");
+    }
+}
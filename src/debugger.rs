@@ -0,0 +1,322 @@
+//! The prompt of `stat_script debug`, a [`DebugHook`] reading commands like `break 12`, `next`
+//! or `print total / n` whenever the program pauses.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::runtime::debug::{DebugHook, Location, PauseReason, Paused, Resume};
+use crate::runtime::value::Value;
+
+const PROMPT: &str = "(debug) ";
+/// The lines shown before and after the paused line by `list`.
+const LISTED_LINES: i128 = 3;
+
+const HELP: &str = "\
+break <line>    pause before the statement at the line, or at <file>:<line> of a module (b)
+delete <line>   remove the breakpoint at the line, or at <file>:<line> (d)
+breakpoints     list the breakpoints
+continue        run to the next breakpoint (c)
+step            go to the next statement, also into calls (s)
+next            go to the next statement, over calls (n)
+out             go on until the function returns (o)
+backtrace       show the running calls, innermost first (bt)
+frame <n>       select the call to inspect, by its number in the backtrace (f)
+locals          show the variables of the selected call
+globals         show the globals
+print <code>    evaluate code in the selected call, e.g. an expression or a `set` (p)
+list            show the source around the line of the selected call (l)
+quit            end the program (q)
+help            show this help";
+
+pub struct Prompt {
+    source: Vec<String>,
+    /// The lines of the modules listed so far, by path.
+    modules: HashMap<PathBuf, Vec<String>>,
+    input: Box<dyn Iterator<Item = String>>,
+    output: Box<dyn Write>,
+    /// The call that `locals`, `print` and `list` inspect, by its depth in the backtrace.
+    selected: usize,
+    /// The command an empty line repeats.
+    last_command: String,
+}
+
+impl Prompt {
+    /// A prompt for the script with the source, reading commands from `input`. The program ends
+    /// when the input does.
+    pub fn new(source: &str, input: Box<dyn Iterator<Item = String>>, output: Box<dyn Write>) -> Self {
+        Self {
+            source: source.lines().map(str::to_string).collect(),
+            modules: HashMap::new(),
+            input,
+            output,
+            selected: 0,
+            last_command: String::new(),
+        }
+    }
+
+    /// A prompt reading commands from the terminal, with line editing and history.
+    pub fn terminal(source: &str) -> rustyline::Result<Self> {
+        let mut editor = DefaultEditor::new()?;
+
+        let input = std::iter::from_fn(move || loop {
+            match editor.readline(PROMPT) {
+                Ok(line) => {
+                    let _ = editor.add_history_entry(line.as_str());
+                    return Some(line);
+                }
+                // Ctrl-C only cancels the line being typed
+                Err(ReadlineError::Interrupted) => continue,
+                Err(_) => return None,
+            }
+        });
+
+        Ok(Self::new(source, Box::new(input), Box::new(std::io::stdout())))
+    }
+
+    /// Runs a command, and returns how the program goes on if the command resumes it.
+    pub fn handle_command(&mut self, paused: &mut Paused, line: &str) -> Option<Resume> {
+        let line = match line.trim() {
+            "" => self.last_command.clone(),
+            line => line.to_string(),
+        };
+        self.last_command = line.clone();
+
+        let (name, argument) = line.split_once(char::is_whitespace).unwrap_or((&line, ""));
+        let argument = argument.trim();
+
+        match name {
+            "continue" | "c" => return Some(Resume::Continue),
+            "step" | "s" => return Some(Resume::StepInto),
+            "next" | "n" => return Some(Resume::StepOver),
+            "out" | "o" => return Some(Resume::StepOut),
+            "quit" | "q" => return Some(Resume::Stop),
+            "break" | "b" => match argument.parse::<Location>() {
+                Ok(Location { module: Some(module), .. }) if !paused.modules().contains(&module) => {
+                    self.write_line(&format!("{} is not a module of the script", module.display()));
+                }
+                Ok(location) => {
+                    self.write_line(&format!("Breakpoint at {location}"));
+                    paused.control().add_breakpoint(location);
+                }
+                Err(e) => self.write_line(&format!("Usage: break <line>, or break <file>:<line> ({e})")),
+            },
+            "delete" | "d" => match argument.parse::<Location>() {
+                Ok(location) if paused.control().remove_breakpoint(&location) => self.write_line(&format!("Removed the breakpoint at {location}")),
+                Ok(location) => self.write_line(&format!("There is no breakpoint at {location}")),
+                Err(e) => self.write_line(&format!("Usage: delete <line>, or delete <file>:<line> ({e})")),
+            },
+            "breakpoints" => {
+                let locations: Vec<String> = paused.control().breakpoints().iter().map(ToString::to_string).collect();
+                self.write_line(&format!("Breakpoints at {}", locations.join(", ")));
+            }
+            "backtrace" | "bt" => {
+                for (depth, call) in paused.stack().iter().enumerate() {
+                    let marker = if depth == self.selected { ">" } else { " " };
+                    let location = Location::new(call.module.as_deref(), call.line);
+                    self.write_line(&format!("{marker} #{depth} {} at {location}", call.function));
+                }
+            }
+            "frame" | "f" => match argument.parse::<usize>() {
+                Ok(depth) if depth < paused.stack().len() => {
+                    self.selected = depth;
+                    let call = &paused.stack()[depth];
+                    let location = Location::new(call.module.as_deref(), call.line);
+                    self.write_line(&format!("#{depth} {} at {location}", call.function));
+                }
+                _ => self.write_line(&format!("Usage: frame <n>, with n from 0 to {}", paused.stack().len().saturating_sub(1))),
+            },
+            "locals" => match paused.stack().get(self.selected) {
+                Some(call) => self.write_variables(&call.frame.borrow().locals()),
+                None => self.write_line("No function is running."),
+            },
+            "globals" => self.write_variables(&paused.globals()),
+            "print" | "p" => match paused.evaluate(argument, self.selected) {
+                Ok(Some(value)) if value != Value::Nothing => self.write_line(&value.to_string()),
+                Ok(_) => {}
+                Err(e) => self.write_line(&e.message()),
+            },
+            "list" | "l" => {
+                let location = match paused.stack().get(self.selected) {
+                    Some(call) => Location::new(call.module.as_deref(), call.line),
+                    None => Location::new(paused.module.as_deref(), paused.line),
+                };
+                self.write_source(&location);
+            }
+            "help" => self.write_line(HELP),
+            _ => self.write_line(&format!("Unknown command '{name}', try help")),
+        }
+
+        None
+    }
+
+    fn write_variables(&mut self, variables: &[(String, Value)]) {
+        if variables.is_empty() {
+            self.write_line("No variables.");
+        }
+
+        for (name, value) in variables {
+            self.write_line(&format!("{name} = {value}"));
+        }
+    }
+
+    fn write_source(&mut self, location: &Location) {
+        let line = location.line;
+
+        for number in (line - LISTED_LINES).max(1)..=line + LISTED_LINES {
+            let Some(text) = self.source_line(location.module.as_deref(), number) else {
+                break;
+            };
+
+            let marker = if number == line { "->" } else { "  " };
+            self.write_line(&format!("{marker} {number:>4} | {text}"));
+        }
+    }
+
+    /// A line of the script, or of the module at the path, read when it is first listed.
+    fn source_line(&mut self, module: Option<&Path>, line: i128) -> Option<String> {
+        let lines = match module {
+            Some(module) => self.modules.entry(module.to_path_buf())
+                .or_insert_with(|| fs::read_to_string(module).map(|source| source.lines().map(str::to_string).collect()).unwrap_or_default()),
+            None => &self.source,
+        };
+
+        usize::try_from(line - 1).ok().and_then(|index| lines.get(index)).cloned()
+    }
+
+    fn write_line(&mut self, text: &str) {
+        // a broken output has nowhere left to report to
+        let _ = writeln!(self.output, "{text}");
+    }
+}
+
+impl DebugHook for Prompt {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        self.selected = 0;
+
+        let reason = match paused.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+            PauseReason::Pause => "paused",
+        };
+        let function = paused.stack().first().map(|call| call.function.clone()).unwrap_or_default();
+        let location = Location::new(paused.module.as_deref(), paused.line);
+        self.write_line(&format!("Paused at {location} in {function} ({reason})"));
+        if let Some(text) = self.source_line(paused.module.as_deref(), paused.line) {
+            self.write_line(&format!("-> {:>4} | {}", paused.line, text.trim_start()));
+        }
+
+        loop {
+            let Some(line) = self.input.next() else {
+                return Resume::Stop;
+            };
+
+            if let Some(resume) = self.handle_command(paused, &line) {
+                return resume;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod debugger_tests {
+    use std::fs;
+
+    use crate::debugger::Prompt;
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::module::loader::Loader;
+    use crate::parse::parser::StatParser;
+    use crate::runtime::debug::Debugger;
+    use crate::runtime::program::Program;
+    use crate::test_support::{SharedOutput, TempDir};
+
+    #[test]
+    fn test_commands() {
+        let source = "func half(x @int) @f64 {
+    return x / 2
+}
+func main() {
+    set<int> n <- 7
+    builtin println(template = half(x = n))
+    builtin println(template = n)
+}";
+        let commands = ["break 2", "c", "bt", "p x + 1", "frame 1", "locals", "p set<int> n <- 0", "out", "nope", "c"];
+
+        let output = SharedOutput::default();
+        let input = commands.into_iter().map(str::to_string);
+        let prompt = Prompt::new(source, Box::new(input), Box::new(output.clone()));
+
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+        let result = Program::new(ast)
+            .with_output(Box::new(output.clone()))
+            .with_debugger(Debugger::new(prompt).stop_on_entry())
+            .execute();
+
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(output.text(), "\
+Paused at line 5 in main (entry)
+->    5 | set<int> n <- 7
+Breakpoint at line 2
+Paused at line 2 in half (breakpoint)
+->    2 | return x / 2
+> #0 half at line 2
+  #1 main at line 6
+8
+#1 main at line 6
+n = 7
+3.5
+Paused at line 7 in main (step)
+->    7 | builtin println(template = n)
+Unknown command 'nope', try help
+0
+");
+    }
+
+    #[test]
+    fn test_breakpoints_in_modules() {
+        let directory = TempDir::new("debug_modules");
+        let source = "import lib
+func main() {
+    set<int> n <- helper(x = 2)
+    builtin println(template = n)
+}";
+        let main = directory.write("main.stsc", source);
+        let lib = directory.write("lib.stsc", "pub func helper(x @int) @int {\n    set<int> y <- x * 10\n    return y\n}");
+        let lib = fs::canonicalize(lib).unwrap().display().to_string();
+
+        // line 3 of the script is a breakpoint, line 3 of the module is not
+        let commands = ["break 3".to_string(), format!("break {lib}:2"), "breakpoints".into(), "c".into(), "bt".into(), "l".into(), "c".into()];
+        let output = SharedOutput::default();
+        let prompt = Prompt::new(source, Box::new(commands.into_iter()), Box::new(output.clone()));
+
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+        let linked = Loader::new(Vec::new()).link(&ast, Some(&main)).unwrap();
+        let result = Program::new(linked)
+            .with_output(Box::new(output.clone()))
+            .with_debugger(Debugger::new(prompt).stop_on_entry())
+            .execute();
+
+        assert_eq!(result.unwrap(), 0);
+        assert_eq!(output.text(), format!("\
+Paused at line 3 in main (entry)
+->    3 | set<int> n <- helper(x = 2)
+Breakpoint at line 3
+Breakpoint at line 2 of {lib}
+Breakpoints at line 3, line 2 of {lib}
+Paused at line 2 of {lib} in lib.helper (breakpoint)
+->    2 | set<int> y <- x * 10
+> #0 lib.helper at line 2 of {lib}
+  #1 main at line 3
+      1 | pub func helper(x @int) @int {{
+->    2 |     set<int> y <- x * 10
+      3 |     return y
+      4 | }}
+20
+"));
+    }
+}
@@ -0,0 +1,196 @@
+//! The API for applications embedding the interpreter.
+//!
+//! ```
+//! use stat_script::{Engine, IntoValue};
+//!
+//! let mut engine = Engine::new();
+//! engine.set_global("threshold", 0.05);
+//! engine.register_function("scale", |arguments| Ok((arguments.number("x")? * 10.0).into_value()));
+//!
+//! engine.compile("func significant(p @f64) @bool { return builtin scale(x = p) < threshold * 10 }").unwrap();
+//! assert!(engine.call::<bool>("significant", (0.01,)).unwrap());
+//! ```
+
+use std::io::Write;
+use std::path::PathBuf;
+use std::rc::Rc;
+
+use crate::check::checker::check_with;
+use crate::error::engine::EngineError;
+use crate::error::runtime::RuntimeError;
+use crate::lexer::tokenizer::{Span, Tokenizer};
+use crate::module::loader::Loader;
+use crate::parse::parser::{Node, NodeType, StatParser};
+use crate::runtime::builtins::Arguments;
+use crate::runtime::cancellation::CancellationToken;
+use crate::runtime::convert::{FromValue, IntoArguments, IntoValue};
+use crate::runtime::debug::Debugger;
+use crate::runtime::native::NativeFunction;
+use crate::runtime::program::{Evaluator, Program};
+use crate::runtime::sandbox::Sandbox;
+use crate::runtime::value::Value;
+
+/// Compiles scripts and calls their functions, with the functions and globals the host
+/// registers.
+pub struct Engine {
+    program: Program,
+    search_path: Vec<PathBuf>,
+}
+
+impl Default for Engine {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Engine {
+    /// An engine running scripts on the bytecode engine and resolving imports with the search
+    /// path of `STAT_SCRIPT_PATH`.
+    pub fn new() -> Self {
+        Self {
+            program: Program::new(Node::new(NodeType::Program(Vec::new()), Span::default())).with_evaluator(Evaluator::Bytecode),
+            search_path: Loader::search_path_from_env(),
+        }
+    }
+
+    pub fn with_evaluator(mut self, evaluator: Evaluator) -> Self {
+        self.program.evaluator = evaluator;
+        self
+    }
+
+    /// Replaces the sink `println` writes to, by default standard output.
+    pub fn with_output(mut self, output: Box<dyn Write>) -> Self {
+        self.program.output = output;
+        self
+    }
+
+    /// Bounds the runs of scripts, e.g. to run scripts that are not trusted. By default calls
+    /// nest at most [`DEFAULT_MAX_DEPTH`](crate::runtime::sandbox::DEFAULT_MAX_DEPTH) deep, which
+    /// the tree-walking evaluator needs a thread with a [`STACK_SIZE`](crate::runtime::sandbox::STACK_SIZE)
+    /// stack for.
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.program.sandbox = sandbox;
+        self
+    }
+
+    /// Stops running scripts when the token is cancelled, e.g. from another thread.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.program.cancellation = cancellation;
+        self
+    }
+
+    /// Lets the debugger pause scripts before their statements. Debugging runs on the
+    /// tree-walking evaluator.
+    pub fn with_debugger(mut self, debugger: Debugger) -> Self {
+        self.program = self.program.with_debugger(debugger);
+        self
+    }
+
+    /// The directories imported modules are looked for in, after the working directory.
+    pub fn with_search_path(mut self, search_path: Vec<PathBuf>) -> Self {
+        self.search_path = search_path;
+        self
+    }
+
+    /// Registers a function scripts call like a builtin, e.g. `builtin scale(x = 2)`, which takes
+    /// any arguments. Scripts compiled before cannot call it, and a builtin of the same name
+    /// takes precedence.
+    pub fn register_function(&mut self, name: &str, function: impl Fn(&mut Arguments) -> Result<Value, RuntimeError> + 'static) -> &mut Self {
+        self.register_native(name, function)
+    }
+
+    /// Registers a function with a signature, e.g. a [`Native`](crate::Native), which the checker
+    /// verifies calls against. Otherwise like [`Engine::register_function`].
+    pub fn register_native(&mut self, name: &str, function: impl NativeFunction + 'static) -> &mut Self {
+        self.program.define_host_function(name.to_string(), Rc::new(function));
+        self
+    }
+
+    /// Sets a global that every function of a script can read, also of a script compiled before.
+    pub fn set_global(&mut self, name: &str, value: impl IntoValue) -> &mut Self {
+        self.program.define_global(name.to_string(), value.into_value());
+        self
+    }
+
+    /// Parses, links and checks a script, whose functions replace those of the previous one.
+    pub fn compile(&mut self, source: &str) -> Result<(), EngineError> {
+        let ast = StatParser::new(Tokenizer::new(source.to_string())).parse().map_err(EngineError::Parse)?;
+        let linked = Loader::new(self.search_path.clone()).link(&ast, None).map_err(EngineError::Module)?;
+
+        let errors = check_with(&linked, self.program.host());
+        if !errors.is_empty() {
+            return Err(EngineError::Check(errors));
+        }
+
+        let program = Program::new(linked).with_host(self.program.host().clone());
+        let previous = std::mem::replace(&mut self.program, program);
+        self.program.output = previous.output;
+        self.program.random = previous.random;
+        self.program.evaluator = previous.evaluator;
+        self.program.sandbox = previous.sandbox;
+        self.program.cancellation = previous.cancellation;
+        self.program.debugger = previous.debugger;
+
+        Ok(())
+    }
+
+    /// Calls a function of the compiled script with arguments for its parameters in order, and
+    /// converts its result.
+    pub fn call<R: FromValue>(&mut self, function: &str, arguments: impl IntoArguments) -> Result<R, EngineError> {
+        let value = self.program.call(function, arguments.into_arguments()).map_err(EngineError::Runtime)?;
+
+        R::from_value(value).map_err(EngineError::Runtime)
+    }
+}
+
+#[cfg(test)]
+mod engine_tests {
+    use crate::engine::Engine;
+    use crate::error::engine::EngineError;
+    use crate::error::runtime::RuntimeErrorKind;
+    use crate::runtime::convert::IntoValue;
+    use crate::runtime::program::Evaluator;
+    use crate::runtime::value::Value;
+
+    const SOURCE: &str = "func weighted(values @list, weight @f64) @f64 {
+            return builtin sum(values = values) * weight + offset
+        }
+        func lookup(key @string) @list {
+            return builtin fetch(key = key)
+        }";
+
+    #[test]
+    fn test_calls_script_functions() {
+        for evaluator in [Evaluator::TreeWalker, Evaluator::Bytecode] {
+            let mut engine = Engine::new().with_evaluator(evaluator);
+            engine.set_global("offset", 1);
+            engine.register_function("fetch", |arguments| {
+                let key = arguments.string("key")?;
+                Ok(vec![key.len() as i64, 2].into_value())
+            });
+            engine.compile(SOURCE).unwrap();
+
+            assert_eq!(engine.call::<f64>("weighted", (vec![1, 2, 3], 0.5)).unwrap(), 4.0);
+            assert_eq!(engine.call::<Vec<i64>>("lookup", ("abc",)).unwrap(), [3, 2]);
+
+            // globals set later are seen by the compiled script
+            engine.set_global("offset", 10);
+            assert_eq!(engine.call::<Value>("weighted", vec![Value::List(Vec::new()), Value::Integer(1)]).unwrap(), Value::Double(10.0));
+
+            let Err(EngineError::Runtime(error)) = engine.call::<f64>("weighted", (1,)) else { panic!() };
+            assert_eq!(error.kind, RuntimeErrorKind::ArgumentMismatch);
+            let Err(EngineError::Runtime(error)) = engine.call::<bool>("weighted", (vec![1], 1)) else { panic!() };
+            assert_eq!(error.kind, RuntimeErrorKind::TypeMismatch);
+        }
+    }
+
+    #[test]
+    fn test_rejects_unknown_host_functions_and_globals() {
+        let Err(EngineError::Check(errors)) = Engine::new().compile(SOURCE) else {
+            panic!("the script calls an unregistered function");
+        };
+
+        let messages: Vec<&str> = errors.iter().map(|e| e.message.as_str()).collect();
+        assert_eq!(messages, ["Variable 'offset' is not defined in this scope.", "Unknown builtin 'fetch'."]);
+    }
+}
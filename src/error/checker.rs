@@ -0,0 +1,67 @@
+use std::fmt::{Display, Formatter};
+
+use crate::lexer::tokenizer::Span;
+
+/// A problem found by the static checker before the program runs.
+#[derive(Debug, Clone, PartialEq)]
+pub struct CheckError {
+    pub kind: CheckErrorKind,
+    /// The function the problem was found in.
+    pub function: String,
+    /// The source of the offending expression or declaration.
+    pub span: Span,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum CheckErrorKind {
+    /// A function or parameter that is defined more than once.
+    DuplicateDefinition,
+    /// A value of the wrong type, e.g. for a declaration, an argument or an operator.
+    TypeMismatch,
+    UndefinedVariable,
+    /// A field access on a value that has no fields.
+    UnknownField,
+    /// A call of a function or builtin that does not exist.
+    UnknownFunction,
+    /// A call whose arguments do not match the parameters, e.g. a missing or unknown one.
+    ArgumentMismatch,
+    UnknownType,
+    /// A function definition or import below the top level.
+    MisplacedDefinition,
+}
+
+impl CheckErrorKind {
+    /// A code that stays the same across versions, unlike the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            CheckErrorKind::DuplicateDefinition => "E0401",
+            CheckErrorKind::TypeMismatch => "E0402",
+            CheckErrorKind::UndefinedVariable => "E0403",
+            CheckErrorKind::UnknownField => "E0404",
+            CheckErrorKind::UnknownFunction => "E0405",
+            CheckErrorKind::ArgumentMismatch => "E0406",
+            CheckErrorKind::UnknownType => "E0407",
+            CheckErrorKind::MisplacedDefinition => "E0408",
+        }
+    }
+}
+
+impl CheckError {
+    pub fn new(function: &str, span: Span, kind: CheckErrorKind, message: String) -> Self {
+        Self {
+            kind,
+            function: function.to_string(),
+            span,
+            message,
+        }
+    }
+}
+
+impl Display for CheckError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "Error in function '{}' (line {}): {}", self.function, self.span.line, self.message)
+    }
+}
+
+impl std::error::Error for CheckError {}
@@ -0,0 +1,47 @@
+use std::fmt::{Display, Formatter};
+
+use crate::error::checker::CheckError;
+use crate::error::module::ModuleError;
+use crate::error::parser::ParserError;
+use crate::error::runtime::RuntimeError;
+
+/// Why an [`Engine`](crate::Engine) could not compile a script or call one of its functions.
+#[derive(Debug)]
+pub enum EngineError {
+    /// The script does not tokenize or parse.
+    Parse(ParserError),
+    /// A module the script imports could not be loaded.
+    Module(ModuleError),
+    /// The script did not pass the static checks.
+    Check(Vec<CheckError>),
+    /// The script failed, or its result does not convert to the requested Rust type.
+    Runtime(RuntimeError),
+}
+
+impl EngineError {
+    pub fn message(&self) -> String {
+        match self {
+            EngineError::Parse(e) => format!("Failed to parse at position {}: {}", e.position, e.message),
+            EngineError::Module(e) => format!("Failed to import a module: {}", e.message),
+            EngineError::Check(errors) => errors.iter().map(ToString::to_string).collect::<Vec<_>>().join("\n"),
+            EngineError::Runtime(e) => e.message.clone(),
+        }
+    }
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message())
+    }
+}
+
+impl std::error::Error for EngineError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            EngineError::Parse(e) => Some(e),
+            EngineError::Module(e) => Some(e),
+            EngineError::Check(errors) => errors.first().map(|e| e as &(dyn std::error::Error + 'static)),
+            EngineError::Runtime(e) => Some(e),
+        }
+    }
+}
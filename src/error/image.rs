@@ -0,0 +1,54 @@
+use std::fmt::{Display, Formatter};
+
+/// A compiled image that can't be loaded: it is not an image, is damaged, or was written by an
+/// incompatible version of the interpreter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ImageError {
+    pub kind: ImageErrorKind,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ImageErrorKind {
+    /// The bytes do not start like an image.
+    NotAnImage,
+    /// The image was written in another format or by another version of the interpreter.
+    IncompatibleVersion,
+    /// The image ends before its program does.
+    Truncated,
+    /// The image holds something no compiler writes, e.g. an index outside of its tables.
+    Damaged,
+    /// The image calls a builtin this interpreter does not have.
+    UnknownBuiltin,
+}
+
+impl ImageErrorKind {
+    /// A code that stays the same across versions, unlike the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ImageErrorKind::NotAnImage => "E0601",
+            ImageErrorKind::IncompatibleVersion => "E0602",
+            ImageErrorKind::Truncated => "E0603",
+            ImageErrorKind::Damaged => "E0604",
+            ImageErrorKind::UnknownBuiltin => "E0605",
+        }
+    }
+}
+
+impl ImageError {
+    pub fn new(kind: ImageErrorKind, message: String) -> Self {
+        Self { kind, message }
+    }
+
+    pub fn truncated() -> Self {
+        Self::new(ImageErrorKind::Truncated, "The image is truncated.".into())
+    }
+}
+
+impl Display for ImageError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ImageError {}
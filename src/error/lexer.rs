@@ -1,18 +1,66 @@
+use std::fmt::{Display, Formatter};
+
 use crate::lexer::tokenizer::Tokenizer;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct TokenizerError {
     pub position: i128,
     pub line: i128,
+    pub kind: TokenizerErrorKind,
     pub message: String,
 }
 
+#[derive(Debug, Clone, PartialEq)]
+pub enum TokenizerErrorKind {
+    /// A char no token starts with.
+    InvalidCharacter(char),
+    UnclosedString,
+    UnclosedCharacter,
+    EmptyCharacter,
+    /// A number with more than one decimal point.
+    MalformedNumber,
+}
+
+impl TokenizerErrorKind {
+    /// A code that stays the same across versions, unlike the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            TokenizerErrorKind::InvalidCharacter(_) => "E0101",
+            TokenizerErrorKind::UnclosedString => "E0102",
+            TokenizerErrorKind::UnclosedCharacter => "E0103",
+            TokenizerErrorKind::EmptyCharacter => "E0104",
+            TokenizerErrorKind::MalformedNumber => "E0105",
+        }
+    }
+}
+
+impl Display for TokenizerErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TokenizerErrorKind::InvalidCharacter(c) => write!(f, "Invalid char at beginning of token: {c}"),
+            TokenizerErrorKind::UnclosedString => write!(f, "Unclosed string literal."),
+            TokenizerErrorKind::UnclosedCharacter => write!(f, "Unclosed char literal."),
+            TokenizerErrorKind::EmptyCharacter => write!(f, "Empty char literal."),
+            TokenizerErrorKind::MalformedNumber => write!(f, "Cannot have more than one decimal in number."),
+        }
+    }
+}
+
 impl TokenizerError {
-    pub fn new(tokenizer: &Tokenizer, message: String) -> Self {
+    pub fn new(tokenizer: &Tokenizer, kind: TokenizerErrorKind) -> Self {
         TokenizerError {
             position: tokenizer.cursor_position,
             line: tokenizer.cursor_line,
-            message,
+            message: kind.to_string(),
+            kind,
         }
     }
 }
+
+impl Display for TokenizerError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (line {}, position {})", self.message, self.line, self.position)
+    }
+}
+
+impl std::error::Error for TokenizerError {}
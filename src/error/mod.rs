@@ -1,3 +1,7 @@
+pub mod checker;
+pub mod engine;
+pub mod image;
 pub mod lexer;
+pub mod module;
 pub mod parser;
 pub mod runtime;
@@ -0,0 +1,69 @@
+use std::fmt::{Display, Formatter};
+
+use crate::lexer::tokenizer::Span;
+
+/// A module that could not be imported: it was not found, does not parse, imports itself, or
+/// lacks an imported function.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ModuleError {
+    pub kind: ModuleErrorKind,
+    pub message: String,
+    /// The imports that led to the failing module, innermost first, e.g.
+    /// `imported by "main.stsc" (line 2)`.
+    pub import_chain: Vec<String>,
+    /// Where the linked script itself goes wrong: its import leading to the failing module, or
+    /// its definition clashing with an imported function.
+    pub span: Option<Box<Span>>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ModuleErrorKind {
+    /// No file for the module next to the importing file or in the search path.
+    NotFound,
+    /// The module file exists but cannot be read.
+    Io,
+    /// The module does not parse.
+    InvalidModule,
+    /// A module that imports itself, directly or through others.
+    ImportCycle,
+    /// An imported function the module does not define.
+    UnknownFunction,
+    /// An imported function the module defines without `pub`.
+    PrivateFunction,
+    /// A function that is imported from two modules, or also defined by the importing file.
+    NameConflict,
+}
+
+impl ModuleErrorKind {
+    /// A code that stays the same across versions, unlike the message.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ModuleErrorKind::NotFound => "E0501",
+            ModuleErrorKind::Io => "E0502",
+            ModuleErrorKind::InvalidModule => "E0503",
+            ModuleErrorKind::ImportCycle => "E0504",
+            ModuleErrorKind::UnknownFunction => "E0505",
+            ModuleErrorKind::PrivateFunction => "E0506",
+            ModuleErrorKind::NameConflict => "E0507",
+        }
+    }
+}
+
+impl ModuleError {
+    pub fn new(kind: ModuleErrorKind, message: String) -> Self {
+        Self {
+            kind,
+            message,
+            import_chain: Vec::new(),
+            span: None,
+        }
+    }
+}
+
+impl Display for ModuleError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for ModuleError {}
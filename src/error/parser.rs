@@ -1,17 +1,84 @@
+use std::fmt::{Display, Formatter};
+
+use crate::error::lexer::TokenizerErrorKind;
 use crate::lexer::tokenizer::Token;
 use crate::parse::parser::StatParser;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, PartialEq)]
 pub struct ParserError {
+    pub kind: ParserErrorKind,
     pub message: String,
     pub position: i128
 }
 
-impl ParserError {
-    pub fn new(parser: &StatParser, message: String) -> Self {
-        ParserError {
-            message,
-            position: parser.current_token.as_ref().unwrap_or(&Token::default()).start_pos
+#[derive(Debug, Clone, PartialEq)]
+pub enum ParserErrorKind {
+    /// The source cannot be split into tokens.
+    Tokenizer(TokenizerErrorKind),
+    /// A token that does not fit, e.g. `expected: "'{' as begin of a block", found: "')'"`.
+    UnexpectedToken { expected: String, found: String },
+    /// The source ends where more is expected.
+    UnexpectedEnd { expected: String },
+    /// A number literal that is not a valid number, or too large for any integer type.
+    InvalidNumber(String),
+    /// A call passing an argument more than once.
+    DuplicateArgument(String),
+    /// A function definition with more parameters than the parser supports.
+    TooManyParameters(usize),
+    /// An edit of [`Parse`](crate::parse::incremental::Parse) outside of its text.
+    EditOutOfRange { start: usize, end: usize },
+    /// Expressions or blocks nested deeper than the parser supports.
+    TooDeeplyNested(usize),
+}
+
+impl ParserErrorKind {
+    /// A code that stays the same across versions, unlike the message. Tokenizer errors keep
+    /// their own codes.
+    pub fn code(&self) -> &'static str {
+        match self {
+            ParserErrorKind::Tokenizer(kind) => kind.code(),
+            ParserErrorKind::UnexpectedToken { .. } => "E0201",
+            ParserErrorKind::UnexpectedEnd { .. } => "E0202",
+            ParserErrorKind::InvalidNumber(_) => "E0203",
+            ParserErrorKind::DuplicateArgument(_) => "E0204",
+            ParserErrorKind::TooManyParameters(_) => "E0205",
+            ParserErrorKind::EditOutOfRange { .. } => "E0206",
+            ParserErrorKind::TooDeeplyNested(_) => "E0207",
+        }
+    }
+}
+
+impl Display for ParserErrorKind {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ParserErrorKind::Tokenizer(kind) => write!(f, "{kind}"),
+            ParserErrorKind::UnexpectedToken { expected, found } => write!(f, "Expected {expected} but found {found}"),
+            ParserErrorKind::UnexpectedEnd { expected } => write!(f, "Expected {expected} but the source ended"),
+            ParserErrorKind::InvalidNumber(literal) => write!(f, "Number {literal} is an invalid number"),
+            ParserErrorKind::DuplicateArgument(name) => write!(f, "Argument '{name}' is passed more than once"),
+            ParserErrorKind::TooManyParameters(max) => write!(f, "Function definition only supports up to {max} arguments"),
+            ParserErrorKind::EditOutOfRange { start, end } => write!(f, "The edit of {start}..{end} is outside of the text"),
+            ParserErrorKind::TooDeeplyNested(max) => write!(f, "Expressions and blocks can only be nested {max} levels deep"),
         }
     }
-}
\ No newline at end of file
+}
+
+impl ParserError {
+    /// An error at the token the parser read last.
+    pub fn new(parser: &StatParser, kind: ParserErrorKind) -> Self {
+        Self::at(parser.current_token.as_ref().unwrap_or(&Token::default()).start_pos, kind)
+    }
+
+    /// An error at a 1-based char position, for problems found after the tokens were read.
+    pub fn at(position: i128, kind: ParserErrorKind) -> Self {
+        ParserError { message: kind.to_string(), kind, position }
+    }
+}
+
+impl Display for ParserError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} (position {})", self.message, self.position)
+    }
+}
+
+impl std::error::Error for ParserError {}
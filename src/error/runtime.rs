@@ -1,18 +1,226 @@
+use std::fmt::{Display, Formatter};
+
+use crate::lexer::tokenizer::Span;
 use crate::parse::parser::NodeType;
+use crate::runtime::record::Record;
+use crate::runtime::sandbox::Limit;
+use crate::runtime::value::Value;
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct RuntimeError {
     pub message: String,
+    pub kind: RuntimeErrorKind,
+    /// The script functions that were active when the error occurred, innermost first.
+    pub stack: Vec<StackFrame>,
+    /// The line of the statement that failed, if the error occurred in a script function.
+    pub line: Option<i128>,
+    /// Set when the error is not a failure but a call of `exit(code)`, which unwinds the program
+    /// the same way.
+    pub exit_code: Option<i32>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RuntimeErrorKind {
+    /// Any other failure, e.g. of a host function.
+    Failure,
+    UndefinedVariable,
+    /// A value of the wrong type, e.g. for a parameter or an operator.
+    TypeMismatch,
+    /// A conversion to a type that does not exist.
+    UnknownType,
+    DivisionByZero,
+    /// An integer that does not fit into its type.
+    Overflow,
+    /// A field a record or table does not have.
+    UnknownField,
+    /// A call of a function or builtin that does not exist.
+    UnknownFunction,
+    /// A call whose arguments do not match the parameters, e.g. a missing or unknown one.
+    ArgumentMismatch,
+    /// An argument a builtin rejects, e.g. a negative standard deviation.
+    InvalidArgument,
+    /// A builtin failed to read or write, e.g. a file.
+    Io,
+    /// The program has no `main`, or a statement where it is not allowed.
+    InvalidProgram,
+    /// The script called `exit(code)`.
+    Exit,
+    /// The script exceeded a limit of its [`Sandbox`](crate::runtime::sandbox::Sandbox).
+    LimitExceeded(Limit),
+    /// The script called a builtin its sandbox does not allow.
+    CapabilityDenied,
+    /// The script was cancelled through its [`CancellationToken`](crate::runtime::cancellation::CancellationToken).
+    Interrupted,
+    /// The script raised the error with `error(message)`.
+    Thrown,
+}
+
+/// The kinds by their codes, which stay the same across versions, unlike the messages.
+const CODES: [(&str, RuntimeErrorKind); 20] = [
+    ("E0300", RuntimeErrorKind::Failure),
+    ("E0301", RuntimeErrorKind::UndefinedVariable),
+    ("E0302", RuntimeErrorKind::TypeMismatch),
+    ("E0303", RuntimeErrorKind::UnknownType),
+    ("E0304", RuntimeErrorKind::DivisionByZero),
+    ("E0305", RuntimeErrorKind::Overflow),
+    ("E0306", RuntimeErrorKind::UnknownField),
+    ("E0307", RuntimeErrorKind::UnknownFunction),
+    ("E0308", RuntimeErrorKind::ArgumentMismatch),
+    ("E0309", RuntimeErrorKind::InvalidArgument),
+    ("E0310", RuntimeErrorKind::Io),
+    ("E0311", RuntimeErrorKind::InvalidProgram),
+    ("E0312", RuntimeErrorKind::Exit),
+    ("E0313", RuntimeErrorKind::LimitExceeded(Limit::Steps)),
+    ("E0314", RuntimeErrorKind::LimitExceeded(Limit::Time)),
+    ("E0315", RuntimeErrorKind::LimitExceeded(Limit::Depth)),
+    ("E0316", RuntimeErrorKind::LimitExceeded(Limit::Memory)),
+    ("E0317", RuntimeErrorKind::CapabilityDenied),
+    ("E0318", RuntimeErrorKind::Interrupted),
+    ("E0319", RuntimeErrorKind::Thrown),
+];
+
+impl RuntimeErrorKind {
+    pub fn code(&self) -> &'static str {
+        CODES.iter().find(|(_, kind)| kind == self).map(|(code, _)| *code).expect("every kind has a code")
+    }
+
+    pub fn from_code(code: &str) -> Option<Self> {
+        CODES.iter().find(|(c, _)| *c == code).map(|(_, kind)| *kind)
+    }
+
+    /// Whether a `try` catches errors of the kind. Exiting, interrupting and exceeding a limit of
+    /// the sandbox always end the program.
+    pub fn is_catchable(&self) -> bool {
+        !matches!(self, RuntimeErrorKind::Exit | RuntimeErrorKind::LimitExceeded(_) | RuntimeErrorKind::Interrupted)
+    }
+}
+
+/// A call of a script function that was active when an error occurred.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub function: String,
+    /// Where the function was called, or `None` for the function the program or the host started.
+    pub call_site: Option<Span>,
+    /// The values of the parameters when the error occurred, in order.
+    pub arguments: Vec<(String, Value)>,
+}
+
+impl Display for StackFrame {
+    /// E.g. `scale(x = 2, factor = 0.5) called at line 4`.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        // tables and records would span many lines
+        let arguments: Vec<String> = self.arguments.iter()
+            .map(|(name, value)| match value {
+                Value::Table(_) | Value::Record(_) => format!("{name} = <{}>", value.type_name()),
+                value => format!("{name} = {value}"),
+            })
+            .collect();
+        write!(f, "{}({})", self.function, arguments.join(", "))?;
+
+        match &self.call_site {
+            Some(span) => write!(f, " called at line {}", span.line),
+            None => Ok(()),
+        }
+    }
 }
 
 impl RuntimeError {
     pub fn new(message: String) -> Self {
+        Self::of_kind(RuntimeErrorKind::Failure, message)
+    }
+
+    pub fn of_kind(kind: RuntimeErrorKind, message: String) -> Self {
+        Self {
+            message,
+            kind,
+            stack: Vec::new(),
+            line: None,
+            exit_code: None,
+        }
+    }
+
+    pub fn exit(code: i32) -> Self {
         Self {
-            message
+            exit_code: Some(code),
+            ..Self::of_kind(RuntimeErrorKind::Exit, format!("The program exited with code {code}."))
         }
     }
-    
+
+    pub fn limit_exceeded(limit: Limit, message: String) -> Self {
+        Self::of_kind(RuntimeErrorKind::LimitExceeded(limit), message)
+    }
+
+    pub fn interrupted() -> Self {
+        Self::of_kind(RuntimeErrorKind::Interrupted, "The script was interrupted.".into())
+    }
+
+    /// Adds a function the error unwinds, going outwards.
+    pub fn in_function(mut self, function: &str, call_site: Option<Span>, arguments: Vec<(String, Value)>) -> Self {
+        self.stack.push(StackFrame { function: function.to_string(), call_site, arguments });
+        self
+    }
+
+    /// Sets where the error occurred, unless a statement it occurred in already did.
+    pub fn at(mut self, span: Span) -> Self {
+        self.line.get_or_insert(span.line);
+        self
+    }
+
+    /// The error as the record a `catch` binds it to, e.g.
+    /// `error { kind: "DivisionByZero", code: "E0304", message: "Division by zero.", line: 3 }`.
+    pub fn to_record(&self) -> Record {
+        let line = match self.line {
+            Some(line) => Value::Integer(line),
+            None => Value::NA,
+        };
+
+        Record::new("error")
+            .with("kind", Value::String(format!("{:?}", self.kind)))
+            .with("code", Value::String(self.kind.code().to_string()))
+            .with("message", Value::String(self.message.clone()))
+            .with("line", line)
+    }
+
+    pub fn capability_denied(builtin: &str) -> Self {
+        Self::of_kind(
+            RuntimeErrorKind::CapabilityDenied,
+            format!("Builtin '{builtin}' cannot access the file system, the environment or the process in this sandbox.")
+        )
+    }
+
     pub fn expected_node_type(node_type: &str, actual_type: &NodeType) -> Self {
-        Self::new(format!("Internal error! Expected node of type '{node_type}' but got '{actual_type:?}'"))
+        Self::of_kind(RuntimeErrorKind::InvalidProgram, format!("Internal error! Expected node of type '{node_type}' but got '{actual_type:?}'"))
+    }
+
+    pub fn undefined_variable(name: &str) -> Self {
+        Self::of_kind(RuntimeErrorKind::UndefinedVariable, format!("Variable '{name}' is not defined in this scope."))
+    }
+
+    pub fn type_mismatch(expected: &str, actual: &str) -> Self {
+        Self::of_kind(RuntimeErrorKind::TypeMismatch, format!("Type mismatch: expected '{expected}' but got '{actual}'."))
     }
-}
\ No newline at end of file
+
+    pub fn division_by_zero() -> Self {
+        Self::of_kind(RuntimeErrorKind::DivisionByZero, "Division by zero.".into())
+    }
+
+    pub fn invalid_argument(function: &str, parameter: &str, message: &str) -> Self {
+        Self::of_kind(RuntimeErrorKind::InvalidArgument, format!("Invalid argument '{parameter}' for builtin '{function}': {message}"))
+    }
+
+    pub fn unknown_function(name: &str) -> Self {
+        Self::of_kind(RuntimeErrorKind::UnknownFunction, format!("Unknown function '{name}'."))
+    }
+
+    pub fn unknown_builtin(name: &str) -> Self {
+        Self::of_kind(RuntimeErrorKind::UnknownFunction, format!("Unknown builtin '{name}'."))
+    }
+}
+
+impl Display for RuntimeError {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for RuntimeError {}
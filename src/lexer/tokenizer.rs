@@ -1,4 +1,6 @@
-use crate::error::lexer::TokenizerError;
+use serde::Serialize;
+
+use crate::error::lexer::{TokenizerError, TokenizerErrorKind};
 use crate::lexer::symbols::SymbolType;
 
 #[derive(Debug, Default, PartialEq, Clone)]
@@ -10,6 +12,47 @@ pub struct Token {
     pub token_type: Option<TokenType>,
 }
 
+/// A range of source text as 1-based, inclusive char positions, and the lines it starts and ends on.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Serialize)]
+pub struct Span {
+    pub start: i128,
+    pub end: i128,
+    pub line: i128,
+    pub end_line: i128,
+}
+
+impl Span {
+    /// The span from the start of `self` to the end of `other`.
+    pub fn to(self, other: Span) -> Span {
+        Span {
+            start: self.start,
+            end: other.end,
+            line: self.line,
+            end_line: other.end_line,
+        }
+    }
+}
+
+impl Token {
+    pub fn span(&self) -> Span {
+        Span {
+            start: self.start_pos,
+            end: self.end_pos,
+            line: self.line,
+            end_line: self.line,
+        }
+    }
+}
+
+/// A `#` comment running to the end of its line. Comments are not part of the token stream, the
+/// tokenizer collects them on the side for the formatter.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Comment {
+    /// The text after the `#`.
+    pub text: String,
+    pub span: Span,
+}
+
 #[derive(Debug, PartialEq, Clone)]
 pub enum TokenType {
     Identifier,
@@ -24,25 +67,30 @@ type TokenReturn = Result<Option<Token>, TokenizerError>;
 #[derive(Debug, Default)]
 pub struct Tokenizer {
     pub content: String,
+    /// The chars of `content`, for reading them by position.
+    chars: Vec<char>,
     pub cursor_position: i128,
     pub cursor_line: i128,
     // pub tokenizer_state: TokenizerState,
     pub buffer: String,
+    pub comments: Vec<Comment>,
 }
 
 impl Tokenizer {
     pub fn new(content: String) -> Self {
         Tokenizer {
+            chars: content.chars().collect(),
             content,
             buffer: String::default(),
             cursor_position: 0,
             cursor_line: 1,
             // tokenizer_state: TokenizerState::None,
+            comments: Vec::new(),
         }
     }
 
     fn next_char(&mut self) -> Option<char> {
-        let r = self.content.chars().nth(self.cursor_position as usize);
+        let r = self.chars.get(self.cursor_position as usize).copied();
         self.cursor_position += 1;
         if let Some(r) = r {
             if r == '\n' {
@@ -52,11 +100,22 @@ impl Tokenizer {
         r
     }
 
+    /// Steps back over the char read last, e.g. the char that ended an identifier.
+    fn step_back(&mut self) {
+        self.cursor_position -= 1;
+        if self.chars.get(self.cursor_position as usize) == Some(&'\n') {
+            self.cursor_line -= 1;
+        }
+    }
+
     pub fn next_token(&mut self) -> TokenReturn {
         self.reset();
 
-        let mut token = Token::default();
-        token.start_pos = self.cursor_position + 1;
+        let token = Token {
+            start_pos: self.cursor_position + 1,
+            line: self.cursor_line,
+            ..Default::default()
+        };
 
         let c = match self.next_char() {
             Some(c) => c,
@@ -72,14 +131,15 @@ impl Tokenizer {
                 self.buffer.push(c);
                 self.parse_number(token)
             }
-            c if c == '"' => self.parse_string(token),
-            c if c == '\'' => self.parse_char(token),
+            '"' => self.parse_string(token),
+            '#' => {
+                self.parse_comment(token);
+                self.next_token()
+            }
+            '\'' => self.parse_char(token),
             c if c.is_whitespace() => self.next_token(),
             c if SymbolType::from_char(&c).is_some() => self.parse_symbol(token, c),
-            c => Err(TokenizerError::new(
-                self,
-                format!("Invalid char at beginning of token: {c}"),
-            )),
+            c => Err(TokenizerError::new(self, TokenizerErrorKind::InvalidCharacter(c))),
         }
     }
 
@@ -91,13 +151,14 @@ impl Tokenizer {
         loop {
             match self.next_char() {
                 None => {
-                    return Err(TokenizerError::new(self, "Unclosed char literal".into()));
+                    return Err(TokenizerError::new(self, TokenizerErrorKind::UnclosedCharacter));
                 }
                 Some(c) => {
                     if c == '\'' {
-                        let char = self.buffer.chars().next().ok_or(TokenizerError::new(self, "Empty char literal".into()))?;
+                        let char = self.buffer.chars().next().ok_or_else(|| TokenizerError::new(self, TokenizerErrorKind::EmptyCharacter))?;
                         token.token_type = Some(TokenType::Character(char));
                         self.finish_token(&mut token);
+                        token.end_pos += 1; // the closing quote belongs to the token
 
                         return Ok(Some(token))
                     }
@@ -119,7 +180,7 @@ impl Tokenizer {
 
         self.finish_token(&mut token);
 
-        self.cursor_position -= 1; // move back in case we have e.g. print()
+        self.step_back(); // move back in case we have e.g. print()
 
         Ok(Some(token))
     }
@@ -135,10 +196,7 @@ impl Tokenizer {
 
             if c == '.' {
                 if is_decimal {
-                    return Err(TokenizerError::new(
-                        self,
-                        "Cannot have more than one decimal in number.".into(),
-                    ));
+                    return Err(TokenizerError::new(self, TokenizerErrorKind::MalformedNumber));
                 }
 
                 self.buffer.push(c);
@@ -153,7 +211,7 @@ impl Tokenizer {
 
         self.finish_token(&mut token);
 
-        self.cursor_position -= 1;
+        self.step_back();
         Ok(Some(token))
     }
 
@@ -161,9 +219,30 @@ impl Tokenizer {
         token.value = Some(c.to_string());
         token.token_type = Some(TokenType::Symbol(SymbolType::from_char(&c).unwrap()));
         token.end_pos = self.cursor_position;
-        token.line = self.cursor_line;
         Ok(Some(token))
     }
+
+    fn parse_comment(&mut self, token: Token) {
+        loop {
+            match self.next_char() {
+                Some('\n') | None => {
+                    self.step_back();
+                    break;
+                }
+                Some(c) => self.buffer.push(c)
+            }
+        }
+
+        self.comments.push(Comment {
+            text: self.buffer.trim_end().to_string(),
+            span: Span {
+                start: token.start_pos,
+                end: self.cursor_position,
+                line: token.line,
+                end_line: token.line,
+            },
+        });
+    }
     
     fn parse_string(&mut self, mut token: Token) -> TokenReturn {
         let mut is_escaped = false;
@@ -189,12 +268,13 @@ impl Tokenizer {
 
                     self.buffer.push(c);
                 }
-                None => return Err(TokenizerError::new(self, "Unclosed string literal.".into())),
+                None => return Err(TokenizerError::new(self, TokenizerErrorKind::UnclosedString)),
             }
         }
 
         token.token_type = Some(TokenType::String);
         self.finish_token(&mut token);
+        token.end_pos += 1; // the closing quote belongs to the token
 
         Ok(Some(token))
     }
@@ -202,12 +282,12 @@ impl Tokenizer {
     fn finish_token(&mut self, token: &mut Token) {
         token.end_pos = self.cursor_position - 1; // -1 because we exclude the overflowed char
         token.value = Some(self.buffer.clone());
-        token.line = self.cursor_line
     }
 }
 
 #[cfg(test)]
 mod tokenizer_tests {
+    use crate::error::lexer::TokenizerErrorKind;
     use crate::lexer::tokenizer::{Token, TokenType, Tokenizer};
 
     #[test]
@@ -240,4 +320,30 @@ mod tokenizer_tests {
             }
         )
     }
+
+    #[test]
+    fn test_lines_and_comments() {
+        let mut tokenizer = Tokenizer::new("abc\n# note\n  12 # trailing\nx".into());
+
+        let lines: Vec<i128> = std::iter::from_fn(|| tokenizer.next_token().unwrap())
+            .map(|t| t.line)
+            .collect();
+        let comments: Vec<(&str, i128)> = tokenizer.comments.iter()
+            .map(|c| (c.text.as_str(), c.span.line))
+            .collect();
+
+        assert_eq!(lines, vec![1, 3, 4]);
+        assert_eq!(comments, vec![(" note", 2), (" trailing", 3)]);
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let kind = |source: &str| Tokenizer::new(source.into()).next_token().unwrap_err().kind;
+
+        assert_eq!(kind("\"abc"), TokenizerErrorKind::UnclosedString);
+        assert_eq!(kind("'a"), TokenizerErrorKind::UnclosedCharacter);
+        assert_eq!(kind("''"), TokenizerErrorKind::EmptyCharacter);
+        assert_eq!(kind("1.2.3"), TokenizerErrorKind::MalformedNumber);
+        assert_eq!(kind("\u{00a7}"), TokenizerErrorKind::InvalidCharacter('\u{00a7}'));
+    }
 }
@@ -1,4 +1,30 @@
+pub mod check;
+pub mod dap;
+pub mod debugger;
+mod engine;
 mod error;
 pub mod lexer;
+pub mod lsp;
+pub mod module;
 pub mod parse;
+pub mod repl;
 pub mod runtime;
+#[cfg(test)]
+mod test_support;
+
+pub use engine::Engine;
+pub use error::checker::{CheckError, CheckErrorKind};
+pub use error::engine::EngineError;
+pub use error::image::{ImageError, ImageErrorKind};
+pub use error::module::{ModuleError, ModuleErrorKind};
+pub use error::lexer::{TokenizerError, TokenizerErrorKind};
+pub use error::parser::{ParserError, ParserErrorKind};
+pub use error::runtime::{RuntimeError, RuntimeErrorKind, StackFrame};
+pub use runtime::builtins::Arguments;
+pub use runtime::cancellation::CancellationToken;
+pub use runtime::convert::{FromValue, IntoArguments, IntoValue};
+pub use runtime::debug::{DebugControl, DebugHook, Debugger, PauseReason, Paused, Resume};
+pub use runtime::native::{Native, NativeFunction, NativeSignature};
+pub use runtime::program::Evaluator;
+pub use runtime::sandbox::{Limit, Sandbox};
+pub use runtime::value::Value;
@@ -0,0 +1,360 @@
+use std::collections::HashMap;
+use std::path::Path;
+
+use crate::check::checker::check;
+use crate::error::parser::ParserError;
+use crate::lexer::tokenizer::{Comment, Span, Token, TokenType, Tokenizer};
+use crate::module::loader::Loader;
+use crate::parse::incremental::Parse;
+use crate::parse::parser::{FunctionDefinition, Node, NodeType};
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SymbolKind {
+    Function,
+    Parameter,
+    Variable,
+}
+
+/// Something declared in a document: a function, a parameter or a variable.
+#[derive(Debug, Clone)]
+pub struct Symbol {
+    pub name: String,
+    pub kind: SymbolKind,
+    /// Where the name is declared.
+    pub span: Span,
+    /// The whole declaration, e.g. the complete function definition.
+    pub declaration_span: Span,
+    /// The declaration as shown in hovers, e.g. `func mean_ci(values @list) @list`.
+    pub signature: String,
+    /// Where the symbol can be referred to by name.
+    pub visible: Span,
+}
+
+/// A problem in a document, from the tokenizer, the parser or the checker.
+#[derive(Debug, Clone)]
+pub struct Problem {
+    pub span: Span,
+    pub message: String,
+}
+
+/// Everything the language server knows about one version of a document.
+#[derive(Debug, Default)]
+pub struct Analysis {
+    pub tokens: Vec<Token>,
+    pub comments: Vec<Comment>,
+    pub ast: Option<Node>,
+    pub problems: Vec<Problem>,
+    pub symbols: Vec<Symbol>,
+    /// Every use of a symbol by name, including its declaration, with the index of the symbol.
+    pub references: Vec<(Span, usize)>,
+}
+
+/// Analyzes the text of a document from its parse, or the error it does not parse with. The
+/// imports of a document with a `path` are resolved relative to it.
+pub fn analyze(text: &str, parse: Result<&Parse, &ParserError>, path: Option<&Path>) -> Analysis {
+    let mut analysis = Analysis::default();
+
+    let mut tokenizer = Tokenizer::new(text.to_string());
+    loop {
+        match tokenizer.next_token() {
+            Ok(Some(token)) => analysis.tokens.push(token),
+            Ok(None) => break,
+            Err(e) => {
+                let span = Span { start: e.position, end: e.position, line: e.line, end_line: e.line };
+                analysis.problems.push(Problem { span, message: e.message });
+                break;
+            }
+        }
+    }
+    analysis.comments = tokenizer.comments;
+
+    // a tokenizer error is reported above, the parser would only repeat it
+    match parse.map_err(Clone::clone).and_then(Parse::ast) {
+        Ok(ast) => {
+            match Loader::new(Loader::search_path_from_env()).link(&ast, path) {
+                // the imported modules are checked too, but only the document's own problems
+                // are reported, and imported functions have a `.` in their linked name
+                Ok(linked) => {
+                    for error in check(&linked).into_iter().filter(|error| !error.function.contains('.')) {
+                        analysis.problems.push(Problem { span: error.span, message: error.message });
+                    }
+                }
+                Err(e) => {
+                    // the last import of the chain is the document's own, which the span shows
+                    let nested = &e.import_chain[..e.import_chain.len().saturating_sub(1)];
+                    let message = std::iter::once(&e.message).chain(nested).cloned().collect::<Vec<_>>().join("\n");
+
+                    analysis.problems.push(Problem { span: e.span.map_or(ast.span, |span| *span), message });
+                }
+            }
+
+            let mut resolver = Resolver { analysis: &mut analysis, scopes: Vec::new(), functions: HashMap::new() };
+            resolver.resolve_program(&ast);
+
+            analysis.ast = Some(ast);
+        }
+        Err(e) if analysis.problems.is_empty() => {
+            let span = analysis.tokens.iter()
+                .find(|t| t.start_pos == e.position)
+                .map(Token::span)
+                .unwrap_or(Span { start: e.position, end: e.position, line: 1, end_line: 1 });
+
+            analysis.problems.push(Problem { span, message: e.message });
+            declare_lexically(&mut analysis);
+        }
+        Err(_) => declare_lexically(&mut analysis)
+    }
+
+    analysis
+}
+
+/// Finds the declarations in a document that does not parse, typically because it is being
+/// typed, so that completion still offers its names.
+fn declare_lexically(analysis: &mut Analysis) {
+    let everywhere = Span { start: 0, end: i128::MAX, line: 0, end_line: i128::MAX };
+    let tokens = &analysis.tokens;
+
+    for (i, token) in tokens.iter().enumerate() {
+        if token.token_type != Some(TokenType::Identifier) {
+            continue;
+        }
+
+        let name = token.value.clone().unwrap_or_default();
+        let previous = |n: usize| i.checked_sub(n).and_then(|j| tokens[j].value.as_deref());
+        let next = tokens.get(i + 1).and_then(|t| t.value.as_deref());
+
+        let (kind, signature) = if previous(1) == Some("func") {
+            (SymbolKind::Function, format!("func {name}"))
+        } else if previous(4) == Some("set") && previous(1) == Some(">") {
+            (SymbolKind::Variable, format!("set<{}> {name}", previous(2).unwrap_or("inherit")))
+        } else if previous(1) == Some("catch") {
+            (SymbolKind::Variable, format!("catch {name}"))
+        } else if next == Some("@") && tokens.get(i + 2).is_some() {
+            let param_type = tokens[i + 2].value.as_deref().unwrap_or("any");
+            (SymbolKind::Parameter, format!("{name} @{param_type}"))
+        } else {
+            continue;
+        };
+
+        let index = analysis.symbols.len();
+        analysis.references.push((token.span(), index));
+        analysis.symbols.push(Symbol {
+            name,
+            kind,
+            span: token.span(),
+            declaration_span: token.span(),
+            signature,
+            visible: everywhere,
+        });
+    }
+}
+
+impl Analysis {
+    /// The symbol referred to at a 1-based char position.
+    pub fn symbol_at(&self, position: i128) -> Option<&Symbol> {
+        self.references.iter()
+            .find(|(span, _)| span.start <= position && position <= span.end + 1)
+            .map(|(_, index)| &self.symbols[*index])
+    }
+
+    /// The symbol a reference at `span` resolves to.
+    pub fn symbol_of(&self, span: Span) -> Option<&Symbol> {
+        self.references.iter()
+            .find(|(reference, _)| reference.start == span.start)
+            .map(|(_, index)| &self.symbols[*index])
+    }
+
+    /// The symbols that can be referred to at a 1-based char position.
+    pub fn visible_symbols(&self, position: i128) -> impl Iterator<Item = &Symbol> {
+        self.symbols.iter().filter(move |s| s.visible.start <= position && position <= s.visible.end)
+    }
+
+    /// The token at or right before a 1-based char position.
+    pub fn token_before(&self, position: i128) -> Option<&Token> {
+        self.tokens.iter().rev().find(|t| t.end_pos < position)
+    }
+}
+
+struct Resolver<'a> {
+    analysis: &'a mut Analysis,
+    scopes: Vec<HashMap<String, usize>>,
+    functions: HashMap<String, usize>,
+}
+
+impl Resolver<'_> {
+    fn resolve_program(&mut self, program: &Node) {
+        let NodeType::Program(nodes) = program.node_type.as_ref() else {
+            return;
+        };
+
+        let everywhere = Span { start: 0, end: i128::MAX, line: 0, end_line: i128::MAX };
+
+        for node in nodes {
+            if let NodeType::FunctionDefinition(definition) = node.node_type.as_ref() {
+                let index = self.declare(Symbol {
+                    name: definition.name.clone(),
+                    kind: SymbolKind::Function,
+                    span: definition.name_span,
+                    declaration_span: node.span,
+                    signature: signature(definition),
+                    visible: everywhere,
+                });
+
+                self.functions.entry(definition.name.clone()).or_insert(index);
+            }
+        }
+
+        for node in nodes {
+            if let NodeType::FunctionDefinition(definition) = node.node_type.as_ref() {
+                self.resolve_function(definition);
+            }
+        }
+    }
+
+    fn resolve_function(&mut self, definition: &FunctionDefinition) {
+        self.scopes = vec![HashMap::new()];
+
+        for parameter in definition.signature.iter() {
+            let index = self.declare(Symbol {
+                name: parameter.param_name.clone(),
+                kind: SymbolKind::Parameter,
+                span: parameter.span,
+                declaration_span: parameter.span,
+                signature: format!("{} @{}", parameter.param_name, parameter.param_type),
+                visible: definition.body.span,
+            });
+
+            self.scopes[0].insert(parameter.param_name.clone(), index);
+        }
+
+        if let NodeType::Block(nodes) = definition.body.node_type.as_ref() {
+            for node in nodes {
+                self.resolve(node, definition.body.span);
+            }
+        }
+    }
+
+    /// Resolves the names used in `node`, which is a statement or expression inside a block
+    /// spanning `block`.
+    fn resolve(&mut self, node: &Node, block: Span) {
+        match node.node_type.as_ref() {
+            NodeType::VariableDeclaration(declaration) => {
+                self.resolve(&declaration.value, block);
+
+                let variable_type = declaration.variable_type.as_deref().unwrap_or("inherit");
+                let index = self.declare(Symbol {
+                    name: declaration.name.clone(),
+                    kind: SymbolKind::Variable,
+                    span: declaration.name_span,
+                    declaration_span: node.span,
+                    signature: format!("set<{variable_type}> {}", declaration.name),
+                    visible: Span { start: node.span.end + 1, ..block },
+                });
+
+                if let Some(scope) = self.scopes.last_mut() {
+                    scope.insert(declaration.name.clone(), index);
+                }
+            }
+            NodeType::VariableReference(name) => {
+                let found = self.scopes.iter().rev().find_map(|scope| scope.get(name).copied());
+
+                if let Some(index) = found {
+                    self.analysis.references.push((node.span, index));
+                }
+            }
+            NodeType::FunctionCall(call) => {
+                if !call.builtin {
+                    if let Some(index) = self.functions.get(&call.name) {
+                        self.analysis.references.push((call.name_span, *index));
+                    }
+                }
+
+                for (_, argument) in call.parameters.iter() {
+                    self.resolve(argument, block);
+                }
+            }
+            NodeType::Block(nodes) => {
+                self.scopes.push(HashMap::new());
+                for inner in nodes {
+                    self.resolve(inner, node.span);
+                }
+                self.scopes.pop();
+            }
+            NodeType::ListLiteral(nodes) => {
+                for inner in nodes {
+                    self.resolve(inner, block);
+                }
+            }
+            NodeType::FieldAccess(target, _) => self.resolve(target, block),
+            NodeType::ReturnExpression(value) => self.resolve(value, block),
+            NodeType::TryCatch(try_catch) => {
+                self.resolve(&try_catch.body, block);
+
+                let index = self.declare(Symbol {
+                    name: try_catch.variable.clone(),
+                    kind: SymbolKind::Variable,
+                    span: try_catch.variable_span,
+                    declaration_span: try_catch.variable_span,
+                    signature: format!("catch {}", try_catch.variable),
+                    visible: try_catch.handler.span,
+                });
+
+                self.scopes.push(HashMap::from([(try_catch.variable.clone(), index)]));
+                self.resolve(&try_catch.handler, block);
+                self.scopes.pop();
+            }
+            NodeType::UnaryNode(_, operand) => self.resolve(operand, block),
+            NodeType::BinaryNode(_, left, right) => {
+                self.resolve(left, block);
+                self.resolve(right, block);
+            }
+            _ => {}
+        }
+    }
+
+    fn declare(&mut self, symbol: Symbol) -> usize {
+        let index = self.analysis.symbols.len();
+
+        self.analysis.references.push((symbol.span, index));
+        self.analysis.symbols.push(symbol);
+
+        index
+    }
+}
+
+pub fn signature(definition: &FunctionDefinition) -> String {
+    let parameters: Vec<String> = definition.signature.iter()
+        .map(|p| format!("{} @{}", p.param_name, p.param_type))
+        .collect();
+
+    format!("func {}({}) @{}", definition.name, parameters.join(", "), definition.return_type)
+}
+
+#[cfg(test)]
+mod analysis_tests {
+    use crate::lsp::analysis::{analyze, SymbolKind};
+    use crate::parse::incremental::Parse;
+
+    #[test]
+    fn test_resolves_scopes() {
+        let text = "func main(n @int) {
+    set<int> a <- n
+    {
+        set<int> a <- a + 1
+    }
+    set<inherit> b <- a
+}";
+        let analysis = analyze(text, Parse::new(text).as_ref(), None);
+        assert!(analysis.problems.is_empty(), "{:?}", analysis.problems);
+
+        // `a` in `a + 1` is the outer variable, `a` in `b <- a` too
+        let inner_use = text.find("a + 1").unwrap() as i128 + 1;
+        let last_use = text.rfind("<- a").unwrap() as i128 + 4;
+        let outer = analysis.symbol_at(inner_use).unwrap();
+
+        assert_eq!(outer.kind, SymbolKind::Variable);
+        assert_eq!(outer.span.line, 2);
+        assert_eq!(analysis.symbol_at(last_use).unwrap().span.line, 2);
+        assert_eq!(analysis.symbol_at(text.find("n\n").unwrap() as i128 + 1).unwrap().kind, SymbolKind::Parameter);
+    }
+}
@@ -0,0 +1,73 @@
+use lsp_types::{Position, Range};
+
+use crate::lexer::tokenizer::Span;
+
+/// Converts between the char positions of spans and LSP positions, which count lines from zero
+/// and columns in UTF-16 code units.
+pub struct LineIndex {
+    chars: Vec<char>,
+    line_starts: Vec<usize>,
+}
+
+impl LineIndex {
+    pub fn new(text: &str) -> Self {
+        let chars: Vec<char> = text.chars().collect();
+        let mut line_starts = vec![0];
+
+        for (i, c) in chars.iter().enumerate() {
+            if *c == '\n' {
+                line_starts.push(i + 1);
+            }
+        }
+
+        Self { chars, line_starts }
+    }
+
+    /// The position of a 0-based char offset.
+    pub fn position(&self, offset: usize) -> Position {
+        let offset = offset.min(self.chars.len());
+        let line = self.line_starts.partition_point(|start| *start <= offset) - 1;
+        let character: usize = self.chars[self.line_starts[line]..offset].iter().map(|c| c.len_utf16()).sum();
+
+        Position::new(line as u32, character as u32)
+    }
+
+    /// The 0-based char offset of a position, clamped to the end of its line.
+    pub fn offset(&self, position: Position) -> usize {
+        let Some(start) = self.line_starts.get(position.line as usize) else {
+            return self.chars.len();
+        };
+
+        let mut offset = *start;
+        let mut character = 0;
+
+        while offset < self.chars.len() && self.chars[offset] != '\n' && character < position.character as usize {
+            character += self.chars[offset].len_utf16();
+            offset += 1;
+        }
+
+        offset
+    }
+
+    /// The range of a span, whose 1-based inclusive positions make its start one char too far.
+    pub fn range(&self, span: Span) -> Range {
+        Range::new(self.position((span.start - 1).max(0) as usize), self.position(span.end.max(0) as usize))
+    }
+}
+
+#[cfg(test)]
+mod line_index_tests {
+    use lsp_types::Position;
+
+    use crate::lsp::line_index::LineIndex;
+
+    #[test]
+    fn test_utf16_columns() {
+        let index = LineIndex::new("ab\n\u{1F600}x\n");
+
+        assert_eq!(index.position(4), Position::new(1, 2));
+        assert_eq!(index.offset(Position::new(1, 2)), 4);
+        assert_eq!(index.offset(Position::new(0, 99)), 2);
+        assert_eq!(index.offset(Position::new(5, 0)), 6);
+    }
+}
@@ -0,0 +1,3 @@
+pub mod analysis;
+mod line_index;
+pub mod server;
@@ -0,0 +1,746 @@
+use std::collections::HashMap;
+use std::path::PathBuf;
+
+use lsp_server::{Connection, ErrorCode, Message, Notification, Request, Response};
+use lsp_types::notification::{DidChangeTextDocument, DidCloseTextDocument, DidOpenTextDocument, LogMessage, Notification as _, PublishDiagnostics};
+use lsp_types::request::{Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Request as _, SemanticTokensFullRequest};
+use lsp_types::{
+    CompletionItem, CompletionItemKind, CompletionOptions, CompletionParams, CompletionResponse, Diagnostic, DiagnosticSeverity,
+    DocumentSymbol, DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, Hover,
+    HoverContents, HoverParams, HoverProviderCapability, Location, LogMessageParams, MarkupContent, MarkupKind, MessageType, OneOf, Position,
+    PublishDiagnosticsParams, SemanticToken, SemanticTokenType, SemanticTokens, SemanticTokensFullOptions,
+    SemanticTokensLegend, SemanticTokensOptions, SemanticTokensParams, SemanticTokensResult,
+    SemanticTokensServerCapabilities, ServerCapabilities, SymbolKind as LspSymbolKind, TextDocumentContentChangeEvent,
+    TextDocumentSyncCapability, TextDocumentSyncKind, Uri,
+};
+
+use crate::error::parser::ParserError;
+use crate::lexer::symbols::SymbolType;
+use crate::lexer::tokenizer::{Span, TokenType};
+use crate::lsp::analysis::{analyze, Analysis, Symbol, SymbolKind};
+use crate::lsp::line_index::LineIndex;
+use crate::parse::incremental::{Parse, TextEdit};
+use crate::runtime::builtins::builtin_names;
+
+const KEYWORDS: [&str; 12] = ["func", "pub", "import", "set", "return", "try", "catch", "builtin", "inherit", "true", "false", "NA"];
+
+/// The token types of the semantic tokens legend, in the order their indices refer to.
+const TOKEN_TYPES: [SemanticTokenType; 9] = [
+    SemanticTokenType::KEYWORD,
+    SemanticTokenType::FUNCTION,
+    SemanticTokenType::PARAMETER,
+    SemanticTokenType::VARIABLE,
+    SemanticTokenType::TYPE,
+    SemanticTokenType::NUMBER,
+    SemanticTokenType::STRING,
+    SemanticTokenType::COMMENT,
+    SemanticTokenType::OPERATOR,
+];
+
+struct Document {
+    text: String,
+    /// The syntax tree of the text, which edits update incrementally, or the error the text does
+    /// not parse with, after which the next edit parses everything again.
+    parse: Result<Parse, ParserError>,
+    line_index: LineIndex,
+    analysis: Analysis,
+}
+
+impl Document {
+    fn new(uri: &Uri, text: &str) -> Self {
+        Self::analyzed(uri, text.to_string(), Parse::new(text))
+    }
+
+    fn analyzed(uri: &Uri, text: String, parse: Result<Parse, ParserError>) -> Self {
+        let analysis = analyze(&text, parse.as_ref(), file_path(uri).as_deref());
+        Self { line_index: LineIndex::new(&text), text, parse, analysis }
+    }
+
+    /// The document after the changes, which are applied in order. A change without a range
+    /// replaces the whole text.
+    fn change(self, uri: &Uri, changes: Vec<TextDocumentContentChangeEvent>) -> Self {
+        let Document { mut text, mut parse, mut line_index, .. } = self;
+
+        for change in changes {
+            match change.range {
+                Some(range) => {
+                    let edit = TextEdit::new(line_index.offset(range.start), line_index.offset(range.end), &change.text);
+                    text = edit.apply(&text);
+                    parse = match &parse {
+                        Ok(parse) => parse.edit(&edit),
+                        Err(_) => Parse::new(&text),
+                    };
+                }
+                None => {
+                    parse = Parse::new(&change.text);
+                    text = change.text;
+                }
+            }
+
+            line_index = LineIndex::new(&text);
+        }
+
+        Self::analyzed(uri, text, parse)
+    }
+
+    /// The 1-based char position of an LSP position, as used by spans.
+    fn position(&self, position: Position) -> i128 {
+        self.line_index.offset(position) as i128 + 1
+    }
+}
+
+struct Server<'a> {
+    connection: &'a Connection,
+    documents: HashMap<Uri, Document>,
+}
+
+/// Serves the language server protocol on standard input and output until the client exits.
+pub fn run_stdio() -> anyhow::Result<()> {
+    let (connection, io_threads) = Connection::stdio();
+
+    run(&connection)?;
+
+    // the writer thread only finishes once the connection is gone
+    drop(connection);
+    io_threads.join()?;
+
+    Ok(())
+}
+
+/// Serves the language server protocol on a connection until the client shuts the server down.
+pub fn run(connection: &Connection) -> anyhow::Result<()> {
+    connection.initialize(serde_json::to_value(capabilities())?)?;
+
+    let mut server = Server { connection, documents: HashMap::new() };
+
+    for message in &connection.receiver {
+        match message {
+            Message::Request(request) => {
+                if connection.handle_shutdown(&request)? {
+                    return Ok(());
+                }
+
+                server.handle_request(request)?;
+            }
+            Message::Notification(notification) => server.handle_notification(notification)?,
+            Message::Response(_) => {}
+        }
+    }
+
+    Ok(())
+}
+
+fn capabilities() -> ServerCapabilities {
+    ServerCapabilities {
+        text_document_sync: Some(TextDocumentSyncCapability::Kind(TextDocumentSyncKind::INCREMENTAL)),
+        definition_provider: Some(OneOf::Left(true)),
+        hover_provider: Some(HoverProviderCapability::Simple(true)),
+        completion_provider: Some(CompletionOptions::default()),
+        document_symbol_provider: Some(OneOf::Left(true)),
+        semantic_tokens_provider: Some(SemanticTokensServerCapabilities::SemanticTokensOptions(SemanticTokensOptions {
+            legend: SemanticTokensLegend { token_types: TOKEN_TYPES.to_vec(), token_modifiers: Vec::new() },
+            full: Some(SemanticTokensFullOptions::Bool(true)),
+            ..Default::default()
+        })),
+        ..Default::default()
+    }
+}
+
+impl Server<'_> {
+    fn handle_request(&mut self, request: Request) -> anyhow::Result<()> {
+        let response = match request.method.as_str() {
+            GotoDefinition::METHOD => self.respond::<GotoDefinition>(request, Self::definition),
+            HoverRequest::METHOD => self.respond::<HoverRequest>(request, Self::hover),
+            Completion::METHOD => self.respond::<Completion>(request, Self::completion),
+            DocumentSymbolRequest::METHOD => self.respond::<DocumentSymbolRequest>(request, Self::document_symbols),
+            SemanticTokensFullRequest::METHOD => self.respond::<SemanticTokensFullRequest>(request, Self::semantic_tokens),
+            method => Response::new_err(request.id, ErrorCode::MethodNotFound as i32, format!("Unknown method '{method}'.")),
+        };
+
+        self.connection.sender.send(Message::Response(response))?;
+        Ok(())
+    }
+
+    fn respond<R: lsp_types::request::Request>(&self, request: Request, handler: fn(&Self, R::Params) -> R::Result) -> Response {
+        match serde_json::from_value(request.params) {
+            Ok(params) => Response::new_ok(request.id, handler(self, params)),
+            Err(e) => Response::new_err(request.id, ErrorCode::InvalidParams as i32, e.to_string()),
+        }
+    }
+
+    fn handle_notification(&mut self, notification: Notification) -> anyhow::Result<()> {
+        match notification.method.as_str() {
+            DidOpenTextDocument::METHOD => {
+                let Some(params) = self.params::<DidOpenTextDocument>(notification)? else {
+                    return Ok(());
+                };
+                let uri = params.text_document.uri;
+                let document = Document::new(&uri, &params.text_document.text);
+                self.update(uri, document)
+            }
+            DidChangeTextDocument::METHOD => {
+                let Some(params) = self.params::<DidChangeTextDocument>(notification)? else {
+                    return Ok(());
+                };
+
+                let uri = params.text_document.uri;
+                let Some(document) = self.documents.remove(&uri) else {
+                    return Ok(());
+                };
+                self.update(uri.clone(), document.change(&uri, params.content_changes))
+            }
+            DidCloseTextDocument::METHOD => {
+                let Some(params) = self.params::<DidCloseTextDocument>(notification)? else {
+                    return Ok(());
+                };
+                self.documents.remove(&params.text_document.uri);
+                self.publish_diagnostics(params.text_document.uri, Vec::new())
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// The params of a notification, or `None` once the client is told they are invalid. Unlike
+    /// a request, a notification has no response to report the problem in.
+    fn params<N: lsp_types::notification::Notification>(&self, notification: Notification) -> anyhow::Result<Option<N::Params>> {
+        match serde_json::from_value(notification.params) {
+            Ok(params) => Ok(Some(params)),
+            Err(e) => {
+                let params = LogMessageParams { typ: MessageType::ERROR, message: format!("Ignored '{}' with invalid params: {e}", N::METHOD) };
+                let log = Notification::new(LogMessage::METHOD.into(), params);
+                self.connection.sender.send(Message::Notification(log))?;
+                Ok(None)
+            }
+        }
+    }
+
+    fn update(&mut self, uri: Uri, document: Document) -> anyhow::Result<()> {
+        let diagnostics = document.analysis.problems.iter()
+            .map(|problem| Diagnostic {
+                range: document.line_index.range(problem.span),
+                severity: Some(DiagnosticSeverity::ERROR),
+                source: Some("stat_script".into()),
+                message: problem.message.clone(),
+                ..Default::default()
+            })
+            .collect();
+
+        self.documents.insert(uri.clone(), document);
+        self.publish_diagnostics(uri, diagnostics)
+    }
+
+    fn publish_diagnostics(&self, uri: Uri, diagnostics: Vec<Diagnostic>) -> anyhow::Result<()> {
+        let params = PublishDiagnosticsParams { uri, diagnostics, version: None };
+        let notification = Notification::new(PublishDiagnostics::METHOD.into(), params);
+
+        self.connection.sender.send(Message::Notification(notification))?;
+        Ok(())
+    }
+
+    /// The document and 1-based char position a request is about.
+    fn locate(&self, uri: &Uri, position: Position) -> Option<(&Document, i128)> {
+        let document = self.documents.get(uri)?;
+        Some((document, document.position(position)))
+    }
+
+    fn definition(&self, params: GotoDefinitionParams) -> Option<GotoDefinitionResponse> {
+        let uri = params.text_document_position_params.text_document.uri;
+        let (document, position) = self.locate(&uri, params.text_document_position_params.position)?;
+        let symbol = document.analysis.symbol_at(position)?;
+
+        Some(GotoDefinitionResponse::Scalar(Location::new(uri.clone(), document.line_index.range(symbol.span))))
+    }
+
+    fn hover(&self, params: HoverParams) -> Option<Hover> {
+        let uri = &params.text_document_position_params.text_document.uri;
+        let (document, position) = self.locate(uri, params.text_document_position_params.position)?;
+
+        let (declaration, span) = match document.analysis.symbol_at(position) {
+            Some(symbol) => (symbol.signature.clone(), symbol.span),
+            None => {
+                // builtins are not declared anywhere, but their calls can still be hovered
+                let tokens = &document.analysis.tokens;
+                let index = tokens.iter().position(|t| t.start_pos <= position && position <= t.end_pos + 1)?;
+                let is_builtin = index > 0 && tokens[index - 1].value.as_deref() == Some("builtin");
+
+                if !is_builtin {
+                    return None;
+                }
+
+                (format!("builtin {}", tokens[index].value.as_deref().unwrap_or_default()), tokens[index].span())
+            }
+        };
+
+        Some(Hover {
+            contents: HoverContents::Markup(MarkupContent {
+                kind: MarkupKind::Markdown,
+                value: format!("```stat_script\n{declaration}\n```"),
+            }),
+            range: Some(document.line_index.range(span)),
+        })
+    }
+
+    fn completion(&self, params: CompletionParams) -> Option<CompletionResponse> {
+        let uri = &params.text_document_position.text_document.uri;
+        let (document, position) = self.locate(uri, params.text_document_position.position)?;
+        let analysis = &document.analysis;
+
+        // skip the name being typed to see what comes before it
+        let mut before = analysis.token_before(position);
+        if let Some(token) = before.filter(|t| t.end_pos == position - 1 && t.token_type == Some(TokenType::Identifier)) {
+            before = analysis.token_before(token.start_pos);
+        }
+
+        let builtins = builtin_names().into_iter().map(|name| CompletionItem {
+            label: name,
+            kind: Some(CompletionItemKind::FUNCTION),
+            detail: Some("builtin".into()),
+            ..Default::default()
+        });
+
+        if before.and_then(|t| t.value.as_deref()) == Some("builtin") {
+            return Some(CompletionResponse::Array(builtins.collect()));
+        }
+
+        let mut items: Vec<CompletionItem> = analysis.visible_symbols(position)
+            .map(|symbol| CompletionItem {
+                label: symbol.name.clone(),
+                kind: Some(match symbol.kind {
+                    SymbolKind::Function => CompletionItemKind::FUNCTION,
+                    SymbolKind::Parameter | SymbolKind::Variable => CompletionItemKind::VARIABLE,
+                }),
+                detail: Some(symbol.signature.clone()),
+                ..Default::default()
+            })
+            .collect();
+
+        items.extend(KEYWORDS.iter().map(|keyword| CompletionItem {
+            label: keyword.to_string(),
+            kind: Some(CompletionItemKind::KEYWORD),
+            ..Default::default()
+        }));
+        items.extend(builtins);
+
+        Some(CompletionResponse::Array(items))
+    }
+
+    fn document_symbols(&self, params: DocumentSymbolParams) -> Option<DocumentSymbolResponse> {
+        let document = self.documents.get(&params.text_document.uri)?;
+
+        let symbols = document.analysis.symbols.iter()
+            .filter(|symbol| symbol.kind == SymbolKind::Function)
+            .map(|symbol| {
+                #[allow(deprecated)]
+                DocumentSymbol {
+                    name: symbol.name.clone(),
+                    detail: Some(symbol.signature.clone()),
+                    kind: LspSymbolKind::FUNCTION,
+                    tags: None,
+                    deprecated: None,
+                    range: document.line_index.range(symbol.declaration_span),
+                    selection_range: document.line_index.range(symbol.span),
+                    children: None,
+                }
+            })
+            .collect();
+
+        Some(DocumentSymbolResponse::Nested(symbols))
+    }
+
+    fn semantic_tokens(&self, params: SemanticTokensParams) -> Option<SemanticTokensResult> {
+        let document = self.documents.get(&params.text_document.uri)?;
+        let analysis = &document.analysis;
+
+        let mut classified: Vec<(Span, SemanticTokenType)> = Vec::new();
+
+        for (i, token) in analysis.tokens.iter().enumerate() {
+            let value = token.value.as_deref().unwrap_or_default();
+            let previous = |n: usize| i.checked_sub(n).and_then(|j| analysis.tokens[j].value.as_deref());
+
+            let token_type = match &token.token_type {
+                Some(TokenType::String | TokenType::Character(_)) => SemanticTokenType::STRING,
+                Some(TokenType::Number(_)) => SemanticTokenType::NUMBER,
+                Some(TokenType::Identifier) if KEYWORDS.contains(&value) => SemanticTokenType::KEYWORD,
+                Some(TokenType::Identifier) => match analysis.symbol_of(token.span()).map(symbol_token_type) {
+                    Some(token_type) => token_type,
+                    None if previous(1) == Some("@") || (previous(1) == Some("<") && previous(2) == Some("set")) => {
+                        SemanticTokenType::TYPE
+                    }
+                    None if previous(1) == Some("builtin") => SemanticTokenType::FUNCTION,
+                    None => continue,
+                },
+                Some(TokenType::Symbol(
+                    SymbolType::ParenthesisLeft | SymbolType::ParenthesisRight | SymbolType::BracketLeft
+                    | SymbolType::BracketRight | SymbolType::BraceLeft | SymbolType::BraceRight | SymbolType::Comma
+                    | SymbolType::AtSign,
+                )) => continue,
+                Some(TokenType::Symbol(_)) => SemanticTokenType::OPERATOR,
+                None => continue,
+            };
+
+            classified.push((token.span(), token_type));
+        }
+
+        classified.extend(analysis.comments.iter().map(|c| (c.span, SemanticTokenType::COMMENT)));
+        classified.sort_by_key(|(span, _)| span.start);
+
+        let mut data = Vec::new();
+        let mut last = Position::new(0, 0);
+
+        for (span, token_type) in classified {
+            let range = document.line_index.range(span);
+
+            // not every client can show tokens spanning lines
+            if range.start.line != range.end.line {
+                continue;
+            }
+
+            let delta_line = range.start.line - last.line;
+            let delta_start = if delta_line == 0 { range.start.character - last.character } else { range.start.character };
+
+            data.push(SemanticToken {
+                delta_line,
+                delta_start,
+                length: range.end.character - range.start.character,
+                token_type: TOKEN_TYPES.iter().position(|t| *t == token_type).unwrap() as u32,
+                token_modifiers_bitset: 0,
+            });
+
+            last = range.start;
+        }
+
+        Some(SemanticTokensResult::Tokens(SemanticTokens { result_id: None, data }))
+    }
+}
+
+/// The path of a `file:` URI, to resolve the imports of its document against.
+fn file_path(uri: &Uri) -> Option<PathBuf> {
+    if uri.scheme()?.as_str() != "file" {
+        return None;
+    }
+
+    Some(PathBuf::from(uri.path().as_estr().decode().into_string_lossy().into_owned()))
+}
+
+fn symbol_token_type(symbol: &Symbol) -> SemanticTokenType {
+    match symbol.kind {
+        SymbolKind::Function => SemanticTokenType::FUNCTION,
+        SymbolKind::Parameter => SemanticTokenType::PARAMETER,
+        SymbolKind::Variable => SemanticTokenType::VARIABLE,
+    }
+}
+
+#[cfg(test)]
+mod server_tests {
+    use std::collections::VecDeque;
+    use std::thread::{self, JoinHandle};
+    use std::time::Duration;
+
+    use lsp_server::{Connection, Message, Notification, Request};
+    use lsp_types::notification::{DidChangeTextDocument, DidOpenTextDocument, Exit, Initialized, LogMessage, Notification as _, PublishDiagnostics};
+    use lsp_types::request::{
+        Completion, DocumentSymbolRequest, GotoDefinition, HoverRequest, Initialize, SemanticTokensFullRequest, Shutdown,
+    };
+    use lsp_types::{
+        CompletionParams, CompletionResponse, DidChangeTextDocumentParams, DidOpenTextDocumentParams,
+        DocumentSymbolParams, DocumentSymbolResponse, GotoDefinitionParams, GotoDefinitionResponse, HoverContents,
+        HoverParams, InitializeParams, InitializedParams, MessageType, Position, Range, SemanticTokensParams, SemanticTokensResult,
+        TextDocumentContentChangeEvent, TextDocumentIdentifier, TextDocumentItem, TextDocumentPositionParams, Uri,
+        VersionedTextDocumentIdentifier,
+    };
+
+    use crate::lsp::server::{run, Document};
+    use crate::parse::incremental::{Parse, Reparsed};
+
+    const SOURCE: &str = "func square(x @int) @int {
+    return x * x
+}
+
+# entry point
+func main() @i32 {
+    set<int> total <- square(x = 4)
+    set<int> doubled <- total * 2
+    builtin println(template = \"done\")
+    return 0
+}
+";
+
+    /// A language client talking to a server running on another thread.
+    struct Client {
+        connection: Connection,
+        server: Option<JoinHandle<()>>,
+        notifications: VecDeque<Notification>,
+        next_id: i32,
+    }
+
+    impl Client {
+        fn start() -> Self {
+            let (server, connection) = Connection::memory();
+            let server = thread::spawn(move || run(&server).unwrap());
+
+            let mut client = Client { connection, server: Some(server), notifications: VecDeque::new(), next_id: 0 };
+            client.request::<Initialize>(InitializeParams::default());
+            client.notify::<Initialized>(InitializedParams {});
+
+            client
+        }
+
+        fn receive(&self) -> Message {
+            self.connection.receiver.recv_timeout(Duration::from_secs(10)).expect("the server did not answer")
+        }
+
+        fn request<R: lsp_types::request::Request>(&mut self, params: R::Params) -> R::Result {
+            self.next_id += 1;
+            let request = Request::new(self.next_id.into(), R::METHOD.into(), params);
+            self.connection.sender.send(Message::Request(request)).unwrap();
+
+            loop {
+                match self.receive() {
+                    Message::Response(response) if response.id == self.next_id.into() => {
+                        assert!(response.error.is_none(), "{:?}", response.error);
+                        return serde_json::from_value(response.result.unwrap_or_default()).unwrap();
+                    }
+                    Message::Notification(notification) => self.notifications.push_back(notification),
+                    message => panic!("unexpected message {message:?}"),
+                }
+            }
+        }
+
+        fn notify<N: lsp_types::notification::Notification>(&self, params: N::Params) {
+            let notification = Notification::new(N::METHOD.into(), params);
+            self.connection.sender.send(Message::Notification(notification)).unwrap();
+        }
+
+        fn notification<N: lsp_types::notification::Notification>(&mut self) -> N::Params {
+            loop {
+                let notification = match self.notifications.pop_front() {
+                    Some(notification) => notification,
+                    None => match self.receive() {
+                        Message::Notification(notification) => notification,
+                        message => panic!("unexpected message {message:?}"),
+                    },
+                };
+
+                if notification.method == N::METHOD {
+                    return serde_json::from_value(notification.params).unwrap();
+                }
+            }
+        }
+
+        fn position(&self, line: u32, character: u32) -> TextDocumentPositionParams {
+            TextDocumentPositionParams::new(TextDocumentIdentifier::new(uri()), Position::new(line, character))
+        }
+    }
+
+    impl Drop for Client {
+        fn drop(&mut self) {
+            if thread::panicking() {
+                return;
+            }
+
+            self.request::<Shutdown>(());
+            self.notify::<Exit>(());
+            self.server.take().unwrap().join().unwrap();
+        }
+    }
+
+    fn uri() -> Uri {
+        "file:///project/main.stsc".parse().unwrap()
+    }
+
+    fn open(client: &mut Client) {
+        client.notify::<DidOpenTextDocument>(DidOpenTextDocumentParams {
+            text_document: TextDocumentItem::new(uri(), "stat_script".into(), 1, SOURCE.into()),
+        });
+    }
+
+    fn labels(response: Option<CompletionResponse>) -> Vec<String> {
+        match response {
+            Some(CompletionResponse::Array(items)) => items.into_iter().map(|i| i.label).collect(),
+            response => panic!("unexpected completion {response:?}"),
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let mut client = Client::start();
+        open(&mut client);
+        assert!(client.notification::<PublishDiagnostics>().diagnostics.is_empty());
+
+        client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), 2),
+            content_changes: vec![TextDocumentContentChangeEvent {
+                range: None,
+                range_length: None,
+                text: SOURCE.replace("square(x = 4)", "square(y = 4)"),
+            }],
+        });
+
+        let diagnostics = client.notification::<PublishDiagnostics>().diagnostics;
+        assert_eq!(diagnostics.len(), 2, "{diagnostics:?}");
+        assert!(diagnostics.iter().all(|d| d.range.start.line == 6), "{diagnostics:?}");
+
+        client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+            text_document: VersionedTextDocumentIdentifier::new(uri(), 3),
+            content_changes: vec![TextDocumentContentChangeEvent { range: None, range_length: None, text: "func main( {}".into() }],
+        });
+
+        let diagnostics = client.notification::<PublishDiagnostics>().diagnostics;
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+        assert_eq!(diagnostics[0].range.start, Position::new(0, 11));
+    }
+
+    fn replace(line: u32, start: u32, end: u32, text: &str) -> TextDocumentContentChangeEvent {
+        TextDocumentContentChangeEvent {
+            range: Some(Range::new(Position::new(line, start), Position::new(line, end))),
+            range_length: None,
+            text: text.into(),
+        }
+    }
+
+    #[test]
+    fn test_incremental_changes() {
+        let mut client = Client::start();
+        open(&mut client);
+        assert!(client.notification::<PublishDiagnostics>().diagnostics.is_empty());
+
+        // `square(x = 4)` loses its `)`, then gets it back with the wrong parameter
+        let change = |client: &mut Client, version, changes| {
+            client.notify::<DidChangeTextDocument>(DidChangeTextDocumentParams {
+                text_document: VersionedTextDocumentIdentifier::new(uri(), version),
+                content_changes: changes,
+            });
+            client.notification::<PublishDiagnostics>().diagnostics
+        };
+
+        let diagnostics = change(&mut client, 2, vec![replace(6, 34, 35, "")]);
+        assert_eq!(diagnostics.len(), 1, "{diagnostics:?}");
+
+        let diagnostics = change(&mut client, 3, vec![replace(6, 34, 34, ")"), replace(6, 29, 30, "y")]);
+        assert_eq!(diagnostics.len(), 2, "{diagnostics:?}");
+        assert!(diagnostics.iter().all(|d| d.range.start.line == 6), "{diagnostics:?}");
+    }
+
+    #[test]
+    fn test_changes_reparse_incrementally() {
+        let document = Document::new(&uri(), SOURCE);
+
+        let renamed = document.change(&uri(), vec![replace(7, 13, 20, "twice")]);
+        assert_eq!(renamed.text, SOURCE.replace("doubled", "twice"));
+        assert_eq!(renamed.parse.as_ref().map(Parse::reparsed).ok(), Some(Reparsed::Token));
+
+        // a text that does not parse keeps its text, and the next edit parses it again
+        let broken = renamed.change(&uri(), vec![replace(1, 14, 14, " *")]);
+        assert!(broken.parse.is_err());
+
+        let fixed = broken.change(&uri(), vec![replace(1, 14, 16, "")]);
+        assert_eq!(fixed.text, SOURCE.replace("doubled", "twice"));
+        assert_eq!(fixed.parse.as_ref().map(Parse::reparsed).ok(), Some(Reparsed::Full));
+        assert!(fixed.analysis.problems.is_empty(), "{:?}", fixed.analysis.problems);
+    }
+
+    #[test]
+    fn test_ignores_invalid_notifications() {
+        let mut client = Client::start();
+        let notification = Notification::new(DidOpenTextDocument::METHOD.into(), serde_json::json!({ "textDocument": 1 }));
+        client.connection.sender.send(Message::Notification(notification)).unwrap();
+
+        let log = client.notification::<LogMessage>();
+        assert_eq!(log.typ, MessageType::ERROR);
+        assert!(log.message.starts_with("Ignored 'textDocument/didOpen'"), "{}", log.message);
+
+        open(&mut client);
+        assert!(client.notification::<PublishDiagnostics>().diagnostics.is_empty());
+    }
+
+    #[test]
+    fn test_navigation() {
+        let mut client = Client::start();
+        open(&mut client);
+
+        let definition = client.request::<GotoDefinition>(GotoDefinitionParams {
+            text_document_position_params: client.position(6, 24),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        match definition {
+            Some(GotoDefinitionResponse::Scalar(location)) => {
+                assert_eq!(location.range, Range::new(Position::new(0, 5), Position::new(0, 11)));
+            }
+            definition => panic!("unexpected definition {definition:?}"),
+        }
+
+        let hover = client.request::<HoverRequest>(HoverParams {
+            text_document_position_params: client.position(7, 26),
+            work_done_progress_params: Default::default(),
+        });
+        match hover.map(|h| h.contents) {
+            Some(HoverContents::Markup(content)) => assert!(content.value.contains("set<int> total"), "{}", content.value),
+            hover => panic!("unexpected hover {hover:?}"),
+        }
+
+        let symbols = client.request::<DocumentSymbolRequest>(DocumentSymbolParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        match symbols {
+            Some(DocumentSymbolResponse::Nested(symbols)) => {
+                let details: Vec<_> = symbols.iter().map(|s| s.detail.clone().unwrap()).collect();
+                assert_eq!(details, ["func square(x @int) @int", "func main() @i32"]);
+                assert_eq!(symbols[1].range.end, Position::new(10, 1));
+            }
+            symbols => panic!("unexpected symbols {symbols:?}"),
+        }
+    }
+
+    #[test]
+    fn test_completion() {
+        let mut client = Client::start();
+        open(&mut client);
+
+        let completion = |client: &mut Client, line, character| {
+            labels(client.request::<Completion>(CompletionParams {
+                text_document_position: client.position(line, character),
+                work_done_progress_params: Default::default(),
+                partial_result_params: Default::default(),
+                context: None,
+            }))
+        };
+
+        let in_main = completion(&mut client, 7, 4);
+        assert!(in_main.contains(&"total".to_string()));
+        assert!(in_main.contains(&"square".to_string()));
+        assert!(!in_main.contains(&"x".to_string()));
+        assert!(!in_main.contains(&"doubled".to_string()));
+
+        let after_builtin = completion(&mut client, 8, 14);
+        assert!(after_builtin.contains(&"println".to_string()));
+        assert!(after_builtin.contains(&"normal_cdf".to_string()));
+        assert!(!after_builtin.contains(&"total".to_string()));
+    }
+
+    #[test]
+    fn test_semantic_tokens() {
+        let mut client = Client::start();
+        open(&mut client);
+
+        let tokens = client.request::<SemanticTokensFullRequest>(SemanticTokensParams {
+            text_document: TextDocumentIdentifier::new(uri()),
+            work_done_progress_params: Default::default(),
+            partial_result_params: Default::default(),
+        });
+        let Some(SemanticTokensResult::Tokens(tokens)) = tokens else {
+            panic!("unexpected semantic tokens {tokens:?}");
+        };
+
+        // (delta line, delta start, length, type) of `func square(x @int) @int`
+        let first: Vec<_> = tokens.data.iter().take(5)
+            .map(|t| (t.delta_line, t.delta_start, t.length, t.token_type))
+            .collect();
+        assert_eq!(first, [(0, 0, 4, 0), (0, 5, 6, 1), (0, 7, 1, 2), (0, 3, 3, 4), (0, 6, 3, 4)]);
+
+        // the comment before main
+        assert!(tokens.data.iter().any(|t| t.delta_start == 0 && t.length == 13 && t.token_type == 7));
+    }
+}
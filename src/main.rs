@@ -1,58 +1,527 @@
 use std::fs;
+use std::io::Read;
+use std::path::{Path, PathBuf};
 use std::process::exit;
 
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 
+use stat_script::check::checker::check;
 use stat_script::lexer::tokenizer::Tokenizer;
-use stat_script::parse::parser::StatParser;
-use stat_script::runtime;
+use stat_script::module::loader::Loader;
+use stat_script::parse::formatter::format_program;
+use stat_script::parse::parser::{Node, StatParser};
+use stat_script::debugger::Prompt;
+use stat_script::repl::Repl;
+use stat_script::{ParserError, RuntimeError, RuntimeErrorKind, StackFrame};
+use stat_script::runtime::cancellation::CancellationToken;
+use stat_script::runtime::compiler::compile;
+use stat_script::runtime::debug::{DebugControl, Debugger, Location};
+use stat_script::runtime::image::{self, Image};
+use stat_script::runtime::main_arguments;
+use stat_script::runtime::program::{Evaluator, Program};
+use stat_script::runtime::sandbox::STACK_SIZE;
+
+/// The script failed at runtime.
+const EXIT_RUNTIME_ERROR: i32 = 1;
+/// `fmt --check` found scripts that are not formatted.
+const EXIT_UNFORMATTED: i32 = 1;
+/// The script could not be read, or its arguments do not match `main`. Invalid command lines
+/// exit with the same code.
+const EXIT_USAGE: i32 = 2;
+/// The script could not be tokenized or parsed, or a module it imports could not be loaded.
+const EXIT_PARSE_ERROR: i32 = 3;
+/// The script did not pass the static checks.
+const EXIT_TYPE_ERROR: i32 = 4;
+/// The script was interrupted with Ctrl-C, like a process killed by SIGINT.
+const EXIT_INTERRUPTED: i32 = 130;
+
+const EXIT_CODES: &str = "\
+Exit codes:
+    0  success, or the integer returned by main or passed to exit()
+    1  runtime error, or `fmt --check` found unformatted scripts
+    2  usage error: unreadable script, invalid arguments
+    3  parse error, or a module could not be imported
+    4  type error
+  130  interrupted with Ctrl-C";
 
 #[derive(Debug, Parser)]
-struct CLI {
-    #[arg(short, long)]
-    file: String,
+#[command(version, about = "The StatScript interpreter", after_help = EXIT_CODES)]
+struct Cli {
+    #[command(subcommand)]
+    command: Command,
+
+    /// A directory to look for imported modules in, after the directory of the importing file.
+    /// Can be given more than once, and is followed by the directories in STAT_SCRIPT_PATH
+    #[arg(long = "module-path", global = true, value_name = "DIR")]
+    module_path: Vec<PathBuf>,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Run a script
+    Run {
+        /// Path to the script or a compiled image, `-` reads it from stdin
+        file: String,
+
+        /// How to run the script
+        #[arg(long, value_enum, default_value_t = EngineArg::Bytecode)]
+        engine: EngineArg,
+
+        /// Arguments for the script's main function, by name (`--name=value`) or by position
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Run a script under a debugger, which pauses before the first statement and reads commands
+    /// like `break`, `step` or `print`. `help` lists them
+    Debug {
+        /// Path to the script
+        file: String,
+
+        /// Pause before the statement at the line, or at FILE:LINE in an imported module. Can be
+        /// given more than once
+        #[arg(short, long = "break", value_name = "[FILE:]LINE")]
+        breakpoints: Vec<Location>,
+
+        /// Arguments for the script's main function, by name (`--name=value`) or by position
+        #[arg(last = true)]
+        args: Vec<String>,
+    },
+    /// Compile a script to an image that later runs skip parsing and checking for. `run` also
+    /// picks up a fresh image next to the script by itself
+    Compile {
+        /// Path to the script, `-` reads it from stdin
+        file: String,
+
+        /// Path of the image, by default the script's path with the extension `.stsb`
+        #[arg(short, long)]
+        output: Option<String>,
+    },
+    /// Parse and check a script without running it
+    Check {
+        /// Path to the script, `-` reads it from stdin
+        file: String,
+    },
+    /// Print the tokens of a script
+    Tokens {
+        /// Path to the script, `-` reads it from stdin
+        file: String,
+    },
+    /// Print the syntax tree of a script
+    Ast {
+        /// Path to the script, `-` reads it from stdin
+        file: String,
+
+        /// Print the tree as JSON instead of pretty text
+        #[arg(long, conflicts_with = "cst")]
+        json: bool,
+
+        /// Print the lossless syntax tree, with every token, space and comment
+        #[arg(long)]
+        cst: bool,
+    },
+    /// Print scripts in canonical formatting
+    Fmt {
+        /// Paths to the scripts, `-` reads one from stdin
+        #[arg(required = true)]
+        files: Vec<String>,
+
+        /// Only list the scripts that are not formatted, and fail if there are any
+        #[arg(long)]
+        check: bool,
+    },
+    /// Start an interactive session
+    Repl,
+    /// Start a language server on stdin and stdout
+    Lsp,
+    /// Start a debug adapter on stdin and stdout, which editors launch scripts under
+    Dap,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum EngineArg {
+    /// Compile the script to bytecode and run that
+    Bytecode,
+    /// Evaluate the syntax tree directly
+    TreeWalker,
 }
 
 fn main() {
-    let arguments = CLI::parse();
+    let arguments = Cli::parse();
 
-    let file_content = match fs::read_to_string(&arguments.file) {
-        Ok(s) => s,
+    // scripts nest calls up to the default depth limit on a stack sized for it
+    let command = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(move || run(arguments));
+    match command.map(|thread| thread.join()) {
+        Ok(Ok(())) => {}
+        Ok(Err(panic)) => std::panic::resume_unwind(panic),
         Err(e) => {
-            eprintln!("Failed to read file: \"{}\": {e}", arguments.file);
-            exit(1)
+            eprintln!("Failed to start: {e}");
+            exit(EXIT_RUNTIME_ERROR)
         }
-    };
+    }
+}
 
-    let tokenizer = Tokenizer::new(file_content);
+fn run(arguments: Cli) {
 
+    let search_path: Vec<PathBuf> = arguments.module_path.into_iter().chain(Loader::search_path_from_env()).collect();
+    let mut loader = Loader::new(search_path.clone());
 
-    let mut parser = StatParser::new(tokenizer);
+    match arguments.command {
+        Command::Run { file, engine, args } => {
+            let engine = match engine {
+                EngineArg::Bytecode => Evaluator::Bytecode,
+                EngineArg::TreeWalker => Evaluator::TreeWalker,
+            };
+            let mut program = load_program(&file, engine, &mut loader).with_arguments(args);
+            interrupt_on_ctrl_c(&program.cancellation);
 
+            check_main_arguments(&program, "run", &file);
+            exit_with(program.execute())
+        }
+        Command::Debug { file, breakpoints, args } => {
+            let source = read_source(&file);
+            let ast = link(&parse(source.clone()), &file, &mut loader);
+            typecheck(&ast);
 
+            let prompt = match Prompt::terminal(&source) {
+                Ok(prompt) => prompt,
+                Err(e) => {
+                    eprintln!("The debugger failed: {e}");
+                    exit(EXIT_RUNTIME_ERROR)
+                }
+            };
+            let control = DebugControl::new();
+            let script = fs::canonicalize(&file).ok();
+            for mut breakpoint in breakpoints {
+                // FILE:LINE of the script itself is a line of the script
+                if breakpoint.module.is_some() && breakpoint.module == script {
+                    breakpoint.module = None;
+                }
+                if let Some(module) = breakpoint.module.as_ref().filter(|module| !loader.modules().any(|loaded| loaded.path == **module)) {
+                    eprintln!("Cannot set a breakpoint in \"{}\", which the script does not import.", module.display());
+                    exit(EXIT_USAGE)
+                }
+                control.add_breakpoint(breakpoint);
+            }
+            pause_on_ctrl_c(&control);
 
-    let ast = match parser.parse() {
-        Ok(a) => a,
-        Err(e) => {
-            eprintln!("Failed to parse at position {}: {}", e.position, e.message);
-            exit(1)
+            let debugger = Debugger::new(prompt).with_control(control).stop_on_entry();
+            let mut program = Program::new(ast).with_arguments(args).with_debugger(debugger);
+
+            check_main_arguments(&program, "debug", &file);
+            exit_with(program.execute())
+        }
+        Command::Compile { file, output } => {
+            let source = read_source(&file);
+            let ast = link(&parse(source.clone()), &file, &mut loader);
+            typecheck(&ast);
+
+            let output = match output {
+                Some(output) => PathBuf::from(output),
+                None if file == "-" => {
+                    eprintln!("Compiling from stdin needs the path of the image, e.g. '-o script.stsb'.");
+                    exit(EXIT_USAGE)
+                }
+                None => image_path(&file),
+            };
+
+            // an absolute path still finds the source when the image is run from elsewhere
+            let source_path = fs::canonicalize(&file).ok().map(|path| path.to_string_lossy().into_owned());
+            let dependencies = loader.modules()
+                .map(|module| (module.path.to_string_lossy().into_owned(), module.source_hash))
+                .collect();
+            let image = Image::new(&source, source_path, dependencies, compile(&ast));
+
+            if let Err(e) = fs::write(&output, image.to_bytes()) {
+                eprintln!("Failed to write file: \"{}\": {e}", output.display());
+                exit(EXIT_USAGE)
+            }
+        }
+        Command::Check { file } => typecheck(&link(&parse(read_source(&file)), &file, &mut loader)),
+        Command::Tokens { file } => {
+            let mut tokenizer = Tokenizer::new(read_source(&file));
+
+            loop {
+                match tokenizer.next_token() {
+                    Ok(Some(token)) => println!(
+                        "{}:{}-{}\t{:?}\t{}",
+                        token.line,
+                        token.start_pos,
+                        token.end_pos,
+                        token.token_type.unwrap(),
+                        token.value.unwrap_or_default()
+                    ),
+                    Ok(None) => break,
+                    Err(e) => {
+                        eprintln!("Failed to tokenize at position {} (line {}): {}", e.position, e.line, e.message);
+                        exit(EXIT_PARSE_ERROR)
+                    }
+                }
+            }
+        }
+        Command::Ast { file, json, cst } => {
+            if cst {
+                let mut parser = StatParser::new(Tokenizer::new(read_source(&file)));
+
+                match parser.parse_syntax() {
+                    Ok(tree) => print!("{}", tree.debug_dump()),
+                    Err(e) => {
+                        eprintln!("Failed to parse at position {}: {}", e.position, e.message);
+                        exit(EXIT_PARSE_ERROR)
+                    }
+                }
+
+                return;
+            }
+
+            let ast = parse(read_source(&file));
+
+            if json {
+                match serde_json::to_string_pretty(&ast) {
+                    Ok(s) => println!("{s}"),
+                    Err(e) => {
+                        eprintln!("Failed to serialize the syntax tree: {e}");
+                        exit(EXIT_RUNTIME_ERROR)
+                    }
+                }
+            } else {
+                println!("{:#?}", ast);
+            }
+        }
+        Command::Fmt { files, check } => {
+            let mut unformatted = false;
+
+            for file in files.iter() {
+                let source = read_source(file);
+                let tree = parsed(StatParser::new(Tokenizer::new(source.clone())).parse_syntax());
+                let formatted = parsed(format_program(&tree));
+
+                if !check {
+                    print!("{formatted}");
+                } else if formatted != source {
+                    eprintln!("{file} is not formatted");
+                    unformatted = true;
+                }
+            }
+
+            if unformatted {
+                exit(EXIT_UNFORMATTED)
+            }
+        }
+        Command::Repl => {
+            let cancellation = CancellationToken::new();
+            interrupt_on_ctrl_c(&cancellation);
+
+            if let Err(e) = Repl::new(Box::new(std::io::stdout())).with_cancellation(cancellation).run() {
+                eprintln!("The repl failed: {e}");
+                exit(EXIT_RUNTIME_ERROR)
+            }
+        }
+        Command::Lsp => {
+            if let Err(e) = stat_script::lsp::server::run_stdio() {
+                eprintln!("The language server failed: {e}");
+                exit(EXIT_RUNTIME_ERROR)
+            }
+        }
+        Command::Dap => {
+            if let Err(e) = stat_script::dap::server::run_stdio(search_path) {
+                eprintln!("The debug adapter failed: {e}");
+                exit(EXIT_RUNTIME_ERROR)
+            }
+        }
+    }
+}
+
+/// Reads the script at `file`, or from stdin if `file` is `-`.
+fn read_source(file: &str) -> String {
+    decode(file, read_bytes(file))
+}
+
+fn decode(file: &str, bytes: Vec<u8>) -> String {
+    match String::from_utf8(bytes) {
+        Ok(s) => s,
+        Err(_) => {
+            eprintln!("Failed to read file: \"{}\": stream did not contain valid UTF-8", file);
+            exit(EXIT_USAGE)
         }
+    }
+}
+
+fn read_bytes(file: &str) -> Vec<u8> {
+    let result = if file == "-" {
+        let mut bytes = Vec::new();
+        std::io::stdin().read_to_end(&mut bytes).map(|_| bytes)
+    } else {
+        fs::read(file)
     };
 
-    println!("{:#?}", ast);
+    match result {
+        Ok(bytes) => bytes,
+        Err(e) => {
+            eprintln!("Failed to read file: \"{}\": {e}", file);
+            exit(EXIT_USAGE)
+        }
+    }
+}
+
+/// Loads the program to run from `file`: a compiled image, the fresh image next to a script, or
+/// else the parsed and checked script.
+fn load_program(file: &str, engine: Evaluator, loader: &mut Loader) -> Program {
+    let bytes = read_bytes(file);
+
+    if image::is_image(&bytes) {
+        if engine == Evaluator::TreeWalker {
+            eprintln!("A compiled image can only run on the bytecode engine.");
+            exit(EXIT_USAGE)
+        }
+
+        let image = match Image::from_bytes(&bytes) {
+            Ok(image) => image,
+            Err(e) => {
+                eprintln!("Failed to load the image \"{file}\": {}", e.message);
+                exit(EXIT_USAGE)
+            }
+        };
+
+        // the source may have been moved or deleted, which leaves nothing to compare against
+        if let Some(path) = image.source_path.as_deref() {
+            if fs::read_to_string(path).is_ok_and(|source| !image.is_fresh(&source)) {
+                eprintln!("The image \"{file}\" is stale: \"{path}\" has changed since it was compiled. Compile it again.");
+                exit(EXIT_USAGE)
+            }
+        }
+
+        return Program::from_compiled(image.program);
+    }
+
+    let source = decode(file, bytes);
 
-    let mut program = runtime::program::Program::new(ast);
-;
-    
-    match program.execute() {
-        None => {}
-        Some(err) => {
+    // a stale or incompatible cached image is ignored
+    if engine == Evaluator::Bytecode && file != "-" {
+        let cached = fs::read(image_path(file)).ok()
+            .and_then(|bytes| Image::from_bytes(&bytes).ok())
+            .filter(|image| image.is_fresh(&source));
+
+        if let Some(image) = cached {
+            return Program::from_compiled(image.program);
+        }
+    }
+
+    let ast = link(&parse(source), file, loader);
+    typecheck(&ast);
+
+    Program::new(ast).with_evaluator(engine)
+}
+
+/// Exits with a usage error if the command line arguments do not match the parameters of `main`.
+fn check_main_arguments(program: &Program, command: &str, file: &str) {
+    if let Err(err) = program.main_arguments() {
+        eprintln!("{}", err.message);
+        if let Some(main_method) = program.find_main_method() {
+            eprintln!("Usage: stat_script {command} {file} -- {}", main_arguments::usage(&main_method));
+        }
+        exit(EXIT_USAGE)
+    }
+}
+
+/// Exits with the status of a program that ran, reporting where it failed.
+fn exit_with(result: Result<i32, RuntimeError>) -> ! {
+    match result {
+        Ok(code) => exit(code),
+        Err(err) if err.kind == RuntimeErrorKind::Interrupted => {
+            eprintln!("{}", err.message);
+            print_traceback(&err.stack);
+            exit(EXIT_INTERRUPTED)
+        }
+        Err(err) => {
             eprintln!("A runtime error occurred: {}", err.message);
-            exit(1)
+            print_traceback(&err.stack);
+            exit(EXIT_RUNTIME_ERROR)
         }
-    };
+    }
+}
 
+fn print_traceback(stack: &[StackFrame]) {
+    if stack.is_empty() {
+        return;
+    }
 
+    eprintln!("Traceback, innermost call first:");
+    for frame in stack {
+        eprintln!("  {frame}");
+    }
 }
 
+/// Cancels the script on the first Ctrl-C, so it unwinds and reports where it was, and exits
+/// right away on the second, e.g. while a builtin blocks.
+fn interrupt_on_ctrl_c(cancellation: &CancellationToken) {
+    let cancellation = cancellation.clone();
 
+    let handler = ctrlc::set_handler(move || {
+        if cancellation.is_cancelled() {
+            exit(EXIT_INTERRUPTED)
+        }
+        cancellation.cancel();
+    });
+
+    if let Err(e) = handler {
+        eprintln!("Failed to handle Ctrl-C: {e}");
+    }
+}
+
+/// Pauses the debugged script on Ctrl-C, at its next statement.
+fn pause_on_ctrl_c(control: &DebugControl) {
+    let control = control.clone();
+
+    if let Err(e) = ctrlc::set_handler(move || control.pause()) {
+        eprintln!("Failed to handle Ctrl-C: {e}");
+    }
+}
+
+/// Where the image of the script at `file` is cached.
+fn image_path(file: &str) -> PathBuf {
+    Path::new(file).with_extension(image::EXTENSION)
+}
+
+fn parse(source: String) -> Node {
+    parsed(StatParser::new(Tokenizer::new(source)).parse())
+}
+
+/// The result of parsing, or exits with the parse error.
+fn parsed<T>(result: Result<T, ParserError>) -> T {
+    match result {
+        Ok(parsed) => parsed,
+        Err(e) => {
+            eprintln!("Failed to parse at position {}: {}", e.position, e.message);
+            exit(EXIT_PARSE_ERROR)
+        }
+    }
+}
+
+/// Links the modules the script at `file` imports into its program.
+fn link(ast: &Node, file: &str, loader: &mut Loader) -> Node {
+    let path = (file != "-").then(|| Path::new(file));
+
+    match loader.link(ast, path) {
+        Ok(linked) => linked,
+        Err(e) => {
+            eprintln!("Failed to import a module: {}", e.message);
+            for location in e.import_chain {
+                eprintln!("  {location}");
+            }
+            exit(EXIT_PARSE_ERROR)
+        }
+    }
+}
+
+/// Reports the problems found by the static checks and exits if there are any.
+fn typecheck(ast: &Node) {
+    let errors = check(ast);
+
+    for error in errors.iter() {
+        eprintln!("{error}");
+    }
+
+    if !errors.is_empty() {
+        exit(EXIT_TYPE_ERROR)
+    }
+}
@@ -0,0 +1,426 @@
+//! Resolves the imports of a script and links the modules it imports into one program.
+//!
+//! A module is resolved relative to the directory of the file importing it, then in each
+//! directory of the search path. Every module is loaded once per [`Loader`], however often it is
+//! imported. In the linked program the functions of imported modules are prefixed with the name
+//! of their module, e.g. `helpers.mean_ci`, which no script can write, so private functions of
+//! different modules never clash. Calls are renamed to match, and the functions keep the path of
+//! their module for debuggers.
+
+use std::collections::{HashMap, HashSet};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+
+use crate::error::module::{ModuleError, ModuleErrorKind};
+use crate::lexer::tokenizer::Tokenizer;
+use crate::parse::parser::{FunctionCall, FunctionDefinition, ModulePath, Node, NodeType, StatParser, TryCatch, VariableDeclaration};
+use crate::runtime::image::source_hash;
+
+/// The environment variable holding the search path, separated like `PATH`.
+pub const SEARCH_PATH_VARIABLE: &str = "STAT_SCRIPT_PATH";
+
+const EXTENSION: &str = "stsc";
+
+pub struct Module {
+    pub path: PathBuf,
+    /// The hash of the source, to tell whether a compiled image is still fresh.
+    pub source_hash: u64,
+    /// The names of all functions defined in the module.
+    defined: HashSet<String>,
+    /// The public functions by name, with their names in a linked program.
+    exports: HashMap<String, String>,
+    /// The function definitions, renamed for a linked program.
+    functions: Vec<Node>,
+    imports: Vec<Rc<Module>>,
+}
+
+/// The names a module can call: its own functions and the ones it imports, with their names in
+/// a linked program.
+#[derive(Default)]
+struct Scope {
+    names: HashMap<String, String>,
+    imports: Vec<Rc<Module>>,
+}
+
+pub struct Loader {
+    search_path: Vec<PathBuf>,
+    /// The loaded modules by canonical path.
+    modules: HashMap<PathBuf, Rc<Module>>,
+    /// The modules being loaded, outermost first, to detect import cycles.
+    loading: Vec<PathBuf>,
+    prefixes: HashSet<String>,
+}
+
+impl Loader {
+    pub fn new(search_path: Vec<PathBuf>) -> Self {
+        Self {
+            search_path,
+            modules: HashMap::new(),
+            loading: Vec::new(),
+            prefixes: HashSet::new(),
+        }
+    }
+
+    /// The search path given by the `STAT_SCRIPT_PATH` environment variable.
+    pub fn search_path_from_env() -> Vec<PathBuf> {
+        env::var_os(SEARCH_PATH_VARIABLE)
+            .map(|paths| env::split_paths(&paths).collect())
+            .unwrap_or_default()
+    }
+
+    /// Links the program of the script at `path` with the modules it imports. The imports of a
+    /// script without a path, e.g. read from stdin, are resolved relative to the working
+    /// directory.
+    pub fn link(&mut self, program: &Node, path: Option<&Path>) -> Result<Node, ModuleError> {
+        let NodeType::Program(nodes) = program.node_type.as_ref() else {
+            return Ok(program.clone());
+        };
+
+        let root = path.and_then(|path| fs::canonicalize(path).ok());
+        let directory = root.as_deref().and_then(Path::parent).map(Path::to_path_buf).unwrap_or_default();
+        let importer = root.as_deref().map_or("the script".to_string(), display);
+
+        self.loading.extend(root.clone());
+        let scope = self.scope(nodes, &directory, &importer, |name| name.to_string());
+        self.loading.clear();
+        let scope = scope?;
+
+        let mut linked: Vec<Node> = nodes.iter()
+            .filter(|node| !matches!(node.node_type.as_ref(), NodeType::Import(_)))
+            .map(|node| rename(node, &scope.names))
+            .collect();
+
+        let mut seen = HashSet::new();
+        for module in scope.imports.iter() {
+            collect(module, &mut seen, &mut linked);
+        }
+
+        Ok(Node::new(NodeType::Program(linked), program.span))
+    }
+
+    /// The modules loaded so far.
+    pub fn modules(&self) -> impl Iterator<Item = &Module> {
+        self.modules.values().map(Rc::as_ref)
+    }
+
+    fn load(&mut self, path: PathBuf) -> Result<Rc<Module>, ModuleError> {
+        if let Some(module) = self.modules.get(&path) {
+            return Ok(Rc::clone(module));
+        }
+
+        if let Some(start) = self.loading.iter().position(|loading| loading == &path) {
+            let cycle: Vec<String> = self.loading[start..].iter().chain([&path]).map(|p| display(p)).collect();
+            return Err(ModuleError::new(ModuleErrorKind::ImportCycle, format!("Import cycle: {}", cycle.join(" -> "))));
+        }
+
+        let source = fs::read_to_string(&path)
+            .map_err(|e| ModuleError::new(ModuleErrorKind::Io, format!("Failed to read module {}: {e}", display(&path))))?;
+        let ast = StatParser::new(Tokenizer::new(source.clone())).parse()
+            .map_err(|e| ModuleError::new(ModuleErrorKind::InvalidModule, format!("Failed to parse module {} at position {}: {}", display(&path), e.position, e.message)))?;
+        let NodeType::Program(nodes) = ast.node_type.as_ref() else {
+            unreachable!("the parser returns a program");
+        };
+
+        let prefix = self.prefix(&path);
+        let directory = path.parent().map(Path::to_path_buf).unwrap_or_default();
+
+        self.loading.push(path.clone());
+        let scope = self.scope(nodes, &directory, &display(&path), |name| format!("{prefix}.{name}"));
+        self.loading.pop();
+        let scope = scope?;
+
+        let definitions = nodes.iter().filter_map(|node| match node.node_type.as_ref() {
+            NodeType::FunctionDefinition(definition) => Some(definition),
+            _ => None
+        });
+
+        let mut module = Module {
+            path: path.clone(),
+            source_hash: source_hash(&source),
+            defined: HashSet::new(),
+            exports: HashMap::new(),
+            functions: Vec::new(),
+            imports: scope.imports,
+        };
+
+        for definition in definitions {
+            module.defined.insert(definition.name.clone());
+
+            if definition.public {
+                module.exports.insert(definition.name.clone(), scope.names[&definition.name].clone());
+            }
+        }
+
+        module.functions = nodes.iter()
+            .filter_map(|node| match rename(node, &scope.names).node_type.as_ref() {
+                NodeType::FunctionDefinition(definition) => Some(Node::new(NodeType::FunctionDefinition(FunctionDefinition {
+                    module: Some(path.clone()),
+                    ..definition.clone()
+                }), node.span)),
+                _ => None
+            })
+            .collect();
+
+        let module = Rc::new(module);
+        self.modules.insert(path, Rc::clone(&module));
+
+        Ok(module)
+    }
+
+    /// Loads the imports of a module or script and names its functions with `linked_name`.
+    fn scope(&mut self, nodes: &[Node], directory: &Path, importer: &str, linked_name: impl Fn(&str) -> String) -> Result<Scope, ModuleError> {
+        let mut scope = Scope::default();
+        let mut origins: HashMap<String, String> = HashMap::new();
+
+        for node in nodes {
+            let NodeType::Import(import) = node.node_type.as_ref() else {
+                continue;
+            };
+
+            let location = format!("imported by {importer} (line {})", node.span.line);
+            let in_import = |mut error: ModuleError| {
+                error.import_chain.push(location.clone());
+                error.span = Some(Box::new(node.span));
+                error
+            };
+
+            let module = self.resolve(&import.module, directory).and_then(|path| self.load(path)).map_err(in_import)?;
+
+            let names: Vec<String> = match &import.names {
+                Some(names) => names.iter().map(|n| n.name.clone()).collect(),
+                None => {
+                    let mut names: Vec<String> = module.exports.keys().cloned().collect();
+                    names.sort();
+                    names
+                }
+            };
+
+            for name in names {
+                let Some(linked) = module.exports.get(&name) else {
+                    let (kind, problem) = if module.defined.contains(&name) {
+                        (ModuleErrorKind::PrivateFunction, "is not public")
+                    } else {
+                        (ModuleErrorKind::UnknownFunction, "does not exist")
+                    };
+                    let message = format!("Function '{name}' of module {} {problem}.", display(&module.path));
+
+                    return Err(in_import(ModuleError::new(kind, message)));
+                };
+
+                if let Some(other) = scope.names.get(&name).filter(|other| *other != linked) {
+                    let message = format!("Function '{name}' is imported from both {} and {}.", origins[other], display(&module.path));
+                    return Err(in_import(ModuleError::new(ModuleErrorKind::NameConflict, message)));
+                }
+
+                origins.insert(linked.clone(), display(&module.path));
+                scope.names.insert(name, linked.clone());
+            }
+
+            scope.imports.push(module);
+        }
+
+        for node in nodes {
+            let NodeType::FunctionDefinition(definition) = node.node_type.as_ref() else {
+                continue;
+            };
+
+            if let Some(origin) = scope.names.get(&definition.name).and_then(|linked| origins.get(linked)) {
+                let mut error = ModuleError::new(ModuleErrorKind::NameConflict, format!(
+                    "Function '{}' is defined in {importer} (line {}) and also imported from {origin}.",
+                    definition.name, definition.name_span.line
+                ));
+                error.span = Some(Box::new(definition.name_span));
+
+                return Err(error);
+            }
+
+            scope.names.insert(definition.name.clone(), linked_name(&definition.name));
+        }
+
+        Ok(scope)
+    }
+
+    fn resolve(&self, module: &ModulePath, directory: &Path) -> Result<PathBuf, ModuleError> {
+        let relative = match module {
+            ModulePath::File(path) => PathBuf::from(path),
+            ModulePath::Dotted(parts) => parts.iter().collect::<PathBuf>().with_extension(EXTENSION),
+        };
+
+        std::iter::once(directory)
+            .chain(self.search_path.iter().map(PathBuf::as_path))
+            .map(|directory| directory.join(&relative))
+            .find(|path| path.is_file())
+            .and_then(|path| fs::canonicalize(path).ok())
+            .ok_or_else(|| {
+                let name = match module {
+                    ModulePath::File(path) => format!("\"{path}\""),
+                    ModulePath::Dotted(parts) => parts.join("."),
+                };
+
+                ModuleError::new(ModuleErrorKind::NotFound, format!("Module {name} was not found next to the importing file or in the search path."))
+            })
+    }
+
+    /// A prefix for the functions of the module at `path` that no other module uses.
+    fn prefix(&mut self, path: &Path) -> String {
+        let stem = path.file_stem().map(|stem| stem.to_string_lossy().into_owned()).unwrap_or_default();
+
+        let prefix = (1..)
+            .map(|n| if n == 1 { stem.clone() } else { format!("{stem}{n}") })
+            .find(|prefix| !self.prefixes.contains(prefix))
+            .expect("there are infinitely many prefixes");
+
+        self.prefixes.insert(prefix.clone());
+        prefix
+    }
+}
+
+/// Adds the functions of `module` and the modules it imports to `nodes`, each module once.
+fn collect(module: &Rc<Module>, seen: &mut HashSet<PathBuf>, nodes: &mut Vec<Node>) {
+    if !seen.insert(module.path.clone()) {
+        return;
+    }
+
+    nodes.extend(module.functions.iter().cloned());
+
+    for import in module.imports.iter() {
+        collect(import, seen, nodes);
+    }
+}
+
+/// Renames the function definitions and calls in `node` to their names in a linked program.
+fn rename(node: &Node, names: &HashMap<String, String>) -> Node {
+    let node_type = match node.node_type.as_ref() {
+        NodeType::FunctionDefinition(definition) => NodeType::FunctionDefinition(FunctionDefinition {
+            name: names.get(&definition.name).cloned().unwrap_or_else(|| definition.name.clone()),
+            body: rename(&definition.body, names),
+            ..definition.clone()
+        }),
+        NodeType::FunctionCall(call) => NodeType::FunctionCall(FunctionCall {
+            name: match names.get(&call.name) {
+                Some(linked) if !call.builtin => linked.clone(),
+                _ => call.name.clone(),
+            },
+            parameters: call.parameters.iter().map(|(name, value)| (name.clone(), rename(value, names))).collect(),
+            ..call.clone()
+        }),
+        NodeType::Block(nodes) => NodeType::Block(nodes.iter().map(|n| rename(n, names)).collect()),
+        NodeType::ListLiteral(nodes) => NodeType::ListLiteral(nodes.iter().map(|n| rename(n, names)).collect()),
+        NodeType::VariableDeclaration(declaration) => NodeType::VariableDeclaration(VariableDeclaration {
+            value: rename(&declaration.value, names),
+            ..declaration.clone()
+        }),
+        NodeType::ReturnExpression(value) => NodeType::ReturnExpression(rename(value, names)),
+        NodeType::TryCatch(try_catch) => NodeType::TryCatch(TryCatch {
+            body: rename(&try_catch.body, names),
+            handler: rename(&try_catch.handler, names),
+            ..try_catch.clone()
+        }),
+        NodeType::FieldAccess(target, field) => NodeType::FieldAccess(rename(target, names), field.clone()),
+        NodeType::UnaryNode(unary_type, operand) => NodeType::UnaryNode(*unary_type, rename(operand, names)),
+        NodeType::BinaryNode(binary_type, left, right) => NodeType::BinaryNode(*binary_type, rename(left, names), rename(right, names)),
+        _ => return node.clone(),
+    };
+
+    Node::new(node_type, node.span)
+}
+
+/// A path as shown in errors: relative to the working directory where possible, and quoted.
+fn display(path: &Path) -> String {
+    let relative = env::current_dir().ok().and_then(|directory| path.strip_prefix(directory).ok().map(Path::to_path_buf));
+
+    format!("\"{}\"", relative.as_deref().unwrap_or(path).display())
+}
+
+#[cfg(test)]
+mod loader_tests {
+    use std::fs;
+    use std::path::PathBuf;
+
+    use crate::error::module::ModuleErrorKind;
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::module::loader::Loader;
+    use crate::parse::parser::{Node, NodeType, StatParser};
+    use crate::runtime::program::Program;
+    use crate::test_support::TempDir;
+
+    /// Writes the files into a fresh directory and returns it with the path of the first one.
+    fn write(test: &str, files: &[(&str, &str)]) -> (TempDir, PathBuf) {
+        let directory = TempDir::new(test);
+
+        for (name, source) in files {
+            directory.write(name, source);
+        }
+
+        let first = directory.path().join(files[0].0);
+        (directory, first)
+    }
+
+    fn parse(path: &PathBuf) -> Node {
+        StatParser::new(Tokenizer::new(fs::read_to_string(path).unwrap())).parse().unwrap()
+    }
+
+    fn function_names(program: &Node) -> Vec<String> {
+        let NodeType::Program(nodes) = program.node_type.as_ref() else { panic!() };
+
+        nodes.iter()
+            .filter_map(|node| match node.node_type.as_ref() {
+                NodeType::FunctionDefinition(definition) => Some(definition.name.clone()),
+                _ => None
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_links_modules_with_prefixes() {
+        let (_directory, main) = write("link", &[
+            ("main.stsc", "import \"lib/stats.stsc\"
+                import helpers.{scale}
+                func double(x @int) @int { return x * 100 }
+                func main() @i32 { return mean_ci(x = 1) + scale(x = 1) + double(x = 0) }"),
+            ("lib/stats.stsc", "import helpers.{scale}
+                func double(x @int) @int { return x * 2 }
+                pub func mean_ci(x @int) @int { return double(x = x) + scale(x = x) }"),
+            ("shared/helpers.stsc", "pub func scale(x @int) @int { return x * 10 }"),
+            ("lib/helpers.stsc", "pub func scale(x @int) @int { return x * 1000 }"),
+        ]);
+        let shared = main.parent().unwrap().join("shared");
+
+        let mut loader = Loader::new(vec![shared]);
+        let linked = loader.link(&parse(&main), Some(&main)).unwrap();
+
+        assert_eq!(function_names(&linked), ["double", "main", "stats.double", "stats.mean_ci", "helpers.scale", "helpers2.scale"]);
+        assert_eq!(loader.modules().count(), 3);
+        // lib/stats.stsc finds lib/helpers.stsc next to it, main.stsc the one in the search path
+        assert_eq!(Program::new(linked).execute().unwrap(), 1012);
+    }
+
+    #[test]
+    fn test_reports_import_errors() {
+        let (_directory, main) = write("errors", &[
+            ("main.stsc", "import a\nfunc main() {}"),
+            ("a.stsc", "\nimport b.{f}"),
+            ("b.stsc", "import a\nfunc f() {}"),
+        ]);
+
+        let directory = fs::canonicalize(main.parent().unwrap()).unwrap();
+        let [a, b] = ["a", "b"].map(|name| format!("\"{}\"", directory.join(format!("{name}.stsc")).display()));
+
+        let error = Loader::new(Vec::new()).link(&parse(&main), Some(&main)).unwrap_err();
+        assert_eq!(error.kind, ModuleErrorKind::ImportCycle);
+        assert!(error.message.ends_with(&format!("{a} -> {b} -> {a}")), "{}", error.message);
+        assert_eq!(error.import_chain.len(), 3);
+        assert_eq!(error.span.map(|span| span.line), Some(1));
+
+        fs::write(main.with_file_name("b.stsc"), "func f() {}").unwrap();
+        let error = Loader::new(Vec::new()).link(&parse(&main), Some(&main)).unwrap_err();
+        assert_eq!(error.kind, ModuleErrorKind::PrivateFunction);
+        assert_eq!(error.import_chain[0], format!("imported by {a} (line 2)"));
+
+        fs::write(&main, "import nowhere.{f}\nfunc main() {}").unwrap();
+        let error = Loader::new(Vec::new()).link(&parse(&main), Some(&main)).unwrap_err();
+        assert_eq!(error.kind, ModuleErrorKind::NotFound);
+    }
+}
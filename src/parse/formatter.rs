@@ -0,0 +1,606 @@
+use std::collections::VecDeque;
+
+use crate::error::parser::ParserError;
+use crate::lexer::tokenizer::Span;
+use crate::parse::lower;
+use crate::parse::parser::{BinaryType, FunctionCall, FunctionDefinition, Import, ModulePath, Node, NodeType, UnaryType};
+use crate::parse::syntax::{SyntaxElement, SyntaxKind, SyntaxNode, SyntaxToken};
+
+const INDENT: &str = "    ";
+
+/// Binding strength of an expression, mirroring the precedence levels of the parser. A child that
+/// binds weaker than its position requires is wrapped in parentheses.
+#[derive(Debug, Clone, Copy, PartialEq, PartialOrd)]
+enum Precedence {
+    Or,
+    And,
+    Comparison,
+    Additive,
+    Multiplicative,
+    Unary,
+    Power,
+    Postfix,
+}
+
+/// A comment with the token it belongs to: the token it trails on its line, or else the next one.
+#[derive(Debug, Clone)]
+struct Comment {
+    /// The text after the `#`.
+    text: String,
+    /// The 1-based char position of the `#`, as in spans.
+    start: i128,
+    line: i128,
+    /// The end of the token the comment trails, as in spans, or `None` for a comment on its own
+    /// line.
+    trails: Option<i128>,
+}
+
+/// Prints a program in the canonical StatScript style: four spaces of indentation, opening
+/// braces on the line of their function, one blank line between functions. Comments are kept
+/// on their own line before the next statement, or at the end of the line they trail, and a
+/// blank line between two statements is kept as one. Comments inside an argument or list
+/// literal put its items on lines of their own, and comments inside other expressions continue
+/// the expression on the next line.
+pub fn format_program(tree: &SyntaxNode) -> Result<String, ParserError> {
+    let program = lower::lower_program(tree)?;
+
+    let mut formatter = Formatter {
+        comments: comments(tree).into(),
+        ..Default::default()
+    };
+
+    match program.node_type.as_ref() {
+        NodeType::Program(nodes) => {
+            for (i, node) in nodes.iter().enumerate() {
+                formatter.write_statement(node);
+
+                // consecutive imports stay together
+                let is_import = |node: Option<&Node>| node.is_some_and(|n| matches!(n.node_type.as_ref(), NodeType::Import(_)));
+                formatter.force_blank_line = !(is_import(Some(node)) && is_import(nodes.get(i + 1)));
+            }
+        }
+        _ => formatter.write_statement(&program)
+    }
+
+    formatter.write_comments_before(i128::MAX);
+
+    Ok(formatter.output)
+}
+
+/// The comments of a syntax tree in source order.
+fn comments(tree: &SyntaxNode) -> Vec<Comment> {
+    let mut comments = Vec::new();
+    let mut line = 1;
+    // the last token before the current position on its line
+    let mut previous: Option<i128> = None;
+
+    for token in tokens(tree) {
+        match token.kind() {
+            SyntaxKind::Comment => comments.push(Comment {
+                text: token.text()[1..].to_string(),
+                start: token.offset() as i128 + 1,
+                line,
+                trails: previous,
+            }),
+            SyntaxKind::Whitespace if token.text().contains('\n') => previous = None,
+            SyntaxKind::Whitespace => {}
+            _ => previous = Some(token.end() as i128),
+        }
+
+        line += token.text().matches('\n').count() as i128;
+    }
+
+    comments
+}
+
+/// Every token below a node, trivia included, in source order.
+fn tokens(node: &SyntaxNode) -> Vec<SyntaxToken> {
+    node.children_with_tokens().into_iter()
+        .flat_map(|element| match element {
+            SyntaxElement::Node(node) => tokens(&node),
+            SyntaxElement::Token(token) => vec![token],
+        })
+        .collect()
+}
+
+#[derive(Default)]
+struct Formatter {
+    output: String,
+    indent: usize,
+    comments: VecDeque<Comment>,
+    /// The source line the last written statement or comment ended on.
+    last_line: i128,
+    /// Set right after an opening brace, where blank lines are dropped.
+    at_block_start: bool,
+    /// Set between top-level functions, which are always separated by a blank line.
+    force_blank_line: bool,
+}
+
+impl Formatter {
+    fn write_indent(&mut self) {
+        for _ in 0..self.indent {
+            self.output.push_str(INDENT);
+        }
+    }
+
+    /// Writes a blank line if the source had one before `line`.
+    fn write_gap(&mut self, line: i128) {
+        let had_gap = self.last_line > 0 && line > self.last_line + 1;
+
+        if !self.at_block_start && (self.force_blank_line || had_gap) {
+            self.output.push('\n');
+        }
+
+        self.at_block_start = false;
+        self.force_blank_line = false;
+    }
+
+    /// Writes the comments starting before `position` on lines of their own.
+    fn write_comments_before(&mut self, position: i128) {
+        while self.has_comments_before(position) {
+            let comment = self.comments.pop_front().unwrap();
+
+            self.write_gap(comment.line);
+            self.write_indent();
+            self.output.push_str(&format!("#{}\n", comment.text));
+            self.last_line = comment.line;
+        }
+    }
+
+    fn has_comments_before(&self, position: i128) -> bool {
+        self.comments.front().is_some_and(|c| c.start < position)
+    }
+
+    /// Writes the comment trailing the token that ends at `end`, if there is one.
+    fn write_trailing_comment(&mut self, end: i128) {
+        if self.comments.front().is_some_and(|c| c.trails == Some(end)) {
+            let comment = self.comments.pop_front().unwrap();
+            self.output.push_str(&format!(" #{}", comment.text));
+        }
+    }
+
+    /// Writes the comments inside an expression that start before `position`, and continues the
+    /// expression on the next line, indented once more.
+    fn write_inner_comments(&mut self, position: i128) {
+        while self.has_comments_before(position) {
+            let comment = self.comments.pop_front().unwrap();
+            let at_line_start = self.output.trim_end_matches(' ').ends_with('\n');
+
+            if comment.trails.is_some() && !at_line_start {
+                if !self.output.ends_with(' ') {
+                    self.output.push(' ');
+                }
+            } else if !at_line_start {
+                self.output.truncate(self.output.trim_end_matches(' ').len());
+                self.output.push('\n');
+                self.write_continuation_indent();
+            }
+
+            self.output.push_str(&format!("#{}\n", comment.text));
+            self.write_continuation_indent();
+        }
+    }
+
+    fn write_continuation_indent(&mut self) {
+        self.indent += 1;
+        self.write_indent();
+        self.indent -= 1;
+    }
+
+    /// Writes the items of an argument list or list literal up to its closing bracket at `end`.
+    /// Comments between the items put every item on a line of its own, so that each comment
+    /// stays with the item it belongs to.
+    fn write_items<T>(&mut self, items: &[T], end: i128, start: impl Fn(&T) -> i128, write: impl Fn(&mut Self, &T)) {
+        if !self.has_comments_before(end) {
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    self.output.push_str(", ");
+                }
+                write(self, item);
+            }
+
+            return;
+        }
+
+        // a comment right after the opening bracket stays there
+        self.write_trailing_comment_before(items.first().map_or(end, &start));
+
+        self.indent += 1;
+        for (i, item) in items.iter().enumerate() {
+            self.output.push('\n');
+            self.write_own_line_comments(start(item));
+            self.write_indent();
+            write(self, item);
+
+            if i + 1 < items.len() {
+                self.output.push(',');
+            }
+            self.write_trailing_comment_before(items.get(i + 1).map_or(end, &start));
+        }
+
+        self.output.push('\n');
+        self.write_own_line_comments(end);
+        self.indent -= 1;
+        self.write_indent();
+    }
+
+    /// Writes the comment trailing a token before `position` at the end of the current line.
+    fn write_trailing_comment_before(&mut self, position: i128) {
+        if self.comments.front().is_some_and(|c| c.trails.is_some() && c.start < position) {
+            let comment = self.comments.pop_front().unwrap();
+            self.output.push_str(&format!(" #{}", comment.text));
+        }
+    }
+
+    /// Writes the comments starting before `position` on lines of their own, inside a statement,
+    /// where blank lines are dropped.
+    fn write_own_line_comments(&mut self, position: i128) {
+        while self.has_comments_before(position) {
+            let comment = self.comments.pop_front().unwrap();
+
+            self.write_indent();
+            self.output.push_str(&format!("#{}\n", comment.text));
+        }
+    }
+
+    fn write_statement(&mut self, node: &Node) {
+        self.write_comments_before(node.span.start);
+        self.write_gap(node.span.line);
+        self.write_indent();
+
+        match node.node_type.as_ref() {
+            NodeType::FunctionDefinition(definition) => self.write_function_definition(definition),
+            NodeType::Import(import) => self.write_import(import),
+            NodeType::VariableDeclaration(declaration) => {
+                let variable_type = declaration.variable_type.as_deref().unwrap_or("inherit");
+
+                self.output.push_str(&format!("set<{variable_type}> {} <- ", declaration.name));
+                self.write_expression(&declaration.value);
+            }
+            NodeType::ReturnExpression(value) => {
+                self.output.push_str("return ");
+                self.write_expression(value);
+            }
+            NodeType::TryCatch(try_catch) => {
+                self.output.push_str("try ");
+                self.write_expression(&try_catch.body);
+                self.output.push_str(&format!(" catch {} ", try_catch.variable));
+                self.write_expression(&try_catch.handler);
+            }
+            _ => self.write_expression(node)
+        }
+
+        self.write_trailing_comment(node.span.end);
+        self.output.push('\n');
+        self.last_line = node.span.end_line;
+    }
+
+    fn write_function_definition(&mut self, definition: &FunctionDefinition) {
+        if definition.public {
+            self.output.push_str("pub ");
+        }
+
+        self.output.push_str(&format!("func {}(", definition.name));
+        self.write_items(&definition.signature, definition.body.span.start, |p| p.span.start, |formatter, p| {
+            formatter.output.push_str(&format!("{} @{}", p.param_name, p.param_type));
+        });
+        self.output.push(')');
+
+        if definition.return_type != "Nothing" {
+            self.output.push_str(&format!(" @{}", definition.return_type));
+        }
+
+        self.output.push(' ');
+        self.write_expression(&definition.body);
+    }
+
+    fn write_import(&mut self, import: &Import) {
+        match &import.module {
+            ModulePath::File(path) => self.output.push_str(&format!("import {}", quote(path))),
+            ModulePath::Dotted(parts) => self.output.push_str(&format!("import {}", parts.join("."))),
+        }
+
+        if let Some(names) = &import.names {
+            let names: Vec<&str> = names.iter().map(|n| n.name.as_str()).collect();
+            self.output.push_str(&format!(".{{{}}}", names.join(", ")));
+        }
+    }
+
+    fn write_block(&mut self, nodes: &[Node], span: Span) {
+        let has_comments = self.has_comments_before(span.end);
+
+        if nodes.is_empty() && !has_comments {
+            self.output.push_str("{}");
+            return;
+        }
+
+        self.output.push_str("{\n");
+        self.at_block_start = true;
+
+        self.indent += 1;
+        for node in nodes {
+            self.write_statement(node);
+        }
+        self.write_comments_before(span.end);
+        self.indent -= 1;
+
+        self.at_block_start = false;
+        self.write_indent();
+        self.output.push('}');
+    }
+
+    fn write_expression(&mut self, node: &Node) {
+        // blocks place their comments on lines of their own
+        if !matches!(node.node_type.as_ref(), NodeType::Block(_)) {
+            self.write_inner_comments(node.span.start);
+        }
+
+        match node.node_type.as_ref() {
+            NodeType::StringLiteral(s) => self.output.push_str(&quote(s)),
+            NodeType::Int8Literal(i) => self.output.push_str(&i.to_string()),
+            NodeType::Int16Literal(i) => self.output.push_str(&i.to_string()),
+            NodeType::Int32Literal(i) => self.output.push_str(&i.to_string()),
+            NodeType::Int64Literal(i) => self.output.push_str(&i.to_string()),
+            NodeType::Int128Literal(i) => self.output.push_str(&i.to_string()),
+            NodeType::DoubleLiteral(d) => {
+                // the tokenizer has no exponent notation, and needs the dot to read a double
+                let mut text = d.to_string();
+                if !text.contains('.') {
+                    text.push_str(".0");
+                }
+
+                self.output.push_str(&text);
+            }
+            NodeType::BooleanLiteral(b) => self.output.push_str(&b.to_string()),
+            NodeType::NaLiteral => self.output.push_str("NA"),
+            NodeType::ListLiteral(elements) => {
+                self.output.push('[');
+                self.write_items(elements, node.span.end, |element| element.span.start, Self::write_expression);
+                self.output.push(']');
+            }
+            NodeType::VariableReference(name) => self.output.push_str(name),
+            NodeType::FieldAccess(target, field) => {
+                self.write_operand(target, Precedence::Postfix);
+                self.output.push('.');
+                self.output.push_str(field);
+            }
+            NodeType::UnaryNode(unary_type, operand) => {
+                self.output.push(match unary_type {
+                    UnaryType::Not => '!',
+                    UnaryType::Negate => '-',
+                });
+                self.write_operand(operand, Precedence::Unary);
+            }
+            NodeType::BinaryNode(binary_type, left, right) => {
+                let precedence = binary_precedence(*binary_type);
+
+                // power is right associative, everything else left associative
+                let (left_minimum, right_minimum) = if *binary_type == BinaryType::Power {
+                    (Precedence::Postfix, Precedence::Unary)
+                } else {
+                    (precedence, next_precedence(precedence))
+                };
+
+                self.write_operand(left, left_minimum);
+                self.output.push_str(&format!(" {} ", binary_type.symbol()));
+                self.write_operand(right, right_minimum);
+            }
+            NodeType::FunctionCall(call) => self.write_function_call(call, node.span),
+            NodeType::Block(nodes) => self.write_block(nodes, node.span),
+            node_type => unreachable!("statement {node_type:?} in expression position")
+        }
+    }
+
+    fn write_operand(&mut self, node: &Node, minimum: Precedence) {
+        let needs_parentheses = expression_precedence(node).is_some_and(|p| p < minimum);
+
+        if needs_parentheses {
+            self.output.push('(');
+        }
+
+        self.write_expression(node);
+
+        if needs_parentheses {
+            self.output.push(')');
+        }
+    }
+
+    fn write_function_call(&mut self, call: &FunctionCall, span: Span) {
+        if call.builtin {
+            self.output.push_str("builtin ");
+        }
+
+        self.output.push_str(&call.name);
+        self.output.push('(');
+
+        self.write_items(&call.parameters, span.end, |(_, value)| value.span.start, |formatter, (name, value)| {
+            formatter.output.push_str(name);
+            formatter.output.push_str(" = ");
+            formatter.write_expression(value);
+        });
+
+        self.output.push(')');
+    }
+}
+
+/// The precedence of an operator expression, `None` for expressions that never need parentheses.
+fn expression_precedence(node: &Node) -> Option<Precedence> {
+    match node.node_type.as_ref() {
+        NodeType::BinaryNode(binary_type, _, _) => Some(binary_precedence(*binary_type)),
+        NodeType::UnaryNode(_, _) => Some(Precedence::Unary),
+        _ => None
+    }
+}
+
+fn binary_precedence(binary_type: BinaryType) -> Precedence {
+    match binary_type {
+        BinaryType::Or => Precedence::Or,
+        BinaryType::And => Precedence::And,
+        BinaryType::Equal | BinaryType::NotEqual | BinaryType::Less | BinaryType::LessEqual
+        | BinaryType::Greater | BinaryType::GreaterEqual => Precedence::Comparison,
+        BinaryType::Add | BinaryType::Subtract => Precedence::Additive,
+        BinaryType::Multiply | BinaryType::Divide | BinaryType::Modulo => Precedence::Multiplicative,
+        BinaryType::Power => Precedence::Power,
+    }
+}
+
+fn next_precedence(precedence: Precedence) -> Precedence {
+    match precedence {
+        Precedence::Or => Precedence::And,
+        Precedence::And => Precedence::Comparison,
+        Precedence::Comparison => Precedence::Additive,
+        Precedence::Additive => Precedence::Multiplicative,
+        Precedence::Multiplicative => Precedence::Unary,
+        Precedence::Unary => Precedence::Power,
+        Precedence::Power | Precedence::Postfix => Precedence::Postfix,
+    }
+}
+
+/// A string literal with the value `s`.
+fn quote(s: &str) -> String {
+    format!("\"{}\"", s.replace('\\', "\\\\").replace('"', "\\\""))
+}
+
+#[cfg(test)]
+mod formatter_tests {
+    use std::fs;
+
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::parse::formatter::format_program;
+    use crate::parse::lower::lower_program;
+    use crate::parse::parser::StatParser;
+
+    fn format(source: &str) -> String {
+        let tree = StatParser::new(Tokenizer::new(source.into())).parse_syntax().unwrap();
+
+        format_program(&tree).unwrap()
+    }
+
+    #[test]
+    fn test_canonical_layout() {
+        let source = "func   main( a@int,b @f64 )@i32{set<int>x<-(1+2)*3 builtin println(template=\"a \\\"b\\\"\")
+            return  -(a - (b - 1)) ^ 2 }";
+
+        assert_eq!(format(source), "func main(a @int, b @f64) @i32 {
+    set<int> x <- (1 + 2) * 3
+    builtin println(template = \"a \\\"b\\\"\")
+    return -(a - (b - 1)) ^ 2
+}
+");
+    }
+
+    #[test]
+    fn test_comments_and_blank_lines() {
+        let source = "# leading
+func helper() {
+    set<int> a <- 1   # trailing
+
+
+    # own line
+    set<int> b <- 2
+    # last
+}
+func main() {}
+# end
+";
+
+        assert_eq!(format(source), "# leading
+func helper() {
+    set<int> a <- 1 # trailing
+
+    # own line
+    set<int> b <- 2
+    # last
+}
+
+func main() {}
+
+# end
+");
+    }
+
+    #[test]
+    fn test_comments_inside_expressions() {
+        let source = "func scale(x @f64, # the value
+    by @f64) @f64 { return x * by }
+func main() {
+    builtin println(template = scale(x = 2, # first
+        # the factor
+        by = 0.5),
+        sep = \"\") # done
+    set<list> values <- [1, # one
+        2]
+    set<int> total <- 1 + # more
+        2
+}
+";
+
+        let formatted = format(source);
+        assert_eq!(format(&formatted), formatted);
+        assert_eq!(formatted, "func scale(
+    x @f64, # the value
+    by @f64
+) @f64 {
+    return x * by
+}
+
+func main() {
+    builtin println(
+        template = scale(
+            x = 2, # first
+            # the factor
+            by = 0.5
+        ),
+        sep = \"\"
+    ) # done
+    set<list> values <- [
+        1, # one
+        2
+    ]
+    set<int> total <- 1 + # more
+        2
+}
+");
+    }
+
+    #[test]
+    fn test_imports_and_public_functions() {
+        let source = "import   \"lib/stats helpers.stsc\"
+import stats.helpers.{ mean_ci  scale }
+pub   func mean(values @list) @f64 { return builtin mean(values = values) }";
+
+        assert_eq!(format(source), "import \"lib/stats helpers.stsc\"
+import stats.helpers.{mean_ci, scale}
+
+pub func mean(values @list) @f64 {
+    return builtin mean(values = values)
+}
+");
+    }
+
+    #[test]
+    fn test_grammar_files_are_stable() {
+        for entry in fs::read_dir("grammar").unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+
+            // some files only exercise the tokenizer
+            let mut parser = StatParser::new(Tokenizer::new(source));
+            let Ok(tree) = parser.parse_syntax() else {
+                continue;
+            };
+
+            let formatted = format_program(&tree).unwrap();
+            let mut reparser = StatParser::new(Tokenizer::new(formatted.clone()));
+            let retree = reparser.parse_syntax().unwrap();
+
+            assert_eq!(
+                serde_json::to_string(&lower_program(&tree).unwrap()).unwrap(),
+                serde_json::to_string(&lower_program(&retree).unwrap()).unwrap(),
+                "formatting changed the meaning of {path:?}"
+            );
+            assert_eq!(parser.comments().len(), reparser.comments().len(), "formatting lost comments of {path:?}");
+            assert_eq!(format_program(&retree).unwrap(), formatted, "formatting {path:?} is not idempotent");
+        }
+    }
+}
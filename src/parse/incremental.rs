@@ -0,0 +1,319 @@
+//! Updating a syntax tree after an edit without parsing the whole text again, for editors that
+//! reparse on every keystroke.
+//!
+//! An edit inside a single identifier, number or string only re-lexes that token. Other edits
+//! reparse the innermost block or function definition around them, and only edits the tree
+//! structure can't contain fall back to parsing everything.
+
+use std::rc::Rc;
+
+use crate::error::parser::{ParserError, ParserErrorKind};
+use crate::lexer::tokenizer::Tokenizer;
+use crate::parse::lower;
+use crate::parse::parser::{Node, StatParser, MAX_NESTING};
+use crate::parse::syntax::{GreenElement, GreenNode, GreenToken, SyntaxKind, SyntaxNode, SyntaxToken};
+
+/// Identifiers that decide how their surroundings are parsed.
+const KEYWORDS: [&str; 11] = ["func", "pub", "import", "set", "return", "try", "catch", "builtin", "true", "false", "NA"];
+
+/// A replacement of the chars `start..end` of a text, as 0-based char offsets.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TextEdit {
+    pub start: usize,
+    pub end: usize,
+    pub text: String,
+}
+
+impl TextEdit {
+    pub fn new(start: usize, end: usize, text: &str) -> Self {
+        Self { start, end, text: text.to_string() }
+    }
+
+    pub fn apply(&self, text: &str) -> String {
+        let mut chars: Vec<char> = text.chars().collect();
+        chars.splice(self.start..self.end, self.text.chars());
+
+        chars.into_iter().collect()
+    }
+}
+
+/// The part of the tree that was rebuilt for an edit.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Reparsed {
+    Token,
+    /// A block or function definition.
+    Node(SyntaxKind),
+    Full,
+}
+
+/// The syntax tree of a text, which can be updated by edits.
+pub struct Parse {
+    tree: SyntaxNode,
+    reparsed: Reparsed,
+}
+
+impl Parse {
+    pub fn new(text: &str) -> Result<Self, ParserError> {
+        let tree = StatParser::new(Tokenizer::new(text.to_string())).parse_syntax()?;
+
+        Ok(Self { tree, reparsed: Reparsed::Full })
+    }
+
+    pub fn tree(&self) -> &SyntaxNode {
+        &self.tree
+    }
+
+    pub fn text(&self) -> String {
+        self.tree.text()
+    }
+
+    pub fn ast(&self) -> Result<Node, ParserError> {
+        lower::lower_program(&self.tree)
+    }
+
+    /// How much of the tree the edit that produced it rebuilt.
+    pub fn reparsed(&self) -> Reparsed {
+        self.reparsed
+    }
+
+    /// The parse of the text after `edit`, sharing the untouched parts of this tree. The result
+    /// is the same as parsing the edited text from scratch.
+    pub fn edit(&self, edit: &TextEdit) -> Result<Parse, ParserError> {
+        if edit.start > edit.end || edit.end > self.tree.end() {
+            return Err(ParserError::at(edit.start as i128 + 1, ParserErrorKind::EditOutOfRange { start: edit.start, end: edit.end }));
+        }
+
+        if let Some(tree) = self.relex_token(edit) {
+            return Ok(Parse { tree, reparsed: Reparsed::Token });
+        }
+
+        if let Some((tree, kind)) = self.reparse_node(edit) {
+            return Ok(Parse { tree, reparsed: Reparsed::Node(kind) });
+        }
+
+        Parse::new(&edit.apply(&self.text()))
+    }
+
+    /// Replaces the token the edit falls into, if it stays one token of the same kind. Neighbours
+    /// can't merge with it then: they ended before it for the same first char, and it ends at the
+    /// same kind of char as before.
+    fn relex_token(&self, edit: &TextEdit) -> Option<SyntaxNode> {
+        // typing at the end of a token is an edit right after it
+        let candidates = [Some(edit.start), edit.start.checked_sub(1)];
+
+        let token = candidates.into_iter().flatten()
+            .filter_map(|offset| self.tree.token_at_offset(offset))
+            .find(|t| t.offset() <= edit.start && edit.end <= t.end() && is_relexable(t))?;
+
+        let mut text: Vec<char> = token.text().chars().collect();
+        text.splice(edit.start - token.offset()..edit.end - token.offset(), edit.text.chars());
+        let text: String = text.into_iter().collect();
+
+        if KEYWORDS.contains(&text.as_str()) {
+            return None;
+        }
+
+        let mut tokenizer = Tokenizer::new(text.clone());
+        let relexed = tokenizer.next_token().ok()??;
+        let single = relexed.start_pos == 1 && relexed.end_pos == text.chars().count() as i128;
+
+        if !single || tokenizer.next_token().ok()?.is_some() || !tokenizer.comments.is_empty() {
+            return None;
+        }
+
+        if relexed.token_type.as_ref().map(SyntaxKind::from_token_type) != Some(token.kind()) {
+            return None;
+        }
+
+        let new = GreenElement::Token(Rc::new(GreenToken::new(token.kind(), text)));
+        let green = self.tree.green().replace(token.offset(), &GreenElement::Token(token.green().clone()), new);
+
+        Some(SyntaxNode::new_root(Rc::new(green)))
+    }
+
+    /// Reparses the innermost block or function definition that contains the edit, keeping its
+    /// first and last char. If its new text is no longer one block or function, the next larger
+    /// one is tried.
+    fn reparse_node(&self, edit: &TextEdit) -> Option<(SyntaxNode, SyntaxKind)> {
+        let mut node = self.tree.clone();
+
+        while let Some(child) = node.children().into_iter().find(|c| c.offset() < edit.start && edit.end < c.end()) {
+            node = child;
+        }
+
+        // every node on the way down strictly contains the edit
+        for candidate in node.ancestors() {
+            if !matches!(candidate.kind(), SyntaxKind::Block | SyntaxKind::FunctionDefinition) {
+                continue;
+            }
+
+            let relative = TextEdit::new(edit.start - candidate.offset(), edit.end - candidate.offset(), &edit.text);
+            let mut parser = StatParser::new(Tokenizer::new(relative.apply(&candidate.text())));
+
+            let reparsed = match candidate.kind() {
+                SyntaxKind::Block => parser.parse_block_syntax(),
+                _ => parser.parse_function_syntax(),
+            };
+
+            if let Ok(reparsed) = reparsed {
+                // the parser nests at most two levels per node, and only a full parse counts them
+                // exactly once the limit is in reach
+                let ancestors = candidate.ancestors().count() - 2;
+                if 2 * (ancestors + depth(reparsed.green())) > MAX_NESTING {
+                    return None;
+                }
+
+                let old = GreenElement::Node(candidate.green().clone());
+                let green = self.tree.green().replace(candidate.offset(), &old, GreenElement::Node(reparsed.green().clone()));
+
+                return Some((SyntaxNode::new_root(Rc::new(green)), candidate.kind()));
+            }
+        }
+
+        None
+    }
+}
+
+/// The nodes on the longest path down from `node`, itself included.
+fn depth(node: &GreenNode) -> usize {
+    let children = node.children().iter().filter_map(|child| match child {
+        GreenElement::Node(node) => Some(depth(node)),
+        GreenElement::Token(_) => None,
+    });
+
+    1 + children.max().unwrap_or(0)
+}
+
+fn is_relexable(token: &SyntaxToken) -> bool {
+    match token.kind() {
+        SyntaxKind::Identifier => !KEYWORDS.contains(&token.text()),
+        SyntaxKind::Number | SyntaxKind::String | SyntaxKind::Character => true,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod incremental_tests {
+    use std::fs;
+
+    use crate::error::parser::ParserErrorKind;
+    use crate::parse::incremental::{Parse, Reparsed, TextEdit};
+    use crate::parse::parser::MAX_NESTING;
+    use crate::parse::syntax::SyntaxKind;
+    use crate::runtime::random::Random;
+    use crate::runtime::sandbox::STACK_SIZE;
+
+    const SOURCE: &str = "func helper(a @int) @int {
+    set<int> total <- a * 2
+    {
+        set<string> label <- \"inner\"
+    }
+    return total
+}
+
+func main() @i32 {
+    builtin println(template = helper(a = 21))
+    return 0
+}
+";
+
+    fn edit(parse: &Parse, find: &str, skip: usize, length: usize, text: &str) -> Parse {
+        let start = SOURCE.find(find).unwrap() + skip;
+        let edited = parse.edit(&TextEdit::new(start, start + length, text)).unwrap();
+        let expected = Parse::new(&TextEdit::new(start, start + length, text).apply(SOURCE)).unwrap();
+
+        assert_eq!(edited.tree().green(), expected.tree().green());
+        edited
+    }
+
+    #[test]
+    fn test_reparses_the_smallest_scope() {
+        let parse = Parse::new(SOURCE).unwrap();
+
+        assert_eq!(edit(&parse, "total <-", 5, 0, "s").reparsed(), Reparsed::Token);
+        assert_eq!(edit(&parse, "\"inner\"", 3, 2, "").reparsed(), Reparsed::Token);
+        assert_eq!(edit(&parse, "* 2", 2, 1, "20").reparsed(), Reparsed::Token);
+        assert_eq!(edit(&parse, "\"inner\"", 7, 0, " + \"!\"").reparsed(), Reparsed::Node(SyntaxKind::Block));
+        assert_eq!(edit(&parse, "    return total", 0, 0, "    total\n").reparsed(), Reparsed::Node(SyntaxKind::Block));
+        assert_eq!(edit(&parse, "(a @int)", 7, 0, ", b @int").reparsed(), Reparsed::Node(SyntaxKind::FunctionDefinition));
+        assert_eq!(edit(&parse, "total <-", 0, 5, "set").reparsed(), Reparsed::Node(SyntaxKind::Block));
+        assert_eq!(edit(&parse, "\nfunc main", 0, 0, "\n# main").reparsed(), Reparsed::Full);
+    }
+
+    #[test]
+    fn test_keeps_the_nesting_limit() {
+        // in debug builds, the levels the parser allows take more than the stack of a test thread
+        let thread = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+            let source = format!("func main() {}{}", "{ ".repeat(MAX_NESTING), " }".repeat(MAX_NESTING));
+            let parse = Parse::new(&source).unwrap();
+
+            let innermost = source.find("{  }").unwrap();
+            let edit = TextEdit::new(innermost + 2, innermost + 2, "{}");
+            assert!(Parse::new(&edit.apply(&source)).is_err());
+
+            parse.edit(&edit).err().map(|e| e.kind)
+        });
+
+        assert_eq!(thread.unwrap().join().unwrap(), Some(ParserErrorKind::TooDeeplyNested(MAX_NESTING)));
+    }
+
+    #[test]
+    fn test_matches_full_parse() {
+        const SNIPPETS: [&str; 20] = [
+            "", " ", "\n", "x", "1", "2.5", "a", "_", "\"", "#", "{", "}", "(", ")", ",", "+", "set<int> y <- 1\n",
+            "return ", "builtin println(template = \"t\")", "helper(a = 1)",
+        ];
+
+        let mut sources: Vec<String> = fs::read_dir("grammar").unwrap()
+            .map(|entry| fs::read_to_string(entry.unwrap().path()).unwrap())
+            .collect();
+        sources.push(SOURCE.to_string());
+
+        let mut random = Random::new(38);
+        let mut incremental = 0;
+
+        for source in sources {
+            let Ok(mut parse) = Parse::new(&source) else {
+                continue;
+            };
+            let mut text = source;
+
+            for _ in 0..300 {
+                let length = text.chars().count() as u64;
+                let start = random.next_below(length + 1) as usize;
+                let end = (start + random.next_below(4) as usize).min(length as usize);
+                let snippet = SNIPPETS[random.next_below(SNIPPETS.len() as u64) as usize];
+
+                let edit = TextEdit::new(start, end, snippet);
+                let edited_text = edit.apply(&text);
+
+                match (parse.edit(&edit), Parse::new(&edited_text)) {
+                    (Ok(edited), Ok(expected)) => {
+                        assert_eq!(edited.text(), edited_text);
+                        assert_eq!(edited.tree().green(), expected.tree().green(), "{edit:?} on {text:?}");
+                        assert_eq!(
+                            format!("{:?}", edited.ast().map_err(|e| e.message)),
+                            format!("{:?}", expected.ast().map_err(|e| e.message)),
+                        );
+
+                        if edited.reparsed() != Reparsed::Full {
+                            incremental += 1;
+                        }
+
+                        // keep editing valid programs
+                        parse = edited;
+                        text = edited_text;
+                    }
+                    (Err(edited), Err(expected)) => assert_eq!(edited.message, expected.message),
+                    (edited, expected) => panic!(
+                        "{edit:?} on {text:?}: incremental {:?}, full {:?}",
+                        edited.map(|p| p.text()).map_err(|e| e.message),
+                        expected.map(|p| p.text()).map_err(|e| e.message),
+                    ),
+                }
+            }
+        }
+
+        assert!(incremental > 100, "only {incremental} edits were reparsed incrementally");
+    }
+}
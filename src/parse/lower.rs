@@ -0,0 +1,257 @@
+//! Derives the [`Node`] tree the checker and the runtime work on from the lossless syntax tree.
+
+use crate::error::parser::{ParserError, ParserErrorKind};
+use crate::lexer::tokenizer::{Span, Tokenizer};
+use crate::parse::parser::{
+    BinaryType, FunctionCall, FunctionDefinition, FunctionParameter, Import, ImportedName, ModulePath, Node, NodeType,
+    TryCatch, UnaryType, VariableDeclaration,
+};
+use crate::parse::syntax::{SyntaxKind, SyntaxNode, SyntaxToken};
+
+type LowerReturn = Result<Node, ParserError>;
+
+pub fn lower_program(tree: &SyntaxNode) -> LowerReturn {
+    let lowering = Lowering::new(&tree.text());
+
+    let nodes = tree.children().iter()
+        .map(|node| lowering.statement(node))
+        .collect::<Result<Vec<Node>, ParserError>>()?;
+
+    Ok(Node::new(NodeType::Program(nodes), Span::default()))
+}
+
+struct Lowering {
+    /// The char offsets at which lines start, to give spans their line numbers.
+    line_starts: Vec<usize>,
+}
+
+impl Lowering {
+    fn new(text: &str) -> Self {
+        let mut line_starts = vec![0];
+        line_starts.extend(text.chars().enumerate().filter(|(_, c)| *c == '\n').map(|(i, _)| i + 1));
+
+        Self { line_starts }
+    }
+
+    fn line(&self, offset: usize) -> i128 {
+        self.line_starts.partition_point(|start| *start <= offset) as i128
+    }
+
+    fn span(&self, start: usize, end: usize) -> Span {
+        Span {
+            start: start as i128 + 1,
+            end: end as i128,
+            line: self.line(start),
+            end_line: self.line(end.saturating_sub(1).max(start)),
+        }
+    }
+
+    fn node_span(&self, node: &SyntaxNode) -> Span {
+        self.span(node.offset(), node.end())
+    }
+
+    fn token_span(&self, token: &SyntaxToken) -> Span {
+        self.span(token.offset(), token.end())
+    }
+
+    fn statement(&self, node: &SyntaxNode) -> LowerReturn {
+        let node_type = match node.kind() {
+            SyntaxKind::FunctionDefinition => NodeType::FunctionDefinition(self.function_definition(node)?),
+            SyntaxKind::Import => NodeType::Import(self.import(node)),
+            SyntaxKind::VariableDeclaration => {
+                let tokens = node.tokens();
+                let variable_type = tokens[2].text();
+
+                NodeType::VariableDeclaration(VariableDeclaration {
+                    name: tokens[4].text().to_string(),
+                    name_span: self.token_span(&tokens[4]),
+                    variable_type: (variable_type != "inherit").then(|| variable_type.to_string()),
+                    value: self.expression(&node.children()[0])?,
+                })
+            }
+            SyntaxKind::ReturnStatement => NodeType::ReturnExpression(self.expression(&node.children()[0])?),
+            SyntaxKind::TryStatement => {
+                let variable = &node.tokens()[2];
+                let children = node.children();
+
+                NodeType::TryCatch(TryCatch {
+                    body: self.expression(&children[0])?,
+                    variable: variable.text().to_string(),
+                    variable_span: self.token_span(variable),
+                    handler: self.expression(&children[1])?,
+                })
+            }
+            _ => return self.expression(node),
+        };
+
+        Ok(Node::new(node_type, self.node_span(node)))
+    }
+
+    fn import(&self, node: &SyntaxNode) -> Import {
+        let tokens = node.tokens();
+
+        let module = match tokens[1].kind() {
+            SyntaxKind::String => ModulePath::File(unescape(tokens[1].text())),
+            _ => ModulePath::Dotted(tokens[1..].iter()
+                .filter(|t| t.kind() == SyntaxKind::Identifier)
+                .map(|t| t.text().to_string())
+                .collect()),
+        };
+
+        let names = node.children().first().map(|list| list.tokens().iter()
+            .filter(|t| t.kind() == SyntaxKind::Identifier)
+            .map(|t| ImportedName { name: t.text().to_string(), span: self.token_span(t) })
+            .collect());
+
+        Import { module, names }
+    }
+
+    fn function_definition(&self, node: &SyntaxNode) -> Result<FunctionDefinition, ParserError> {
+        let tokens = node.tokens();
+        let public = tokens[0].text() == "pub";
+        let name = &tokens[public as usize + 1];
+        let mut signature = Vec::new();
+        let mut return_type = "Nothing".to_string();
+        let mut body = None;
+
+        for child in node.children() {
+            match child.kind() {
+                SyntaxKind::ParameterList => {
+                    for parameter in child.children() {
+                        let tokens = parameter.tokens();
+
+                        signature.push(FunctionParameter {
+                            param_type: tokens[2].text().to_string(),
+                            param_name: tokens[0].text().to_string(),
+                            span: self.token_span(&tokens[0]),
+                        });
+                    }
+                }
+                SyntaxKind::ReturnType => return_type = child.tokens()[1].text().to_string(),
+                _ => body = Some(self.expression(&child)?),
+            }
+        }
+
+        Ok(FunctionDefinition {
+            public,
+            name: name.text().to_string(),
+            name_span: self.token_span(name),
+            signature,
+            return_type,
+            body: body.expect("a function definition has a body"),
+            module: None,
+        })
+    }
+
+    fn expression(&self, node: &SyntaxNode) -> LowerReturn {
+        let children = node.children();
+        let tokens = node.tokens();
+
+        let node_type = match node.kind() {
+            SyntaxKind::Literal => self.literal(&tokens[0])?,
+            SyntaxKind::VariableReference => NodeType::VariableReference(tokens[0].text().to_string()),
+            SyntaxKind::ParenthesizedExpression => return self.expression(&children[0]),
+            SyntaxKind::ListLiteral => NodeType::ListLiteral(self.expressions(&children)?),
+            SyntaxKind::Block => {
+                let statements = children.iter()
+                    .map(|child| self.statement(child))
+                    .collect::<Result<Vec<Node>, ParserError>>()?;
+
+                NodeType::Block(statements)
+            }
+            SyntaxKind::FieldAccess => {
+                NodeType::FieldAccess(self.expression(&children[0])?, tokens[1].text().to_string())
+            }
+            SyntaxKind::UnaryExpression => {
+                let unary_type = if tokens[0].text() == "!" { UnaryType::Not } else { UnaryType::Negate };
+
+                NodeType::UnaryNode(unary_type, self.expression(&children[0])?)
+            }
+            SyntaxKind::BinaryExpression => {
+                // `<=` and friends are two tokens
+                let symbol: String = tokens.iter().map(SyntaxToken::text).collect();
+                let binary_type = BinaryType::from_symbol(&symbol).expect("the parser only accepts known operators");
+
+                NodeType::BinaryNode(binary_type, self.expression(&children[0])?, self.expression(&children[1])?)
+            }
+            SyntaxKind::FunctionCall => NodeType::FunctionCall(self.function_call(&tokens, &children[0])?),
+            kind => unreachable!("{kind:?} is not an expression"),
+        };
+
+        Ok(Node::new(node_type, self.node_span(node)))
+    }
+
+    fn expressions(&self, nodes: &[SyntaxNode]) -> Result<Vec<Node>, ParserError> {
+        nodes.iter().map(|node| self.expression(node)).collect()
+    }
+
+    fn literal(&self, token: &SyntaxToken) -> Result<NodeType, ParserError> {
+        let position = token.offset() as i128 + 1;
+        let value = token.text();
+
+        match token.kind() {
+            SyntaxKind::String => Ok(NodeType::StringLiteral(unescape(value))),
+            SyntaxKind::Number if value.contains('.') => {
+                let numeric: f64 = value.parse()
+                    .map_err(|_| ParserError::at(position, ParserErrorKind::InvalidNumber(value.to_string())))?;
+
+                Ok(NodeType::DoubleLiteral(numeric))
+            }
+            SyntaxKind::Number => {
+                let numeric: i128 = value.parse()
+                    .map_err(|_| ParserError::at(position, ParserErrorKind::InvalidNumber(value.to_string())))?;
+                let log = numeric.checked_ilog2().unwrap_or(0) + 1;
+
+                if log <= 8 {
+                    Ok(NodeType::Int8Literal(u8::try_from(numeric).unwrap()))
+                } else if log <= 16 {
+                    Ok(NodeType::Int16Literal(u16::try_from(numeric).unwrap()))
+                } else if log <= 32 {
+                    Ok(NodeType::Int32Literal(u32::try_from(numeric).unwrap()))
+                } else if log <= 64 {
+                    Ok(NodeType::Int64Literal(u64::try_from(numeric).unwrap()))
+                } else if log <= 128 {
+                    Ok(NodeType::Int128Literal(u128::try_from(numeric).unwrap()))
+                } else {
+                    Err(ParserError::at(position, ParserErrorKind::InvalidNumber(value.to_string())))
+                }
+            }
+            _ => Ok(match value {
+                "true" => NodeType::BooleanLiteral(true),
+                "false" => NodeType::BooleanLiteral(false),
+                _ => NodeType::NaLiteral,
+            }),
+        }
+    }
+
+    fn function_call(&self, tokens: &[SyntaxToken], arguments: &SyntaxNode) -> Result<FunctionCall, ParserError> {
+        let builtin = tokens.len() == 2;
+        let name = tokens.last().expect("a call has a name");
+        let mut parameters: Vec<(String, Node)> = Vec::new();
+
+        for argument in arguments.children() {
+            let argument_name = &argument.tokens()[0];
+            let parameter_name = argument_name.text().to_string();
+
+            if parameters.iter().any(|(name, _)| name == &parameter_name) {
+                let position = argument_name.offset() as i128 + 1;
+                return Err(ParserError::at(position, ParserErrorKind::DuplicateArgument(parameter_name)));
+            }
+
+            parameters.push((parameter_name, self.expression(&argument.children()[0])?));
+        }
+
+        Ok(FunctionCall {
+            builtin,
+            name: name.text().to_string(),
+            name_span: self.token_span(name),
+            parameters,
+        })
+    }
+}
+
+/// The value of a string token.
+fn unescape(text: &str) -> String {
+    // the tokenizer knows how to unescape
+    Tokenizer::new(text.to_string()).next_token().ok().flatten().and_then(|t| t.value).unwrap_or_default()
+}
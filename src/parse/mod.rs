@@ -1 +1,5 @@
-pub mod parser;
\ No newline at end of file
+pub mod formatter;
+pub mod incremental;
+mod lower;
+pub mod parser;
+pub mod syntax;
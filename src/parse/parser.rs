@@ -1,13 +1,20 @@
-use std::collections::HashMap;
-use crate::error::parser::ParserError;
+use crate::error::parser::{ParserError, ParserErrorKind};
 use crate::lexer::symbols::SymbolType;
-use crate::lexer::symbols::SymbolType::{AtSign, BraceLeft, BraceRight, Colon, Comma, Equals, ParenthesisLeft, ParenthesisRight};
-use crate::lexer::tokenizer::{Token, TokenType, Tokenizer};
+use crate::lexer::symbols::SymbolType::{AtSign, BraceLeft, BraceRight, BracketLeft, BracketRight, Comma, Dot, Equals, ParenthesisLeft, ParenthesisRight};
+use crate::lexer::tokenizer::{Comment, Span, Token, TokenType, Tokenizer};
+use crate::parse::lower;
+use crate::parse::syntax::{GreenBuilder, SyntaxKind, SyntaxNode};
+use std::path::PathBuf;
 use std::rc::Rc;
+use serde::Serialize;
 
 type ParserReturn = Result<Node, ParserError>;
 
-#[derive(Debug)]
+/// How deeply expressions and blocks can be nested. The parser and everything walking its trees
+/// recurse once per level, so deeper sources would overflow the stack.
+pub const MAX_NESTING: usize = 200;
+
+#[derive(Debug, Serialize)]
 pub enum NodeType {
     Program(Vec<Node>),
     Block(Vec<Node>),
@@ -18,449 +25,923 @@ pub enum NodeType {
     Int64Literal(u64),
     Int128Literal(u128),
     DoubleLiteral(f64),
+    BooleanLiteral(bool),
+    NaLiteral,
+    ListLiteral(Vec<Node>),
+    VariableReference(String),
+    FieldAccess(Node, String),
     ReturnExpression(Node),
     UnaryNode(UnaryType, Node),
+    BinaryNode(BinaryType, Node, Node),
     FunctionDefinition(FunctionDefinition),
     VariableDeclaration(VariableDeclaration),
-    FunctionCall(FunctionCall)
+    FunctionCall(FunctionCall),
+    Import(Import),
+    TryCatch(TryCatch)
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
 pub enum UnaryType {
-    Not
+    Not,
+    Negate
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize)]
+pub enum BinaryType {
+    Add,
+    Subtract,
+    Multiply,
+    Divide,
+    Modulo,
+    Power,
+    Equal,
+    NotEqual,
+    Less,
+    LessEqual,
+    Greater,
+    GreaterEqual,
+    And,
+    Or
 }
 
-#[derive(Debug, Clone)]
+impl BinaryType {
+    /// The operator as written in source.
+    pub fn symbol(&self) -> &'static str {
+        match self {
+            Self::Add => "+",
+            Self::Subtract => "-",
+            Self::Multiply => "*",
+            Self::Divide => "/",
+            Self::Modulo => "%",
+            Self::Power => "^",
+            Self::Equal => "==",
+            Self::NotEqual => "!=",
+            Self::Less => "<",
+            Self::LessEqual => "<=",
+            Self::Greater => ">",
+            Self::GreaterEqual => ">=",
+            Self::And => "&",
+            Self::Or => "|",
+        }
+    }
+
+    pub fn from_symbol(symbol: &str) -> Option<Self> {
+        [
+            Self::Add, Self::Subtract, Self::Multiply, Self::Divide, Self::Modulo, Self::Power, Self::Equal,
+            Self::NotEqual, Self::Less, Self::LessEqual, Self::Greater, Self::GreaterEqual, Self::And, Self::Or,
+        ].into_iter().find(|binary_type| binary_type.symbol() == symbol)
+    }
+}
+
+#[derive(Debug, Clone, Serialize)]
 pub struct FunctionDefinition {
+    /// Whether other modules can import the function.
+    pub public: bool,
     pub name: String,
+    #[serde(skip)]
+    pub name_span: Span,
     pub signature: Vec<FunctionParameter>,
     pub return_type: String,
     pub body: Node,
+    /// The file of the module the function was linked from, `None` for a function of the script.
+    #[serde(skip)]
+    pub module: Option<PathBuf>,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct FunctionCall {
     pub builtin: bool,
     pub name: String,
-    pub parameters: HashMap<String, Node>
+    #[serde(skip)]
+    pub name_span: Span,
+    /// The named arguments in source order.
+    pub parameters: Vec<(String, Node)>
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
 pub struct VariableDeclaration {
     pub name: String,
+    #[serde(skip)]
+    pub name_span: Span,
     pub variable_type: Option<String>,
     pub value: Node,
 }
 
+/// `try { ... } catch e { ... }`: if the body fails, the handler runs with the error as a record
+/// in `variable`.
+#[derive(Debug, Clone, Serialize)]
+pub struct TryCatch {
+    pub body: Node,
+    pub variable: String,
+    #[serde(skip)]
+    pub variable_span: Span,
+    pub handler: Node,
+}
+
+
+/// `import "lib/helpers.stsc"` or `import lib.helpers.{mean_ci, sd_ci}`.
+#[derive(Debug, Clone, Serialize)]
+pub struct Import {
+    pub module: ModulePath,
+    /// The functions imported by name, or `None` to import every public function of the module.
+    pub names: Option<Vec<ImportedName>>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub enum ModulePath {
+    /// A path to a file, e.g. `"lib/helpers.stsc"`.
+    File(String),
+    /// The parts of a dotted module name, e.g. `lib.helpers` for `lib/helpers.stsc`.
+    Dotted(Vec<String>),
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct ImportedName {
+    pub name: String,
+    #[serde(skip)]
+    pub span: Span,
+}
 
-#[derive(Debug, Clone, Default)]
+#[derive(Debug, Clone, Default, Serialize)]
 pub struct FunctionParameter {
     pub param_type: String,
     pub param_name: String,
+    #[serde(skip)]
+    pub span: Span,
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug, Clone, Serialize)]
+#[serde(transparent)]
 pub struct Node {
     pub node_type: Rc<NodeType>,
+    /// The source text the node was parsed from.
+    #[serde(skip)]
+    pub span: Span,
 }
 
+impl Node {
+    pub fn new(node_type: NodeType, span: Span) -> Self {
+        Self {
+            node_type: Rc::new(node_type),
+            span,
+        }
+    }
+}
+
+
+/// Parses StatScript source into a lossless [`SyntaxNode`] tree, from which the [`Node`] tree is
+/// derived.
 pub struct StatParser {
     tokenizer: Tokenizer,
-    ast: Node,
+    source: Vec<char>,
+    builder: GreenBuilder,
+    /// The char offset up to which the source has been added to the syntax tree.
+    consumed: usize,
     pub current_token: Option<Token>,
+    peeked_token: Option<Token>,
+    /// The expressions and blocks being parsed around the next token.
+    nesting: usize,
 }
 
 impl StatParser {
     pub fn new(tokenizer: Tokenizer) -> Self {
         Self {
+            source: tokenizer.content.chars().collect(),
             tokenizer,
-            ast: Node {
-                node_type: Rc::new(NodeType::Program(Vec::new()))
-            },
+            builder: GreenBuilder::default(),
+            consumed: 0,
             current_token: None,
+            peeked_token: None,
+            nesting: 0,
         }
     }
 
     pub fn parse(&mut self) -> ParserReturn {
-        let program = self.parse_program()?;
-        
-        self.ast.node_type = Rc::new(program);
-        Ok(self.ast.clone())
+        let tree = self.parse_syntax()?;
+
+        lower::lower_program(&tree)
     }
 
-    fn parse_program(&mut self) -> Result<NodeType, ParserError> {
-        let mut nodes: Vec<Node> = Vec::new();
+    /// Parses a program into its syntax tree, keeping every token, space and comment.
+    pub fn parse_syntax(&mut self) -> Result<SyntaxNode, ParserError> {
+        self.builder.start_node(SyntaxKind::Program);
+        self.parse_program()?;
 
+        Ok(self.finish())
+    }
 
-        while let Some(token) = self.next_token()? {
-            self.current_token = Some(token.clone());
-            return match &token.token_type {
-                None => {
-                    Err(ParserError::new(self, "None token type found... internal parsing error".into()))
-                }
-                Some(t) => {
-                    match t {
-                        TokenType::Identifier => {
-                            let identifier_value = self.unwrap_guaranteed_value(token.value)?;
-
-                            if identifier_value == "func" {
-                                nodes.push(self.parse_function_definition()?);
-                                continue;
-                            }
-
-                            Err(ParserError::new(self, format!("Unexpected identifier '{}'", identifier_value)))
-                        }
-                        tt => {
-                            Err(ParserError::new(self, format!("Invalid token type {:?} as top-level statement", tt)))
-                        }
+    /// Parses input of the interactive mode: a mix of function definitions and statements, which
+    /// are returned in order as the children of a `Program` node.
+    pub fn parse_interactive(&mut self) -> ParserReturn {
+        self.builder.start_node(SyntaxKind::Program);
+
+        while let Some(token) = self.peek_token()? {
+            if token.token_type == Some(TokenType::Identifier) {
+                match token.value.as_deref() {
+                    Some("func" | "pub") => {
+                        self.parse_function_definition()?;
+                        continue;
+                    }
+                    Some("import") => {
+                        self.parse_import()?;
+                        continue;
                     }
+                    _ => {}
                 }
-            };
+            }
+
+            self.parse_statement()?;
+        }
+
+        lower::lower_program(&self.finish())
+    }
+
+    /// Parses source that is exactly one block, e.g. the new text of a block after an edit.
+    pub fn parse_block_syntax(&mut self) -> Result<SyntaxNode, ParserError> {
+        if self.peek_symbol()? != Some(BraceLeft) {
+            self.next_token()?;
+            return Err(self.unexpected("'{' as begin of a block"));
+        }
+
+        self.parse_fragment(Self::parse_block)
+    }
+
+    /// Parses source that is exactly one function definition.
+    pub fn parse_function_syntax(&mut self) -> Result<SyntaxNode, ParserError> {
+        if !matches!(self.peek_identifier()?.as_deref(), Some("func" | "pub")) {
+            self.next_token()?;
+            return Err(self.unexpected("'func' as begin of a function definition"));
+        }
+
+        self.parse_fragment(Self::parse_function_definition)
+    }
+
+    fn parse_fragment(&mut self, parse: fn(&mut Self) -> Result<(), ParserError>) -> Result<SyntaxNode, ParserError> {
+        parse(self)?;
+
+        if self.next_token()?.is_some() || self.consumed < self.source.len() {
+            return Err(self.unexpected("the end of the fragment"));
         }
 
+        Ok(SyntaxNode::new_root(std::mem::take(&mut self.builder).finish()))
+    }
+
+    /// Adds the trailing trivia and finishes the root node.
+    fn finish(&mut self) -> SyntaxNode {
+        self.add_trivia(self.source.len());
+        self.builder.finish_node();
 
-        Ok(NodeType::Program(nodes))
+        SyntaxNode::new_root(std::mem::take(&mut self.builder).finish())
+    }
+
+    fn parse_program(&mut self) -> Result<(), ParserError> {
+        while let Some(token) = self.peek_token()? {
+            if token.token_type == Some(TokenType::Identifier) {
+                match token.value.as_deref() {
+                    Some("func" | "pub") => {
+                        self.parse_function_definition()?;
+                        continue;
+                    }
+                    Some("import") => {
+                        self.parse_import()?;
+                        continue;
+                    }
+                    _ => {}
+                }
+            }
+
+            self.next_token()?;
+
+            return Err(self.unexpected("'func', 'pub' or 'import' as top-level statement"));
+        }
+
+        Ok(())
     }
 
     fn next_token(&mut self) -> Result<Option<Token>, ParserError> {
-        let t = self.tokenizer.next_token().map_err(|e| ParserError::new(self, format!("Tokenizer error: {}:{} {}", e.position, e.line, e.message)))?;
+        let t = match self.peeked_token.take() {
+            Some(t) => Some(t),
+            None => self.read_token()?
+        };
 
+        if let Some(token) = &t {
+            self.add_token(token);
+        }
 
         self.current_token.clone_from(&t);
 
         Ok(t)
     }
 
+    /// Looks at the next token without consuming it. The token is handed out by the next call to
+    /// [`StatParser::next_token`].
+    fn peek_token(&mut self) -> Result<Option<Token>, ParserError> {
+        if self.peeked_token.is_none() {
+            self.peeked_token = self.read_token()?;
+        }
+
+        Ok(self.peeked_token.clone())
+    }
+
+    /// The comments of the source read so far.
+    pub fn comments(&self) -> &[Comment] {
+        &self.tokenizer.comments
+    }
+
+    fn peek_symbol(&mut self) -> Result<Option<SymbolType>, ParserError> {
+        match self.peek_token()?.and_then(|t| t.token_type) {
+            Some(TokenType::Symbol(s)) => Ok(Some(s)),
+            _ => Ok(None)
+        }
+    }
+
+    fn peek_identifier(&mut self) -> Result<Option<String>, ParserError> {
+        match self.peek_token()? {
+            Some(t) if t.token_type == Some(TokenType::Identifier) => Ok(t.value),
+            _ => Ok(None)
+        }
+    }
+
+    fn read_token(&mut self) -> Result<Option<Token>, ParserError> {
+        self.tokenizer.next_token().map_err(|e| ParserError::at(e.position, ParserErrorKind::Tokenizer(e.kind)))
+    }
+
+    /// Adds a consumed token to the syntax tree, after the trivia in front of it.
+    fn add_token(&mut self, token: &Token) {
+        let start = (token.start_pos - 1) as usize;
+        let end = token.end_pos as usize;
+
+        self.add_trivia(start);
+
+        let kind = token.token_type.as_ref().map_or(SyntaxKind::Identifier, SyntaxKind::from_token_type);
+
+        self.builder.token(kind, self.source[start..end].iter().collect());
+        self.consumed = end;
+    }
+
+    /// Adds the whitespace and comments between the last token and the char offset `end`.
+    fn add_trivia(&mut self, end: usize) {
+        while self.consumed < end {
+            let start = self.consumed;
+            let kind = if self.source[start] == '#' { SyntaxKind::Comment } else { SyntaxKind::Whitespace };
+
+            let length = self.source[start..end].iter()
+                .position(|c| match kind {
+                    SyntaxKind::Comment => *c == '\n',
+                    _ => *c == '#',
+                })
+                .unwrap_or(end - start);
+
+            self.builder.token(kind, self.source[start..start + length].iter().collect());
+            self.consumed += length;
+        }
+    }
+
     fn next_token_expect(&mut self) -> Result<Token, ParserError> {
         match self.next_token()? {
-            None => Err(ParserError::new(self, "Expected token".into())),
+            None => Err(self.unexpected("more tokens")),
             Some(t) => {
                 Ok(t)
             }
         }
     }
 
-    fn unwrap_guaranteed_value(&self, o: Option<String>) -> Result<String, ParserError> {
-        o.clone().ok_or(ParserError::new(self, "Identifier has None value... internal parsing error".into()))
+    /// An error for the token read last, or the end of the source, where the grammar expects
+    /// something else.
+    fn unexpected(&self, expected: &str) -> ParserError {
+        let kind = match &self.current_token {
+            Some(token) => {
+                let text: String = self.source[(token.start_pos - 1) as usize..token.end_pos as usize].iter().collect();
+                ParserErrorKind::UnexpectedToken { expected: expected.to_string(), found: format!("'{text}'") }
+            }
+            None => ParserErrorKind::UnexpectedEnd { expected: expected.to_string() },
+        };
+
+        ParserError::new(self, kind)
     }
 
-    fn parse_function_definition(&mut self) -> ParserReturn {
-        let mut body: Option<Node> = None;
-        let mut name: Option<String> = None;
-        let mut signature: Vec<FunctionParameter> = Vec::new();
-        let mut return_type: Option<String> = None;
+    fn parse_function_definition(&mut self) -> Result<(), ParserError> {
+        self.builder.start_node(SyntaxKind::FunctionDefinition);
 
+        if self.next_token_expect()?.value.as_deref() == Some("pub") && self.next_token_expect()?.value.as_deref() != Some("func") {
+            return Err(self.unexpected("'func' after 'pub'"));
+        }
 
         let name_token = self.next_token_expect()?;
 
-        if name_token.token_type.unwrap() != TokenType::Identifier {
-            return Err(ParserError::new(self, "Expected identifier as function name".into()));
+        if name_token.token_type != Some(TokenType::Identifier) {
+            return Err(self.unexpected("an identifier as function name"));
         }
 
+        self.parse_function_definition_signature()?;
 
-        name = Some(self.unwrap_guaranteed_value(name_token.value)?);
-
-        if self.next_token_expect()?.token_type.unwrap() != TokenType::Symbol(ParenthesisLeft) {
-            return Err(ParserError::new(self, "Expected '(' after function name".into()));
-        }
-
-        if self.next_token_expect()?.token_type.unwrap() != TokenType::Symbol(ParenthesisRight) {
-            signature = self.parse_function_definition_signature()?
-        }
+        if self.peek_symbol()? == Some(AtSign) {
+            self.builder.start_node(SyntaxKind::ReturnType);
+            self.next_token_expect()?;
 
-        if self.next_token_expect()?.token_type.unwrap() == TokenType::Symbol(AtSign) {
             let return_token = self.next_token_expect()?;
             if return_token.token_type.unwrap() != TokenType::Identifier {
-                return Err(ParserError::new(self, "Expected identifier as function return type".into()));
+                return Err(self.unexpected("an identifier as function return type"));
             }
 
-            return_type = Some(self.unwrap_guaranteed_value(return_token.value)?);
-
-            self.next_token_expect()?;
+            self.builder.finish_node();
         }
 
-        if self.current_token.clone().unwrap().token_type.unwrap() != TokenType::Symbol(BraceLeft) {
-            return Err(ParserError::new(self, "Expected '{' as begin of function body".into()));
+        if self.peek_symbol()? != Some(BraceLeft) {
+            self.next_token_expect()?;
+            return Err(self.unexpected("'{' as begin of function body"));
         }
 
-        body = Some(self.parse_block()?);
+        self.parse_block()?;
+        self.builder.finish_node();
 
-        Ok(Node {
-            node_type: Rc::new(NodeType::FunctionDefinition(FunctionDefinition {
-                name: name.unwrap(),
-                signature,
-                return_type: return_type.unwrap_or("Nothing".into()),
-                body: body.unwrap(),
-            }))
-        })
+        Ok(())
     }
 
-    fn parse_function_definition_signature(&mut self) -> Result<Vec<FunctionParameter>, ParserError> {
-        let mut next_token = self.current_token.clone().unwrap();
+    fn parse_function_definition_signature(&mut self) -> Result<(), ParserError> {
         let max_arguments = 128;
-        let mut parameters: Vec<FunctionParameter> = Vec::new();
 
+        self.builder.start_node(SyntaxKind::ParameterList);
+
+        if self.next_token_expect()?.token_type.unwrap() != TokenType::Symbol(ParenthesisLeft) {
+            return Err(self.unexpected("'(' after function name"));
+        }
 
         let mut i = 0;
-        while next_token.token_type != Some(TokenType::Symbol(ParenthesisRight)) {
-            
-            if !parameters.is_empty() {
-                if next_token.token_type != Some(TokenType::Symbol(Comma)) {
-                    return Err(ParserError::new(self, "Expected symbol ',' to separate different parameters".to_string()))
-                }
-                
-                next_token = self.next_token_expect()?
+        while self.peek_symbol()? != Some(ParenthesisRight) {
+            if i > 0 && self.next_token_expect()?.token_type != Some(TokenType::Symbol(Comma)) {
+                return Err(self.unexpected("',' to separate different parameters"))
             }
 
             i += 1;
             if i > max_arguments {
-                return Err(ParserError::new(self, "Function definition only supports up to 128 arguments".into()))
+                return Err(ParserError::new(self, ParserErrorKind::TooManyParameters(max_arguments)))
             }
 
-            if next_token.token_type != Some(TokenType::Identifier) {
-                return Err(ParserError::new(self, format!("Expected identifier got {:?}", next_token.token_type.unwrap())))
+            self.builder.start_node(SyntaxKind::Parameter);
+
+            let name_token = self.next_token_expect()?;
+            if name_token.token_type != Some(TokenType::Identifier) {
+                return Err(self.unexpected("an identifier as parameter name"))
             }
 
-            let parameter_name = next_token.value.unwrap();
+            let at_token = self.next_token_expect()?;
+            if at_token.token_type != Some(TokenType::Symbol(AtSign)) {
+                return Err(self.unexpected("'@' before the parameter type"))
+            }
 
-            next_token = self.next_token_expect()?;
+            let type_token = self.next_token_expect()?;
+            if type_token.token_type != Some(TokenType::Identifier) {
+                return Err(self.unexpected("an identifier as parameter type"))
+            }
 
+            self.builder.finish_node();
+        }
 
-            if next_token.token_type != Some(TokenType::Symbol(AtSign)) {
-                return Err(ParserError::new(self, format!("Expected token '@' got {:?}", next_token.token_type.unwrap())))
+        self.next_token_expect()?;
+        self.builder.finish_node();
 
+        Ok(())
+    }
+
+    /// Parses `import "path"` or `import a.b`, optionally followed by `.{names}`.
+    fn parse_import(&mut self) -> Result<(), ParserError> {
+        self.builder.start_node(SyntaxKind::Import);
+        self.next_token_expect()?;
+
+        match self.next_token_expect()?.token_type {
+            Some(TokenType::String) => {}
+            Some(TokenType::Identifier) => {
+                while self.peek_symbol()? == Some(Dot) {
+                    self.next_token_expect()?;
+
+                    if self.peek_symbol()? == Some(BraceLeft) {
+                        self.parse_import_list()?;
+                        break;
+                    }
+
+                    self.get_expected_identifier()?;
+                }
             }
+            _ => return Err(self.unexpected("a module name or a path in quotes after 'import'"))
+        }
+
+        self.builder.finish_node();
+
+        Ok(())
+    }
 
-            next_token = self.next_token_expect()?;
+    /// Parses the `{names}` of an import. Commas between the names are optional.
+    fn parse_import_list(&mut self) -> Result<(), ParserError> {
+        self.builder.start_node(SyntaxKind::ImportList);
+        self.next_token_expect()?;
+
+        if self.peek_symbol()? == Some(BraceRight) {
+            self.next_token_expect()?;
+            return Err(self.unexpected("the name of a function to import"));
+        }
 
-            if next_token.token_type != Some(TokenType::Identifier) {
-                return Err(ParserError::new(self, format!("Expected identifier got {:?}", next_token.token_type.unwrap())))
+        while self.peek_symbol()? != Some(BraceRight) {
+            self.get_expected_identifier()?;
+
+            if self.peek_symbol()? == Some(Comma) {
+                self.next_token_expect()?;
             }
+        }
 
-            let parameter_type = next_token.value.clone().unwrap();
+        self.next_token_expect()?;
+        self.builder.finish_node();
+
+        Ok(())
+    }
 
+    fn parse_expression(&mut self) -> Result<(), ParserError> {
+        self.nested(Self::parse_or)
+    }
 
-            let parameter = FunctionParameter {
-                param_type: parameter_type,
-                param_name: parameter_name,
-            };
+    /// Parses one level of nesting deeper, or fails beyond [`MAX_NESTING`] levels.
+    fn nested(&mut self, parse: fn(&mut Self) -> Result<(), ParserError>) -> Result<(), ParserError> {
+        if self.nesting == MAX_NESTING {
+            self.next_token_expect()?;
+            return Err(ParserError::new(self, ParserErrorKind::TooDeeplyNested(MAX_NESTING)));
+        }
 
-            parameters.push(parameter);
+        self.nesting += 1;
+        let result = parse(self);
+        self.nesting -= 1;
 
-            next_token = self.next_token_expect()?;
+        result
+    }
+
+    fn parse_or(&mut self) -> Result<(), ParserError> {
+        let checkpoint = self.builder.checkpoint();
+        self.parse_and()?;
+
+        while self.peek_symbol()? == Some(SymbolType::Pipe) {
+            self.builder.start_node_at(checkpoint, SyntaxKind::BinaryExpression);
+            self.next_token_expect()?;
+            self.parse_and()?;
+            self.builder.finish_node();
         }
 
-        Ok(parameters)
+        Ok(())
     }
 
-    fn parse_expression(&mut self) -> ParserReturn {
-        let tok = self.next_token_expect()?;
+    fn parse_and(&mut self) -> Result<(), ParserError> {
+        let checkpoint = self.builder.checkpoint();
+        self.parse_comparison()?;
 
-        return match tok.token_type.as_ref().unwrap() {
-            TokenType::String => {
-                Ok(Node {
-                    node_type: Rc::new(NodeType::StringLiteral(self.unwrap_guaranteed_value(tok.value)?))
-                })
+        while self.peek_symbol()? == Some(SymbolType::Ampersand) {
+            self.builder.start_node_at(checkpoint, SyntaxKind::BinaryExpression);
+            self.next_token_expect()?;
+            self.parse_comparison()?;
+            self.builder.finish_node();
+        }
+
+        Ok(())
+    }
+
+    fn parse_comparison(&mut self) -> Result<(), ParserError> {
+        let checkpoint = self.builder.checkpoint();
+        self.parse_additive()?;
+
+        loop {
+            let symbol = self.peek_symbol()?;
+            if !matches!(symbol, Some(Equals | SymbolType::ExclamationMark | SymbolType::TagLeft | SymbolType::TagRight)) {
+                break;
             }
-            TokenType::Number(b) => {
-                let value = self.unwrap_guaranteed_value(tok.value)?;
 
-                if *b {
-                    let numeric: f64 = value.parse().map_err(|_| ParserError::new(self, format!("Literal {value} is not a valid double.")))?;
+            self.builder.start_node_at(checkpoint, SyntaxKind::BinaryExpression);
+            self.next_token_expect()?;
 
-                    return Ok(Node {
-                        node_type: Rc::new(NodeType::DoubleLiteral(numeric))
-                    });
+            match symbol {
+                // `==` and `!=`
+                Some(Equals | SymbolType::ExclamationMark) => {
+                    self.expect_token_type(TokenType::Symbol(Equals), "'='")?;
+                }
+                // `<=` and `>=`, or `<` and `>` alone
+                _ => {
+                    if self.peek_symbol()? == Some(Equals) {
+                        self.next_token_expect()?;
+                    }
                 }
+            }
+
+            self.parse_additive()?;
+            self.builder.finish_node();
+        }
+
+        Ok(())
+    }
+
+    fn parse_additive(&mut self) -> Result<(), ParserError> {
+        let checkpoint = self.builder.checkpoint();
+        self.parse_multiplicative()?;
+
+        while matches!(self.peek_symbol()?, Some(SymbolType::Plus | SymbolType::Minus)) {
+            self.builder.start_node_at(checkpoint, SyntaxKind::BinaryExpression);
+            self.next_token_expect()?;
+            self.parse_multiplicative()?;
+            self.builder.finish_node();
+        }
+
+        Ok(())
+    }
+
+    fn parse_multiplicative(&mut self) -> Result<(), ParserError> {
+        let checkpoint = self.builder.checkpoint();
+        self.parse_unary()?;
+
+        while matches!(self.peek_symbol()?, Some(SymbolType::Asterisk | SymbolType::Slash | SymbolType::Percent)) {
+            self.builder.start_node_at(checkpoint, SyntaxKind::BinaryExpression);
+            self.next_token_expect()?;
+            self.parse_unary()?;
+            self.builder.finish_node();
+        }
+
+        Ok(())
+    }
+
+    fn parse_unary(&mut self) -> Result<(), ParserError> {
+        if !matches!(self.peek_symbol()?, Some(SymbolType::ExclamationMark | SymbolType::Minus)) {
+            return self.parse_power();
+        }
+
+        self.builder.start_node(SyntaxKind::UnaryExpression);
+        self.next_token_expect()?;
+        self.nested(Self::parse_unary)?;
+        self.builder.finish_node();
+
+        Ok(())
+    }
+
+    fn parse_power(&mut self) -> Result<(), ParserError> {
+        let checkpoint = self.builder.checkpoint();
+        self.parse_field_access()?;
+
+        if self.peek_symbol()? != Some(SymbolType::Power) {
+            return Ok(());
+        }
+
+        self.builder.start_node_at(checkpoint, SyntaxKind::BinaryExpression);
+        self.next_token_expect()?;
+        // right associative, so 2 ^ 3 ^ 2 is 2 ^ (3 ^ 2)
+        self.nested(Self::parse_unary)?;
+        self.builder.finish_node();
+
+        Ok(())
+    }
+
+    fn parse_field_access(&mut self) -> Result<(), ParserError> {
+        let checkpoint = self.builder.checkpoint();
+        self.parse_primary()?;
+
+        while self.peek_symbol()? == Some(SymbolType::Dot) {
+            self.builder.start_node_at(checkpoint, SyntaxKind::FieldAccess);
+            self.next_token_expect()?;
+            self.get_expected_identifier()?;
+            self.builder.finish_node();
+        }
+
+        Ok(())
+    }
+
+    fn parse_primary(&mut self) -> Result<(), ParserError> {
+        let Some(tok) = self.peek_token()? else {
+            self.next_token_expect()?;
+            unreachable!("there is no token left");
+        };
 
-                let numeric: i128 = value.parse().map_err(|_| ParserError::new(self, format!("Number {value} is an invalid number")))?;
-                let log = numeric.ilog2() + 1;
-
-                return if log <= 8 {
-                    Ok(Node {
-                        node_type: Rc::new(NodeType::Int8Literal(u8::try_from(numeric).unwrap()))
-                    })
-                } else if log <= 16 {
-                    Ok(Node {
-                        node_type: Rc::new(NodeType::Int16Literal(u16::try_from(numeric).unwrap()))
-                    })
-                } else if log <= 32 {
-                    Ok(Node {
-                        node_type: Rc::new(NodeType::Int32Literal(u32::try_from(numeric).unwrap()))
-                    })
-                } else if log <= 64 {
-                    Ok(Node {
-                        node_type: Rc::new(NodeType::Int64Literal(u64::try_from(numeric).unwrap()))
-                    })
-                } else if log <= 128 {
-                    Ok(Node {
-                        node_type: Rc::new(NodeType::Int128Literal(u128::try_from(numeric).unwrap()))
-                    })
+        match tok.token_type.as_ref().unwrap() {
+            TokenType::String | TokenType::Number(_) => {
+                self.builder.start_node(SyntaxKind::Literal);
+                self.next_token_expect()?;
+                self.builder.finish_node();
+            }
+            TokenType::Symbol(BraceLeft) => {
+                self.parse_block()?;
+            }
+            TokenType::Symbol(ParenthesisLeft) => {
+                self.builder.start_node(SyntaxKind::ParenthesizedExpression);
+                self.next_token_expect()?;
+                self.parse_expression()?;
+                self.expect_token_type(TokenType::Symbol(ParenthesisRight), "')'")?;
+                self.builder.finish_node();
+            }
+            TokenType::Symbol(BracketLeft) => {
+                self.parse_list_literal()?;
+            }
+            TokenType::Identifier => {
+                let identifier = tok.value.expect("the tokenizer gives identifiers a value");
+                let checkpoint = self.builder.checkpoint();
+                self.next_token_expect()?;
+
+                if identifier == "builtin" {
+                    self.builder.start_node_at(checkpoint, SyntaxKind::FunctionCall);
+                    self.get_expected_identifier()?;
+                    self.parse_function_call()?;
+                } else if self.peek_symbol()? == Some(ParenthesisLeft) {
+                    self.builder.start_node_at(checkpoint, SyntaxKind::FunctionCall);
+                    self.parse_function_call()?;
+                } else if matches!(identifier.as_str(), "true" | "false" | "NA") {
+                    self.builder.start_node_at(checkpoint, SyntaxKind::Literal);
                 } else {
-                    Err(ParserError::new(self, "You've bent the universe... or my memory idk".into()))
+                    self.builder.start_node_at(checkpoint, SyntaxKind::VariableReference);
                 }
+
+                self.builder.finish_node();
             }
-            TokenType::Symbol(BraceLeft) => {
-                Ok(self.parse_block()?)
+            _ => {
+                self.next_token_expect()?;
+                return Err(self.unexpected("an expression"));
             }
-            _ => Err(ParserError::new(self, format!("Invalid token {tok:?} as start of an expression.")))
-        };
+        }
+
+        Ok(())
+    }
+
+    fn parse_list_literal(&mut self) -> Result<(), ParserError> {
+        self.builder.start_node(SyntaxKind::ListLiteral);
+        self.next_token_expect()?;
+
+        if self.peek_symbol()? == Some(BracketRight) {
+            self.next_token_expect()?;
+        } else {
+            loop {
+                self.parse_expression()?;
+
+                let separator = self.next_token_expect()?;
+                match separator.token_type {
+                    Some(TokenType::Symbol(BracketRight)) => break,
+                    Some(TokenType::Symbol(Comma)) => continue,
+                    _ => return Err(self.unexpected("',' or ']' in list literal"))
+                }
+            }
+        }
+
+        self.builder.finish_node();
+
+        Ok(())
     }
 
-    fn parse_variable_declaration(&mut self) -> ParserReturn {
-        let err: String = "Syntax error in variable declaration. Example: 'set<i32> num <- 3".into();
+    fn parse_variable_declaration(&mut self) -> Result<(), ParserError> {
+
+        self.builder.start_node(SyntaxKind::VariableDeclaration);
+        self.next_token_expect()?;
 
         if self.next_token_expect()?.token_type.unwrap() != TokenType::Symbol(SymbolType::TagLeft) {
-            return Err(ParserError::new(self, err));
+            return Err(self.unexpected("'<' before the variable type"));
         }
 
         let type_token = self.next_token_expect()?;
         if type_token.token_type.unwrap() != TokenType::Identifier {
-            return Err(ParserError::new(self, "Expected identifier as variable type".into()));
+            return Err(self.unexpected("an identifier as variable type"));
         }
 
         if self.next_token_expect()?.token_type.unwrap() != TokenType::Symbol(SymbolType::TagRight) {
-            return Err(ParserError::new(self, err));
+            return Err(self.unexpected("'>' after the variable type"));
         }
 
         let name_token = self.next_token_expect()?;
 
-        if name_token.token_type.unwrap() != TokenType::Identifier {
-            return Err(ParserError::new(self, "Expected identifier as variable name".into()));
+        if name_token.token_type != Some(TokenType::Identifier) {
+            return Err(self.unexpected("an identifier as variable name"));
         }
 
         if self.next_token_expect()?.token_type.unwrap() != TokenType::Symbol(SymbolType::TagLeft) {
-            return Err(ParserError::new(self, err));
+            return Err(self.unexpected("'<-' after the variable name"));
         }
 
         if self.next_token_expect()?.token_type.unwrap() != TokenType::Symbol(SymbolType::Minus) {
-            return Err(ParserError::new(self, err));
+            return Err(self.unexpected("'<-' after the variable name"));
         }
 
-        let value = self.parse_expression()?;
+        self.parse_expression()?;
+        self.builder.finish_node();
 
-        let var_type = self.unwrap_guaranteed_value(type_token.value)?;
+        Ok(())
+    }
 
-        let mut t = Some(var_type.clone());
+    fn parse_try_statement(&mut self) -> Result<(), ParserError> {
+        self.builder.start_node(SyntaxKind::TryStatement);
+        self.next_token_expect()?;
 
-        if var_type == "inherit" {
-            t = None;
+        if self.peek_symbol()? != Some(BraceLeft) {
+            self.next_token_expect()?;
+            return Err(self.unexpected("'{' after 'try'"));
+        }
+        self.parse_block()?;
+
+        if self.next_token_expect()?.value.as_deref() != Some("catch") {
+            return Err(self.unexpected("'catch' after the 'try' block"));
+        }
+
+        if self.next_token_expect()?.token_type != Some(TokenType::Identifier) {
+            return Err(self.unexpected("an identifier as the name of the caught error"));
+        }
+
+        if self.peek_symbol()? != Some(BraceLeft) {
+            self.next_token_expect()?;
+            return Err(self.unexpected("'{' after the name of the caught error"));
         }
+        self.parse_block()?;
+        self.builder.finish_node();
 
-        Ok(Node {
-            node_type: Rc::new(NodeType::VariableDeclaration(VariableDeclaration {
-                name: self.unwrap_guaranteed_value(name_token.value)?,
-                variable_type: t,
-                value,
-            }))
-        })
+        Ok(())
     }
 
-    fn get_expected_identifier(&mut self, fetch_next_token: bool) -> Result<String, ParserError> {
-        let token = if fetch_next_token {
-            self.next_token_expect()?
-        } else {
-            self.current_token.clone().unwrap()
-        };
+    fn get_expected_identifier(&mut self) -> Result<String, ParserError> {
+        let token = self.next_token_expect()?;
 
         if token.token_type != Some(TokenType::Identifier) {
-            return Err(ParserError::new(self, format!("Expected identifier got {:?}", token.token_type.unwrap())))
+            return Err(self.unexpected("an identifier"))
         }
 
-        self.unwrap_guaranteed_value(token.value)
+        Ok(token.value.expect("the tokenizer gives identifiers a value"))
     }
 
-    fn expect_token_type(&mut self, token_type: TokenType) -> Result<bool, ParserError> {
+    fn expect_token_type(&mut self, token_type: TokenType, expected: &str) -> Result<bool, ParserError> {
         let token = self.next_token_expect()?;
 
         if token.token_type != Some(token_type.clone()) {
-            return Err(ParserError::new(self, format!("Expected {:?} got {:?}", token_type, token.token_type.unwrap())))
+            return Err(self.unexpected(expected))
         }
 
         Ok(true)
     }
 
-    fn parse_function_call(&mut self, builtin: bool) -> ParserReturn {
-        let mut parameters: HashMap<String, Node> = HashMap::new();
-
-
-        let method_name = self.get_expected_identifier(true)?;
-
-
-        self.expect_token_type(TokenType::Symbol(ParenthesisLeft))?;
-
-        let mut next_token = self.next_token_expect()?;
-
-        if next_token.token_type != Some(TokenType::Symbol(ParenthesisRight)) {
-            while next_token.token_type != Some(TokenType::Symbol(ParenthesisRight)) {
-
-                let parameter_name = self.get_expected_identifier(false)?;
-
-                self.expect_token_type(TokenType::Symbol(Equals))?;
-
-                let value = self.parse_expression()?;
-
-                parameters.insert(parameter_name, value);
-
-                next_token = self.next_token_expect()?;
+    /// Parses the arguments of a call whose name has been consumed. Commas between the arguments
+    /// are optional.
+    fn parse_function_call(&mut self) -> Result<(), ParserError> {
+        self.builder.start_node(SyntaxKind::ArgumentList);
+        self.expect_token_type(TokenType::Symbol(ParenthesisLeft), "'(' before the arguments")?;
+
+        while self.peek_symbol()? != Some(ParenthesisRight) {
+            self.builder.start_node(SyntaxKind::Argument);
+            self.get_expected_identifier()?;
+            self.expect_token_type(TokenType::Symbol(Equals), "'=' after the argument name")?;
+            self.parse_expression()?;
+            self.builder.finish_node();
+
+            if self.peek_symbol()? == Some(Comma) {
+                self.next_token_expect()?;
             }
         }
 
-        Ok(Node {
-            node_type: Rc::new(NodeType::FunctionCall(FunctionCall {
-                builtin,
-                name: method_name,
-                parameters,
-            }))
-        })
+        self.next_token_expect()?;
+        self.builder.finish_node();
 
+        Ok(())
     }
 
-    fn parse_block(&mut self) -> ParserReturn {
-        let mut nodes: Vec<Node> = Vec::new();
-
-        let mut closed = false;
-
-        while let Some(token) = self.next_token()? {
-            if token.token_type.as_ref().unwrap() == &TokenType::Symbol(BraceRight) {
-                closed = true;
-                break;
-            }
+    fn parse_block(&mut self) -> Result<(), ParserError> {
+        self.nested(Self::parse_block_contents)
+    }
 
+    fn parse_block_contents(&mut self) -> Result<(), ParserError> {
+        self.builder.start_node(SyntaxKind::Block);
+        self.next_token_expect()?;
 
-            let token_value = self.unwrap_guaranteed_value(token.value)?;
-            if token_value == "set" {
-                nodes.push(self.parse_variable_declaration()?);
-                continue;
+        loop {
+            match self.peek_token()? {
+                None => {
+                    self.next_token()?;
+                    return Err(self.unexpected("'}' to close the block"));
+                }
+                Some(token) if token.token_type == Some(TokenType::Symbol(BraceRight)) => {
+                    self.next_token_expect()?;
+                    break;
+                }
+                Some(_) => self.parse_statement()?
             }
+        }
 
-            if token_value == "return" {
-                nodes.push(Node {
-                    node_type: Rc::new(NodeType::ReturnExpression(self.parse_expression()?))
-                });
-                continue;
-            }
+        self.builder.finish_node();
 
-            if token_value == "builtin" {
-                nodes.push(self.parse_function_call(true)?);
-                continue;
-            }
+        Ok(())
+    }
 
-            if token.token_type == Some(TokenType::Symbol(BraceLeft)) {
-                nodes.push(self.parse_block()?);
-                continue;
+    /// Parses the statement starting with the next token. Anything that is not a declaration,
+    /// `return` or block is parsed as an expression statement.
+    fn parse_statement(&mut self) -> Result<(), ParserError> {
+        match self.peek_identifier()?.as_deref() {
+            Some("set") => return self.parse_variable_declaration(),
+            Some("try") => return self.parse_try_statement(),
+            Some("return") => {
+                self.builder.start_node(SyntaxKind::ReturnStatement);
+                self.next_token_expect()?;
+                self.parse_expression()?;
+                self.builder.finish_node();
+
+                return Ok(());
             }
-
-
-            return Err(ParserError::new(self, format!("Unexpected token {token_value}")));
+            _ => {}
         }
 
-        if !closed {
-            return Err(ParserError::new(self, "Expected block closure".into()));
+        if self.peek_symbol()? == Some(BraceLeft) {
+            return self.parse_block();
         }
 
-        Ok(Node {
-            node_type: Rc::new(NodeType::Block(nodes))
-        })
+        self.parse_expression()
     }
-}
\ No newline at end of file
+}
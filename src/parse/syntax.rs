@@ -0,0 +1,527 @@
+//! The lossless syntax tree. Every char of the source, including whitespace and comments, is part
+//! of exactly one token of the tree, so printing the tree gives back the source.
+//!
+//! The tree comes in two layers. Green nodes only know their kind, their length and their
+//! children, which lets equal subtrees be shared and subtrees be replaced cheaply. Syntax nodes
+//! wrap a green node with its position in the source and its parent, for navigating the tree.
+
+use std::fmt;
+use std::fmt::Write;
+use std::rc::Rc;
+
+use crate::lexer::tokenizer::TokenType;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum SyntaxKind {
+    // tokens
+    Whitespace,
+    Comment,
+    Identifier,
+    Number,
+    String,
+    Character,
+    Symbol,
+
+    // nodes
+    Program,
+    Import,
+    ImportList,
+    FunctionDefinition,
+    ParameterList,
+    Parameter,
+    ReturnType,
+    Block,
+    VariableDeclaration,
+    ReturnStatement,
+    TryStatement,
+    FunctionCall,
+    ArgumentList,
+    Argument,
+    Literal,
+    VariableReference,
+    ListLiteral,
+    ParenthesizedExpression,
+    FieldAccess,
+    UnaryExpression,
+    BinaryExpression,
+}
+
+impl SyntaxKind {
+    pub fn from_token_type(token_type: &TokenType) -> Self {
+        match token_type {
+            TokenType::Identifier => Self::Identifier,
+            TokenType::Number(_) => Self::Number,
+            TokenType::String => Self::String,
+            TokenType::Character(_) => Self::Character,
+            TokenType::Symbol(_) => Self::Symbol,
+        }
+    }
+
+    /// Whether tokens of this kind only carry layout.
+    pub fn is_trivia(self) -> bool {
+        matches!(self, Self::Whitespace | Self::Comment)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenToken {
+    kind: SyntaxKind,
+    text: String,
+    text_len: usize,
+}
+
+impl GreenToken {
+    pub fn new(kind: SyntaxKind, text: String) -> Self {
+        Self { kind, text_len: text.chars().count(), text }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GreenElement {
+    Node(Rc<GreenNode>),
+    Token(Rc<GreenToken>),
+}
+
+impl GreenElement {
+    pub fn kind(&self) -> SyntaxKind {
+        match self {
+            Self::Node(node) => node.kind,
+            Self::Token(token) => token.kind,
+        }
+    }
+
+    /// The length in chars.
+    pub fn text_len(&self) -> usize {
+        match self {
+            Self::Node(node) => node.text_len,
+            Self::Token(token) => token.text_len,
+        }
+    }
+
+    /// Whether both are the very same element, not just equal ones.
+    pub fn ptr_eq(&self, other: &GreenElement) -> bool {
+        match (self, other) {
+            (Self::Node(a), Self::Node(b)) => Rc::ptr_eq(a, b),
+            (Self::Token(a), Self::Token(b)) => Rc::ptr_eq(a, b),
+            _ => false,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct GreenNode {
+    kind: SyntaxKind,
+    text_len: usize,
+    children: Vec<GreenElement>,
+}
+
+impl GreenNode {
+    pub fn new(kind: SyntaxKind, children: Vec<GreenElement>) -> Self {
+        Self { kind, text_len: children.iter().map(GreenElement::text_len).sum(), children }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.kind
+    }
+
+    /// The length in chars.
+    pub fn text_len(&self) -> usize {
+        self.text_len
+    }
+
+    pub fn children(&self) -> &[GreenElement] {
+        &self.children
+    }
+
+    /// A copy of the node with `old`, which starts `offset` chars into the node, replaced by
+    /// `new`. Only the nodes on the way down to `old` are copied, the rest is shared.
+    pub fn replace(&self, offset: usize, old: &GreenElement, new: GreenElement) -> GreenNode {
+        let mut children = self.children.clone();
+        let mut child_offset = 0;
+
+        for child in children.iter_mut() {
+            if child_offset == offset && child.ptr_eq(old) {
+                *child = new;
+                break;
+            }
+
+            if let GreenElement::Node(node) = child {
+                if child_offset <= offset && offset < child_offset + node.text_len {
+                    *child = GreenElement::Node(Rc::new(node.replace(offset - child_offset, old, new)));
+                    break;
+                }
+            }
+
+            child_offset += child.text_len();
+        }
+
+        GreenNode::new(self.kind, children)
+    }
+
+    fn write_text(&self, text: &mut String) {
+        for child in self.children.iter() {
+            match child {
+                GreenElement::Node(node) => node.write_text(text),
+                GreenElement::Token(token) => text.push_str(&token.text),
+            }
+        }
+    }
+}
+
+/// Assembles a green tree from tokens and node boundaries in source order.
+///
+/// Trivia in front of the first token of a node is moved in front of the node when the node is
+/// finished, so that nodes start and end with their own tokens.
+#[derive(Debug, Default)]
+pub struct GreenBuilder {
+    parents: Vec<(SyntaxKind, usize)>,
+    children: Vec<GreenElement>,
+}
+
+/// A position in the builder where a node can be started after its first children are known,
+/// e.g. the binary expression around an already built left operand.
+#[derive(Debug, Clone, Copy)]
+pub struct Checkpoint(usize);
+
+impl GreenBuilder {
+    pub fn token(&mut self, kind: SyntaxKind, text: String) {
+        self.children.push(GreenElement::Token(Rc::new(GreenToken::new(kind, text))));
+    }
+
+    pub fn start_node(&mut self, kind: SyntaxKind) {
+        self.parents.push((kind, self.children.len()));
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint {
+        Checkpoint(self.children.len())
+    }
+
+    /// Starts a node holding everything added since `checkpoint`.
+    pub fn start_node_at(&mut self, checkpoint: Checkpoint, kind: SyntaxKind) {
+        self.parents.push((kind, checkpoint.0));
+    }
+
+    pub fn finish_node(&mut self) {
+        let (kind, mut first) = self.parents.pop().expect("no node to finish");
+
+        // the root keeps its leading trivia, there is nowhere else to put it
+        if !self.parents.is_empty() {
+            while self.children.get(first).is_some_and(|c| c.kind().is_trivia()) {
+                first += 1;
+            }
+        }
+
+        let children = self.children.split_off(first);
+        self.children.push(GreenElement::Node(Rc::new(GreenNode::new(kind, children))));
+    }
+
+    /// The finished tree, after the root node has been finished.
+    pub fn finish(mut self) -> Rc<GreenNode> {
+        assert!(self.parents.is_empty() && self.children.len() == 1, "unbalanced syntax tree");
+
+        match self.children.pop() {
+            Some(GreenElement::Node(node)) => node,
+            _ => unreachable!("the root of a syntax tree is a node"),
+        }
+    }
+}
+
+/// A node of the syntax tree at its place in the source.
+#[derive(Clone)]
+pub struct SyntaxNode {
+    green: Rc<GreenNode>,
+    offset: usize,
+    parent: Option<Rc<SyntaxNode>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SyntaxToken {
+    green: Rc<GreenToken>,
+    offset: usize,
+}
+
+#[derive(Debug, Clone)]
+pub enum SyntaxElement {
+    Node(SyntaxNode),
+    Token(SyntaxToken),
+}
+
+impl SyntaxNode {
+    pub fn new_root(green: Rc<GreenNode>) -> Self {
+        Self { green, offset: 0, parent: None }
+    }
+
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenNode> {
+        &self.green
+    }
+
+    /// The 0-based char offset of the node in the source.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The 0-based char offset right after the node.
+    pub fn end(&self) -> usize {
+        self.offset + self.green.text_len
+    }
+
+    pub fn text(&self) -> String {
+        let mut text = String::new();
+        self.green.write_text(&mut text);
+        text
+    }
+
+    pub fn parent(&self) -> Option<&SyntaxNode> {
+        self.parent.as_deref()
+    }
+
+    /// The node and its parents up to the root.
+    pub fn ancestors(&self) -> impl Iterator<Item = &SyntaxNode> {
+        std::iter::successors(Some(self), |node| node.parent())
+    }
+
+    pub fn children_with_tokens(&self) -> Vec<SyntaxElement> {
+        let parent = Rc::new(self.clone());
+        let mut offset = self.offset;
+
+        self.green.children.iter()
+            .map(|child| {
+                let element = match child {
+                    GreenElement::Node(node) => SyntaxElement::Node(SyntaxNode {
+                        green: node.clone(),
+                        offset,
+                        parent: Some(parent.clone()),
+                    }),
+                    GreenElement::Token(token) => SyntaxElement::Token(SyntaxToken { green: token.clone(), offset }),
+                };
+
+                offset += child.text_len();
+                element
+            })
+            .collect()
+    }
+
+    /// The child nodes, without tokens.
+    pub fn children(&self) -> Vec<SyntaxNode> {
+        self.children_with_tokens().into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Node(node) => Some(node),
+                SyntaxElement::Token(_) => None,
+            })
+            .collect()
+    }
+
+    /// The tokens directly below the node, without trivia.
+    pub fn tokens(&self) -> Vec<SyntaxToken> {
+        self.children_with_tokens().into_iter()
+            .filter_map(|element| match element {
+                SyntaxElement::Token(token) if !token.kind().is_trivia() => Some(token),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// The token containing the char at a 0-based offset, descending through the nodes on the way.
+    pub fn token_at_offset(&self, offset: usize) -> Option<SyntaxToken> {
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) if node.offset <= offset && offset < node.end() => {
+                    return node.token_at_offset(offset);
+                }
+                SyntaxElement::Token(token) if token.offset <= offset && offset < token.end() => return Some(token),
+                _ => {}
+            }
+        }
+
+        None
+    }
+
+    /// An indented listing of the tree with the char range of every node and token.
+    pub fn debug_dump(&self) -> String {
+        let mut dump = String::new();
+        self.write_dump(&mut dump, 0);
+        dump
+    }
+
+    fn write_dump(&self, dump: &mut String, depth: usize) {
+        let _ = writeln!(dump, "{:indent$}{self:?}", "", indent = depth * 2);
+
+        for element in self.children_with_tokens() {
+            match element {
+                SyntaxElement::Node(node) => node.write_dump(dump, depth + 1),
+                SyntaxElement::Token(token) => {
+                    let _ = writeln!(dump, "{:indent$}{token}", "", indent = (depth + 1) * 2);
+                }
+            }
+        }
+    }
+}
+
+impl fmt::Debug for SyntaxNode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{}..{}", self.kind(), self.offset, self.end())
+    }
+}
+
+impl SyntaxToken {
+    pub fn kind(&self) -> SyntaxKind {
+        self.green.kind
+    }
+
+    pub fn green(&self) -> &Rc<GreenToken> {
+        &self.green
+    }
+
+    pub fn text(&self) -> &str {
+        &self.green.text
+    }
+
+    /// The 0-based char offset of the token in the source.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+
+    /// The 0-based char offset right after the token.
+    pub fn end(&self) -> usize {
+        self.offset + self.green.text_len
+    }
+}
+
+impl fmt::Display for SyntaxToken {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:?}@{}..{} {:?}", self.kind(), self.offset, self.end(), self.text())
+    }
+}
+
+#[cfg(test)]
+mod syntax_tests {
+    use std::fs;
+
+    use crate::error::lexer::TokenizerErrorKind;
+    use crate::error::parser::ParserErrorKind;
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::parse::parser::{StatParser, MAX_NESTING};
+    use crate::parse::syntax::SyntaxKind;
+    use crate::runtime::program::{Evaluator, Program};
+    use crate::runtime::sandbox::STACK_SIZE;
+
+    #[test]
+    fn test_tree_keeps_layout() {
+        let source = "# square\nfunc f(x @int) @int {\n    return x ^ 2 # power\n}\n";
+        let tree = StatParser::new(Tokenizer::new(source.into())).parse_syntax().unwrap();
+
+        assert_eq!(tree.debug_dump(), r##"Program@0..58
+  Comment@0..8 "# square"
+  Whitespace@8..9 "\n"
+  FunctionDefinition@9..57
+    Identifier@9..13 "func"
+    Whitespace@13..14 " "
+    Identifier@14..15 "f"
+    ParameterList@15..23
+      Symbol@15..16 "("
+      Parameter@16..22
+        Identifier@16..17 "x"
+        Whitespace@17..18 " "
+        Symbol@18..19 "@"
+        Identifier@19..22 "int"
+      Symbol@22..23 ")"
+    Whitespace@23..24 " "
+    ReturnType@24..28
+      Symbol@24..25 "@"
+      Identifier@25..28 "int"
+    Whitespace@28..29 " "
+    Block@29..57
+      Symbol@29..30 "{"
+      Whitespace@30..35 "\n    "
+      ReturnStatement@35..47
+        Identifier@35..41 "return"
+        Whitespace@41..42 " "
+        BinaryExpression@42..47
+          VariableReference@42..43
+            Identifier@42..43 "x"
+          Whitespace@43..44 " "
+          Symbol@44..45 "^"
+          Whitespace@45..46 " "
+          Literal@46..47
+            Number@46..47 "2"
+      Whitespace@47..48 " "
+      Comment@48..55 "# power"
+      Whitespace@55..56 "\n"
+      Symbol@56..57 "}"
+  Whitespace@57..58 "\n"
+"##);
+    }
+
+    #[test]
+    fn test_grammar_files_are_lossless() {
+        for entry in fs::read_dir("grammar").unwrap() {
+            let path = entry.unwrap().path();
+            let source = fs::read_to_string(&path).unwrap();
+
+            let Ok(tree) = StatParser::new(Tokenizer::new(source.clone())).parse_syntax() else {
+                continue;
+            };
+
+            assert_eq!(tree.text(), source, "the syntax tree of {path:?} lost text");
+            assert_eq!(tree.kind(), SyntaxKind::Program);
+        }
+    }
+
+    #[test]
+    fn test_error_kinds() {
+        let kind = |source: &str| StatParser::new(Tokenizer::new(source.into())).parse().unwrap_err().kind;
+
+        assert_eq!(kind("func f() { set<int> x = 1 }"), ParserErrorKind::UnexpectedToken {
+            expected: "'<-' after the variable name".into(),
+            found: "'='".into(),
+        });
+        assert_eq!(kind("func f() { return 1"), ParserErrorKind::UnexpectedEnd { expected: "'}' to close the block".into() });
+        assert_eq!(kind("func f() { g(a = 1, a = 2) }"), ParserErrorKind::DuplicateArgument("a".into()));
+        assert_eq!(kind("func f() { return \"a }"), ParserErrorKind::Tokenizer(TokenizerErrorKind::UnclosedString));
+        assert_eq!(kind("func f() { try {} finally {} }"), ParserErrorKind::UnexpectedToken {
+            expected: "'catch' after the 'try' block".into(),
+            found: "'finally'".into(),
+        });
+        assert_eq!(kind("x <- 1").code(), "E0201");
+    }
+
+    #[test]
+    fn test_limits_nesting() {
+        let source = |body: String| format!("func main() @int {{ {body} }}");
+        let nested = |open: &str, inner: &str, close: &str, levels: usize| open.repeat(levels) + inner + &close.repeat(levels);
+
+        // in debug builds, the levels the parser allows take more than the stack of a test thread
+        let thread = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(move || {
+            // the block of main and its return statement are the first two levels
+            let deepest = source(format!("return {}", nested("(", "1", ")", MAX_NESTING - 2)));
+            let ast = StatParser::new(Tokenizer::new(deepest)).parse().unwrap();
+            assert_eq!(Program::new(ast.clone()).with_evaluator(Evaluator::TreeWalker).execute().unwrap(), 1);
+            assert_eq!(Program::new(ast).with_evaluator(Evaluator::Bytecode).execute().unwrap(), 1);
+
+            [
+                format!("return {}", nested("(", "1", ")", MAX_NESTING - 1)),
+                format!("return {}", nested("(", "1", ")", 2000)),
+                format!("return {}", nested("[", "1", "]", 2000)),
+                format!("return {}1", "-".repeat(2000)),
+                format!("return {}1", "2 ^ ".repeat(2000)),
+                nested("{ ", "", " }", 2000),
+            ].map(|body| StatParser::new(Tokenizer::new(source(body))).parse().unwrap_err().kind)
+        });
+
+        for kind in thread.unwrap().join().unwrap() {
+            assert_eq!(kind, ParserErrorKind::TooDeeplyNested(MAX_NESTING));
+        }
+    }
+}
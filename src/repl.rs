@@ -0,0 +1,302 @@
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+
+use crate::check::checker::infer_entry;
+use crate::error::lexer::TokenizerErrorKind;
+use crate::lexer::symbols::SymbolType;
+use crate::lexer::tokenizer::{Span, TokenType, Tokenizer};
+use crate::parse::parser::{Node, NodeType, StatParser};
+use crate::runtime::cancellation::CancellationToken;
+use crate::runtime::program::Program;
+use crate::runtime::value::Value;
+
+const PROMPT: &str = ">> ";
+const CONTINUATION_PROMPT: &str = ".. ";
+const HISTORY_FILE: &str = ".stat_script_history";
+
+const HELP: &str = "\
+:type <expr>   show the type of an expression, without running it
+:ast <code>    show the syntax tree of some code
+:load <file>   run a script, keeping its functions and variables
+:reset         forget all functions and variables
+:help          show this help
+:quit          leave the repl";
+
+/// What the repl did with a line of input.
+#[derive(Debug, PartialEq)]
+pub enum LineResult {
+    /// The input was handled.
+    Complete,
+    /// The input is not finished yet, e.g. because a `{` is still open.
+    Incomplete,
+    Quit,
+}
+
+/// An interactive session. Every entry runs in the same top-level frame, so variables and
+/// functions defined by one entry are visible to all later ones.
+pub struct Repl {
+    program: Program,
+    pending: String,
+}
+
+impl Repl {
+    pub fn new(output: Box<dyn Write>) -> Self {
+        Self {
+            program: Program::new(empty_program()).with_output(output),
+            pending: String::new(),
+        }
+    }
+
+    /// Interrupts the entry running when the token is cancelled, e.g. on Ctrl-C. The token is
+    /// reset after each entry, so the session goes on.
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.program.cancellation = cancellation;
+        self
+    }
+
+    /// Runs the interactive loop on the terminal until `:quit` or end of input.
+    pub fn run(&mut self) -> rustyline::Result<()> {
+        let mut editor = DefaultEditor::new()?;
+        let history = history_path();
+
+        if let Some(history) = &history {
+            // there is no history yet on the first start
+            let _ = editor.load_history(history);
+        }
+
+        loop {
+            let prompt = if self.pending.is_empty() { PROMPT } else { CONTINUATION_PROMPT };
+
+            match editor.readline(prompt) {
+                Ok(line) => {
+                    if !line.trim().is_empty() {
+                        editor.add_history_entry(line.as_str())?;
+                    }
+
+                    if self.handle_line(&line) == LineResult::Quit {
+                        break;
+                    }
+                }
+                Err(ReadlineError::Interrupted) => self.pending.clear(),
+                Err(ReadlineError::Eof) => break,
+                Err(e) => return Err(e)
+            }
+        }
+
+        if let Some(history) = &history {
+            editor.save_history(history)?;
+        }
+
+        Ok(())
+    }
+
+    pub fn handle_line(&mut self, line: &str) -> LineResult {
+        if self.pending.is_empty() {
+            if let Some(command) = line.trim().strip_prefix(':') {
+                return self.handle_command(command);
+            }
+        }
+
+        self.pending.push_str(line);
+        self.pending.push('\n');
+
+        if is_incomplete(&self.pending) {
+            return LineResult::Incomplete;
+        }
+
+        let source = std::mem::take(&mut self.pending);
+        self.evaluate_source(&source, true)
+    }
+
+    fn handle_command(&mut self, command: &str) -> LineResult {
+        let (name, argument) = command.split_once(char::is_whitespace).unwrap_or((command, ""));
+        let argument = argument.trim();
+
+        match name {
+            "type" => {
+                if let Some(nodes) = self.parse(argument) {
+                    // inferred without running the code, which must not print or change anything
+                    let variables = self.program.top_level_frame.borrow().sorted_variables();
+                    match infer_entry(&nodes, self.program.functions(), &variables, self.program.host()) {
+                        Ok(entry_type) => self.write_line(entry_type.name()),
+                        Err(errors) => {
+                            for error in errors {
+                                self.write_line(&error.message);
+                            }
+                        }
+                    }
+                }
+            }
+            "ast" => {
+                if let Some(nodes) = self.parse(argument) {
+                    for node in nodes {
+                        self.write_line(&format!("{node:#?}"));
+                    }
+                }
+            }
+            "load" => match fs::read_to_string(argument) {
+                Ok(source) => return self.evaluate_source(&source, false),
+                Err(e) => self.write_line(&format!("Failed to read file \"{argument}\": {e}"))
+            },
+            "reset" => {
+                let output = std::mem::replace(&mut self.program.output, Box::new(std::io::sink()));
+                self.program = Program::new(empty_program())
+                    .with_output(output)
+                    .with_cancellation(self.program.cancellation.clone());
+            }
+            "help" => self.write_line(HELP),
+            "quit" | "q" => return LineResult::Quit,
+            _ => self.write_line(&format!("Unknown command ':{name}', try :help"))
+        }
+
+        LineResult::Complete
+    }
+
+    fn evaluate_source(&mut self, source: &str, print_result: bool) -> LineResult {
+        let Some(nodes) = self.parse(source) else {
+            return LineResult::Complete;
+        };
+
+        let result = self.program.evaluate_entry(&nodes);
+        // a cancellation interrupts only the entry it came during
+        self.program.cancellation.reset();
+
+        let line_result = match result {
+            Ok(Some(value)) if print_result && value != Value::Nothing => {
+                self.write_line(&value.to_string());
+                LineResult::Complete
+            }
+            Ok(_) => LineResult::Complete,
+            // `exit()` ends the session
+            Err(e) if e.exit_code.is_some() => LineResult::Quit,
+            Err(e) => {
+                self.write_line(&format!("Runtime error: {}", e.message));
+                LineResult::Complete
+            }
+        };
+        self.program.output.flush().ok();
+
+        line_result
+    }
+
+    fn parse(&mut self, source: &str) -> Option<Vec<Node>> {
+        let mut parser = StatParser::new(Tokenizer::new(source.to_string()));
+
+        match parser.parse_interactive() {
+            Ok(node) => match node.node_type.as_ref() {
+                NodeType::Program(nodes) => Some(nodes.clone()),
+                _ => Some(vec![node.clone()])
+            },
+            Err(e) => {
+                self.write_line(&format!("Failed to parse at position {}: {}", e.position, e.message));
+                None
+            }
+        }
+    }
+
+    fn write_line(&mut self, text: &str) {
+        // a broken output has nowhere left to report to
+        let _ = writeln!(self.program.output, "{text}");
+    }
+}
+
+fn empty_program() -> Node {
+    Node::new(NodeType::Program(Vec::new()), Span::default())
+}
+
+fn history_path() -> Option<PathBuf> {
+    std::env::var_os("HOME").map(|home| PathBuf::from(home).join(HISTORY_FILE))
+}
+
+/// Whether `source` stops in the middle of a string or with unclosed brackets, in which case the
+/// repl keeps reading lines.
+pub fn is_incomplete(source: &str) -> bool {
+    let mut tokenizer = Tokenizer::new(source.to_string());
+    let mut depth = 0;
+
+    loop {
+        match tokenizer.next_token() {
+            Ok(Some(token)) => match token.token_type {
+                Some(TokenType::Symbol(SymbolType::BraceLeft | SymbolType::ParenthesisLeft | SymbolType::BracketLeft)) => depth += 1,
+                Some(TokenType::Symbol(SymbolType::BraceRight | SymbolType::ParenthesisRight | SymbolType::BracketRight)) => depth -= 1,
+                _ => {}
+            },
+            Ok(None) => return depth > 0,
+            Err(e) => return matches!(e.kind, TokenizerErrorKind::UnclosedString | TokenizerErrorKind::UnclosedCharacter)
+        }
+    }
+}
+
+#[cfg(test)]
+mod repl_tests {
+    use crate::repl::{is_incomplete, LineResult, Repl};
+    use crate::runtime::cancellation::CancellationToken;
+    use crate::test_support::SharedOutput;
+
+    fn run(lines: &[&str]) -> String {
+        let output = SharedOutput::default();
+        let mut repl = Repl::new(Box::new(output.clone()));
+
+        for line in lines {
+            repl.handle_line(line);
+        }
+
+        output.text()
+    }
+
+    #[test]
+    fn test_incomplete_input() {
+        assert!(is_incomplete("func f() @int {"));
+        assert!(is_incomplete("set<string> s <- \"abc"));
+        assert!(!is_incomplete("func f() @int { return 1 }"));
+        assert!(!is_incomplete("1 + 2"));
+    }
+
+    #[test]
+    fn test_state_persists_between_entries() {
+        let output = run(&[
+            "set<int> x <- 20",
+            "func double(value @int) @int {",
+            "    return value * 2",
+            "}",
+            "double(value = x) + 2",
+        ]);
+
+        assert_eq!(output, "42\n");
+    }
+
+    #[test]
+    fn test_interrupts_an_entry() {
+        let output = SharedOutput::default();
+        let cancellation = CancellationToken::new();
+        let mut repl = Repl::new(Box::new(output.clone())).with_cancellation(cancellation.clone());
+
+        cancellation.cancel();
+        repl.handle_line("1 + 1");
+        repl.handle_line("2 + 2");
+        repl.handle_line(":reset");
+        cancellation.cancel();
+        repl.handle_line("3");
+
+        assert_eq!(output.text(), "Runtime error: The script was interrupted.\n4\nRuntime error: The script was interrupted.\n");
+    }
+
+    #[test]
+    fn test_meta_commands() {
+        let output = run(&["set<f64> x <- 1", ":type x", ":type [x, builtin println(template = x)]", ":reset", "x"]);
+
+        assert_eq!(output, "f64\nlist\nRuntime error: Variable 'x' is not defined in this scope.\n");
+
+        let output = run(&["func half(n @int) @f64 { return n / 2 }", ":type half(n = 3) + 1", ":type y"]);
+        assert_eq!(output, "f64\nVariable 'y' is not defined in this scope.\n");
+
+        let mut repl = Repl::new(Box::new(SharedOutput::default()));
+        assert_eq!(repl.handle_line("{"), LineResult::Incomplete);
+        assert_eq!(repl.handle_line("}"), LineResult::Complete);
+        assert_eq!(repl.handle_line(":quit"), LineResult::Quit);
+    }
+}
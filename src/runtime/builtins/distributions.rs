@@ -0,0 +1,535 @@
+//! Probability distributions. Every distribution `d` is exposed as the builtins `d_pdf` (`d_pmf`
+//! for discrete distributions), `d_cdf`, `d_quantile` and `d_sample`, taking the distribution
+//! parameters as named arguments, e.g. `builtin normal_cdf(x = 1.96, mean = 0, sd = 1)`.
+
+use std::f64::consts::PI;
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::runtime::builtins::sampling::sample_size;
+use crate::runtime::builtins::special::{beta_i, gamma_p, gamma_q, ln_beta, ln_gamma, normal_cdf, normal_quantile};
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::random::Random;
+use crate::runtime::value::Value;
+
+const DISTRIBUTIONS: [&str; 10] = [
+    "normal", "t", "chi_squared", "f", "binomial", "poisson", "uniform", "exponential", "gamma", "beta"
+];
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub(crate) enum Distribution {
+    Normal { mean: f64, sd: f64 },
+    StudentT { df: f64 },
+    ChiSquared { df: f64 },
+    F { df1: f64, df2: f64 },
+    Binomial { trials: u64, prob: f64 },
+    Poisson { lambda: f64 },
+    Uniform { min: f64, max: f64 },
+    Exponential { rate: f64 },
+    Gamma { shape: f64, rate: f64 },
+    Beta { alpha: f64, beta: f64 },
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Operation {
+    Density,
+    Cdf,
+    Quantile,
+    Sample,
+}
+
+pub fn is_distribution_function(name: &str) -> bool {
+    split_function_name(name).is_some()
+}
+
+/// The names of all distribution builtins.
+pub fn function_names() -> Vec<String> {
+    DISTRIBUTIONS.iter()
+        .flat_map(|distribution| {
+            let density = if matches!(*distribution, "binomial" | "poisson") { "pmf" } else { "pdf" };
+
+            [density, "cdf", "quantile", "sample"].map(|operation| format!("{distribution}_{operation}"))
+        })
+        .collect()
+}
+
+fn split_function_name(name: &str) -> Option<(&str, Operation)> {
+    let (distribution, operation) = name.rsplit_once('_')?;
+
+    if !DISTRIBUTIONS.contains(&distribution) {
+        return None;
+    }
+
+    let discrete = matches!(distribution, "binomial" | "poisson");
+
+    match operation {
+        "pdf" if !discrete => Some((distribution, Operation::Density)),
+        "pmf" if discrete => Some((distribution, Operation::Density)),
+        "cdf" => Some((distribution, Operation::Cdf)),
+        "quantile" => Some((distribution, Operation::Quantile)),
+        "sample" => Some((distribution, Operation::Sample)),
+        _ => None
+    }
+}
+
+/// Dispatches every `<distribution>_<operation>` builtin.
+pub fn call(program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let function = arguments.function.clone();
+    let (name, operation) = split_function_name(&function)
+        .ok_or_else(|| RuntimeError::unknown_builtin(&function))?;
+
+    let distribution = Distribution::from_arguments(name, arguments)?;
+
+    match operation {
+        Operation::Density => map_numbers(arguments, "x", |x| Ok(Value::Double(distribution.density(x)))),
+        Operation::Cdf => map_numbers(arguments, "x", |x| Ok(Value::Double(distribution.cdf(x)))),
+        Operation::Quantile => {
+            let invalid = arguments.invalid("p", "probabilities must lie between 0 and 1");
+
+            map_numbers(arguments, "p", |p| {
+                if !(0.0..=1.0).contains(&p) {
+                    return Err(invalid.clone());
+                }
+
+                Ok(distribution.to_value(distribution.quantile(p)))
+            })
+        }
+        Operation::Sample => {
+            let Some(count) = sample_size(program, arguments)? else {
+                return Ok(distribution.to_value(distribution.sample(&mut program.random)));
+            };
+
+            Ok(Value::List((0..count)
+                .map(|_| distribution.to_value(distribution.sample(&mut program.random)))
+                .collect()))
+        }
+    }
+}
+
+/// Applies `f` to a number, or to every element of a list of numbers. Missing values stay missing.
+fn map_numbers(
+    arguments: &mut Arguments,
+    parameter: &str,
+    f: impl Fn(f64) -> Result<Value, RuntimeError>,
+) -> EvalReturn {
+    let value = arguments.required(parameter)?;
+    let apply = |value: &Value| match value {
+        Value::NA => Ok(Value::NA),
+        v => match v.as_f64() {
+            Some(n) => f(n),
+            None => Err(arguments.invalid(parameter, &format!("expected a number but got '{}'", v.type_name())))
+        }
+    };
+
+    match value {
+        Value::List(values) => values.iter()
+            .map(apply)
+            .collect::<Result<Vec<Value>, RuntimeError>>()
+            .map(Value::List),
+        v => apply(&v)
+    }
+}
+
+impl Distribution {
+    pub(crate) fn from_arguments(name: &str, arguments: &mut Arguments) -> Result<Self, RuntimeError> {
+        let positive = |arguments: &mut Arguments, parameter: &str, default: Option<f64>| {
+            let value = match default {
+                Some(default) => arguments.number_or(parameter, default)?,
+                None => arguments.number(parameter)?
+            };
+
+            if value.is_nan() || value <= 0.0 {
+                return Err(arguments.invalid(parameter, "must be positive"));
+            }
+
+            Ok(value)
+        };
+
+        let distribution = match name {
+            "normal" => Distribution::Normal {
+                mean: arguments.number_or("mean", 0.0)?,
+                sd: positive(arguments, "sd", Some(1.0))?,
+            },
+            "t" => Distribution::StudentT { df: positive(arguments, "df", None)? },
+            "chi_squared" => Distribution::ChiSquared { df: positive(arguments, "df", None)? },
+            "f" => Distribution::F {
+                df1: positive(arguments, "df1", None)?,
+                df2: positive(arguments, "df2", None)?,
+            },
+            "binomial" => {
+                let trials = match arguments.required("trials")? {
+                    Value::Integer(n) if n >= 0 => n as u64,
+                    v => return Err(arguments.invalid("trials", &format!("expected a non-negative integer but got '{v}'")))
+                };
+                let prob = arguments.number("prob")?;
+
+                if !(0.0..=1.0).contains(&prob) {
+                    return Err(arguments.invalid("prob", "must lie between 0 and 1"));
+                }
+
+                Distribution::Binomial { trials, prob }
+            }
+            "poisson" => Distribution::Poisson { lambda: positive(arguments, "lambda", None)? },
+            "uniform" => {
+                let min = arguments.number_or("min", 0.0)?;
+                let max = arguments.number_or("max", 1.0)?;
+
+                if max <= min {
+                    return Err(arguments.invalid("max", "must be greater than 'min'"));
+                }
+
+                Distribution::Uniform { min, max }
+            }
+            "exponential" => Distribution::Exponential { rate: positive(arguments, "rate", Some(1.0))? },
+            "gamma" => Distribution::Gamma {
+                shape: positive(arguments, "shape", None)?,
+                rate: positive(arguments, "rate", Some(1.0))?,
+            },
+            "beta" => Distribution::Beta {
+                alpha: positive(arguments, "alpha", None)?,
+                beta: positive(arguments, "beta", None)?,
+            },
+            _ => return Err(RuntimeError::of_kind(RuntimeErrorKind::UnknownFunction, format!("Unknown distribution '{name}'.")))
+        };
+
+        Ok(distribution)
+    }
+
+    fn is_discrete(&self) -> bool {
+        matches!(self, Distribution::Binomial { .. } | Distribution::Poisson { .. })
+    }
+
+    fn to_value(self, x: f64) -> Value {
+        if self.is_discrete() && x.is_finite() {
+            return Value::Integer(x as i128);
+        }
+
+        Value::Double(x)
+    }
+
+    fn support(&self) -> (f64, f64) {
+        match *self {
+            Distribution::Normal { .. } | Distribution::StudentT { .. } => (f64::NEG_INFINITY, f64::INFINITY),
+            Distribution::Binomial { trials, .. } => (0.0, trials as f64),
+            Distribution::Uniform { min, max } => (min, max),
+            Distribution::Beta { .. } => (0.0, 1.0),
+            _ => (0.0, f64::INFINITY)
+        }
+    }
+
+    /// Probability density, or probability mass for discrete distributions.
+    pub fn density(&self, x: f64) -> f64 {
+        let (lower, upper) = self.support();
+        if x < lower || x > upper {
+            return 0.0;
+        }
+
+        match *self {
+            Distribution::Normal { mean, sd } => {
+                let z = (x - mean) / sd;
+                (-0.5 * z * z).exp() / (sd * (2.0 * PI).sqrt())
+            }
+            Distribution::StudentT { df } => {
+                (ln_gamma((df + 1.0) / 2.0) - ln_gamma(df / 2.0) - 0.5 * (df * PI).ln()
+                    - (df + 1.0) / 2.0 * (1.0 + x * x / df).ln()).exp()
+            }
+            Distribution::ChiSquared { df } => Distribution::Gamma { shape: df / 2.0, rate: 0.5 }.density(x),
+            Distribution::F { df1, df2 } => {
+                if x == 0.0 {
+                    return match df1 {
+                        d if d < 2.0 => f64::INFINITY,
+                        2.0 => 1.0,
+                        _ => 0.0
+                    };
+                }
+
+                (0.5 * (df1 * (df1 * x).ln() + df2 * df2.ln() - (df1 + df2) * (df1 * x + df2).ln())
+                    - x.ln() - ln_beta(df1 / 2.0, df2 / 2.0)).exp()
+            }
+            Distribution::Binomial { trials, prob } => {
+                if x.fract() != 0.0 {
+                    return 0.0;
+                }
+
+                let n = trials as f64;
+                match prob {
+                    0.0 => if x == 0.0 { 1.0 } else { 0.0 },
+                    1.0 => if x == n { 1.0 } else { 0.0 },
+                    p => (ln_gamma(n + 1.0) - ln_gamma(x + 1.0) - ln_gamma(n - x + 1.0)
+                        + x * p.ln() + (n - x) * (1.0 - p).ln()).exp()
+                }
+            }
+            Distribution::Poisson { lambda } => {
+                if x.fract() != 0.0 {
+                    return 0.0;
+                }
+
+                (x * lambda.ln() - lambda - ln_gamma(x + 1.0)).exp()
+            }
+            Distribution::Uniform { min, max } => 1.0 / (max - min),
+            Distribution::Exponential { rate } => rate * (-rate * x).exp(),
+            Distribution::Gamma { shape, rate } => {
+                if x == 0.0 {
+                    return match shape {
+                        s if s < 1.0 => f64::INFINITY,
+                        1.0 => rate,
+                        _ => 0.0
+                    };
+                }
+
+                (shape * rate.ln() + (shape - 1.0) * x.ln() - rate * x - ln_gamma(shape)).exp()
+            }
+            Distribution::Beta { alpha, beta } => {
+                // an exponent of 0 leaves a factor of 1 at the edges, where the logarithm is infinite
+                let power = |exponent: f64, base: f64| if exponent == 0.0 { 0.0 } else { exponent * base.ln() };
+                (power(alpha - 1.0, x) + power(beta - 1.0, 1.0 - x) - ln_beta(alpha, beta)).exp()
+            }
+        }
+    }
+
+    pub fn cdf(&self, x: f64) -> f64 {
+        let (lower, upper) = self.support();
+        if x < lower {
+            return 0.0;
+        }
+        if x >= upper {
+            return 1.0;
+        }
+
+        match *self {
+            Distribution::Normal { mean, sd } => normal_cdf((x - mean) / sd),
+            Distribution::StudentT { df } => {
+                let tail = 0.5 * beta_i(df / 2.0, 0.5, df / (df + x * x));
+                if x > 0.0 { 1.0 - tail } else { tail }
+            }
+            Distribution::ChiSquared { df } => gamma_p(df / 2.0, x / 2.0),
+            Distribution::F { df1, df2 } => beta_i(df1 / 2.0, df2 / 2.0, df1 * x / (df1 * x + df2)),
+            Distribution::Binomial { trials, prob } => {
+                let k = x.floor();
+                beta_i(trials as f64 - k, k + 1.0, 1.0 - prob)
+            }
+            Distribution::Poisson { lambda } => gamma_q(x.floor() + 1.0, lambda),
+            Distribution::Uniform { min, max } => (x - min) / (max - min),
+            Distribution::Exponential { rate } => -(-rate * x).exp_m1(),
+            Distribution::Gamma { shape, rate } => gamma_p(shape, rate * x),
+            Distribution::Beta { alpha, beta } => beta_i(alpha, beta, x),
+        }
+    }
+
+    /// Inverse of the cumulative distribution function. For discrete distributions this is the
+    /// smallest `k` with `cdf(k) >= p`.
+    pub fn quantile(&self, p: f64) -> f64 {
+        let (lower, upper) = self.support();
+        if p <= 0.0 {
+            return lower;
+        }
+        if p >= 1.0 {
+            return upper;
+        }
+
+        match *self {
+            Distribution::Normal { mean, sd } => mean + sd * normal_quantile(p),
+            Distribution::Uniform { min, max } => min + p * (max - min),
+            Distribution::Exponential { rate } => -(-p).ln_1p() / rate,
+            _ if self.is_discrete() => self.discrete_quantile(p),
+            _ => self.continuous_quantile(p)
+        }
+    }
+
+    /// Inverts the cdf by bracketing the quantile and bisecting down to machine precision.
+    fn continuous_quantile(&self, p: f64) -> f64 {
+        let (lower, upper) = self.support();
+
+        let mut low = if lower.is_finite() { lower } else { -1.0 };
+        while self.cdf(low) > p {
+            low = low * 2.0 - 1.0;
+        }
+
+        let mut high = if upper.is_finite() { upper } else { 1.0 };
+        while self.cdf(high) < p {
+            high = high * 2.0 + 1.0;
+        }
+
+        for _ in 0..2_000 {
+            let middle = low + (high - low) / 2.0;
+            if middle <= low || middle >= high {
+                break;
+            }
+
+            if self.cdf(middle) < p {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        low + (high - low) / 2.0
+    }
+
+    fn discrete_quantile(&self, p: f64) -> f64 {
+        let (_, upper) = self.support();
+
+        let mut high = 1.0;
+        while self.cdf(high) < p && high < upper {
+            high = (high * 2.0).min(upper);
+        }
+
+        // smallest k in [low, high] with cdf(k) >= p
+        let mut low = 0.0;
+        if self.cdf(low) >= p {
+            return low;
+        }
+        while high - low > 1.0 {
+            let middle = ((low + high) / 2.0).floor();
+            if self.cdf(middle) < p {
+                low = middle;
+            } else {
+                high = middle;
+            }
+        }
+
+        high
+    }
+
+    pub fn sample(&self, random: &mut Random) -> f64 {
+        match *self {
+            Distribution::Normal { mean, sd } => mean + sd * standard_normal(random),
+            Distribution::StudentT { df } => {
+                standard_normal(random) / (standard_gamma(random, df / 2.0) * 2.0 / df).sqrt()
+            }
+            Distribution::ChiSquared { df } => 2.0 * standard_gamma(random, df / 2.0),
+            Distribution::F { df1, df2 } => {
+                (standard_gamma(random, df1 / 2.0) / df1) / (standard_gamma(random, df2 / 2.0) / df2)
+            }
+            Distribution::Gamma { shape, rate } => standard_gamma(random, shape) / rate,
+            Distribution::Beta { alpha, beta } => {
+                let x = standard_gamma(random, alpha);
+                x / (x + standard_gamma(random, beta))
+            }
+            // inversion, keeping the uniform draw away from 0 so the quantile stays finite
+            _ => self.quantile(1.0 - random.next_f64())
+        }
+    }
+}
+
+/// Standard normal draw using the Box-Muller transform.
+fn standard_normal(random: &mut Random) -> f64 {
+    let u1 = 1.0 - random.next_f64();
+    let u2 = random.next_f64();
+
+    (-2.0 * u1.ln()).sqrt() * (2.0 * PI * u2).cos()
+}
+
+/// Gamma(shape, 1) draw using the method of Marsaglia and Tsang (2000).
+fn standard_gamma(random: &mut Random, shape: f64) -> f64 {
+    if shape < 1.0 {
+        let u = 1.0 - random.next_f64();
+        return standard_gamma(random, shape + 1.0) * u.powf(1.0 / shape);
+    }
+
+    let d = shape - 1.0 / 3.0;
+    let c = 1.0 / (9.0 * d).sqrt();
+
+    loop {
+        let x = standard_normal(random);
+        let v = (1.0 + c * x).powi(3);
+        if v <= 0.0 {
+            continue;
+        }
+
+        let u = 1.0 - random.next_f64();
+        if u.ln() < 0.5 * x * x + d - d * v + d * v.ln() {
+            return d * v;
+        }
+    }
+}
+
+#[cfg(test)]
+mod distributions_tests {
+    use crate::runtime::builtins::distributions::Distribution;
+    use crate::runtime::random::Random;
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "expected {expected} but got {actual} (tolerance {tolerance})"
+        );
+    }
+
+    #[test]
+    fn test_normal() {
+        let normal = Distribution::Normal { mean: 0.0, sd: 1.0 };
+
+        assert_close(normal.density(0.0), 0.398_942_280_401_432_7, 1e-14);
+        assert_close(normal.cdf(1.96), 0.975_002_104_851_779_6, 1e-12);
+        assert_close(normal.cdf(-3.0), 0.001_349_898_031_630_094_6, 1e-14);
+        assert_close(normal.quantile(0.975), 1.959_963_984_540_054, 1e-12);
+        assert_close(normal.quantile(1e-10), -6.361_340_902_404_056, 1e-9);
+    }
+
+    #[test]
+    fn test_student_t() {
+        let t = Distribution::StudentT { df: 10.0 };
+
+        assert_close(t.density(0.0), 0.389_108_383_966_031_8, 1e-12);
+        assert_close(t.cdf(2.228_138_851_986_274), 0.975, 1e-10);
+        assert_close(t.quantile(0.975), 2.228_138_851_986_274, 1e-9);
+        assert_close(Distribution::StudentT { df: 1.0 }.quantile(0.95), 6.313_751_514_675_043, 1e-8);
+    }
+
+    #[test]
+    fn test_chi_squared_and_f() {
+        let chi_squared = Distribution::ChiSquared { df: 5.0 };
+        assert_close(chi_squared.quantile(0.95), 11.070_497_693_516_351, 1e-8);
+        assert_close(chi_squared.cdf(3.0), 0.300_014_164_121_372_5, 1e-12);
+
+        let f = Distribution::F { df1: 5.0, df2: 10.0 };
+        assert_close(f.quantile(0.95), 3.325_834_530_413_011, 1e-8);
+        assert_close(f.cdf(1.0), 0.534_880_573_462_197, 1e-10);
+    }
+
+    #[test]
+    fn test_discrete() {
+        let binomial = Distribution::Binomial { trials: 10, prob: 0.5 };
+        assert_close(binomial.density(3.0), 0.117_187_5, 1e-14);
+        assert_close(binomial.cdf(3.0), 0.171_875, 1e-14);
+        assert_eq!(binomial.quantile(0.5), 5.0);
+
+        let poisson = Distribution::Poisson { lambda: 3.0 };
+        assert_close(poisson.density(2.0), 0.224_041_807_655_388_3, 1e-14);
+        assert_close(poisson.cdf(2.0), 0.423_190_081_126_843_5, 1e-13);
+        assert_eq!(poisson.quantile(0.95), 6.0);
+    }
+
+    #[test]
+    fn test_gamma_beta_exponential_uniform() {
+        let gamma = Distribution::Gamma { shape: 2.0, rate: 1.0 };
+        assert_close(gamma.cdf(2.0), 0.593_994_150_290_161_9, 1e-13);
+        assert_close(gamma.quantile(0.593_994_150_290_161_9), 2.0, 1e-9);
+
+        let beta = Distribution::Beta { alpha: 2.0, beta: 3.0 };
+        assert_close(beta.cdf(0.5), 0.6875, 1e-13);
+        assert_close(beta.density(0.5), 1.5, 1e-12);
+        assert_close(Distribution::Beta { alpha: 1.0, beta: 2.0 }.density(0.0), 2.0, 1e-12);
+        assert_close(Distribution::Beta { alpha: 3.0, beta: 1.0 }.density(1.0), 3.0, 1e-12);
+        assert_close(Distribution::Beta { alpha: 1.0, beta: 1.0 }.density(1.0), 1.0, 1e-12);
+        assert_eq!(Distribution::Beta { alpha: 2.0, beta: 1.0 }.density(0.0), 0.0);
+        assert_eq!(Distribution::Beta { alpha: 0.5, beta: 1.0 }.density(0.0), f64::INFINITY);
+
+        let exponential = Distribution::Exponential { rate: 2.0 };
+        assert_close(exponential.quantile(0.5), std::f64::consts::LN_2 / 2.0, 1e-15);
+
+        let uniform = Distribution::Uniform { min: 2.0, max: 4.0 };
+        assert_close(uniform.cdf(3.0), 0.5, 1e-15);
+    }
+
+    #[test]
+    fn test_sample_moments() {
+        let mut random = Random::new(42);
+        let gamma = Distribution::Gamma { shape: 3.0, rate: 2.0 };
+
+        let samples: Vec<f64> = (0..20_000).map(|_| gamma.sample(&mut random)).collect();
+        let mean = samples.iter().sum::<f64>() / samples.len() as f64;
+
+        assert_close(mean, 1.5, 0.05);
+    }
+}
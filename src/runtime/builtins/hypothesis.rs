@@ -0,0 +1,705 @@
+//! Hypothesis tests. Every test returns a record with the fields `method`, `statistic`,
+//! `p_value`, `df`, `conf_int` and `alternative`; fields that do not apply to a test are `NA`.
+//! Missing values in the samples are dropped before testing.
+
+use crate::error::runtime::RuntimeError;
+use crate::runtime::builtins::distributions::Distribution;
+use crate::runtime::builtins::stats::{mean_of, variance_of};
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::record::Record;
+use crate::runtime::value::Value;
+
+/// Samples at or above this size use the normal approximation in rank tests instead of the exact
+/// null distribution.
+const EXACT_RANK_LIMIT: usize = 50;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Alternative {
+    TwoSided,
+    Less,
+    Greater,
+}
+
+impl Alternative {
+    fn from_arguments(arguments: &mut Arguments) -> Result<Self, RuntimeError> {
+        match arguments.optional("alternative") {
+            None => Ok(Alternative::TwoSided),
+            Some(Value::String(s)) => match s.as_str() {
+                "two_sided" => Ok(Alternative::TwoSided),
+                "less" => Ok(Alternative::Less),
+                "greater" => Ok(Alternative::Greater),
+                _ => Err(arguments.invalid("alternative", "expected \"two_sided\", \"less\" or \"greater\""))
+            },
+            Some(v) => Err(arguments.invalid("alternative", &format!("expected 'string' but got '{}'", v.type_name())))
+        }
+    }
+
+    fn name(&self) -> &'static str {
+        match self {
+            Alternative::TwoSided => "two_sided",
+            Alternative::Less => "less",
+            Alternative::Greater => "greater",
+        }
+    }
+
+    /// p-value of a statistic with the given cdf, where `upper(x)` is `P(X >= x)`.
+    fn p_value(&self, lower: f64, upper: f64) -> f64 {
+        match self {
+            Alternative::TwoSided => (2.0 * lower.min(upper)).min(1.0),
+            Alternative::Less => lower,
+            Alternative::Greater => upper,
+        }
+    }
+}
+
+fn result(method: &str, statistic: f64, p_value: f64, df: Value, conf_int: Value, alternative: Alternative) -> Record {
+    Record::new(method)
+        .with("method", Value::String(method.to_string()))
+        .with("statistic", Value::Double(statistic))
+        .with("p_value", Value::Double(p_value))
+        .with("df", df)
+        .with("conf_int", conf_int)
+        .with("alternative", Value::String(alternative.name().to_string()))
+}
+
+/// Converts a list argument into a numeric sample, dropping missing values.
+fn to_sample(arguments: &Arguments, parameter: &str, value: Value) -> Result<Vec<f64>, RuntimeError> {
+    let values = match value {
+        Value::List(values) => values,
+        v => return Err(arguments.invalid(parameter, &format!("expected 'list' but got '{}'", v.type_name())))
+    };
+
+    values.iter()
+        .filter(|v| !v.is_na())
+        .map(|v| v.as_f64().ok_or_else(|| arguments.invalid(
+            parameter, &format!("expected numeric elements but got '{}'", v.type_name())
+        )))
+        .collect()
+}
+
+fn sample(arguments: &mut Arguments, parameter: &str) -> Result<Vec<f64>, RuntimeError> {
+    let value = arguments.required(parameter)?;
+    to_sample(arguments, parameter, value)
+}
+
+fn optional_sample(arguments: &mut Arguments, parameter: &str) -> Result<Option<Vec<f64>>, RuntimeError> {
+    match arguments.optional(parameter) {
+        None => Ok(None),
+        Some(value) => to_sample(arguments, parameter, value).map(Some)
+    }
+}
+
+/// The differences `x - y` of paired samples. A pair is dropped when either of its values is
+/// missing.
+fn paired_differences(arguments: &Arguments, x: &[Value], y: &[Value]) -> Result<Vec<f64>, RuntimeError> {
+    if x.len() != y.len() {
+        return Err(arguments.invalid("y", "paired samples must have the same length"));
+    }
+
+    let mut differences = Vec::with_capacity(x.len());
+    for (a, b) in x.iter().zip(y.iter()) {
+        match (a.as_f64(), b.as_f64()) {
+            (Some(a), Some(b)) => differences.push(a - b),
+            _ if a.is_na() || b.is_na() => continue,
+            _ => return Err(arguments.invalid("x", "expected numeric elements"))
+        }
+    }
+
+    Ok(differences)
+}
+
+fn require_size(arguments: &Arguments, parameter: &str, values: &[f64], minimum: usize) -> Result<(), RuntimeError> {
+    if values.len() < minimum {
+        return Err(arguments.invalid(parameter, &format!("needs at least {minimum} non-missing values")));
+    }
+
+    Ok(())
+}
+
+/// `t_test(x, y, mu = 0, paired = false, var_equal = false, alternative = "two_sided",
+/// conf_level = 0.95)`: one sample, paired, Welch or pooled two sample t-test.
+pub fn t_test(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let alternative = Alternative::from_arguments(arguments)?;
+    let mu = arguments.number_or("mu", 0.0)?;
+    let paired = arguments.bool_or("paired", false)?;
+    let var_equal = arguments.bool_or("var_equal", false)?;
+    let conf_level = arguments.number_or("conf_level", 0.95)?;
+
+    if !(0.0..1.0).contains(&conf_level) {
+        return Err(arguments.invalid("conf_level", "must lie between 0 and 1"));
+    }
+
+    let (method, estimate, standard_error, df) = if paired {
+        let x = arguments.list("x")?;
+        let y = arguments.list("y")?;
+        let differences = paired_differences(arguments, &x, &y)?;
+        require_size(arguments, "x", &differences, 2)?;
+
+        let n = differences.len() as f64;
+        ("Paired t-test", mean_of(&differences), (variance_of(&differences) / n).sqrt(), n - 1.0)
+    } else {
+        let x = sample(arguments, "x")?;
+        require_size(arguments, "x", &x, 2)?;
+
+        match optional_sample(arguments, "y")? {
+            None => {
+                let n = x.len() as f64;
+                ("One Sample t-test", mean_of(&x), (variance_of(&x) / n).sqrt(), n - 1.0)
+            }
+            Some(y) => {
+                require_size(arguments, "y", &y, 2)?;
+
+                let (n1, n2) = (x.len() as f64, y.len() as f64);
+                let (v1, v2) = (variance_of(&x), variance_of(&y));
+                let estimate = mean_of(&x) - mean_of(&y);
+
+                if var_equal {
+                    let pooled = ((n1 - 1.0) * v1 + (n2 - 1.0) * v2) / (n1 + n2 - 2.0);
+                    ("Two Sample t-test", estimate, (pooled * (1.0 / n1 + 1.0 / n2)).sqrt(), n1 + n2 - 2.0)
+                } else {
+                    let (s1, s2) = (v1 / n1, v2 / n2);
+                    let df = (s1 + s2).powi(2) / (s1.powi(2) / (n1 - 1.0) + s2.powi(2) / (n2 - 1.0));
+                    ("Welch Two Sample t-test", estimate, (s1 + s2).sqrt(), df)
+                }
+            }
+        }
+    };
+
+    let t = (estimate - mu) / standard_error;
+    let distribution = Distribution::StudentT { df };
+    let p_value = alternative.p_value(distribution.cdf(t), 1.0 - distribution.cdf(t));
+
+    let conf_int = match alternative {
+        Alternative::TwoSided => {
+            let q = distribution.quantile(0.5 + conf_level / 2.0);
+            (estimate - q * standard_error, estimate + q * standard_error)
+        }
+        Alternative::Less => (f64::NEG_INFINITY, estimate + distribution.quantile(conf_level) * standard_error),
+        Alternative::Greater => (estimate - distribution.quantile(conf_level) * standard_error, f64::INFINITY),
+    };
+
+    let conf_int = Value::List(vec![Value::Double(conf_int.0), Value::Double(conf_int.1)]);
+
+    Ok(Value::Record(result(method, t, p_value, Value::Double(df), conf_int, alternative)
+        .with("estimate", Value::Double(estimate))))
+}
+
+/// `chi_squared_test(observed, probabilities, correct = true)`: a goodness of fit test for a list
+/// of counts, or a test of independence for a contingency table given as a list of rows or as a
+/// table. `correct` applies Yates' continuity correction to 2x2 tables.
+pub fn chi_squared_test(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let observed = arguments.required("observed")?;
+    let correct = arguments.bool_or("correct", true)?;
+
+    let rows: Vec<Vec<Value>> = match observed {
+        Value::List(values) if values.iter().all(|v| matches!(v, Value::List(_))) => values.into_iter()
+            .map(|v| match v {
+                Value::List(row) => row,
+                _ => unreachable!()
+            })
+            .collect(),
+        Value::List(values) => return chi_squared_goodness_of_fit(arguments, values),
+        Value::Table(table) => (0..table.row_count())
+            .map(|row| table.columns.iter().map(|c| c.values[row].clone()).collect())
+            .collect(),
+        v => return Err(arguments.invalid("observed", &format!("expected 'list' or 'table' but got '{}'", v.type_name())))
+    };
+
+    let counts: Vec<Vec<f64>> = rows.iter()
+        .map(|row| row.iter()
+            .map(|v| v.as_f64().ok_or_else(|| arguments.invalid("observed", "expected numeric counts")))
+            .collect::<Result<Vec<f64>, RuntimeError>>())
+        .collect::<Result<Vec<Vec<f64>>, RuntimeError>>()?;
+
+    let columns = counts.first().map(|r| r.len()).unwrap_or(0);
+    if counts.len() < 2 || columns < 2 || counts.iter().any(|r| r.len() != columns) {
+        return Err(arguments.invalid("observed", "expected a rectangular table with at least 2 rows and columns"));
+    }
+
+    let row_totals: Vec<f64> = counts.iter().map(|r| r.iter().sum()).collect();
+    let column_totals: Vec<f64> = (0..columns).map(|c| counts.iter().map(|r| r[c]).sum()).collect();
+    let total: f64 = row_totals.iter().sum();
+
+    let yates = correct && counts.len() == 2 && columns == 2;
+    let mut statistic = 0.0;
+    for (i, row) in counts.iter().enumerate() {
+        for (j, observed) in row.iter().enumerate() {
+            let expected = row_totals[i] * column_totals[j] / total;
+            let difference = if yates {
+                ((observed - expected).abs() - 0.5).max(0.0)
+            } else {
+                observed - expected
+            };
+
+            statistic += difference * difference / expected;
+        }
+    }
+
+    let df = ((counts.len() - 1) * (columns - 1)) as f64;
+    let method = if yates {
+        "Pearson's Chi-squared test with Yates' continuity correction"
+    } else {
+        "Pearson's Chi-squared test"
+    };
+
+    Ok(Value::Record(result(
+        method,
+        statistic,
+        1.0 - Distribution::ChiSquared { df }.cdf(statistic),
+        Value::Double(df),
+        Value::NA,
+        Alternative::Greater,
+    )))
+}
+
+fn chi_squared_goodness_of_fit(arguments: &mut Arguments, observed: Vec<Value>) -> EvalReturn {
+    let observed: Vec<f64> = observed.iter()
+        .map(|v| v.as_f64().ok_or_else(|| arguments.invalid("observed", "expected numeric counts")))
+        .collect::<Result<Vec<f64>, RuntimeError>>()?;
+
+    if observed.len() < 2 {
+        return Err(arguments.invalid("observed", "needs at least 2 categories"));
+    }
+
+    let probabilities = match optional_sample(arguments, "probabilities")? {
+        None => vec![1.0 / observed.len() as f64; observed.len()],
+        Some(p) => p
+    };
+
+    if probabilities.len() != observed.len() {
+        return Err(arguments.invalid("probabilities", "must have one entry per category"));
+    }
+
+    if (probabilities.iter().sum::<f64>() - 1.0).abs() > 1e-8 {
+        return Err(arguments.invalid("probabilities", "must sum to 1"));
+    }
+
+    let total: f64 = observed.iter().sum();
+    let statistic: f64 = observed.iter()
+        .zip(probabilities.iter())
+        .map(|(o, p)| (o - total * p).powi(2) / (total * p))
+        .sum();
+    let df = (observed.len() - 1) as f64;
+
+    Ok(Value::Record(result(
+        "Chi-squared test for given probabilities",
+        statistic,
+        1.0 - Distribution::ChiSquared { df }.cdf(statistic),
+        Value::Double(df),
+        Value::NA,
+        Alternative::Greater,
+    )))
+}
+
+/// Ranks with ties replaced by their average rank, plus the sizes of all tie groups.
+fn ranks(values: &[f64]) -> (Vec<f64>, Vec<usize>) {
+    let mut order: Vec<usize> = (0..values.len()).collect();
+    order.sort_by(|a, b| values[*a].total_cmp(&values[*b]));
+
+    let mut ranks = vec![0.0; values.len()];
+    let mut ties = Vec::new();
+    let mut start = 0;
+
+    while start < order.len() {
+        let mut end = start;
+        while end + 1 < order.len() && values[order[end + 1]] == values[order[start]] {
+            end += 1;
+        }
+
+        let rank = (start + end) as f64 / 2.0 + 1.0;
+        for index in order[start..=end].iter() {
+            ranks[*index] = rank;
+        }
+
+        if end > start {
+            ties.push(end - start + 1);
+        }
+        start = end + 1;
+    }
+
+    (ranks, ties)
+}
+
+fn tie_correction(ties: &[usize]) -> f64 {
+    ties.iter().map(|t| (*t as f64).powi(3) - *t as f64).sum()
+}
+
+/// Normal approximation of a rank statistic with continuity correction.
+fn normal_p_value(statistic: f64, mean: f64, sd: f64, alternative: Alternative) -> f64 {
+    let normal = Distribution::Normal { mean: 0.0, sd: 1.0 };
+    let correction = match alternative {
+        // no correction when the statistic is at its mean, where it could point either way
+        Alternative::TwoSided if statistic == mean => 0.0,
+        Alternative::TwoSided => 0.5 * (statistic - mean).signum(),
+        Alternative::Less => -0.5,
+        Alternative::Greater => 0.5,
+    };
+
+    let z = (statistic - mean - correction) / sd;
+    alternative.p_value(normal.cdf(z), 1.0 - normal.cdf(z))
+}
+
+/// Exact null distribution of a rank statistic from the coefficients of its generating
+/// polynomial, returning `(P(S <= s), P(S >= s))`.
+fn exact_p_values(counts: &[f64], statistic: f64) -> (f64, f64) {
+    let total: f64 = counts.iter().sum();
+    let s = statistic.round() as usize;
+
+    let lower: f64 = counts.iter().take(s + 1).sum();
+    let upper: f64 = counts.iter().skip(s).sum();
+
+    (lower / total, upper / total)
+}
+
+/// Frequencies of the Mann-Whitney U statistic for sample sizes `m` and `n`, i.e. the
+/// coefficients of the Gaussian binomial coefficient `[m + n choose m]`.
+fn mann_whitney_counts(m: usize, n: usize) -> Vec<f64> {
+    let mut counts = vec![0.0; m * n + 1];
+    counts[0] = 1.0;
+
+    for i in 1..=m {
+        // multiply by (1 - q^(n + i))
+        for k in (n + i..counts.len()).rev() {
+            counts[k] -= counts[k - n - i];
+        }
+        // divide by (1 - q^i)
+        for k in i..counts.len() {
+            counts[k] += counts[k - i];
+        }
+    }
+
+    counts
+}
+
+/// Frequencies of the Wilcoxon signed rank statistic for `n` observations, the coefficients of
+/// the product of `(1 + q^i)` for `i` in `1..=n`.
+fn signed_rank_counts(n: usize) -> Vec<f64> {
+    let mut counts = vec![0.0; n * (n + 1) / 2 + 1];
+    counts[0] = 1.0;
+
+    for i in 1..=n {
+        for k in (i..counts.len()).rev() {
+            counts[k] += counts[k - i];
+        }
+    }
+
+    counts
+}
+
+/// `mann_whitney_test(x, y, alternative = "two_sided")`: the Wilcoxon rank sum test. Exact for
+/// small samples without ties, normal approximation otherwise.
+pub fn mann_whitney_test(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let alternative = Alternative::from_arguments(arguments)?;
+    let x = sample(arguments, "x")?;
+    let y = sample(arguments, "y")?;
+    require_size(arguments, "x", &x, 1)?;
+    require_size(arguments, "y", &y, 1)?;
+
+    let (m, n) = (x.len(), y.len());
+    let combined: Vec<f64> = x.iter().chain(y.iter()).copied().collect();
+    let (ranks, ties) = ranks(&combined);
+
+    let rank_sum: f64 = ranks.iter().take(m).sum();
+    let w = rank_sum - (m * (m + 1)) as f64 / 2.0;
+
+    let p_value = if m < EXACT_RANK_LIMIT && n < EXACT_RANK_LIMIT && ties.is_empty() {
+        let (lower, upper) = exact_p_values(&mann_whitney_counts(m, n), w);
+        alternative.p_value(lower, upper)
+    } else {
+        let (mf, nf) = (m as f64, n as f64);
+        let total = mf + nf;
+        let sd = (mf * nf / 12.0 * (total + 1.0 - tie_correction(&ties) / (total * (total - 1.0)))).sqrt();
+        normal_p_value(w, mf * nf / 2.0, sd, alternative)
+    };
+
+    Ok(Value::Record(result("Wilcoxon rank sum test", w, p_value, Value::NA, Value::NA, alternative)))
+}
+
+/// `wilcoxon_test(x, y, mu = 0, alternative = "two_sided")`: the Wilcoxon signed rank test of
+/// `x - mu`, or of the paired differences `x - y - mu`. Zero differences are dropped.
+pub fn wilcoxon_test(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let alternative = Alternative::from_arguments(arguments)?;
+    let mu = arguments.number_or("mu", 0.0)?;
+    let differences = match arguments.optional("y") {
+        None => sample(arguments, "x")?,
+        Some(Value::List(y)) => {
+            let x = arguments.list("x")?;
+            paired_differences(arguments, &x, &y)?
+        }
+        Some(v) => return Err(arguments.invalid("y", &format!("expected 'list' but got '{}'", v.type_name())))
+    };
+    let differences: Vec<f64> = differences.into_iter().map(|d| d - mu).collect();
+
+    let had_zeros = differences.contains(&0.0);
+    let differences: Vec<f64> = differences.into_iter().filter(|d| *d != 0.0).collect();
+    require_size(arguments, "x", &differences, 1)?;
+
+    let absolute: Vec<f64> = differences.iter().map(|d| d.abs()).collect();
+    let (ranks, ties) = ranks(&absolute);
+    let v: f64 = ranks.iter().zip(differences.iter()).filter(|(_, d)| **d > 0.0).map(|(r, _)| r).sum();
+
+    let n = differences.len();
+    let p_value = if n < EXACT_RANK_LIMIT && ties.is_empty() && !had_zeros {
+        let (lower, upper) = exact_p_values(&signed_rank_counts(n), v);
+        alternative.p_value(lower, upper)
+    } else {
+        let nf = n as f64;
+        let sd = (nf * (nf + 1.0) * (2.0 * nf + 1.0) / 24.0 - tie_correction(&ties) / 48.0).sqrt();
+        normal_p_value(v, nf * (nf + 1.0) / 4.0, sd, alternative)
+    };
+
+    Ok(Value::Record(result("Wilcoxon signed rank test", v, p_value, Value::NA, Value::NA, alternative)))
+}
+
+/// Asymptotic distribution of the Kolmogorov statistic, `P(K > lambda)`.
+fn kolmogorov_upper_tail(lambda: f64) -> f64 {
+    if lambda < 0.2 {
+        return 1.0;
+    }
+
+    let mut sum = 0.0;
+    for k in 1..=100 {
+        let k = k as f64;
+        let term = 2.0 * (-1.0f64).powf(k - 1.0) * (-2.0 * k * k * lambda * lambda).exp();
+        sum += term;
+
+        if term.abs() < 1e-16 {
+            break;
+        }
+    }
+
+    sum.clamp(0.0, 1.0)
+}
+
+/// `ks_test(x, y)` or `ks_test(x, distribution, ...)`: two sided Kolmogorov-Smirnov test of two
+/// samples, or of one sample against a distribution given by name and parameters, e.g.
+/// `builtin ks_test(x = xs, distribution = "normal", mean = 0, sd = 1)`. The p-value uses the
+/// asymptotic Kolmogorov distribution with Stephens' small sample correction.
+pub fn ks_test(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let mut x = sample(arguments, "x")?;
+    require_size(arguments, "x", &x, 1)?;
+    x.sort_by(|a, b| a.total_cmp(b));
+
+    let (method, statistic, effective_n) = match optional_sample(arguments, "y")? {
+        Some(mut y) => {
+            require_size(arguments, "y", &y, 1)?;
+            y.sort_by(|a, b| a.total_cmp(b));
+
+            let (m, n) = (x.len() as f64, y.len() as f64);
+            let (mut i, mut j, mut d) = (0, 0, 0.0f64);
+
+            while i < x.len() && j < y.len() {
+                let value = x[i].min(y[j]);
+                while i < x.len() && x[i] == value {
+                    i += 1;
+                }
+                while j < y.len() && y[j] == value {
+                    j += 1;
+                }
+
+                d = d.max((i as f64 / m - j as f64 / n).abs());
+            }
+
+            ("Two-sample Kolmogorov-Smirnov test", d, m * n / (m + n))
+        }
+        None => {
+            let name = arguments.string("distribution")?;
+            let distribution = Distribution::from_arguments(&name, arguments)?;
+            let n = x.len() as f64;
+
+            let d = x.iter()
+                .enumerate()
+                .map(|(i, v)| {
+                    let f = distribution.cdf(*v);
+                    ((i + 1) as f64 / n - f).max(f - i as f64 / n)
+                })
+                .fold(0.0, f64::max);
+
+            ("One-sample Kolmogorov-Smirnov test", d, n)
+        }
+    };
+
+    let root = effective_n.sqrt();
+    let p_value = kolmogorov_upper_tail((root + 0.12 + 0.11 / root) * statistic);
+
+    Ok(Value::Record(result(method, statistic, p_value, Value::NA, Value::NA, Alternative::TwoSided)))
+}
+
+/// `anova(groups)`: one-way analysis of variance over a list of samples.
+pub fn anova(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let groups = arguments.list("groups")?;
+
+    let groups: Vec<Vec<f64>> = groups.into_iter()
+        .map(|group| to_sample(arguments, "groups", group))
+        .collect::<Result<Vec<Vec<f64>>, RuntimeError>>()?;
+
+    if groups.len() < 2 || groups.iter().any(|g| g.is_empty()) {
+        return Err(arguments.invalid("groups", "needs at least 2 non-empty groups"));
+    }
+
+    let total = groups.iter().map(|g| g.len()).sum::<usize>() as f64;
+    let grand_mean = groups.iter().flatten().sum::<f64>() / total;
+
+    let between: f64 = groups.iter().map(|g| g.len() as f64 * (mean_of(g) - grand_mean).powi(2)).sum();
+    let within: f64 = groups.iter()
+        .map(|g| {
+            let mean = mean_of(g);
+            g.iter().map(|v| (v - mean).powi(2)).sum::<f64>()
+        })
+        .sum();
+
+    let df_between = groups.len() as f64 - 1.0;
+    let df_within = total - groups.len() as f64;
+
+    if df_within <= 0.0 {
+        return Err(arguments.invalid("groups", "needs more observations than groups"));
+    }
+
+    let f = (between / df_between) / (within / df_within);
+    let p_value = 1.0 - Distribution::F { df1: df_between, df2: df_within }.cdf(f);
+
+    let df = Value::List(vec![Value::Double(df_between), Value::Double(df_within)]);
+
+    Ok(Value::Record(result("One-way analysis of variance", f, p_value, df, Value::NA, Alternative::Greater)
+        .with("sum_sq_between", Value::Double(between))
+        .with("sum_sq_within", Value::Double(within))))
+}
+
+#[cfg(test)]
+mod hypothesis_tests {
+    use crate::lexer::tokenizer::Span;
+    use crate::parse::parser::{Node, NodeType};
+    use crate::runtime::builtins::hypothesis::{
+        anova, chi_squared_test, ks_test, mann_whitney_counts, mann_whitney_test, ranks, signed_rank_counts, t_test, wilcoxon_test,
+    };
+    use crate::runtime::builtins::Arguments;
+    use crate::runtime::program::{EvalReturn, Program};
+    use crate::runtime::record::Record;
+    use crate::runtime::value::Value;
+
+    /// Student's sleep data, the extra hours of sleep with two drugs.
+    const SLEEP_1: [f64; 10] = [0.7, -1.6, -0.2, -1.2, -0.1, 3.4, 3.7, 0.8, 0.0, 2.0];
+    const SLEEP_2: [f64; 10] = [1.9, 0.8, 1.1, 0.1, -0.1, 4.4, 5.5, 1.6, 4.6, 3.4];
+
+    fn list(values: &[f64]) -> Value {
+        Value::List(values.iter().map(|v| Value::Double(*v)).collect())
+    }
+
+    fn run(test: fn(&mut Program, &mut Arguments) -> EvalReturn, arguments: Vec<(&str, Value)>) -> Record {
+        let mut program = Program::new(Node::new(NodeType::Program(Vec::new()), Span::default()));
+        let values = arguments.into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+
+        match test(&mut program, &mut Arguments::new("test".into(), values)).unwrap() {
+            Value::Record(record) => record,
+            value => panic!("tests must return a record, not {value}"),
+        }
+    }
+
+    /// Asserts the statistic and p-value of a test, as R reports them.
+    fn assert_result(record: &Record, statistic: f64, p_value: f64) {
+        let field = |name: &str| record.field(name).unwrap().as_f64().unwrap();
+        assert!((field("statistic") - statistic).abs() < 1e-6, "statistic {} instead of {statistic}", field("statistic"));
+        assert!((field("p_value") - p_value).abs() < 1e-8, "p-value {} instead of {p_value}", field("p_value"));
+    }
+
+    #[test]
+    fn test_t_tests() {
+        // compared against R's t.test
+        let welch = run(t_test, vec![("x", list(&SLEEP_1)), ("y", list(&SLEEP_2))]);
+        assert_result(&welch, -1.860_813, 0.079_394_14);
+        assert!((welch.field("df").unwrap().as_f64().unwrap() - 17.776_47).abs() < 1e-5);
+
+        let pooled = run(t_test, vec![("x", list(&SLEEP_1)), ("y", list(&SLEEP_2)), ("var_equal", Value::Boolean(true))]);
+        assert_result(&pooled, -1.860_813_467, 0.079_186_714);
+
+        let paired = run(t_test, vec![("x", list(&SLEEP_1)), ("y", list(&SLEEP_2)), ("paired", Value::Boolean(true))]);
+        assert_result(&paired, -4.062_127_683, 0.002_832_890);
+    }
+
+    #[test]
+    fn test_chi_squared_tests() {
+        // chisq.test(c(20, 15, 25))
+        let counts = Value::List([20, 15, 25].map(Value::Integer).to_vec());
+        assert_result(&run(chi_squared_test, vec![("observed", counts)]), 2.5, 0.286_504_797);
+
+        // the example of R's chisq.test, votes by gender and party
+        let votes = Value::List(vec![list(&[762.0, 327.0, 468.0]), list(&[484.0, 239.0, 477.0])]);
+        assert_result(&run(chi_squared_test, vec![("observed", votes)]), 30.070_149_096, 2.953_589_183e-7);
+
+        let two_by_two = Value::List(vec![list(&[12.0, 5.0]), list(&[7.0, 9.0])]);
+        assert_result(&run(chi_squared_test, vec![("observed", two_by_two)]), 1.455_996_379, 0.227_568_215);
+    }
+
+    #[test]
+    fn test_anova() {
+        // R's PlantGrowth, summary(aov(weight ~ group))
+        let groups = Value::List(vec![
+            list(&[4.17, 5.58, 5.18, 6.11, 4.50, 4.61, 5.17, 4.53, 5.33, 5.14]),
+            list(&[4.81, 4.17, 4.41, 3.59, 5.87, 3.83, 6.03, 4.89, 4.32, 4.69]),
+            list(&[6.31, 5.12, 5.54, 5.50, 5.37, 5.29, 4.92, 6.15, 5.80, 5.26]),
+        ]);
+
+        let record = run(anova, vec![("groups", groups)]);
+        assert_result(&record, 4.846_087_862, 0.015_909_958);
+        assert!((record.field("sum_sq_between").unwrap().as_f64().unwrap() - 3.766_34).abs() < 1e-9);
+        assert!((record.field("sum_sq_within").unwrap().as_f64().unwrap() - 10.492_09).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_rank_tests() {
+        // the examples of R's wilcox.test
+        let x = list(&[0.80, 0.83, 1.89, 1.04, 1.45, 1.38, 1.91, 1.64, 0.73, 1.46]);
+        let y = list(&[1.15, 0.88, 0.90, 0.74, 1.21]);
+        let greater = Value::String("greater".into());
+        assert_result(&run(mann_whitney_test, vec![("x", x.clone()), ("y", y.clone())]), 35.0, 0.254_412_254);
+        assert_result(&run(mann_whitney_test, vec![("x", x), ("y", y), ("alternative", greater.clone())]), 35.0, 0.127_206_127);
+
+        let before = [1.83, 0.50, 1.62, 2.48, 1.68, 1.88, 1.55, 3.06, 1.30];
+        #[allow(clippy::approx_constant)] // a measurement, not pi
+        let after = [0.878, 0.647, 0.598, 2.05, 1.06, 1.29, 1.06, 3.14, 1.29];
+        assert_result(&run(wilcoxon_test, vec![("x", list(&before)), ("y", list(&after)), ("alternative", greater.clone())]), 40.0, 0.019_531_25);
+
+        // a missing value drops its pair, not the values after it
+        let inserted = |values: &[f64], value: Value| match list(values) {
+            Value::List(mut values) => {
+                values.insert(3, value);
+                Value::List(values)
+            }
+            _ => unreachable!(),
+        };
+        let (x, y) = (inserted(&before, Value::NA), inserted(&after, Value::Double(100.0)));
+        assert_result(&run(wilcoxon_test, vec![("x", x), ("y", y), ("alternative", greater)]), 40.0, 0.019_531_25);
+
+        // at its mean a statistic is not corrected for continuity, wilcox.test(c(1, -1, 2, -2))
+        assert_result(&run(wilcoxon_test, vec![("x", list(&[1.0, -1.0, 2.0, -2.0]))]), 5.0, 1.0);
+    }
+
+    #[test]
+    fn test_ks_tests() {
+        // R's ks.test gives the same statistics; the p-values are the asymptotic ones
+        let x = list(&[0.80, 0.83, 1.89, 1.04, 1.45, 1.38, 1.91, 1.64, 0.73, 1.46]);
+        let y = list(&[1.15, 0.88, 0.90, 0.74, 1.21]);
+        assert_result(&run(ks_test, vec![("x", x), ("y", y)]), 0.6, 0.110_327_542);
+
+        let x = list(&[-1.2, -0.5, 0.1, 0.4, 0.9, 1.6, 2.3]);
+        let normal = vec![("x", x), ("distribution", Value::String("normal".into())), ("mean", Value::Integer(0)), ("sd", Value::Integer(1))];
+        assert_result(&run(ks_test, normal), 0.254_113_552, 0.688_861_680);
+    }
+
+    #[test]
+    fn test_ranks_average_ties() {
+        let (ranks, ties) = ranks(&[3.0, 1.0, 3.0, 2.0]);
+
+        assert_eq!(ranks, vec![3.5, 1.0, 3.5, 2.0]);
+        assert_eq!(ties, vec![2]);
+    }
+
+    #[test]
+    fn test_exact_rank_distributions() {
+        // U for samples of 2 and 2: arrangements 1, 1, 2, 1, 1
+        assert_eq!(mann_whitney_counts(2, 2), vec![1.0, 1.0, 2.0, 1.0, 1.0]);
+        // signed rank sums for 3 observations: 0, 1, 2, 3, 3, 4, 5, 6
+        assert_eq!(signed_rank_counts(3), vec![1.0, 1.0, 1.0, 2.0, 1.0, 1.0, 1.0]);
+    }
+}
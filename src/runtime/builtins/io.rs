@@ -0,0 +1,139 @@
+use std::fs;
+use std::io::Write;
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::table::{Column, Table};
+use crate::runtime::value::Value;
+
+pub fn println(program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let template = arguments.required("template")?;
+
+    writeln!(program.output, "{template}")
+        .map_err(|e| RuntimeError::of_kind(RuntimeErrorKind::Io, format!("Failed to write output: {e}")))?;
+
+    Ok(Value::Nothing)
+}
+
+/// `read_csv(path, separator = ",")`: reads a CSV file with a header row into a table. Empty
+/// cells and cells containing `NA` become missing values.
+pub fn read_csv(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let path = arguments.string("path")?;
+    let separator = match arguments.optional("separator") {
+        None => ',',
+        Some(Value::Character(c)) => c,
+        Some(Value::String(s)) if s.chars().count() == 1 => s.chars().next().unwrap(),
+        Some(v) => return Err(arguments.invalid("separator", &format!("expected a single character but got '{v}'")))
+    };
+
+    let content = fs::read_to_string(&path)
+        .map_err(|e| RuntimeError::of_kind(RuntimeErrorKind::Io, format!("Failed to read file \"{path}\": {e}")))?;
+
+    parse_csv(&content, separator).map(Value::Table)
+}
+
+/// `column(table, name)`: the values of a table column as a list.
+pub fn column(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let table = match arguments.required("table")? {
+        Value::Table(table) => table,
+        v => return Err(arguments.invalid("table", &format!("expected 'table' but got '{}'", v.type_name())))
+    };
+    let name = arguments.string("name")?;
+
+    match table.column(&name) {
+        Some(column) => Ok(Value::List(column.values.clone())),
+        None => Err(arguments.invalid("name", &format!("the table has no column '{name}'")))
+    }
+}
+
+pub(crate) fn parse_csv(content: &str, separator: char) -> Result<Table, RuntimeError> {
+    let mut lines = content.lines().filter(|l| !l.trim().is_empty());
+
+    let header = match lines.next() {
+        Some(header) => split_record(header, separator),
+        None => return Ok(Table::default())
+    };
+
+    let mut columns: Vec<Column> = header.into_iter()
+        .map(|name| Column { name, values: Vec::new() })
+        .collect();
+
+    for (i, line) in lines.enumerate() {
+        let record = split_record(line, separator);
+
+        if record.len() != columns.len() {
+            return Err(RuntimeError::of_kind(RuntimeErrorKind::Io, format!(
+                "CSV row {} has {} cells but the header has {} columns.", i + 2, record.len(), columns.len()
+            )));
+        }
+
+        for (column, cell) in columns.iter_mut().zip(record) {
+            column.values.push(parse_cell(cell));
+        }
+    }
+
+    Ok(Table { columns })
+}
+
+fn split_record(line: &str, separator: char) -> Vec<String> {
+    let mut cells = Vec::new();
+    let mut cell = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                chars.next();
+                cell.push('"');
+            }
+            '"' => quoted = !quoted,
+            c if c == separator && !quoted => cells.push(std::mem::take(&mut cell)),
+            c => cell.push(c)
+        }
+    }
+
+    cells.push(cell);
+    cells
+}
+
+fn parse_cell(cell: String) -> Value {
+    let trimmed = cell.trim();
+
+    if trimmed.is_empty() || trimmed == "NA" {
+        return Value::NA;
+    }
+
+    if let Ok(i) = trimmed.parse::<i128>() {
+        return Value::Integer(i);
+    }
+
+    if let Ok(d) = trimmed.parse::<f64>() {
+        return Value::Double(d);
+    }
+
+    match trimmed {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => Value::String(cell)
+    }
+}
+
+#[cfg(test)]
+mod io_tests {
+    use crate::runtime::builtins::io::parse_csv;
+    use crate::runtime::value::Value;
+
+    #[test]
+    fn test_empty_cells_are_na() {
+        let table = parse_csv("a,b,c\n1,,x\n,2.5,\"y, z\"\n", ',').unwrap();
+
+        assert_eq!(table.row_count(), 2);
+        assert_eq!(table.column("a").unwrap().values, vec![Value::Integer(1), Value::NA]);
+        assert_eq!(table.column("b").unwrap().values, vec![Value::NA, Value::Double(2.5)]);
+        assert_eq!(
+            table.column("c").unwrap().values,
+            vec![Value::String("x".into()), Value::String("y, z".into())]
+        );
+    }
+}
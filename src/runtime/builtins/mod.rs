@@ -0,0 +1,209 @@
+use std::collections::HashMap;
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::runtime::native::NativeSignature;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::value::Value;
+
+pub(crate) mod distributions;
+mod hypothesis;
+mod io;
+mod na;
+mod regression;
+mod sampling;
+pub(crate) mod special;
+pub(crate) mod stats;
+mod system;
+
+pub type BuiltinFunction = fn(&mut Program, &mut Arguments) -> EvalReturn;
+
+const BUILTINS: [(&str, BuiltinFunction); 31] = [
+    ("println", io::println),
+    ("read_csv", io::read_csv),
+    ("column", io::column),
+    ("is_na", na::is_na),
+    ("fill_na", na::fill_na),
+    ("drop_na", na::drop_na),
+    ("sum", stats::sum),
+    ("mean", stats::mean),
+    ("median", stats::median),
+    ("variance", stats::variance),
+    ("sd", stats::sd),
+    ("min", stats::min),
+    ("max", stats::max),
+    ("args", system::args),
+    ("env", system::env),
+    ("exit", system::exit),
+    ("error", system::error),
+    ("set_seed", sampling::set_seed),
+    ("random", sampling::random),
+    ("random_int", sampling::random_int),
+    ("shuffle", sampling::shuffle),
+    ("sample", sampling::sample),
+    ("t_test", hypothesis::t_test),
+    ("chi_squared_test", hypothesis::chi_squared_test),
+    ("mann_whitney_test", hypothesis::mann_whitney_test),
+    ("wilcoxon_test", hypothesis::wilcoxon_test),
+    ("ks_test", hypothesis::ks_test),
+    ("anova", hypothesis::anova),
+    ("linear_regression", regression::linear_regression),
+    ("logistic_regression", regression::logistic_regression),
+    ("predict", regression::predict),
+];
+
+/// The builtins reading files, the environment or the process, which a sandbox can deny.
+const SYSTEM_BUILTINS: [&str; 4] = ["read_csv", "args", "env", "exit"];
+
+pub fn is_system_builtin(name: &str) -> bool {
+    SYSTEM_BUILTINS.contains(&name)
+}
+
+pub fn find_builtin(name: &str) -> Option<BuiltinFunction> {
+    if let Some((_, function)) = BUILTINS.iter().find(|(builtin, _)| *builtin == name) {
+        return Some(*function);
+    }
+
+    if distributions::is_distribution_function(name) {
+        return Some(distributions::call);
+    }
+
+    None
+}
+
+/// The names of all builtins, e.g. for completion in editors.
+pub fn builtin_names() -> Vec<String> {
+    let mut names: Vec<String> = BUILTINS.iter().map(|(name, _)| name.to_string()).collect();
+    names.extend(distributions::function_names());
+
+    names
+}
+
+/// The evaluated, named arguments of a builtin call.
+pub struct Arguments {
+    pub function: String,
+    values: HashMap<String, Value>,
+}
+
+impl Arguments {
+    pub fn new(function: String, values: HashMap<String, Value>) -> Self {
+        Self {
+            function,
+            values
+        }
+    }
+
+    pub fn required(&mut self, name: &str) -> Result<Value, RuntimeError> {
+        self.values.remove(name).ok_or_else(|| self.missing(name))
+    }
+
+    fn missing(&self, name: &str) -> RuntimeError {
+        RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Builtin '{}' is missing the required argument '{name}'.", self.function))
+    }
+
+    pub fn optional(&mut self, name: &str) -> Option<Value> {
+        self.values.remove(name)
+    }
+
+    pub fn bool_or(&mut self, name: &str, default: bool) -> Result<bool, RuntimeError> {
+        match self.optional(name) {
+            None => Ok(default),
+            Some(Value::Boolean(b)) => Ok(b),
+            Some(v) => Err(self.invalid(name, &format!("expected 'bool' but got '{}'", v.type_name())))
+        }
+    }
+
+    pub fn string(&mut self, name: &str) -> Result<String, RuntimeError> {
+        match self.required(name)? {
+            Value::String(s) => Ok(s),
+            v => Err(self.invalid(name, &format!("expected 'string' but got '{}'", v.type_name())))
+        }
+    }
+
+    pub fn number(&mut self, name: &str) -> Result<f64, RuntimeError> {
+        let value = self.required(name)?;
+
+        value.as_f64().ok_or_else(|| self.invalid(name, &format!("expected a number but got '{}'", value.type_name())))
+    }
+
+    pub fn number_or(&mut self, name: &str, default: f64) -> Result<f64, RuntimeError> {
+        if !self.values.contains_key(name) {
+            return Ok(default);
+        }
+
+        self.number(name)
+    }
+
+    pub fn list(&mut self, name: &str) -> Result<Vec<Value>, RuntimeError> {
+        match self.required(name)? {
+            Value::List(values) => Ok(values),
+            v => Err(self.invalid(name, &format!("expected 'list' but got '{}'", v.type_name())))
+        }
+    }
+
+    /// Takes the arguments not read yet, sorted by name, e.g. those of a variadic native function.
+    pub fn rest(&mut self) -> Vec<(String, Value)> {
+        let mut rest: Vec<(String, Value)> = self.values.drain().collect();
+        rest.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+        rest
+    }
+
+    /// Checks the arguments of a native function against its signature and converts them to the
+    /// declared types.
+    pub fn bind(&mut self, signature: &NativeSignature) -> Result<(), RuntimeError> {
+        if let Some(missing) = signature.parameters.iter().find(|p| !p.optional && !self.values.contains_key(&p.name)) {
+            return Err(self.missing(&missing.name));
+        }
+
+        let mut names: Vec<String> = self.values.keys().cloned().collect();
+        names.sort();
+
+        let mut unknown = Vec::new();
+        for name in names {
+            let param_type = match signature.parameter(&name) {
+                Some(parameter) => &parameter.param_type,
+                None => match &signature.variadic {
+                    Some(param_type) => param_type,
+                    None => {
+                        unknown.push(name);
+                        continue;
+                    }
+                }
+            };
+
+            let value = self.values.remove(&name).expect("the name is a key").coerce(param_type)
+                .map_err(|e| self.invalid(&name, &e.message))?;
+            self.values.insert(name, value);
+        }
+
+        if unknown.is_empty() {
+            return Ok(());
+        }
+
+        Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!(
+            "Builtin '{}' does not take the argument(s) {:?}.", self.function, unknown
+        )))
+    }
+
+    pub fn invalid(&self, parameter: &str, message: &str) -> RuntimeError {
+        RuntimeError::invalid_argument(&self.function, parameter, message)
+    }
+
+    /// The number of values passed, counting the elements of lists, tables and records.
+    pub fn value_count(&self) -> usize {
+        self.values.values().map(Value::count).sum()
+    }
+
+    /// Fails if the call passed arguments the builtin did not consume.
+    pub fn finish(self) -> Result<(), RuntimeError> {
+        let mut unknown: Vec<&String> = self.values.keys().collect();
+
+        if unknown.is_empty() {
+            return Ok(());
+        }
+
+        unknown.sort();
+        Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!(
+            "Builtin '{}' does not take the argument(s) {:?}.", self.function, unknown
+        )))
+    }
+}
@@ -0,0 +1,46 @@
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::table::Column;
+use crate::runtime::value::Value;
+
+/// `is_na(value)`: `true` for a missing value, applied element-wise to lists.
+pub fn is_na(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let value = arguments.required("value")?;
+
+    Ok(match value {
+        Value::List(values) => Value::List(values.iter().map(|v| Value::Boolean(v.is_na())).collect()),
+        v => Value::Boolean(v.is_na())
+    })
+}
+
+/// `fill_na(values, with)`: replaces every missing value in a scalar, list or table.
+pub fn fill_na(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let values = arguments.required("values")?;
+    let with = arguments.required("with")?;
+
+    let fill = |v: &Value| if v.is_na() { with.clone() } else { v.clone() };
+
+    Ok(match values {
+        Value::List(values) => Value::List(values.iter().map(fill).collect()),
+        Value::Table(mut table) => {
+            for column in table.columns.iter_mut() {
+                column.values = column.values.iter().map(fill).collect();
+            }
+            Value::Table(table)
+        }
+        v => fill(&v)
+    })
+}
+
+/// `drop_na(values)`: removes missing elements from a list, or every row of a table that has a
+/// missing cell.
+pub fn drop_na(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    match arguments.required("values")? {
+        Value::List(values) => Ok(Value::List(values.into_iter().filter(|v| !v.is_na()).collect())),
+        Value::Table(table) => {
+            let complete = |row: usize| table.columns.iter().all(|c: &Column| !c.values[row].is_na());
+            Ok(Value::Table(table.filter_rows(complete)))
+        }
+        v => Err(arguments.invalid("values", &format!("expected 'list' or 'table' but got '{}'", v.type_name())))
+    }
+}
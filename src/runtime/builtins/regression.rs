@@ -0,0 +1,512 @@
+//! Regression models fitted on table columns. `linear_regression` and `logistic_regression` take
+//! `data` (a table), `response` (a column name) and `predictors` (a list of column names) and
+//! return a model record; `predict` applies a model to a table. Rows with a missing value in any
+//! of the used columns are dropped before fitting.
+
+use crate::error::runtime::RuntimeError;
+use crate::runtime::builtins::distributions::Distribution;
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::record::Record;
+use crate::runtime::table::{Column, Table};
+use crate::runtime::value::Value;
+
+const INTERCEPT: &str = "(Intercept)";
+const MAX_IRLS_ITERATIONS: usize = 25;
+const IRLS_TOLERANCE: f64 = 1e-8;
+
+type Matrix = Vec<Vec<f64>>;
+
+/// The design matrix (with a leading intercept column) and response of the complete rows.
+struct Design {
+    terms: Vec<String>,
+    x: Matrix,
+    y: Vec<f64>,
+}
+
+impl Design {
+    fn from_arguments(arguments: &mut Arguments) -> Result<(Design, String, Vec<String>), RuntimeError> {
+        let table = table(arguments, "data")?;
+        let response = arguments.string("response")?;
+        let predictors: Vec<String> = arguments.list("predictors")?
+            .into_iter()
+            .map(|p| match p {
+                Value::String(s) => Ok(s),
+                v => Err(arguments.invalid("predictors", &format!("expected column names but got '{}'", v.type_name())))
+            })
+            .collect::<Result<Vec<String>, RuntimeError>>()?;
+
+        let response_column = table_column(arguments, &table, "response", &response)?;
+        let predictor_columns = predictors.iter()
+            .map(|p| table_column(arguments, &table, "predictors", p))
+            .collect::<Result<Vec<&Column>, RuntimeError>>()?;
+
+        let mut x = Vec::new();
+        let mut y = Vec::new();
+
+        'rows: for row in 0..table.row_count() {
+            let mut design_row = vec![1.0];
+
+            for column in predictor_columns.iter() {
+                match numeric_cell(arguments, column, row)? {
+                    Some(value) => design_row.push(value),
+                    None => continue 'rows
+                }
+            }
+
+            match numeric_cell(arguments, response_column, row)? {
+                Some(value) => y.push(value),
+                None => continue 'rows
+            }
+
+            x.push(design_row);
+        }
+
+        let terms = std::iter::once(INTERCEPT.to_string()).chain(predictors.iter().cloned()).collect();
+
+        if x.len() <= predictors.len() + 1 {
+            return Err(arguments.invalid("data", "needs more complete rows than coefficients"));
+        }
+
+        Ok((Design { terms, x, y }, response, predictors))
+    }
+}
+
+fn table(arguments: &mut Arguments, parameter: &str) -> Result<Table, RuntimeError> {
+    match arguments.required(parameter)? {
+        Value::Table(table) => Ok(table),
+        v => Err(arguments.invalid(parameter, &format!("expected 'table' but got '{}'", v.type_name())))
+    }
+}
+
+fn table_column<'t>(arguments: &Arguments, table: &'t Table, parameter: &str, name: &str) -> Result<&'t Column, RuntimeError> {
+    table.column(name).ok_or_else(|| arguments.invalid(parameter, &format!("the table has no column '{name}'")))
+}
+
+/// A numeric table cell, `None` when it is missing. Booleans count as 0 and 1.
+fn numeric_cell(arguments: &Arguments, column: &Column, row: usize) -> Result<Option<f64>, RuntimeError> {
+    match &column.values[row] {
+        Value::NA => Ok(None),
+        Value::Boolean(b) => Ok(Some(if *b { 1.0 } else { 0.0 })),
+        v => v.as_f64().map(Some).ok_or_else(|| arguments.invalid(
+            "data", &format!("column '{}' contains the non-numeric value '{v}'", column.name)
+        ))
+    }
+}
+
+/// `X' W X` for a diagonal weight matrix, or `X' X` without weights.
+fn weighted_cross_product(x: &Matrix, weights: Option<&[f64]>) -> Matrix {
+    let p = x[0].len();
+    let mut result = vec![vec![0.0; p]; p];
+
+    for (i, row) in x.iter().enumerate() {
+        let w = weights.map(|w| w[i]).unwrap_or(1.0);
+        for a in 0..p {
+            for b in 0..p {
+                result[a][b] += w * row[a] * row[b];
+            }
+        }
+    }
+
+    result
+}
+
+/// `X' W v` for a diagonal weight matrix, or `X' v` without weights.
+fn weighted_transpose_product(x: &Matrix, v: &[f64], weights: Option<&[f64]>) -> Vec<f64> {
+    let mut result = vec![0.0; x[0].len()];
+
+    for (i, row) in x.iter().enumerate() {
+        let w = weights.map(|w| w[i]).unwrap_or(1.0);
+        for (a, value) in row.iter().enumerate() {
+            result[a] += w * value * v[i];
+        }
+    }
+
+    result
+}
+
+fn multiply(matrix: &Matrix, v: &[f64]) -> Vec<f64> {
+    matrix.iter().map(|row| row.iter().zip(v.iter()).map(|(a, b)| a * b).sum()).collect()
+}
+
+/// Gauss-Jordan inversion with partial pivoting. Returns `None` for a singular matrix.
+fn invert(matrix: &Matrix) -> Option<Matrix> {
+    let n = matrix.len();
+    let mut left = matrix.clone();
+    let mut right: Matrix = (0..n).map(|i| (0..n).map(|j| if i == j { 1.0 } else { 0.0 }).collect()).collect();
+
+    let scale = matrix.iter().flatten().fold(0.0f64, |m, v| m.max(v.abs())).max(1.0);
+
+    for column in 0..n {
+        let pivot = (column..n).max_by(|a, b| left[*a][column].abs().total_cmp(&left[*b][column].abs()))?;
+        if left[pivot][column].abs() < 1e-12 * scale {
+            return None;
+        }
+
+        left.swap(column, pivot);
+        right.swap(column, pivot);
+
+        let divisor = left[column][column];
+        for j in 0..n {
+            left[column][j] /= divisor;
+            right[column][j] /= divisor;
+        }
+
+        for row in 0..n {
+            if row == column {
+                continue;
+            }
+
+            let factor = left[row][column];
+            for j in 0..n {
+                left[row][j] -= factor * left[column][j];
+                right[row][j] -= factor * right[column][j];
+            }
+        }
+    }
+
+    Some(right)
+}
+
+fn collinear(arguments: &Arguments) -> RuntimeError {
+    arguments.invalid("predictors", "the predictors are collinear")
+}
+
+fn list(values: &[f64]) -> Value {
+    Value::List(values.iter().map(|v| Value::Double(*v)).collect())
+}
+
+fn string_list(values: &[String]) -> Value {
+    Value::List(values.iter().map(|v| Value::String(v.clone())).collect())
+}
+
+fn coefficient_table(terms: &[String], estimates: &[f64], errors: &[f64], statistic: &str, p_value: impl Fn(f64) -> f64) -> Table {
+    let statistics: Vec<f64> = estimates.iter().zip(errors.iter()).map(|(e, s)| e / s).collect();
+
+    Table {
+        columns: vec![
+            Column { name: "term".into(), values: terms.iter().map(|t| Value::String(t.clone())).collect() },
+            Column { name: "estimate".into(), values: estimates.iter().map(|v| Value::Double(*v)).collect() },
+            Column { name: "std_error".into(), values: errors.iter().map(|v| Value::Double(*v)).collect() },
+            Column { name: statistic.into(), values: statistics.iter().map(|v| Value::Double(*v)).collect() },
+            Column { name: "p_value".into(), values: statistics.iter().map(|v| Value::Double(p_value(*v))).collect() },
+        ]
+    }
+}
+
+/// `linear_regression(data, response, predictors)`: ordinary least squares with an intercept.
+pub fn linear_regression(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let (design, response, predictors) = Design::from_arguments(arguments)?;
+
+    let inverse = invert(&weighted_cross_product(&design.x, None)).ok_or_else(|| collinear(arguments))?;
+    let coefficients = multiply(&inverse, &weighted_transpose_product(&design.x, &design.y, None));
+
+    let fitted = multiply(&design.x, &coefficients);
+    let residuals: Vec<f64> = design.y.iter().zip(fitted.iter()).map(|(y, f)| y - f).collect();
+
+    let n = design.y.len() as f64;
+    let p = coefficients.len() as f64;
+    let df = n - p;
+
+    let rss: f64 = residuals.iter().map(|r| r * r).sum();
+    let mean = design.y.iter().sum::<f64>() / n;
+    let tss: f64 = design.y.iter().map(|y| (y - mean).powi(2)).sum();
+    let sigma_squared = rss / df;
+
+    let errors: Vec<f64> = (0..coefficients.len()).map(|i| (sigma_squared * inverse[i][i]).sqrt()).collect();
+    let t = Distribution::StudentT { df };
+    let coefficients_table = coefficient_table(
+        &design.terms, &coefficients, &errors, "t_value", |v| 2.0 * (1.0 - t.cdf(v.abs()))
+    );
+
+    let r_squared = 1.0 - rss / tss;
+    let adj_r_squared = 1.0 - (1.0 - r_squared) * (n - 1.0) / df;
+    let f_statistic = ((tss - rss) / (p - 1.0)) / sigma_squared;
+    let f_p_value = 1.0 - Distribution::F { df1: p - 1.0, df2: df }.cdf(f_statistic);
+
+    Ok(Value::Record(Record::new("Linear regression")
+        .with("model", Value::String("linear".into()))
+        .with("response", Value::String(response))
+        .with("predictors", string_list(&predictors))
+        .with("coefficients", Value::Table(coefficients_table))
+        .with("r_squared", Value::Double(r_squared))
+        .with("adj_r_squared", Value::Double(adj_r_squared))
+        .with("sigma", Value::Double(sigma_squared.sqrt()))
+        .with("f_statistic", Value::Double(f_statistic))
+        .with("f_p_value", Value::Double(f_p_value))
+        .with("df", Value::Double(df))
+        .with("residuals", list(&residuals))
+        .with("fitted", list(&fitted))))
+}
+
+fn sigmoid(eta: f64) -> f64 {
+    1.0 / (1.0 + (-eta).exp())
+}
+
+/// Binomial deviance of fitted probabilities.
+fn deviance(y: &[f64], mu: &[f64]) -> f64 {
+    let term = |y: f64, mu: f64| if y == 0.0 { 0.0 } else { y * (y / mu).ln() };
+
+    2.0 * y.iter().zip(mu.iter()).map(|(y, mu)| term(*y, *mu) + term(1.0 - y, 1.0 - mu)).sum::<f64>()
+}
+
+/// `logistic_regression(data, response, predictors)`: logistic regression of a 0/1 response,
+/// fitted with iteratively reweighted least squares.
+pub fn logistic_regression(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let (design, response, predictors) = Design::from_arguments(arguments)?;
+
+    if design.y.iter().any(|y| *y != 0.0 && *y != 1.0) {
+        return Err(arguments.invalid("response", "logistic regression needs a response of 0 and 1"));
+    }
+
+    let mut coefficients = vec![0.0; design.terms.len()];
+    let mut mu = vec![0.5; design.y.len()];
+    let mut current_deviance = deviance(&design.y, &mu);
+    let mut iterations = 0;
+    let mut converged = false;
+
+    while iterations < MAX_IRLS_ITERATIONS {
+        iterations += 1;
+
+        let eta = multiply(&design.x, &coefficients);
+        let weights: Vec<f64> = mu.iter().map(|m| (m * (1.0 - m)).max(1e-10)).collect();
+        let working_response: Vec<f64> = (0..design.y.len())
+            .map(|i| eta[i] + (design.y[i] - mu[i]) / weights[i])
+            .collect();
+
+        let inverse = invert(&weighted_cross_product(&design.x, Some(&weights))).ok_or_else(|| collinear(arguments))?;
+        coefficients = multiply(&inverse, &weighted_transpose_product(&design.x, &working_response, Some(&weights)));
+        mu = multiply(&design.x, &coefficients).into_iter().map(sigmoid).collect();
+
+        let next_deviance = deviance(&design.y, &mu);
+        let change = (next_deviance - current_deviance).abs() / (next_deviance.abs() + 0.1);
+        current_deviance = next_deviance;
+
+        if change < IRLS_TOLERANCE {
+            converged = true;
+            break;
+        }
+    }
+
+    let weights: Vec<f64> = mu.iter().map(|m| (m * (1.0 - m)).max(1e-10)).collect();
+    let inverse = invert(&weighted_cross_product(&design.x, Some(&weights))).ok_or_else(|| collinear(arguments))?;
+    let errors: Vec<f64> = (0..coefficients.len()).map(|i| inverse[i][i].sqrt()).collect();
+
+    let normal = Distribution::Normal { mean: 0.0, sd: 1.0 };
+    let coefficients_table = coefficient_table(
+        &design.terms, &coefficients, &errors, "z_value", |v| 2.0 * (1.0 - normal.cdf(v.abs()))
+    );
+
+    let mean = design.y.iter().sum::<f64>() / design.y.len() as f64;
+    let null_deviance = deviance(&design.y, &vec![mean; design.y.len()]);
+    let residuals: Vec<f64> = design.y.iter().zip(mu.iter()).map(|(y, m)| y - m).collect();
+
+    Ok(Value::Record(Record::new("Logistic regression")
+        .with("model", Value::String("logistic".into()))
+        .with("response", Value::String(response))
+        .with("predictors", string_list(&predictors))
+        .with("coefficients", Value::Table(coefficients_table))
+        .with("deviance", Value::Double(current_deviance))
+        .with("null_deviance", Value::Double(null_deviance))
+        .with("aic", Value::Double(current_deviance + 2.0 * coefficients.len() as f64))
+        .with("iterations", Value::Integer(iterations as i128))
+        .with("converged", Value::Boolean(converged))
+        .with("residuals", list(&residuals))
+        .with("fitted", list(&mu))))
+}
+
+/// `predict(model, data)`: predictions of a regression model for every row of a table; the
+/// probability of a 1 for logistic models. Rows with a missing predictor predict `NA`.
+pub fn predict(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let model = match arguments.required("model")? {
+        Value::Record(record) if record.field("coefficients").is_some() => record,
+        v => return Err(arguments.invalid("model", &format!("expected a regression model but got '{}'", v.type_name())))
+    };
+    let data = table(arguments, "data")?;
+
+    let logistic = model.field("model") == Some(&Value::String("logistic".into()));
+    let coefficients = match model.field("coefficients") {
+        Some(Value::Table(coefficients)) => coefficients,
+        _ => return Err(arguments.invalid("model", "the model has no coefficient table"))
+    };
+
+    let terms = coefficients.column("term").map(|c| &c.values);
+    let estimates = coefficients.column("estimate").map(|c| &c.values);
+    let (terms, estimates) = match (terms, estimates) {
+        (Some(terms), Some(estimates)) => (terms, estimates),
+        _ => return Err(arguments.invalid("model", "the coefficient table needs 'term' and 'estimate' columns"))
+    };
+
+    let mut predictions = Vec::with_capacity(data.row_count());
+    'rows: for row in 0..data.row_count() {
+        let mut eta = 0.0;
+
+        for (term, estimate) in terms.iter().zip(estimates.iter()) {
+            let estimate = estimate.as_f64().unwrap_or(f64::NAN);
+
+            let value = match term {
+                Value::String(t) if t == INTERCEPT => 1.0,
+                Value::String(t) => {
+                    let column = table_column(arguments, &data, "data", t)?;
+                    match numeric_cell(arguments, column, row)? {
+                        Some(value) => value,
+                        None => {
+                            predictions.push(Value::NA);
+                            continue 'rows;
+                        }
+                    }
+                }
+                _ => return Err(arguments.invalid("model", "coefficient terms must be column names"))
+            };
+
+            eta += estimate * value;
+        }
+
+        predictions.push(Value::Double(if logistic { sigmoid(eta) } else { eta }));
+    }
+
+    Ok(Value::List(predictions))
+}
+
+#[cfg(test)]
+mod regression_tests {
+    use crate::lexer::tokenizer::Span;
+    use crate::parse::parser::{Node, NodeType};
+    use crate::runtime::builtins::regression::{invert, linear_regression, logistic_regression, predict};
+    use crate::runtime::builtins::Arguments;
+    use crate::runtime::program::{EvalReturn, Program};
+    use crate::runtime::record::Record;
+    use crate::runtime::table::{Column, Table};
+    use crate::runtime::value::Value;
+
+    /// R's mtcars: miles per gallon, weight in 1000 lbs, horsepower and manual transmission.
+    const MPG: [f64; 32] = [
+        21.0, 21.0, 22.8, 21.4, 18.7, 18.1, 14.3, 24.4, 22.8, 19.2, 17.8, 16.4, 17.3, 15.2, 10.4, 10.4,
+        14.7, 32.4, 30.4, 33.9, 21.5, 15.5, 15.2, 13.3, 19.2, 27.3, 26.0, 30.4, 15.8, 19.7, 15.0, 21.4,
+    ];
+    const WT: [f64; 32] = [
+        2.620, 2.875, 2.320, 3.215, 3.440, 3.460, 3.570, 3.190, 3.150, 3.440, 3.440, 4.070, 3.730, 3.780, 5.250, 5.424,
+        5.345, 2.200, 1.615, 1.835, 2.465, 3.520, 3.435, 3.840, 3.845, 1.935, 2.140, 1.513, 3.170, 2.770, 3.570, 2.780,
+    ];
+    const HP: [f64; 32] = [
+        110.0, 110.0, 93.0, 110.0, 175.0, 105.0, 245.0, 62.0, 95.0, 123.0, 123.0, 180.0, 180.0, 180.0, 205.0, 215.0,
+        230.0, 66.0, 52.0, 65.0, 97.0, 150.0, 150.0, 245.0, 175.0, 66.0, 91.0, 113.0, 264.0, 175.0, 335.0, 109.0,
+    ];
+    const AM: [f64; 32] = [
+        1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0,
+        0.0, 1.0, 1.0, 1.0, 0.0, 0.0, 0.0, 0.0, 0.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0, 1.0,
+    ];
+
+    fn table(columns: &[(&str, &[f64])]) -> Value {
+        Value::Table(Table {
+            columns: columns.iter()
+                .map(|(name, values)| Column { name: name.to_string(), values: values.iter().map(|v| Value::Double(*v)).collect() })
+                .collect(),
+        })
+    }
+
+    fn mtcars() -> Value {
+        table(&[("mpg", &MPG), ("wt", &WT), ("hp", &HP), ("am", &AM)])
+    }
+
+    fn run(builtin: fn(&mut Program, &mut Arguments) -> EvalReturn, arguments: Vec<(&str, Value)>) -> Value {
+        let mut program = Program::new(Node::new(NodeType::Program(Vec::new()), Span::default()));
+        let values = arguments.into_iter().map(|(name, value)| (name.to_string(), value)).collect();
+
+        builtin(&mut program, &mut Arguments::new("test".into(), values)).unwrap()
+    }
+
+    fn fit(builtin: fn(&mut Program, &mut Arguments) -> EvalReturn, response: &str, predictors: &[&str]) -> Record {
+        let predictors = Value::List(predictors.iter().map(|p| Value::String(p.to_string())).collect());
+
+        match run(builtin, vec![("data", mtcars()), ("response", Value::String(response.into())), ("predictors", predictors)]) {
+            Value::Record(model) => model,
+            value => panic!("expected a model but got {value}"),
+        }
+    }
+
+    fn assert_close(actual: f64, expected: f64, tolerance: f64) {
+        assert!(
+            (actual - expected).abs() <= tolerance * expected.abs().max(1.0),
+            "expected {expected} but got {actual} (tolerance {tolerance})"
+        );
+    }
+
+    /// Asserts the rows of a model's coefficient table, `[estimate, std_error, statistic, p_value]`.
+    fn assert_coefficients(model: &Record, expected: &[[f64; 4]], tolerance: f64) {
+        let Some(Value::Table(coefficients)) = model.field("coefficients") else {
+            panic!("the model has no coefficient table");
+        };
+
+        for (column, index) in coefficients.columns.iter().skip(1).zip(0..) {
+            for (value, row) in column.values.iter().zip(expected) {
+                assert_close(value.as_f64().unwrap(), row[index], tolerance);
+            }
+        }
+    }
+
+    fn number(model: &Record, field: &str) -> f64 {
+        model.field(field).unwrap().as_f64().unwrap()
+    }
+
+    #[test]
+    fn test_linear_regression() {
+        // summary(lm(mpg ~ wt + hp, mtcars)) in R
+        let model = fit(linear_regression, "mpg", &["wt", "hp"]);
+
+        assert_coefficients(&model, &[
+            [37.227_270_116, 1.598_787_538, 23.284_688_698, 2.565_458_512e-20],
+            [-3.877_830_742, 0.632_733_494, -6.128_695_220, 1.119_647_136e-6],
+            [-0.031_772_947, 0.009_029_710, -3.518_711_910, 1.451_228_532e-3],
+        ], 1e-8);
+        assert_close(number(&model, "r_squared"), 0.826_785_452, 1e-9);
+        assert_close(number(&model, "adj_r_squared"), 0.814_839_621, 1e-9);
+        assert_close(number(&model, "sigma"), 2.593_411_777, 1e-9);
+        assert_close(number(&model, "f_statistic"), 69.211_213_392, 1e-9);
+        assert_eq!(number(&model, "df"), 29.0);
+
+        let data = table(&[("wt", &[3.0]), ("hp", &[150.0])]);
+        let predictions = run(predict, vec![("model", Value::Record(model)), ("data", data)]);
+        let Value::List(predictions) = predictions else {
+            panic!("predict must return a list");
+        };
+        assert_close(predictions[0].as_f64().unwrap(), 20.827_835_842, 1e-9);
+    }
+
+    #[test]
+    fn test_logistic_regression() {
+        // summary(glm(am ~ hp + wt, binomial, mtcars)) in R
+        let model = fit(logistic_regression, "am", &["hp", "wt"]);
+
+        assert_eq!(model.field("converged"), Some(&Value::Boolean(true)));
+        assert_coefficients(&model, &[
+            [18.866_298_717, 7.443_558_428, 2.534_580_591, 0.011_258_203],
+            [0.036_255_596, 0.017_734_154, 2.044_393_852, 0.040_914_654],
+            [-8.083_475_182, 3.068_675_275, -2.634_190_475, 0.008_433_816],
+        ], 1e-5);
+        assert_close(number(&model, "deviance"), 10.059_110_472, 1e-7);
+        assert_close(number(&model, "null_deviance"), 43.229_733_277, 1e-9);
+        assert_close(number(&model, "aic"), 16.059_110_472, 1e-7);
+
+        // predict(model, data.frame(hp = 120, wt = 2.8), type = "response")
+        let data = table(&[("hp", &[120.0]), ("wt", &[2.8])]);
+        let Value::List(predictions) = run(predict, vec![("model", Value::Record(model)), ("data", data)]) else {
+            panic!("predict must return a list");
+        };
+        assert_close(predictions[0].as_f64().unwrap(), 0.641_812_528, 1e-5);
+    }
+
+    #[test]
+    fn test_invert() {
+        let inverse = invert(&vec![vec![4.0, 7.0], vec![2.0, 6.0]]).unwrap();
+
+        let expected = [[0.6, -0.7], [-0.2, 0.4]];
+        for (row, expected_row) in inverse.iter().zip(expected.iter()) {
+            for (value, expected) in row.iter().zip(expected_row.iter()) {
+                assert!((value - expected).abs() < 1e-12);
+            }
+        }
+
+        assert!(invert(&vec![vec![1.0, 2.0], vec![2.0, 4.0]]).is_none());
+    }
+}
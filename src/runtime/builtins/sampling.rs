@@ -0,0 +1,114 @@
+//! Builtins drawing from the program's random number generator. Seeding it with `set_seed` makes
+//! every draw reproducible across runs and platforms.
+
+use crate::error::runtime::RuntimeError;
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::random::Random;
+use crate::runtime::value::Value;
+
+/// The most values one call draws, so that a huge `n` fails instead of exhausting memory.
+const MAX_SAMPLE_SIZE: i128 = 10_000_000;
+
+/// `set_seed(seed)`: restarts the random number generator from `seed`.
+pub fn set_seed(program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let seed = match arguments.required("seed")? {
+        Value::Integer(seed) => seed as u64,
+        v => return Err(arguments.invalid("seed", &format!("expected an integer but got '{}'", v.type_name())))
+    };
+
+    program.random = Random::new(seed);
+
+    Ok(Value::Nothing)
+}
+
+/// `random()`: a uniformly distributed double in `[0, 1)`.
+pub fn random(program: &mut Program, _arguments: &mut Arguments) -> EvalReturn {
+    Ok(Value::Double(program.random.next_f64()))
+}
+
+/// `random_int(lo, hi)`: a uniformly distributed integer between `lo` and `hi`, both inclusive.
+pub fn random_int(program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let mut integer = |name: &str| match arguments.required(name)? {
+        Value::Integer(i) => Ok(i),
+        v => Err(arguments.invalid(name, &format!("expected an integer but got '{}'", v.type_name())))
+    };
+
+    let lo = integer("lo")?;
+    let hi = integer("hi")?;
+
+    if hi < lo {
+        return Err(arguments.invalid("hi", "must not be less than 'lo'"));
+    }
+
+    let span = hi.checked_sub(lo)
+        .and_then(|difference| difference.checked_add(1))
+        .and_then(|span| u64::try_from(span).ok())
+        .ok_or_else(|| arguments.invalid("hi", "the range between 'lo' and 'hi' is too large"))?;
+
+    Ok(Value::Integer(lo + program.random.next_below(span) as i128))
+}
+
+/// `shuffle(values)`: a random permutation of a list.
+pub fn shuffle(program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let mut values = arguments.list("values")?;
+    let count = values.len();
+
+    partial_shuffle(&mut program.random, &mut values, count);
+
+    Ok(Value::List(values))
+}
+
+/// `sample(values, n = length, replace = false)`: draws `n` elements of a list, with or without
+/// replacement.
+pub fn sample(program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let mut values = arguments.list("values")?;
+    let replace = arguments.bool_or("replace", false)?;
+    let n = sample_size(program, arguments)?.unwrap_or(values.len());
+
+    if replace {
+        if values.is_empty() && n > 0 {
+            return Err(arguments.invalid("values", "cannot sample from an empty list"));
+        }
+
+        return Ok(Value::List((0..n)
+            .map(|_| values[program.random.next_below(values.len() as u64) as usize].clone())
+            .collect()));
+    }
+
+    if n > values.len() {
+        return Err(arguments.invalid("n", &format!(
+            "cannot take {n} elements from a list of {} without replacement", values.len()
+        )));
+    }
+
+    partial_shuffle(&mut program.random, &mut values, n);
+    values.truncate(n);
+
+    Ok(Value::List(values))
+}
+
+/// Reads the optional number `n` of values to draw, and reserves them in the limits of the program
+/// before they are drawn.
+pub(crate) fn sample_size(program: &mut Program, arguments: &mut Arguments) -> Result<Option<usize>, RuntimeError> {
+    let n = match arguments.optional("n") {
+        None => return Ok(None),
+        Some(Value::Integer(n)) if (0..=MAX_SAMPLE_SIZE).contains(&n) => n as usize,
+        Some(Value::Integer(n)) if n > MAX_SAMPLE_SIZE => {
+            return Err(arguments.invalid("n", &format!("cannot draw more than {MAX_SAMPLE_SIZE} values but got {n}")))
+        }
+        Some(v) => return Err(arguments.invalid("n", &format!("expected a non-negative integer but got '{v}'")))
+    };
+
+    program.reserve(n)?;
+
+    Ok(Some(n))
+}
+
+/// Fisher-Yates shuffle of the first `count` positions of `values`.
+fn partial_shuffle(random: &mut Random, values: &mut [Value], count: usize) {
+    for i in 0..count.min(values.len().saturating_sub(1)) {
+        let j = i + random.next_below((values.len() - i) as u64) as usize;
+        values.swap(i, j);
+    }
+}
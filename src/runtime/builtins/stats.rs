@@ -0,0 +1,134 @@
+use crate::error::runtime::RuntimeError;
+use crate::parse::parser::BinaryType;
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::value::Value;
+
+/// Reads the numeric list passed as `parameter` together with the `na_rm` option. Returns `None`
+/// when the list contains a missing value and `na_rm` is not set, in which case the statistic is
+/// `NA` itself.
+pub(crate) fn numbers(arguments: &mut Arguments, parameter: &str) -> Result<Option<Vec<f64>>, RuntimeError> {
+    let values = arguments.list(parameter)?;
+    let na_rm = arguments.bool_or("na_rm", false)?;
+
+    let mut numbers = Vec::with_capacity(values.len());
+    for value in values.iter() {
+        if value.is_na() {
+            if na_rm {
+                continue;
+            }
+
+            return Ok(None);
+        }
+
+        match value.as_f64() {
+            Some(n) => numbers.push(n),
+            None => return Err(arguments.invalid(
+                parameter,
+                &format!("expected numeric elements but got '{}'", value.type_name())
+            ))
+        }
+    }
+
+    Ok(Some(numbers))
+}
+
+pub(crate) fn mean_of(numbers: &[f64]) -> f64 {
+    numbers.iter().sum::<f64>() / numbers.len() as f64
+}
+
+/// Sample variance with Bessel's correction.
+pub(crate) fn variance_of(numbers: &[f64]) -> f64 {
+    let mean = mean_of(numbers);
+    numbers.iter().map(|n| (n - mean).powi(2)).sum::<f64>() / (numbers.len() - 1) as f64
+}
+
+pub(crate) fn median_of(numbers: &[f64]) -> f64 {
+    let mut sorted = numbers.to_vec();
+    sorted.sort_by(|a, b| a.total_cmp(b));
+
+    let middle = sorted.len() / 2;
+    if sorted.len().is_multiple_of(2) {
+        (sorted[middle - 1] + sorted[middle]) / 2.0
+    } else {
+        sorted[middle]
+    }
+}
+
+fn statistic(arguments: &mut Arguments, minimum_count: usize, f: fn(&[f64]) -> f64) -> EvalReturn {
+    Ok(match numbers(arguments, "values")? {
+        Some(numbers) if numbers.len() >= minimum_count => Value::Double(f(&numbers)),
+        _ => Value::NA
+    })
+}
+
+/// `sum(values, na_rm)`: stays an integer as long as every element is one.
+pub fn sum(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let values = arguments.list("values")?;
+    let na_rm = arguments.bool_or("na_rm", false)?;
+
+    let mut total = Value::Integer(0);
+    for value in values.iter() {
+        if value.is_na() && na_rm {
+            continue;
+        }
+
+        total = total.binary(BinaryType::Add, value)?;
+    }
+
+    Ok(total)
+}
+
+pub fn mean(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    statistic(arguments, 1, mean_of)
+}
+
+pub fn median(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    statistic(arguments, 1, median_of)
+}
+
+pub fn variance(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    statistic(arguments, 2, variance_of)
+}
+
+pub fn sd(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    statistic(arguments, 2, |n| variance_of(n).sqrt())
+}
+
+pub fn min(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    extreme(arguments, |candidate, current| candidate < current)
+}
+
+pub fn max(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    extreme(arguments, |candidate, current| candidate > current)
+}
+
+/// Shared implementation of `min` and `max`, which return the original element so integers stay
+/// integers.
+fn extreme(arguments: &mut Arguments, replaces: fn(f64, f64) -> bool) -> EvalReturn {
+    let values = arguments.list("values")?;
+    let na_rm = arguments.bool_or("na_rm", false)?;
+
+    let mut best: Option<(f64, Value)> = None;
+    for value in values.into_iter() {
+        if value.is_na() {
+            if na_rm {
+                continue;
+            }
+
+            return Ok(Value::NA);
+        }
+
+        let n = value.as_f64().ok_or_else(|| arguments.invalid(
+            "values",
+            &format!("expected numeric elements but got '{}'", value.type_name())
+        ))?;
+
+        match &best {
+            Some((current, _)) if !replaces(n, *current) => {}
+            _ => best = Some((n, value))
+        }
+    }
+
+    Ok(best.map(|(_, v)| v).unwrap_or(Value::NA))
+}
@@ -0,0 +1,42 @@
+//! Builtins giving scripts access to the process they run in.
+
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::runtime::builtins::Arguments;
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::value::Value;
+
+/// `args()`: the raw command line arguments passed to the script, as a list of strings.
+pub fn args(program: &mut Program, _arguments: &mut Arguments) -> EvalReturn {
+    Ok(Value::List(program.arguments.iter().cloned().map(Value::String).collect()))
+}
+
+/// `env(name)`: the value of an environment variable, `NA` if it is not set.
+pub fn env(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let name = arguments.string("name")?;
+
+    match std::env::var(&name) {
+        Ok(value) => Ok(Value::String(value)),
+        Err(std::env::VarError::NotPresent) => Ok(Value::NA),
+        Err(std::env::VarError::NotUnicode(_)) => Err(arguments.invalid("name", &format!("the value of '{name}' is not valid unicode")))
+    }
+}
+
+/// `exit(code = 0)`: ends the program with the given exit status. Every function and block on the
+/// way out is left like after an error, so no further statements run.
+pub fn exit(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let code = match arguments.optional("code") {
+        None => 0,
+        Some(Value::Integer(code)) => i32::try_from(code)
+            .map_err(|_| arguments.invalid("code", &format!("{code} is not a valid exit status")))?,
+        Some(v) => return Err(arguments.invalid("code", &format!("expected an integer but got '{}'", v.type_name())))
+    };
+
+    Err(RuntimeError::exit(code))
+}
+
+/// `error(message)`: fails with the message, like a failing builtin, so a `try` can catch it.
+pub fn error(_program: &mut Program, arguments: &mut Arguments) -> EvalReturn {
+    let message = arguments.string("message")?;
+
+    Err(RuntimeError::of_kind(RuntimeErrorKind::Thrown, message))
+}
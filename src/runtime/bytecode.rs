@@ -0,0 +1,184 @@
+//! The compact form of a program the [`vm`](crate::runtime::vm) runs, compiled from the AST by
+//! the [`compiler`](crate::runtime::compiler).
+//!
+//! Each function is a [`Chunk`] of instructions for a stack machine. Literals live in a constant
+//! pool and variables in numbered slots of the function's frame, so running a function only
+//! looks up globals and host functions by name.
+
+use std::collections::HashMap;
+use std::fmt::{Display, Formatter};
+
+use crate::error::runtime::RuntimeErrorKind;
+use crate::lexer::tokenizer::Span;
+use crate::parse::parser::{BinaryType, FunctionDefinition, FunctionParameter, Node, NodeType, UnaryType};
+use crate::runtime::builtins::BuiltinFunction;
+use crate::runtime::value::Value;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Instruction {
+    /// Pushes a constant of the chunk.
+    Constant(usize),
+    Nothing,
+    /// Pushes the value of a slot of the current frame.
+    Load(usize),
+    /// Pops a value into a slot of the current frame.
+    Store(usize),
+    /// Pushes the value of the global named by a name of the chunk, or fails if there is none.
+    Global(usize),
+    Pop,
+    /// Replaces the top value by its conversion to the type named by a name of the chunk.
+    Coerce(usize),
+    /// Replaces the top `n` values by a list of them.
+    List(usize),
+    /// Replaces the top value by the field named by a name of the chunk.
+    Field(usize),
+    Unary(UnaryType),
+    /// Replaces the top two values by the result of the operator.
+    Binary(BinaryType),
+    /// Jumps if the top value alone decides an `&` or `|`, keeping it as the result.
+    ShortCircuit(BinaryType, usize),
+    Jump(usize),
+    /// Calls the function with the index, whose arguments are on top of the stack in the order
+    /// of its parameters.
+    Call(usize),
+    /// Calls the builtin call of the chunk with the index.
+    CallBuiltin(usize),
+    /// Returns the top value converted to the return type of the function.
+    Return,
+    /// Returns `Nothing` when the body of a function ends without `return`.
+    ReturnNothing,
+    /// Fails with the error of the kind whose message is a name of the chunk. Errors the tree-walking
+    /// evaluator reports when it gets to a node, like undefined variables, compile to this.
+    Fail(usize, RuntimeErrorKind),
+    /// Starts a `try`: until the matching `EndTry`, a catchable error unwinds to this frame and
+    /// jumps to the target with the error record on the stack.
+    Try(usize),
+    EndTry,
+}
+
+/// A call of a builtin with the names of its arguments, whose values are on top of the stack.
+#[derive(Debug, Clone)]
+pub struct BuiltinCall {
+    pub name: String,
+    /// The builtin, or `None` for a function of the host.
+    pub function: Option<BuiltinFunction>,
+    pub arguments: Vec<String>,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct Chunk {
+    pub code: Vec<Instruction>,
+    pub constants: Vec<Value>,
+    /// Type names, field names, globals and error messages.
+    pub names: Vec<String>,
+    pub builtin_calls: Vec<BuiltinCall>,
+    /// The spans of the calls of script functions, by the index of their `Call` instruction in
+    /// ascending order, for stack traces.
+    pub call_sites: Vec<(usize, Span)>,
+    /// The spans of the statements by the index of their first instruction in ascending order,
+    /// for the locations of errors. A statement nested in a block used as an expression is
+    /// followed by its enclosing statement again.
+    pub statements: Vec<(usize, Span)>,
+}
+
+impl Chunk {
+    pub fn push(&mut self, instruction: Instruction) -> usize {
+        self.code.push(instruction);
+        self.code.len() - 1
+    }
+
+    pub fn constant(&mut self, value: Value) -> usize {
+        // `0.0 == -0.0`, so doubles are told apart by their bits
+        let same = |c: &Value| match (c, &value) {
+            (Value::Double(a), Value::Double(b)) => a.to_bits() == b.to_bits(),
+            (c, value) => c == value,
+        };
+
+        if let Some(index) = self.constants.iter().position(same) {
+            return index;
+        }
+
+        self.constants.push(value);
+        self.constants.len() - 1
+    }
+
+    /// The span of the call the instruction at `ip` makes.
+    pub fn call_site(&self, ip: usize) -> Option<Span> {
+        self.call_sites.binary_search_by_key(&ip, |(index, _)| *index).ok().map(|found| self.call_sites[found].1)
+    }
+
+    /// The span of the statement the instruction at `ip` belongs to.
+    pub fn statement(&self, ip: usize) -> Option<Span> {
+        let following = self.statements.partition_point(|(start, _)| *start <= ip);
+        following.checked_sub(1).map(|found| self.statements[found].1)
+    }
+
+    pub fn name(&mut self, name: &str) -> usize {
+        if let Some(index) = self.names.iter().position(|n| n == name) {
+            return index;
+        }
+
+        self.names.push(name.to_string());
+        self.names.len() - 1
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct CompiledFunction {
+    pub name: String,
+    /// The parameters, which are the first slots of the frame.
+    pub parameters: Vec<FunctionParameter>,
+    pub return_type: String,
+    /// The number of slots the frame needs for parameters and variables.
+    pub slot_count: usize,
+    pub chunk: Chunk,
+}
+
+impl CompiledFunction {
+    /// The definition of the function without its body, e.g. to bind command line arguments to
+    /// its parameters.
+    pub fn declaration(&self) -> FunctionDefinition {
+        FunctionDefinition {
+            public: false,
+            name: self.name.clone(),
+            name_span: Span::default(),
+            signature: self.parameters.clone(),
+            return_type: self.return_type.clone(),
+            body: Node::new(NodeType::Block(Vec::new()), Span::default()),
+            module: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct CompiledProgram {
+    pub functions: Vec<CompiledFunction>,
+    /// The function a call by name resolves to, which is the last one defined with that name.
+    pub function_indices: HashMap<String, usize>,
+    /// The first function named `main`, where the program starts.
+    pub main: Option<usize>,
+}
+
+impl Display for CompiledProgram {
+    /// Lists the instructions of every function, for debugging the compiler.
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        for function in self.functions.iter() {
+            let parameters: Vec<&str> = function.parameters.iter().map(|p| p.param_name.as_str()).collect();
+            writeln!(f, "{}({}) slots={}", function.name, parameters.join(", "), function.slot_count)?;
+
+            for (offset, instruction) in function.chunk.code.iter().enumerate() {
+                let operand = match instruction {
+                    Instruction::Constant(i) => format!("{:?}", function.chunk.constants[*i]),
+                    Instruction::Coerce(i) | Instruction::Global(i) | Instruction::Field(i) | Instruction::Fail(i, _) => format!("{:?}", function.chunk.names[*i]),
+                    Instruction::Call(i) => self.functions[*i].name.clone(),
+                    Instruction::CallBuiltin(i) => function.chunk.builtin_calls[*i].name.clone(),
+                    _ => String::new(),
+                };
+
+                writeln!(f, "{offset:>6}  {instruction:?} {operand}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,66 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+/// Stops a running script from elsewhere, e.g. on Ctrl-C or from another thread: the script
+/// fails with an interrupted error at its next step, unwinding every function on the way out.
+/// What the script printed is flushed after it; builtins keep no files open between calls, so
+/// there is nothing else to clean up.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Lets scripts run again after a cancellation.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+#[cfg(test)]
+mod cancellation_tests {
+    use crate::engine::Engine;
+    use crate::error::engine::EngineError;
+    use crate::error::runtime::RuntimeErrorKind;
+    use crate::runtime::cancellation::CancellationToken;
+    use crate::runtime::program::Evaluator;
+    use crate::runtime::value::Value;
+
+    #[test]
+    fn test_interrupts_between_statements() {
+        let source = "func outer() @int { return inner(n = 1) + 1 }
+            func inner(n @int) @int {
+                builtin stop()
+                set<int> m <- n + 1
+                return m
+            }";
+
+        for evaluator in [Evaluator::TreeWalker, Evaluator::Bytecode] {
+            let cancellation = CancellationToken::new();
+            let mut engine = Engine::new().with_evaluator(evaluator).with_cancellation(cancellation.clone());
+            let stop = cancellation.clone();
+            engine.register_function("stop", move |_| {
+                stop.cancel();
+                Ok(Value::Nothing)
+            });
+            engine.compile(source).unwrap();
+
+            let Err(EngineError::Runtime(error)) = engine.call::<i64>("outer", ()) else {
+                panic!("the script was cancelled");
+            };
+            assert_eq!(error.kind, RuntimeErrorKind::Interrupted);
+            let stack: Vec<&str> = error.stack.iter().map(|frame| frame.function.as_str()).collect();
+            assert_eq!(stack, ["inner", "outer"]);
+        }
+    }
+}
@@ -0,0 +1,340 @@
+//! Compiles the AST of a program to [`bytecode`](crate::runtime::bytecode).
+//!
+//! Names are resolved here: variables become slots of the function's frame, calls refer to
+//! functions and builtins directly. Errors the tree-walking evaluator only reports when it gets
+//! to a node, like an undefined variable, compile to an instruction failing with the same message
+//! at the same point of the evaluation. Variables no block declares are looked up among the
+//! globals when they are read, like the tree-walker finds them in the top-level frame.
+
+use std::collections::HashMap;
+
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::lexer::tokenizer::Span;
+use crate::parse::parser::{BinaryType, FunctionCall, FunctionDefinition, Node, NodeType};
+use crate::runtime::builtins::find_builtin;
+use crate::runtime::bytecode::{BuiltinCall, Chunk, CompiledFunction, CompiledProgram, Instruction};
+use crate::runtime::host::Host;
+use crate::runtime::value::Value;
+
+pub fn compile(program: &Node) -> CompiledProgram {
+    compile_with(program, &Host::default())
+}
+
+/// Compiles a program that can call the functions of an embedding application.
+pub fn compile_with(program: &Node, host: &Host) -> CompiledProgram {
+    let definitions: Vec<&FunctionDefinition> = match program.node_type.as_ref() {
+        NodeType::Program(nodes) => nodes.iter()
+            .filter_map(|node| match node.node_type.as_ref() {
+                NodeType::FunctionDefinition(definition) => Some(definition),
+                _ => None
+            })
+            .collect(),
+        _ => Vec::new()
+    };
+
+    let mut compiled = CompiledProgram::default();
+    for (index, definition) in definitions.iter().enumerate() {
+        compiled.function_indices.insert(definition.name.clone(), index);
+    }
+    compiled.main = definitions.iter().position(|definition| definition.name == "main");
+
+    for definition in definitions.iter() {
+        let function = FunctionCompiler::new(&definitions, &compiled.function_indices, host).compile(definition);
+        compiled.functions.push(function);
+    }
+
+    compiled
+}
+
+struct FunctionCompiler<'a> {
+    definitions: &'a [&'a FunctionDefinition],
+    function_indices: &'a HashMap<String, usize>,
+    host: &'a Host,
+    chunk: Chunk,
+    /// The slots of the variables visible in each enclosing block, innermost last.
+    scopes: Vec<HashMap<String, usize>>,
+    next_slot: usize,
+    slot_count: usize,
+    /// For each enclosing block used as an expression, the jumps of its `return`s to its end, and
+    /// the number of `try`s enclosing the block.
+    block_exits: Vec<(Vec<usize>, usize)>,
+    /// The spans of the enclosing statements, innermost last.
+    statements: Vec<Span>,
+    /// The number of enclosing `try` bodies.
+    tries: usize,
+}
+
+impl<'a> FunctionCompiler<'a> {
+    fn new(definitions: &'a [&'a FunctionDefinition], function_indices: &'a HashMap<String, usize>, host: &'a Host) -> Self {
+        Self {
+            definitions,
+            function_indices,
+            host,
+            chunk: Chunk::default(),
+            scopes: Vec::new(),
+            next_slot: 0,
+            slot_count: 0,
+            block_exits: Vec::new(),
+            statements: Vec::new(),
+            tries: 0,
+        }
+    }
+
+    fn compile(mut self, definition: &FunctionDefinition) -> CompiledFunction {
+        // the arguments are passed in the first slots, and the top-level statements of the body
+        // declare their variables next to them
+        let parameters = definition.signature.iter().enumerate().map(|(slot, p)| (p.param_name.clone(), slot));
+        self.scopes.push(parameters.collect());
+        self.next_slot = definition.signature.len();
+        self.slot_count = self.next_slot;
+
+        match definition.body.node_type.as_ref() {
+            NodeType::Block(nodes) => {
+                for node in nodes {
+                    self.statement(node);
+                }
+                self.chunk.push(Instruction::ReturnNothing);
+            }
+            node_type => self.fail(RuntimeError::expected_node_type("block", node_type))
+        }
+
+        CompiledFunction {
+            name: definition.name.clone(),
+            parameters: definition.signature.clone(),
+            return_type: definition.return_type.clone(),
+            slot_count: self.slot_count,
+            chunk: self.chunk,
+        }
+    }
+
+    fn declare(&mut self, name: &str) -> usize {
+        let scope = self.scopes.last_mut().expect("there is always a scope");
+
+        if let Some(slot) = scope.get(name) {
+            return *slot;
+        }
+
+        let slot = self.next_slot;
+        scope.insert(name.to_string(), slot);
+        self.next_slot += 1;
+        self.slot_count = self.slot_count.max(self.next_slot);
+
+        slot
+    }
+
+    fn resolve(&self, name: &str) -> Option<usize> {
+        self.scopes.iter().rev().find_map(|scope| scope.get(name).copied())
+    }
+
+    fn fail(&mut self, error: RuntimeError) {
+        let message = self.chunk.name(&error.message);
+        self.chunk.push(Instruction::Fail(message, error.kind));
+    }
+
+    /// Points the jump at `offset` to the next instruction.
+    fn patch(&mut self, offset: usize) {
+        let target = self.chunk.code.len();
+
+        self.chunk.code[offset] = match self.chunk.code[offset] {
+            Instruction::Jump(_) => Instruction::Jump(target),
+            Instruction::ShortCircuit(binary_type, _) => Instruction::ShortCircuit(binary_type, target),
+            Instruction::Try(_) => Instruction::Try(target),
+            instruction => unreachable!("{instruction:?} is not a jump"),
+        };
+    }
+
+    fn statement(&mut self, node: &Node) {
+        self.chunk.statements.push((self.chunk.code.len(), node.span));
+        self.statements.push(node.span);
+
+        match node.node_type.as_ref() {
+            NodeType::VariableDeclaration(declaration) => {
+                self.expression(&declaration.value);
+
+                if let Some(variable_type) = &declaration.variable_type {
+                    let type_name = self.chunk.name(variable_type);
+                    self.chunk.push(Instruction::Coerce(type_name));
+                }
+
+                let slot = self.declare(&declaration.name);
+                self.chunk.push(Instruction::Store(slot));
+            }
+            NodeType::ReturnExpression(value) => {
+                self.expression(value);
+
+                // a `return` ends the innermost block used as an expression, or else the function,
+                // and leaves the `try`s in between
+                let tries = self.tries - self.block_exits.last().map_or(0, |(_, tries)| *tries);
+                for _ in 0..tries {
+                    self.chunk.push(Instruction::EndTry);
+                }
+
+                match self.block_exits.last_mut() {
+                    Some((exits, _)) => {
+                        exits.push(self.chunk.code.len());
+                        self.chunk.push(Instruction::Jump(0));
+                    }
+                    None => {
+                        self.chunk.push(Instruction::Return);
+                    }
+                }
+            }
+            NodeType::Block(nodes) => self.block(nodes),
+            NodeType::TryCatch(try_catch) => {
+                let start = self.chunk.push(Instruction::Try(0));
+                self.tries += 1;
+                self.statement(&try_catch.body);
+                self.tries -= 1;
+                self.chunk.push(Instruction::EndTry);
+                let end = self.chunk.push(Instruction::Jump(0));
+
+                // the handler starts with the error record on the stack
+                self.patch(start);
+                let next_slot = self.next_slot;
+                self.scopes.push(HashMap::new());
+                let slot = self.declare(&try_catch.variable);
+                self.chunk.push(Instruction::Store(slot));
+                self.statement(&try_catch.handler);
+                self.scopes.pop();
+                self.next_slot = next_slot;
+
+                self.patch(end);
+            }
+            _ => {
+                self.expression(node);
+                self.chunk.push(Instruction::Pop);
+            }
+        }
+
+        self.statements.pop();
+        if let Some(enclosing) = self.statements.last() {
+            self.chunk.statements.push((self.chunk.code.len(), *enclosing));
+        }
+    }
+
+    fn block(&mut self, nodes: &[Node]) {
+        let next_slot = self.next_slot;
+        self.scopes.push(HashMap::new());
+
+        for node in nodes {
+            self.statement(node);
+        }
+
+        self.scopes.pop();
+        self.next_slot = next_slot;
+    }
+
+    fn expression(&mut self, node: &Node) {
+        match node.node_type.as_ref() {
+            NodeType::StringLiteral(s) => self.constant(Value::String(s.clone())),
+            NodeType::Int8Literal(i) => self.constant(Value::Integer(*i as i128)),
+            NodeType::Int16Literal(i) => self.constant(Value::Integer(*i as i128)),
+            NodeType::Int32Literal(i) => self.constant(Value::Integer(*i as i128)),
+            NodeType::Int64Literal(i) => self.constant(Value::Integer(*i as i128)),
+            NodeType::Int128Literal(i) => match i128::try_from(*i) {
+                Ok(i) => self.constant(Value::Integer(i)),
+                Err(_) => self.fail(RuntimeError::of_kind(RuntimeErrorKind::Overflow, format!("Integer literal {i} is out of range."))),
+            },
+            NodeType::DoubleLiteral(d) => self.constant(Value::Double(*d)),
+            NodeType::BooleanLiteral(b) => self.constant(Value::Boolean(*b)),
+            NodeType::NaLiteral => self.constant(Value::NA),
+            NodeType::ListLiteral(elements) => {
+                for element in elements {
+                    self.expression(element);
+                }
+                self.chunk.push(Instruction::List(elements.len()));
+            }
+            NodeType::VariableReference(name) => match self.resolve(name) {
+                Some(slot) => {
+                    self.chunk.push(Instruction::Load(slot));
+                }
+                None => {
+                    let name = self.chunk.name(name);
+                    self.chunk.push(Instruction::Global(name));
+                }
+            },
+            NodeType::FieldAccess(target, field) => {
+                self.expression(target);
+                let field = self.chunk.name(field);
+                self.chunk.push(Instruction::Field(field));
+            }
+            NodeType::UnaryNode(unary_type, operand) => {
+                self.expression(operand);
+                self.chunk.push(Instruction::Unary(*unary_type));
+            }
+            NodeType::BinaryNode(binary_type @ (BinaryType::And | BinaryType::Or), left, right) => {
+                self.expression(left);
+                let short_circuit = self.chunk.push(Instruction::ShortCircuit(*binary_type, 0));
+                self.expression(right);
+                self.chunk.push(Instruction::Binary(*binary_type));
+                self.patch(short_circuit);
+            }
+            NodeType::BinaryNode(binary_type, left, right) => {
+                self.expression(left);
+                self.expression(right);
+                self.chunk.push(Instruction::Binary(*binary_type));
+            }
+            NodeType::FunctionCall(call) if call.builtin => self.builtin_call(call),
+            NodeType::FunctionCall(call) => self.user_function_call(call, node.span),
+            NodeType::Block(nodes) => {
+                self.block_exits.push((Vec::new(), self.tries));
+                self.block(nodes);
+                self.chunk.push(Instruction::Nothing);
+
+                for exit in self.block_exits.pop().expect("pushed above").0 {
+                    self.patch(exit);
+                }
+            }
+            node_type => self.fail(RuntimeError::expected_node_type("expression", node_type))
+        }
+    }
+
+    fn constant(&mut self, value: Value) {
+        let constant = self.chunk.constant(value);
+        self.chunk.push(Instruction::Constant(constant));
+    }
+
+    fn builtin_call(&mut self, call: &FunctionCall) {
+        // a host function is looked up by name when it is called
+        let function = find_builtin(&call.name);
+        if function.is_none() && !self.host.functions.contains_key(&call.name) {
+            return self.fail(RuntimeError::unknown_builtin(&call.name));
+        }
+
+        for (_, argument) in call.parameters.iter() {
+            self.expression(argument);
+        }
+
+        self.chunk.builtin_calls.push(BuiltinCall {
+            name: call.name.clone(),
+            function,
+            arguments: call.parameters.iter().map(|(name, _)| name.clone()).collect(),
+        });
+        self.chunk.push(Instruction::CallBuiltin(self.chunk.builtin_calls.len() - 1));
+    }
+
+    fn user_function_call(&mut self, call: &FunctionCall, span: Span) {
+        let Some(index) = self.function_indices.get(&call.name).copied() else {
+            return self.fail(RuntimeError::unknown_function(&call.name));
+        };
+        let definition = self.definitions[index];
+
+        if let Some(unknown) = call.parameters.iter().map(|(name, _)| name).find(|name| !definition.signature.iter().any(|p| &p.param_name == *name)) {
+            return self.fail(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Function '{}' has no parameter '{unknown}'.", call.name)));
+        }
+
+        // the arguments are evaluated in the order of the parameters, like the tree-walker does
+        for parameter in definition.signature.iter() {
+            let Some((_, argument)) = call.parameters.iter().find(|(name, _)| name == &parameter.param_name) else {
+                return self.fail(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Missing argument '{}' for function '{}'.", parameter.param_name, call.name)));
+            };
+
+            self.expression(argument);
+            let type_name = self.chunk.name(&parameter.param_type);
+            self.chunk.push(Instruction::Coerce(type_name));
+        }
+
+        let call = self.chunk.push(Instruction::Call(index));
+        self.chunk.call_sites.push((call, span));
+    }
+}
@@ -0,0 +1,225 @@
+//! Conversions between Rust values and [`Value`]s, for applications embedding the interpreter.
+//!
+//! `NA` converts to `None` of an `Option` and fails to convert to anything else, so a host
+//! decides explicitly where missing values are acceptable.
+
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::runtime::value::Value;
+
+pub trait IntoValue {
+    fn into_value(self) -> Value;
+}
+
+pub trait FromValue: Sized {
+    fn from_value(value: Value) -> Result<Self, RuntimeError>;
+}
+
+/// The arguments of a call of a script function from Rust, in the order of its parameters: a
+/// tuple of values converting into [`Value`]s, or a `Vec<Value>`.
+pub trait IntoArguments {
+    fn into_arguments(self) -> Vec<Value>;
+}
+
+impl IntoValue for Value {
+    fn into_value(self) -> Value {
+        self
+    }
+}
+
+impl FromValue for Value {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        Ok(value)
+    }
+}
+
+impl IntoValue for () {
+    fn into_value(self) -> Value {
+        Value::Nothing
+    }
+}
+
+impl FromValue for () {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Nothing => Ok(()),
+            value => Err(RuntimeError::type_mismatch("Nothing", value.type_name())),
+        }
+    }
+}
+
+macro_rules! integers {
+    ($($integer:ty),*) => {
+        $(
+            impl IntoValue for $integer {
+                fn into_value(self) -> Value {
+                    Value::Integer(self as i128)
+                }
+            }
+
+            impl FromValue for $integer {
+                fn from_value(value: Value) -> Result<Self, RuntimeError> {
+                    match value {
+                        Value::Integer(i) => <$integer>::try_from(i).map_err(|_| {
+                            RuntimeError::of_kind(RuntimeErrorKind::Overflow, format!("The integer {i} does not fit into '{}'.", stringify!($integer)))
+                        }),
+                        value => Err(RuntimeError::type_mismatch("int", value.type_name())),
+                    }
+                }
+            }
+        )*
+    };
+}
+
+integers!(i8, i16, i32, i64, i128, u8, u16, u32, u64, usize);
+
+impl IntoValue for f64 {
+    fn into_value(self) -> Value {
+        Value::Double(self)
+    }
+}
+
+/// Integers are widened, like for a parameter declared `@f64`.
+impl FromValue for f64 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        value.as_f64().ok_or_else(|| RuntimeError::type_mismatch("f64", value.type_name()))
+    }
+}
+
+impl IntoValue for f32 {
+    fn into_value(self) -> Value {
+        Value::Double(self as f64)
+    }
+}
+
+impl FromValue for f32 {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        f64::from_value(value).map(|d| d as f32)
+    }
+}
+
+impl IntoValue for bool {
+    fn into_value(self) -> Value {
+        Value::Boolean(self)
+    }
+}
+
+impl FromValue for bool {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Boolean(b) => Ok(b),
+            value => Err(RuntimeError::type_mismatch("bool", value.type_name())),
+        }
+    }
+}
+
+impl IntoValue for char {
+    fn into_value(self) -> Value {
+        Value::Character(self)
+    }
+}
+
+impl FromValue for char {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::Character(c) => Ok(c),
+            value => Err(RuntimeError::type_mismatch("char", value.type_name())),
+        }
+    }
+}
+
+impl IntoValue for String {
+    fn into_value(self) -> Value {
+        Value::String(self)
+    }
+}
+
+impl IntoValue for &str {
+    fn into_value(self) -> Value {
+        Value::String(self.to_string())
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::String(s) => Ok(s),
+            value => Err(RuntimeError::type_mismatch("string", value.type_name())),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Vec<T> {
+    fn into_value(self) -> Value {
+        Value::List(self.into_iter().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: IntoValue + Clone> IntoValue for &[T] {
+    fn into_value(self) -> Value {
+        Value::List(self.iter().cloned().map(IntoValue::into_value).collect())
+    }
+}
+
+impl<T: FromValue> FromValue for Vec<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::List(values) => values.into_iter().map(T::from_value).collect(),
+            value => Err(RuntimeError::type_mismatch("list", value.type_name())),
+        }
+    }
+}
+
+impl<T: IntoValue> IntoValue for Option<T> {
+    fn into_value(self) -> Value {
+        self.map_or(Value::NA, IntoValue::into_value)
+    }
+}
+
+impl<T: FromValue> FromValue for Option<T> {
+    fn from_value(value: Value) -> Result<Self, RuntimeError> {
+        match value {
+            Value::NA => Ok(None),
+            value => T::from_value(value).map(Some),
+        }
+    }
+}
+
+impl IntoArguments for Vec<Value> {
+    fn into_arguments(self) -> Vec<Value> {
+        self
+    }
+}
+
+macro_rules! tuples {
+    ($(($($name:ident),*)),*) => {
+        $(
+            impl<$($name: IntoValue),*> IntoArguments for ($($name,)*) {
+                #[allow(non_snake_case)]
+                fn into_arguments(self) -> Vec<Value> {
+                    let ($($name,)*) = self;
+                    vec![$($name.into_value()),*]
+                }
+            }
+        )*
+    };
+}
+
+tuples!((), (A), (A, B), (A, B, C), (A, B, C, D), (A, B, C, D, E), (A, B, C, D, E, F));
+
+#[cfg(test)]
+mod convert_tests {
+    use crate::error::runtime::RuntimeErrorKind;
+    use crate::runtime::convert::{FromValue, IntoArguments, IntoValue};
+    use crate::runtime::value::Value;
+
+    #[test]
+    fn test_round_trips() {
+        assert_eq!(i64::from_value(42i64.into_value()).unwrap(), 42);
+        assert_eq!(f64::from_value(Value::Integer(2)).unwrap(), 2.0);
+        assert_eq!(Vec::<Option<f64>>::from_value(vec![Some(1.5), None].into_value()).unwrap(), [Some(1.5), None]);
+        assert_eq!(("a", 1, true).into_arguments(), [Value::String("a".into()), Value::Integer(1), Value::Boolean(true)]);
+
+        assert_eq!(u8::from_value(Value::Integer(300)).unwrap_err().kind, RuntimeErrorKind::Overflow);
+        assert_eq!(String::from_value(Value::NA).unwrap_err().kind, RuntimeErrorKind::TypeMismatch);
+    }
+}
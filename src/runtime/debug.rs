@@ -0,0 +1,419 @@
+//! Hooks into the tree-walking evaluator for debuggers.
+//!
+//! A [`Debugger`] attached to a [`Program`] is asked before every statement whether to pause:
+//! at a breakpoint, after a step, or because a pause was requested through its [`DebugControl`].
+//! The paused program goes to the debugger's [`DebugHook`], e.g. the prompt of
+//! `stat_script debug` or a Debug Adapter Protocol server, which inspects it and decides how to
+//! go on.
+
+use std::cell::RefCell;
+use std::collections::BTreeSet;
+use std::fmt;
+use std::path::{Path, PathBuf};
+use std::rc::Rc;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::error::engine::EngineError;
+use crate::error::runtime::RuntimeError;
+use crate::lexer::tokenizer::{Span, Tokenizer};
+use crate::parse::parser::{NodeType, StatParser};
+use crate::runtime::frame::Frame;
+use crate::runtime::program::Program;
+use crate::runtime::value::Value;
+
+/// Decides what a paused program does next, e.g. by asking the user.
+pub trait DebugHook {
+    fn paused(&mut self, paused: &mut Paused) -> Resume;
+}
+
+impl<F: FnMut(&mut Paused) -> Resume> DebugHook for F {
+    fn paused(&mut self, paused: &mut Paused) -> Resume {
+        self(paused)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum PauseReason {
+    /// The first statement of the program, see [`Debugger::stop_on_entry`].
+    Entry,
+    Breakpoint,
+    Step,
+    /// [`DebugControl::pause`] was called.
+    Pause,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Resume {
+    /// Runs until the next breakpoint.
+    Continue,
+    /// Pauses at the next statement, also in a function the current one calls.
+    StepInto,
+    /// Pauses at the next statement of the current function or a caller.
+    StepOver,
+    /// Pauses at the next statement of a caller.
+    StepOut,
+    /// Ends the program as if it was interrupted.
+    Stop,
+}
+
+/// A line of the script, or of a module it imports.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub struct Location {
+    /// The canonical path of the module, `None` in the script.
+    pub module: Option<PathBuf>,
+    pub line: i128,
+}
+
+impl Location {
+    pub fn new(module: Option<&Path>, line: i128) -> Self {
+        Self { module: module.map(Path::to_path_buf), line }
+    }
+}
+
+/// `line 12` in the script, `line 12 of lib.stsc` in a module, with the path relative to the
+/// working directory where possible.
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "line {}", self.line)?;
+
+        if let Some(module) = &self.module {
+            let relative = std::env::current_dir().ok().and_then(|directory| module.strip_prefix(directory).ok().map(Path::to_path_buf));
+            write!(f, " of {}", relative.as_deref().unwrap_or(module).display())?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Parses `LINE` for a line of the script, or `FILE:LINE` for a line of a module.
+impl FromStr for Location {
+    type Err = String;
+
+    fn from_str(text: &str) -> Result<Self, Self::Err> {
+        let (file, line) = match text.rsplit_once(':') {
+            Some((file, line)) => (Some(file), line),
+            None => (None, text),
+        };
+
+        let line = line.parse().map_err(|_| format!("expected LINE or FILE:LINE but got '{text}'"))?;
+        let module = match file {
+            Some(file) => Some(std::fs::canonicalize(file).map_err(|e| format!("cannot find \"{file}\": {e}"))?),
+            None => None,
+        };
+
+        Ok(Self { module, line })
+    }
+}
+
+/// The breakpoints of a debugger, and requests to pause, which other threads can change while
+/// the program runs.
+#[derive(Debug, Clone, Default)]
+pub struct DebugControl {
+    breakpoints: Arc<Mutex<BTreeSet<Location>>>,
+    pause: Arc<AtomicBool>,
+}
+
+impl DebugControl {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Replaces the breakpoints in the script, or in a module, by the lines.
+    pub fn set_breakpoints(&self, module: Option<&Path>, lines: impl IntoIterator<Item = i128>) {
+        let mut breakpoints = self.lock();
+        breakpoints.retain(|breakpoint| breakpoint.module.as_deref() != module);
+        breakpoints.extend(lines.into_iter().map(|line| Location::new(module, line)));
+    }
+
+    pub fn add_breakpoint(&self, location: Location) {
+        self.lock().insert(location);
+    }
+
+    /// Removes the breakpoint at the location, and returns whether there was one.
+    pub fn remove_breakpoint(&self, location: &Location) -> bool {
+        self.lock().remove(location)
+    }
+
+    /// The locations with breakpoints, those of the script first, in ascending order.
+    pub fn breakpoints(&self) -> Vec<Location> {
+        self.lock().iter().cloned().collect()
+    }
+
+    fn has_breakpoint(&self, module: Option<&Path>, line: i128) -> bool {
+        self.lock().iter().any(|breakpoint| breakpoint.line == line && breakpoint.module.as_deref() == module)
+    }
+
+    /// Pauses the program before its next statement.
+    pub fn pause(&self) {
+        self.pause.store(true, Ordering::Relaxed);
+    }
+
+    fn take_pause(&self) -> bool {
+        self.pause.swap(false, Ordering::Relaxed)
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, BTreeSet<Location>> {
+        // the set stays consistent even if a thread panicked while holding it
+        self.breakpoints.lock().unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+enum Stepping {
+    Run,
+    Into,
+    /// Pauses at a depth of calls up to the one given.
+    Over(usize),
+    /// Pauses at a depth of calls below the one given.
+    Out(usize),
+}
+
+/// Pauses a program before its statements, see the [module](self). Debugging runs on the
+/// tree-walking evaluator, whose frames hold the variables by name.
+pub struct Debugger {
+    control: DebugControl,
+    hook: Box<dyn DebugHook>,
+    stepping: Stepping,
+    entry: bool,
+    /// The depth of calls and the location of the previous statement, so that a breakpoint or a
+    /// step pauses once per line.
+    previous: Option<(usize, Location)>,
+}
+
+impl Debugger {
+    pub fn new(hook: impl DebugHook + 'static) -> Self {
+        Self {
+            control: DebugControl::new(),
+            hook: Box::new(hook),
+            stepping: Stepping::Run,
+            entry: false,
+            previous: None,
+        }
+    }
+
+    /// Pauses before the first statement of the program.
+    pub fn stop_on_entry(mut self) -> Self {
+        self.stepping = Stepping::Into;
+        self.entry = true;
+        self
+    }
+
+    /// Uses breakpoints and pause requests shared with the caller.
+    pub fn with_control(mut self, control: DebugControl) -> Self {
+        self.control = control;
+        self
+    }
+
+    pub fn control(&self) -> &DebugControl {
+        &self.control
+    }
+
+    /// Called by the evaluator before each statement, at a depth of calls.
+    pub(crate) fn before_statement(&mut self, program: &mut Program, span: Span) -> Result<(), RuntimeError> {
+        let depth = program.debug_calls.len();
+        // a statement is in the file of the function running it
+        let module = program.debug_calls.last().and_then(|call| call.module.clone());
+
+        let Some(reason) = self.pause_reason(depth, module.as_deref(), span.line) else {
+            return Ok(());
+        };
+
+        let control = self.control.clone();
+        let resume = self.hook.paused(&mut Paused { program, control, reason, module, line: span.line });
+
+        self.stepping = match resume {
+            Resume::Continue => Stepping::Run,
+            Resume::StepInto => Stepping::Into,
+            Resume::StepOver => Stepping::Over(depth),
+            Resume::StepOut => Stepping::Out(depth),
+            Resume::Stop => return Err(RuntimeError::interrupted()),
+        };
+
+        Ok(())
+    }
+
+    fn pause_reason(&mut self, depth: usize, module: Option<&Path>, line: i128) -> Option<PauseReason> {
+        let new_line = self.previous.as_ref().is_none_or(|(previous_depth, previous)| {
+            *previous_depth != depth || previous.line != line || previous.module.as_deref() != module
+        });
+        if new_line {
+            self.previous = Some((depth, Location::new(module, line)));
+        }
+
+        let stepped = match self.stepping {
+            Stepping::Run => false,
+            Stepping::Into => true,
+            Stepping::Over(from) => depth <= from,
+            Stepping::Out(from) => depth < from,
+        };
+
+        if self.control.take_pause() {
+            Some(PauseReason::Pause)
+        } else if std::mem::take(&mut self.entry) {
+            Some(PauseReason::Entry)
+        } else if !new_line {
+            None
+        } else if self.control.has_breakpoint(module, line) {
+            Some(PauseReason::Breakpoint)
+        } else if stepped {
+            Some(PauseReason::Step)
+        } else {
+            None
+        }
+    }
+}
+
+/// A call of a script function that is running while the program is paused.
+#[derive(Clone)]
+pub struct DebugFrame {
+    pub function: String,
+    /// The file of the module the function is from, `None` in the script.
+    pub module: Option<PathBuf>,
+    /// The line of the statement the function is at.
+    pub line: i128,
+    /// The frame of the innermost block the function is in. Its parents hold the variables of
+    /// the enclosing blocks, and the last one the globals.
+    pub frame: Rc<RefCell<Frame>>,
+}
+
+/// A program paused before a statement.
+pub struct Paused<'a> {
+    program: &'a mut Program,
+    control: DebugControl,
+    pub reason: PauseReason,
+    /// The file of the module the statement the program is paused before is in, `None` in the
+    /// script.
+    pub module: Option<PathBuf>,
+    /// The line of the statement the program is paused before.
+    pub line: i128,
+}
+
+impl Paused<'_> {
+    /// The breakpoints of the debugger.
+    pub fn control(&self) -> &DebugControl {
+        &self.control
+    }
+
+    /// The running calls, innermost first.
+    pub fn stack(&self) -> Vec<DebugFrame> {
+        self.program.debug_calls.iter().rev().cloned().collect()
+    }
+
+    /// The files of the modules the program's functions are from, in ascending order.
+    pub fn modules(&self) -> Vec<PathBuf> {
+        let modules: BTreeSet<PathBuf> = self.program.functions().values().filter_map(|definition| definition.module.clone()).collect();
+        modules.into_iter().collect()
+    }
+
+    /// The variables of the program's top-level frame, like globals of the host.
+    pub fn globals(&self) -> Vec<(String, Value)> {
+        self.program.top_level_frame.borrow().sorted_variables()
+    }
+
+    /// Evaluates source in the innermost block of the call at `depth` of the [stack](Self::stack),
+    /// e.g. an expression, or a `set` that changes a variable of the paused program. Returns the
+    /// value of the source if it ends in an expression.
+    pub fn evaluate(&mut self, source: &str, depth: usize) -> Result<Option<Value>, EngineError> {
+        let node = StatParser::new(Tokenizer::new(source.to_string())).parse_interactive().map_err(EngineError::Parse)?;
+        let nodes = match node.node_type.as_ref() {
+            NodeType::Program(nodes) => nodes.clone(),
+            _ => vec![node],
+        };
+
+        let frame = match self.stack().get(depth) {
+            Some(call) => Rc::clone(&call.frame),
+            None => Rc::clone(&self.program.top_level_frame),
+        };
+
+        self.program.evaluate_entry_in(frame, &nodes).map_err(EngineError::Runtime)
+    }
+
+    pub fn program(&mut self) -> &mut Program {
+        self.program
+    }
+}
+
+#[cfg(test)]
+mod debug_tests {
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    use crate::engine::Engine;
+    use crate::runtime::debug::{DebugControl, Debugger, PauseReason, Paused, Resume};
+
+    const SOURCE: &str = "func scale(x @int) @int {
+    set<int> y <- x * factor
+    return y
+}
+func total(a @int, b @int) @int {
+    set<int> first <- scale(x = a)
+    set<int> second <- scale(x = b)
+    return first + second
+}";
+
+    /// Calls `total(a = 1, b = 2)` with a breakpoint at line 2, resuming with the steps in turn,
+    /// and returns where the program paused with the functions running there.
+    fn pauses(steps: &'static [Resume]) -> Vec<(PauseReason, i128, Vec<String>)> {
+        let pauses = Rc::new(RefCell::new(Vec::new()));
+        let mut steps = steps.iter().copied();
+
+        let recorded = Rc::clone(&pauses);
+        let hook = move |paused: &mut Paused| {
+            let functions = paused.stack().iter().map(|call| call.function.clone()).collect();
+            recorded.borrow_mut().push((paused.reason, paused.line, functions));
+            steps.next().unwrap_or(Resume::Continue)
+        };
+
+        let control = DebugControl::new();
+        control.set_breakpoints(None, [2]);
+        let mut engine = Engine::new().with_debugger(Debugger::new(hook).with_control(control));
+        engine.set_global("factor", 10);
+        engine.compile(SOURCE).unwrap();
+
+        assert_eq!(engine.call::<i64>("total", (1, 2)).unwrap(), 30);
+        let pauses = pauses.borrow().clone();
+        pauses
+    }
+
+    #[test]
+    fn test_breakpoints_and_steps() {
+        let at = |reason, line, functions: &[&str]| (reason, line, functions.iter().map(|f| f.to_string()).collect::<Vec<_>>());
+
+        assert_eq!(pauses(&[]), [
+            at(PauseReason::Breakpoint, 2, &["scale", "total"]),
+            at(PauseReason::Breakpoint, 2, &["scale", "total"]),
+        ]);
+        assert_eq!(pauses(&[Resume::StepOver, Resume::StepOut, Resume::StepInto, Resume::StepOver]), [
+            at(PauseReason::Breakpoint, 2, &["scale", "total"]),
+            at(PauseReason::Step, 3, &["scale", "total"]),
+            at(PauseReason::Step, 7, &["total"]),
+            at(PauseReason::Breakpoint, 2, &["scale", "total"]),
+            at(PauseReason::Step, 3, &["scale", "total"]),
+        ]);
+    }
+
+    #[test]
+    fn test_inspects_and_changes_variables() {
+        let seen = Rc::new(RefCell::new(Vec::new()));
+
+        let recorded = Rc::clone(&seen);
+        let hook = move |paused: &mut Paused| {
+            let locals: Vec<String> = paused.stack()[1].frame.borrow().locals().iter().map(|(name, value)| format!("{name} = {value}")).collect();
+            let value = paused.evaluate("x * 100 + factor", 0).unwrap().unwrap();
+            recorded.borrow_mut().push(format!("{} | {value}", locals.join(", ")));
+
+            paused.evaluate("set<int> y <- 50", 0).unwrap();
+            Resume::Continue
+        };
+
+        let control = DebugControl::new();
+        control.set_breakpoints(None, [3]);
+        let mut engine = Engine::new().with_debugger(Debugger::new(hook).with_control(control));
+        engine.set_global("factor", 10);
+        engine.compile(SOURCE).unwrap();
+
+        assert_eq!(engine.call::<i64>("total", (1, 2)).unwrap(), 100);
+        assert_eq!(*seen.borrow(), ["a = 1, b = 2 | 110", "a = 1, b = 2, first = 50 | 210"]);
+    }
+}
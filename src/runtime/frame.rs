@@ -1,41 +1,53 @@
-use std::any::Any;
-use std::cell::Ref;
+use std::cell::RefCell;
 use std::collections::HashMap;
 use std::rc::Rc;
+use crate::runtime::value::Value;
 
-pub struct Frame<'a> {
-    pub children: Vec<Frame<'a>>,
-    pub parent_frame: Option<Ref<'a, Frame<'a>>>,
-    pub variables: HashMap<String, Rc<dyn Any>>
+pub struct Frame {
+    pub parent_frame: Option<Rc<RefCell<Frame>>>,
+    pub variables: HashMap<String, Value>
 }
 
-impl<'a> Frame<'a> {
-    
-    pub fn new(parent_frame: Option<Ref<'a, Frame<'a>>>) -> Self {
+impl Frame {
+
+    pub fn new(parent_frame: Option<Rc<RefCell<Frame>>>) -> Self {
         Self {
             parent_frame,
             variables: Default::default(),
-            children: Vec::new()
         }
     }
 
-    fn find_variable(&self, name: String) -> Option<Rc<dyn Any>> {
-        let mut next_frame: Option<&Self> = Some(self);
-
-        while next_frame.is_some() {
-            let variable = next_frame?.variables.get(&name);
-            
-            match variable { 
-                Some(var) => {
-                    return Some(var.clone())
-                },
-                None => {
-                    next_frame = next_frame?.parent_frame.as_deref();
-                    continue
-                }
-            }
+    pub fn find_variable(&self, name: &str) -> Option<Value> {
+        match self.variables.get(name) {
+            Some(var) => Some(var.clone()),
+            None => self.parent_frame.as_ref()?.borrow().find_variable(name)
         }
+    }
+
+    pub fn declare_variable(&mut self, name: String, value: Value) {
+        self.variables.insert(name, value);
+    }
+
+    /// The variables declared in this frame, sorted by name.
+    pub fn sorted_variables(&self) -> Vec<(String, Value)> {
+        let mut variables: Vec<(String, Value)> = self.variables.iter().map(|(name, value)| (name.clone(), value.clone())).collect();
+        variables.sort_by(|a, b| a.0.cmp(&b.0));
 
-        None
+        variables
     }
-}
\ No newline at end of file
+
+    /// The variables of this frame and the enclosing blocks, without the globals of the
+    /// top-level frame, sorted by name. A variable of an inner block hides one outside.
+    pub fn locals(&self) -> Vec<(String, Value)> {
+        let Some(parent) = &self.parent_frame else {
+            return Vec::new();
+        };
+
+        let mut locals = parent.borrow().locals();
+        locals.retain(|(name, _)| !self.variables.contains_key(name));
+        locals.extend(self.sorted_variables());
+        locals.sort_by(|a, b| a.0.cmp(&b.0));
+
+        locals
+    }
+}
@@ -0,0 +1,20 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::runtime::native::NativeFunction;
+use crate::runtime::value::Value;
+
+/// What the application embedding the interpreter provides to scripts besides the builtins.
+#[derive(Clone, Default)]
+pub struct Host {
+    pub functions: HashMap<String, Rc<dyn NativeFunction>>,
+    /// Values every function of a script can read by name, unless a variable of its own
+    /// shadows them.
+    pub globals: HashMap<String, Value>,
+}
+
+impl Host {
+    pub fn function(&self, name: &str) -> Option<Rc<dyn NativeFunction>> {
+        self.functions.get(name).cloned()
+    }
+}
@@ -0,0 +1,592 @@
+//! Compiled images: the bytecode of a program written to a file by `stat_script compile`, so
+//! later runs skip parsing, checking and compiling.
+//!
+//! An image starts with a header naming the format version, the interpreter version and the hash
+//! of the source it was compiled from. Images of other versions are rejected, since the bytecode
+//! and the builtins it calls may have changed, and an image whose source has changed since is
+//! stale. So is an image whose imported modules have changed.
+
+use std::collections::HashMap;
+use std::fs;
+
+use crate::error::image::{ImageError, ImageErrorKind};
+use crate::error::runtime::RuntimeErrorKind;
+use crate::lexer::tokenizer::Span;
+use crate::parse::parser::{BinaryType, FunctionParameter, UnaryType};
+use crate::runtime::builtins::find_builtin;
+use crate::runtime::bytecode::{BuiltinCall, Chunk, CompiledFunction, CompiledProgram, Instruction};
+use crate::runtime::value::Value;
+
+const MAGIC: &[u8; 4] = b"STSB";
+/// Changes whenever the layout of images changes.
+const FORMAT_VERSION: u32 = 6;
+const INTERPRETER_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// The file extension of images, next to the `.stsc` of scripts.
+pub const EXTENSION: &str = "stsb";
+
+pub struct Image {
+    /// The hash of the source the program was compiled from.
+    pub source_hash: u64,
+    /// The path of the source, if it was read from a file.
+    pub source_path: Option<String>,
+    /// The paths and source hashes of the modules linked into the program.
+    pub dependencies: Vec<(String, u64)>,
+    pub program: CompiledProgram,
+}
+
+impl Image {
+    pub fn new(source: &str, source_path: Option<String>, dependencies: Vec<(String, u64)>, program: CompiledProgram) -> Self {
+        Self {
+            source_hash: source_hash(source),
+            source_path,
+            dependencies,
+            program,
+        }
+    }
+
+    /// Whether the image was compiled from `source` and the modules it imports are unchanged.
+    pub fn is_fresh(&self, source: &str) -> bool {
+        self.source_hash == source_hash(source) && self.dependencies.iter()
+            .all(|(path, hash)| fs::read_to_string(path).is_ok_and(|source| source_hash(&source) == *hash))
+    }
+
+    pub fn to_bytes(&self) -> Vec<u8> {
+        let mut writer = Writer::default();
+
+        writer.bytes.extend_from_slice(MAGIC);
+        writer.u32(FORMAT_VERSION);
+        writer.string(INTERPRETER_VERSION);
+        writer.u64(self.source_hash);
+        writer.string(self.source_path.as_deref().unwrap_or_default());
+        writer.index(self.dependencies.len());
+        for (path, hash) in self.dependencies.iter() {
+            writer.string(path);
+            writer.u64(*hash);
+        }
+        writer.program(&self.program);
+
+        writer.bytes
+    }
+
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, ImageError> {
+        if !is_image(bytes) {
+            return Err(ImageError::new(ImageErrorKind::NotAnImage, "The file is not a compiled StatScript image.".into()));
+        }
+
+        let mut reader = Reader { bytes, position: MAGIC.len() };
+
+        let format_version = reader.u32()?;
+        if format_version != FORMAT_VERSION {
+            return Err(ImageError::new(ImageErrorKind::IncompatibleVersion, format!(
+                "The image has format {format_version}, but this interpreter reads format {FORMAT_VERSION}. Compile the script again."
+            )));
+        }
+
+        let interpreter_version = reader.string()?;
+        if interpreter_version != INTERPRETER_VERSION {
+            return Err(ImageError::new(ImageErrorKind::IncompatibleVersion, format!(
+                "The image was compiled by version {interpreter_version} of the interpreter, but this is version \
+                {INTERPRETER_VERSION}. Compile the script again."
+            )));
+        }
+
+        let source_hash = reader.u64()?;
+        let source_path = Some(reader.string()?).filter(|path| !path.is_empty());
+        let dependencies = (0..reader.index()?)
+            .map(|_| Ok((reader.string()?, reader.u64()?)))
+            .collect::<Result<Vec<_>, ImageError>>()?;
+        let program = reader.program()?;
+
+        if reader.position != bytes.len() {
+            return Err(ImageError::new(ImageErrorKind::Damaged, "The image has trailing bytes.".into()));
+        }
+
+        Ok(Self { source_hash, source_path, dependencies, program })
+    }
+}
+
+pub fn is_image(bytes: &[u8]) -> bool {
+    bytes.starts_with(MAGIC)
+}
+
+/// FNV-1a, which unlike the hasher of the standard library stays the same across builds.
+pub fn source_hash(source: &str) -> u64 {
+    source.bytes().fold(0xcbf29ce484222325, |hash, byte| (hash ^ byte as u64).wrapping_mul(0x100000001b3))
+}
+
+const UNARY_TYPES: [UnaryType; 2] = [UnaryType::Not, UnaryType::Negate];
+
+#[derive(Default)]
+struct Writer {
+    bytes: Vec<u8>,
+}
+
+impl Writer {
+    fn u8(&mut self, value: u8) {
+        self.bytes.push(value);
+    }
+
+    fn u32(&mut self, value: u32) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn u64(&mut self, value: u64) {
+        self.bytes.extend_from_slice(&value.to_le_bytes());
+    }
+
+    fn index(&mut self, value: usize) {
+        self.u64(value as u64);
+    }
+
+    fn string(&mut self, value: &str) {
+        self.index(value.len());
+        self.bytes.extend_from_slice(value.as_bytes());
+    }
+
+    fn span(&mut self, span: &Span) {
+        for position in [span.start, span.end, span.line, span.end_line] {
+            self.u64(position as u64);
+        }
+    }
+
+    fn strings(&mut self, values: &[String]) {
+        self.index(values.len());
+        for value in values {
+            self.string(value);
+        }
+    }
+
+    fn program(&mut self, program: &CompiledProgram) {
+        match program.main {
+            Some(main) => {
+                self.u8(1);
+                self.index(main);
+            }
+            None => self.u8(0),
+        }
+
+        self.index(program.functions.len());
+        for function in program.functions.iter() {
+            self.string(&function.name);
+            self.index(function.parameters.len());
+            for parameter in function.parameters.iter() {
+                self.string(&parameter.param_name);
+                self.string(&parameter.param_type);
+            }
+            self.string(&function.return_type);
+            self.index(function.slot_count);
+            self.chunk(&function.chunk);
+        }
+    }
+
+    fn chunk(&mut self, chunk: &Chunk) {
+        self.index(chunk.code.len());
+        for instruction in chunk.code.iter() {
+            self.instruction(instruction);
+        }
+
+        self.index(chunk.constants.len());
+        for constant in chunk.constants.iter() {
+            self.constant(constant);
+        }
+
+        self.strings(&chunk.names);
+
+        self.index(chunk.builtin_calls.len());
+        for call in chunk.builtin_calls.iter() {
+            self.string(&call.name);
+            self.strings(&call.arguments);
+        }
+
+        self.index(chunk.call_sites.len());
+        for (call, span) in chunk.call_sites.iter() {
+            self.index(*call);
+            self.span(span);
+        }
+
+        self.index(chunk.statements.len());
+        for (start, span) in chunk.statements.iter() {
+            self.index(*start);
+            self.span(span);
+        }
+    }
+
+    fn instruction(&mut self, instruction: &Instruction) {
+        match *instruction {
+            Instruction::Constant(index) => self.operand(0, index),
+            Instruction::Nothing => self.u8(1),
+            Instruction::Load(slot) => self.operand(2, slot),
+            Instruction::Store(slot) => self.operand(3, slot),
+            Instruction::Pop => self.u8(4),
+            Instruction::Coerce(name) => self.operand(5, name),
+            Instruction::List(count) => self.operand(6, count),
+            Instruction::Field(name) => self.operand(7, name),
+            Instruction::Unary(unary_type) => {
+                self.u8(8);
+                self.u8(UNARY_TYPES.iter().position(|u| *u == unary_type).expect("every unary type is listed") as u8);
+            }
+            Instruction::Binary(binary_type) => {
+                self.u8(9);
+                self.string(binary_type.symbol());
+            }
+            Instruction::ShortCircuit(binary_type, target) => {
+                self.u8(10);
+                self.string(binary_type.symbol());
+                self.index(target);
+            }
+            Instruction::Jump(target) => self.operand(11, target),
+            Instruction::Call(function) => self.operand(12, function),
+            Instruction::CallBuiltin(call) => self.operand(13, call),
+            Instruction::Return => self.u8(14),
+            Instruction::ReturnNothing => self.u8(15),
+            Instruction::Fail(message, kind) => {
+                self.operand(16, message);
+                self.string(kind.code());
+            }
+            Instruction::Global(name) => self.operand(17, name),
+            Instruction::Try(target) => self.operand(18, target),
+            Instruction::EndTry => self.u8(19),
+        }
+    }
+
+    fn operand(&mut self, opcode: u8, operand: usize) {
+        self.u8(opcode);
+        self.index(operand);
+    }
+
+    fn constant(&mut self, value: &Value) {
+        match value {
+            Value::Nothing => self.u8(0),
+            Value::NA => self.u8(1),
+            Value::Boolean(b) => {
+                self.u8(2);
+                self.u8(*b as u8);
+            }
+            Value::Integer(i) => {
+                self.u8(3);
+                self.bytes.extend_from_slice(&i.to_le_bytes());
+            }
+            Value::Double(d) => {
+                self.u8(4);
+                self.u64(d.to_bits());
+            }
+            Value::String(s) => {
+                self.u8(5);
+                self.string(s);
+            }
+            Value::Character(c) => {
+                self.u8(6);
+                self.u32(*c as u32);
+            }
+            v => unreachable!("constants are literals, not '{}'", v.type_name()),
+        }
+    }
+}
+
+struct Reader<'a> {
+    bytes: &'a [u8],
+    position: usize,
+}
+
+impl Reader<'_> {
+    fn take(&mut self, count: usize) -> Result<&[u8], ImageError> {
+        let end = self.position.checked_add(count).filter(|end| *end <= self.bytes.len()).ok_or_else(ImageError::truncated)?;
+        let bytes = &self.bytes[self.position..end];
+        self.position = end;
+
+        Ok(bytes)
+    }
+
+    fn u8(&mut self) -> Result<u8, ImageError> {
+        Ok(self.take(1)?[0])
+    }
+
+    fn u32(&mut self) -> Result<u32, ImageError> {
+        Ok(u32::from_le_bytes(self.take(4)?.try_into().expect("took 4 bytes")))
+    }
+
+    fn u64(&mut self) -> Result<u64, ImageError> {
+        Ok(u64::from_le_bytes(self.take(8)?.try_into().expect("took 8 bytes")))
+    }
+
+    fn index(&mut self) -> Result<usize, ImageError> {
+        usize::try_from(self.u64()?).map_err(|_| damaged())
+    }
+
+    fn string(&mut self) -> Result<String, ImageError> {
+        let length = self.index()?;
+
+        String::from_utf8(self.take(length)?.to_vec()).map_err(|_| damaged())
+    }
+
+    fn strings(&mut self) -> Result<Vec<String>, ImageError> {
+        (0..self.index()?).map(|_| self.string()).collect()
+    }
+
+    fn span(&mut self) -> Result<Span, ImageError> {
+        Ok(Span { start: self.u64()? as i128, end: self.u64()? as i128, line: self.u64()? as i128, end_line: self.u64()? as i128 })
+    }
+
+    fn program(&mut self) -> Result<CompiledProgram, ImageError> {
+        let main = match self.u8()? {
+            0 => None,
+            _ => Some(self.index()?),
+        };
+
+        let mut functions = Vec::new();
+        for _ in 0..self.index()? {
+            let name = self.string()?;
+
+            let mut parameters = Vec::new();
+            for _ in 0..self.index()? {
+                let param_name = self.string()?;
+                let param_type = self.string()?;
+                parameters.push(FunctionParameter { param_type, param_name, span: Span::default() });
+            }
+
+            functions.push(CompiledFunction {
+                name,
+                parameters,
+                return_type: self.string()?,
+                slot_count: self.index()?,
+                chunk: self.chunk()?,
+            });
+        }
+
+        let mut function_indices = HashMap::new();
+        for (index, function) in functions.iter().enumerate() {
+            function_indices.insert(function.name.clone(), index);
+        }
+
+        let program = CompiledProgram { functions, function_indices, main };
+        validate(&program)?;
+
+        Ok(program)
+    }
+
+    fn chunk(&mut self) -> Result<Chunk, ImageError> {
+        let code = (0..self.index()?).map(|_| self.instruction()).collect::<Result<Vec<_>, _>>()?;
+        let constants = (0..self.index()?).map(|_| self.constant()).collect::<Result<Vec<_>, _>>()?;
+        let names = self.strings()?;
+
+        let mut builtin_calls = Vec::new();
+        for _ in 0..self.index()? {
+            let name = self.string()?;
+            let function = find_builtin(&name)
+                .ok_or_else(|| ImageError::new(ImageErrorKind::UnknownBuiltin, format!("The image calls the unknown builtin '{name}'.")))?;
+
+            builtin_calls.push(BuiltinCall { name, function: Some(function), arguments: self.strings()? });
+        }
+
+        let call_sites = (0..self.index()?).map(|_| Ok((self.index()?, self.span()?))).collect::<Result<Vec<_>, ImageError>>()?;
+        let statements = (0..self.index()?).map(|_| Ok((self.index()?, self.span()?))).collect::<Result<Vec<_>, ImageError>>()?;
+
+        Ok(Chunk { code, constants, names, builtin_calls, call_sites, statements })
+    }
+
+    fn instruction(&mut self) -> Result<Instruction, ImageError> {
+        Ok(match self.u8()? {
+            0 => Instruction::Constant(self.index()?),
+            1 => Instruction::Nothing,
+            2 => Instruction::Load(self.index()?),
+            3 => Instruction::Store(self.index()?),
+            4 => Instruction::Pop,
+            5 => Instruction::Coerce(self.index()?),
+            6 => Instruction::List(self.index()?),
+            7 => Instruction::Field(self.index()?),
+            8 => Instruction::Unary(*UNARY_TYPES.get(self.u8()? as usize).ok_or_else(damaged)?),
+            9 => Instruction::Binary(self.binary_type()?),
+            10 => Instruction::ShortCircuit(self.binary_type()?, self.index()?),
+            11 => Instruction::Jump(self.index()?),
+            12 => Instruction::Call(self.index()?),
+            13 => Instruction::CallBuiltin(self.index()?),
+            14 => Instruction::Return,
+            15 => Instruction::ReturnNothing,
+            16 => Instruction::Fail(self.index()?, RuntimeErrorKind::from_code(&self.string()?).ok_or_else(damaged)?),
+            17 => Instruction::Global(self.index()?),
+            18 => Instruction::Try(self.index()?),
+            19 => Instruction::EndTry,
+            _ => return Err(damaged()),
+        })
+    }
+
+    fn binary_type(&mut self) -> Result<BinaryType, ImageError> {
+        BinaryType::from_symbol(&self.string()?).ok_or_else(damaged)
+    }
+
+    fn constant(&mut self) -> Result<Value, ImageError> {
+        Ok(match self.u8()? {
+            0 => Value::Nothing,
+            1 => Value::NA,
+            2 => Value::Boolean(self.u8()? != 0),
+            3 => Value::Integer(i128::from_le_bytes(self.take(16)?.try_into().expect("took 16 bytes"))),
+            4 => Value::Double(f64::from_bits(self.u64()?)),
+            5 => Value::String(self.string()?),
+            6 => Value::Character(char::from_u32(self.u32()?).ok_or_else(damaged)?),
+            _ => return Err(damaged()),
+        })
+    }
+}
+
+fn damaged() -> ImageError {
+    ImageError::new(ImageErrorKind::Damaged, "The image is damaged.".into())
+}
+
+/// Checks that every index in the program points into its tables, so the VM can rely on them.
+fn validate(program: &CompiledProgram) -> Result<(), ImageError> {
+    if program.main.is_some_and(|main| main >= program.functions.len()) {
+        return Err(damaged());
+    }
+
+    for function in program.functions.iter() {
+        let chunk = &function.chunk;
+
+        if function.slot_count < function.parameters.len() || chunk.code.is_empty() {
+            return Err(damaged());
+        }
+
+        let valid = chunk.code.iter().all(|instruction| match *instruction {
+            Instruction::Constant(index) => index < chunk.constants.len(),
+            Instruction::Load(slot) | Instruction::Store(slot) => slot < function.slot_count,
+            Instruction::Coerce(name) | Instruction::Global(name) | Instruction::Field(name) | Instruction::Fail(name, _) => name < chunk.names.len(),
+            Instruction::ShortCircuit(_, target) | Instruction::Jump(target) | Instruction::Try(target) => target < chunk.code.len(),
+            Instruction::Call(index) => index < program.functions.len(),
+            Instruction::CallBuiltin(index) => index < chunk.builtin_calls.len(),
+            _ => true,
+        });
+
+        let calls_found = chunk.call_sites.windows(2).all(|pair| pair[0].0 < pair[1].0)
+            && chunk.call_sites.iter().all(|(call, _)| matches!(chunk.code.get(*call), Some(Instruction::Call(_))));
+
+        let statements_sorted = chunk.statements.windows(2).all(|pair| pair[0].0 <= pair[1].0);
+
+        if !valid || !calls_found || !statements_sorted || !balanced(function, &program.functions) {
+            return Err(damaged());
+        }
+    }
+
+    Ok(())
+}
+
+/// Whether every instruction of the function that can run finds the values it takes on the stack,
+/// above the slots of the frame, with the same height on every path to it, and no path runs past
+/// the end of the code. The VM trusts this instead of checking the stack as it runs.
+fn balanced(function: &CompiledFunction, functions: &[CompiledFunction]) -> bool {
+    let code = &function.chunk.code;
+    let mut heights: Vec<Option<usize>> = vec![None; code.len()];
+    let mut pending = vec![(0, function.slot_count)];
+
+    while let Some((ip, height)) = pending.pop() {
+        match heights.get(ip) {
+            None => return false,
+            Some(Some(known)) if *known != height => return false,
+            Some(Some(_)) => continue,
+            Some(None) => heights[ip] = Some(height),
+        }
+
+        let (taken, pushed) = match code[ip] {
+            Instruction::Constant(_) | Instruction::Nothing | Instruction::Load(_) | Instruction::Global(_) => (0, 1),
+            Instruction::Store(_) | Instruction::Pop | Instruction::Return => (1, 0),
+            Instruction::Coerce(_) | Instruction::Field(_) | Instruction::Unary(_) | Instruction::ShortCircuit(..) => (1, 1),
+            Instruction::Binary(_) => (2, 1),
+            Instruction::List(count) => (count, 1),
+            Instruction::Call(index) => (functions[index].parameters.len(), 1),
+            Instruction::CallBuiltin(index) => (function.chunk.builtin_calls[index].arguments.len(), 1),
+            Instruction::Jump(_) | Instruction::ReturnNothing | Instruction::Fail(..) | Instruction::Try(_) | Instruction::EndTry => (0, 0),
+        };
+
+        let Some(rest) = height.checked_sub(taken).filter(|rest| *rest >= function.slot_count) else {
+            return false;
+        };
+        let next = rest + pushed;
+
+        match code[ip] {
+            Instruction::Jump(target) => pending.push((target, next)),
+            Instruction::ShortCircuit(_, target) => pending.extend([(target, next), (ip + 1, next)]),
+            // the `catch` block starts with the error record on the stack of the `try`
+            Instruction::Try(target) => pending.extend([(target, height + 1), (ip + 1, next)]),
+            Instruction::Return | Instruction::ReturnNothing | Instruction::Fail(..) => {}
+            _ => pending.push((ip + 1, next)),
+        }
+    }
+
+    true
+}
+
+#[cfg(test)]
+mod image_tests {
+    use crate::error::image::ImageErrorKind;
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::parse::parser::StatParser;
+    use crate::runtime::compiler::compile;
+    use crate::runtime::bytecode::Instruction;
+    use crate::runtime::image::{Image, FORMAT_VERSION};
+
+    const SOURCE: &str = "func scale(x @f64, by @f64) @f64 { return x * by }
+func main(n @int) @i32 {
+    set<list> values <- [1, 2.5, \"three\", NA, true]
+    set<bool> b <- !false & n > 0 | -n < 0
+    builtin println(template = scale(x = 2, by = 0.5))
+    return n % 3
+}";
+
+    #[test]
+    fn test_round_trip() {
+        let ast = StatParser::new(Tokenizer::new(SOURCE.into())).parse().unwrap();
+        let program = compile(&ast);
+        let image = Image::new(SOURCE, Some("script.stsc".into()), Vec::new(), program.clone());
+
+        let loaded = Image::from_bytes(&image.to_bytes()).unwrap();
+
+        assert!(loaded.is_fresh(SOURCE));
+        assert!(!loaded.is_fresh(&SOURCE.replace("0.5", "0.25")));
+        assert_eq!(loaded.source_path.as_deref(), Some("script.stsc"));
+        assert_eq!(loaded.program.to_string(), program.to_string());
+        assert_eq!(loaded.program.main, program.main);
+    }
+
+    #[test]
+    fn test_rejects_other_versions_and_damage() {
+        let ast = StatParser::new(Tokenizer::new(SOURCE.into())).parse().unwrap();
+        let bytes = Image::new(SOURCE, None, Vec::new(), compile(&ast)).to_bytes();
+
+        let mut other_format = bytes.clone();
+        other_format[4..8].copy_from_slice(&(FORMAT_VERSION + 1).to_le_bytes());
+        let kind = |bytes: &[u8]| Image::from_bytes(bytes).err().map(|error| error.kind);
+
+        assert_eq!(kind(&other_format), Some(ImageErrorKind::IncompatibleVersion));
+        assert_eq!(kind(&bytes[..bytes.len() - 1]), Some(ImageErrorKind::Truncated));
+        assert_eq!(kind(SOURCE.as_bytes()), Some(ImageErrorKind::NotAnImage));
+    }
+
+    #[test]
+    fn test_rejects_unbalanced_stacks() {
+        let source = "func main() @i32 {
+    set<list> values <- [1, true & false | true]
+    try { builtin println(template = values) } catch e { builtin println(template = e.message) }
+    return 0
+}";
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+        let program = compile(&ast);
+        assert!(Image::from_bytes(&Image::new(source, None, Vec::new(), program.clone()).to_bytes()).is_ok());
+
+        let damages: [fn(&mut Vec<Instruction>); 3] = [
+            |code| for instruction in code.iter_mut() {
+                if let Instruction::List(count) = instruction {
+                    *count += 1;
+                }
+            },
+            |code| code.insert(0, Instruction::Pop),
+            |code| code.retain(|instruction| !matches!(instruction, Instruction::Return | Instruction::ReturnNothing)),
+        ];
+
+        for damage in damages {
+            let mut damaged = program.clone();
+            damage(&mut damaged.functions[0].chunk.code);
+
+            let bytes = Image::new(source, None, Vec::new(), damaged).to_bytes();
+            assert_eq!(Image::from_bytes(&bytes).err().map(|error| error.kind), Some(ImageErrorKind::Damaged));
+        }
+    }
+}
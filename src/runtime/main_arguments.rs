@@ -0,0 +1,193 @@
+//! Binds the command line arguments of a script to the parameters of its `main` function.
+//!
+//! Arguments are passed either by name as `--name=value` or positionally, in which case they fill
+//! the parameters not given by name in declaration order. A bare `--name` sets a `bool` parameter.
+//! A `main` without parameters leaves all arguments to the `args()` builtin.
+
+use std::collections::HashMap;
+
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::parse::parser::{FunctionDefinition, FunctionParameter};
+use crate::runtime::value::Value;
+
+pub fn bind(definition: &FunctionDefinition, raw: &[String]) -> Result<HashMap<String, Value>, RuntimeError> {
+    if definition.signature.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let mut named: HashMap<String, Value> = HashMap::new();
+    let mut positional: Vec<&String> = Vec::new();
+
+    for argument in raw {
+        let Some(option) = argument.strip_prefix("--") else {
+            positional.push(argument);
+            continue;
+        };
+
+        let (name, text) = match option.split_once('=') {
+            Some((name, text)) => (name, Some(text)),
+            None => (option, None)
+        };
+
+        let parameter = definition.signature.iter()
+            .find(|p| p.param_name == name)
+            .ok_or_else(|| RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Unknown argument '--{name}'.")))?;
+
+        let value = match text {
+            Some(text) => convert(parameter, text)?,
+            None if parameter.param_type == "bool" => Value::Boolean(true),
+            None => return Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Argument '--{name}' needs a value, e.g. '--{name}=...'.")))
+        };
+
+        if named.insert(name.to_string(), value).is_some() {
+            return Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Argument '--{name}' is given more than once.")));
+        }
+    }
+
+    let mut positional = positional.into_iter();
+    let mut values = HashMap::new();
+
+    for parameter in definition.signature.iter() {
+        let value = match named.remove(&parameter.param_name) {
+            Some(value) => value,
+            None => match positional.next() {
+                Some(text) => convert(parameter, text)?,
+                None => return Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Missing argument '{}'.", parameter.param_name)))
+            }
+        };
+
+        values.insert(parameter.param_name.clone(), value);
+    }
+
+    if let Some(extra) = positional.next() {
+        return Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Unexpected argument '{extra}'.")));
+    }
+
+    Ok(values)
+}
+
+/// A usage line listing the parameters of `main`, e.g. `--name=<string> --runs=<i32>`.
+pub fn usage(definition: &FunctionDefinition) -> String {
+    definition.signature.iter()
+        .map(|p| format!("--{}=<{}>", p.param_name, p.param_type))
+        .collect::<Vec<String>>()
+        .join(" ")
+}
+
+/// Reads the text of an argument as the declared type of its parameter.
+fn convert(parameter: &FunctionParameter, text: &str) -> Result<Value, RuntimeError> {
+    let invalid = || RuntimeError::of_kind(RuntimeErrorKind::InvalidArgument, format!(
+        "Argument '{}' expects '{}' but got '{text}'.", parameter.param_name, parameter.param_type
+    ));
+
+    if text == "NA" {
+        return Ok(Value::NA);
+    }
+
+    let value = match parameter.param_type.as_str() {
+        "any" => infer(text),
+        "list" => Value::List(text.split(',').map(|element| infer(element.trim())).collect()),
+        "f32" | "f64" | "double" => Value::Double(text.parse().map_err(|_| invalid())?),
+        "bool" => Value::Boolean(text.parse().map_err(|_| invalid())?),
+        "string" => Value::String(text.to_string()),
+        "char" => {
+            let mut chars = text.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), None) => Value::Character(c),
+                _ => return Err(invalid())
+            }
+        }
+        // everything else is an integer type, or a type that can not be written on a command line
+        _ => Value::Integer(text.parse().map_err(|_| invalid())?)
+    };
+
+    value.coerce(&parameter.param_type).map_err(|_| invalid())
+}
+
+/// Reads untyped text as an integer, double or boolean where it looks like one.
+fn infer(text: &str) -> Value {
+    if text == "NA" {
+        return Value::NA;
+    }
+
+    if let Ok(i) = text.parse::<i128>() {
+        return Value::Integer(i);
+    }
+
+    if let Ok(d) = text.parse::<f64>() {
+        return Value::Double(d);
+    }
+
+    match text {
+        "true" => Value::Boolean(true),
+        "false" => Value::Boolean(false),
+        _ => Value::String(text.to_string())
+    }
+}
+
+#[cfg(test)]
+mod main_arguments_tests {
+    use crate::error::runtime::RuntimeErrorKind;
+    use crate::lexer::tokenizer::Span;
+    use crate::parse::parser::{FunctionDefinition, FunctionParameter, Node, NodeType};
+    use crate::runtime::main_arguments::bind;
+    use crate::runtime::value::Value;
+
+    fn main_with(parameters: &[(&str, &str)]) -> FunctionDefinition {
+        FunctionDefinition {
+            public: false,
+            name: "main".into(),
+            name_span: Span::default(),
+            signature: parameters.iter()
+                .map(|(name, param_type)| FunctionParameter {
+                    param_name: name.to_string(),
+                    param_type: param_type.to_string(),
+                    span: Span::default(),
+                })
+                .collect(),
+            return_type: "i32".into(),
+            body: Node::new(NodeType::Block(Vec::new()), Span::default()),
+            module: None,
+        }
+    }
+
+    fn strings(raw: &[&str]) -> Vec<String> {
+        raw.iter().map(|s| s.to_string()).collect()
+    }
+
+    #[test]
+    fn test_named_and_positional() {
+        let main = main_with(&[("path", "string"), ("runs", "i32"), ("verbose", "bool")]);
+        let values = bind(&main, &strings(&["--runs=3", "data.csv", "--verbose"])).unwrap();
+
+        assert_eq!(values["path"], Value::String("data.csv".into()));
+        assert_eq!(values["runs"], Value::Integer(3));
+        assert_eq!(values["verbose"], Value::Boolean(true));
+    }
+
+    #[test]
+    fn test_usage_errors() {
+        let main = main_with(&[("runs", "i32")]);
+
+        let kind = |arguments: &[&str]| bind(&main, &strings(arguments)).unwrap_err().kind;
+
+        assert_eq!(kind(&["--runs=many"]), RuntimeErrorKind::InvalidArgument);
+        assert_eq!(kind(&[]), RuntimeErrorKind::ArgumentMismatch);
+        assert_eq!(kind(&["1", "2"]), RuntimeErrorKind::ArgumentMismatch);
+        assert_eq!(kind(&["--walks=1"]), RuntimeErrorKind::ArgumentMismatch);
+    }
+
+    #[test]
+    fn test_integer_ranges() {
+        let main = main_with(&[("small", "u8"), ("signed", "i8")]);
+
+        let bound = |arguments: &[&str]| bind(&main, &strings(arguments)).map_err(|e| e.kind);
+
+        let values = bound(&["255", "-128"]).unwrap();
+        assert_eq!((&values["small"], &values["signed"]), (&Value::Integer(255), &Value::Integer(-128)));
+
+        for arguments in [["-300", "0"], ["256", "0"], ["-1", "0"], ["0", "128"], ["0", "-129"]] {
+            assert_eq!(bound(&arguments).unwrap_err(), RuntimeErrorKind::InvalidArgument, "{arguments:?}");
+        }
+    }
+}
@@ -1,2 +1,18 @@
-mod frame;
-pub mod program;
\ No newline at end of file
+pub mod builtins;
+pub mod bytecode;
+pub mod cancellation;
+pub mod compiler;
+pub mod convert;
+pub mod debug;
+pub mod frame;
+pub mod host;
+pub mod image;
+pub mod main_arguments;
+pub mod native;
+pub mod program;
+pub mod random;
+pub mod record;
+pub mod sandbox;
+pub mod table;
+pub mod value;
+mod vm;
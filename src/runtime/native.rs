@@ -0,0 +1,184 @@
+//! Functions an embedding application provides to scripts, called like builtins:
+//! `builtin lookup(key = "a")`.
+//!
+//! A native function declares its parameters, which the checker verifies calls against before
+//! the script runs, and the runtime again before calling it. Plain closures declare nothing and
+//! take any arguments; [`Native`] gives a closure a signature.
+
+use crate::error::runtime::RuntimeError;
+use crate::runtime::builtins::Arguments;
+use crate::runtime::value::Value;
+
+pub trait NativeFunction {
+    fn signature(&self) -> NativeSignature;
+
+    /// Calls the function with arguments that match its signature, converted to the declared
+    /// types.
+    fn call(&self, arguments: &mut Arguments) -> Result<Value, RuntimeError>;
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeParameter {
+    pub name: String,
+    /// A type name as in scripts, e.g. `f64` or `list`.
+    pub param_type: String,
+    pub optional: bool,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct NativeSignature {
+    pub parameters: Vec<NativeParameter>,
+    /// The type of the named arguments a variadic function takes besides its parameters, which
+    /// it reads with [`Arguments::rest`].
+    pub variadic: Option<String>,
+    pub return_type: String,
+}
+
+impl NativeSignature {
+    /// The signature of a function taking any arguments and returning anything.
+    pub fn any() -> Self {
+        Self {
+            parameters: Vec::new(),
+            variadic: Some("any".into()),
+            return_type: "any".into(),
+        }
+    }
+
+    pub fn parameter(&self, name: &str) -> Option<&NativeParameter> {
+        self.parameters.iter().find(|parameter| parameter.name == name)
+    }
+}
+
+impl<F: Fn(&mut Arguments) -> Result<Value, RuntimeError>> NativeFunction for F {
+    fn signature(&self) -> NativeSignature {
+        NativeSignature::any()
+    }
+
+    fn call(&self, arguments: &mut Arguments) -> Result<Value, RuntimeError> {
+        self(arguments)
+    }
+}
+
+/// A closure with a declared signature, e.g.
+/// `Native::new(|arguments| ...).parameter("key", "string").optional("limit", "int").returns("list")`.
+pub struct Native<F> {
+    signature: NativeSignature,
+    function: F,
+}
+
+impl<F: Fn(&mut Arguments) -> Result<Value, RuntimeError>> Native<F> {
+    /// A function without parameters returning anything.
+    pub fn new(function: F) -> Self {
+        Self {
+            signature: NativeSignature { parameters: Vec::new(), variadic: None, return_type: "any".into() },
+            function,
+        }
+    }
+
+    pub fn parameter(self, name: &str, param_type: &str) -> Self {
+        self.with_parameter(name, param_type, false)
+    }
+
+    pub fn optional(self, name: &str, param_type: &str) -> Self {
+        self.with_parameter(name, param_type, true)
+    }
+
+    /// Accepts any further named arguments of the type.
+    pub fn variadic(mut self, param_type: &str) -> Self {
+        self.signature.variadic = Some(param_type.into());
+        self
+    }
+
+    pub fn returns(mut self, return_type: &str) -> Self {
+        self.signature.return_type = return_type.into();
+        self
+    }
+
+    fn with_parameter(mut self, name: &str, param_type: &str, optional: bool) -> Self {
+        self.signature.parameters.push(NativeParameter { name: name.into(), param_type: param_type.into(), optional });
+        self
+    }
+}
+
+impl<F: Fn(&mut Arguments) -> Result<Value, RuntimeError>> NativeFunction for Native<F> {
+    fn signature(&self) -> NativeSignature {
+        self.signature.clone()
+    }
+
+    fn call(&self, arguments: &mut Arguments) -> Result<Value, RuntimeError> {
+        (self.function)(arguments)
+    }
+}
+
+#[cfg(test)]
+mod native_tests {
+    use crate::engine::Engine;
+    use crate::error::checker::CheckErrorKind;
+    use crate::error::engine::EngineError;
+    use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+    use crate::runtime::convert::IntoValue;
+    use crate::runtime::native::Native;
+    use crate::runtime::program::Evaluator;
+    use crate::runtime::value::Value;
+
+    fn engine(evaluator: Evaluator) -> Engine {
+        let mut engine = Engine::new().with_evaluator(evaluator);
+
+        let query = Native::new(|arguments| {
+            let table = arguments.string("table")?;
+            let limit = arguments.optional("limit").unwrap_or(Value::Integer(10));
+            let filters: Vec<String> = arguments.rest().into_iter().map(|(name, value)| format!("{name}={value}")).collect();
+
+            if table.is_empty() {
+                return Err(RuntimeError::of_kind(RuntimeErrorKind::InvalidArgument, "The table name is empty.".into()));
+            }
+
+            Ok(format!("{table} {limit} {}", filters.join(",")).into_value())
+        });
+        engine.register_native("query", query.parameter("table", "string").optional("limit", "int").variadic("any").returns("string"));
+
+        let scale = Native::new(|arguments| Ok(Value::Double(arguments.number("x")? * 2.0)));
+        engine.register_native("scale", scale.parameter("x", "f64").returns("f64"));
+
+        engine
+    }
+
+    #[test]
+    fn test_calls_natives_with_signatures() {
+        let source = "func run(table @string) @string {
+                return builtin query(table = table, year = 2020, region = \"north\") + builtin query(table = table, limit = 3)
+            }
+            func twice(x @int) @f64 { return builtin scale(x = x) }";
+
+        for evaluator in [Evaluator::TreeWalker, Evaluator::Bytecode] {
+            let mut engine = engine(evaluator);
+            engine.compile(source).unwrap();
+
+            assert_eq!(engine.call::<String>("run", ("sales",)).unwrap(), "sales 10 region=north,year=2020sales 3 ");
+            assert_eq!(engine.call::<f64>("twice", (4,)).unwrap(), 8.0);
+            let Err(EngineError::Runtime(error)) = engine.call::<String>("run", ("",)) else { panic!() };
+            assert_eq!(error.kind, RuntimeErrorKind::InvalidArgument);
+        }
+    }
+
+    #[test]
+    fn test_checks_calls_of_natives() {
+        let source = "func main() {
+                builtin scale(x = \"a\")
+                builtin scale(y = 1)
+                set<int> wrong <- builtin query(table = \"t\")
+            }";
+
+        let Err(EngineError::Check(errors)) = engine(Evaluator::Bytecode).compile(source) else {
+            panic!("the calls do not match the signatures");
+        };
+
+        let kinds: Vec<CheckErrorKind> = errors.iter().map(|e| e.kind).collect();
+        assert_eq!(kinds, [
+            CheckErrorKind::TypeMismatch,
+            CheckErrorKind::ArgumentMismatch,
+            CheckErrorKind::ArgumentMismatch,
+            CheckErrorKind::TypeMismatch,
+        ]);
+    }
+}
@@ -1,95 +1,604 @@
 use std::cell::RefCell;
-use std::ops::Deref;
+use std::collections::HashMap;
+use std::io::Write;
 use std::rc::Rc;
 use std::time::Instant;
-use crate::error::runtime::RuntimeError;
-use crate::parse::parser::{Node, NodeType};
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::lexer::tokenizer::Span;
+use crate::parse::parser::{BinaryType, FunctionCall, FunctionDefinition, Node, NodeType, TryCatch, VariableDeclaration};
+use crate::runtime::builtins::{find_builtin, is_system_builtin, Arguments, BuiltinFunction};
+use crate::runtime::bytecode::CompiledProgram;
+use crate::runtime::cancellation::CancellationToken;
+use crate::runtime::compiler;
+use crate::runtime::debug::{DebugFrame, Debugger};
 use crate::runtime::frame::Frame;
+use crate::runtime::host::Host;
+use crate::runtime::native::NativeFunction;
+use crate::runtime::main_arguments;
+use crate::runtime::random::Random;
+use crate::runtime::sandbox::{Sandbox, Usage};
+use crate::runtime::value::Value;
+use crate::runtime::vm;
 
-pub struct Program<'a> {
+pub struct Program {
     pub started_at: Instant,
     pub ast: Node,
-    pub top_level_frame: Rc<RefCell<Frame<'a>>>,
-    pub current_frame: Rc<RefCell<Frame<'a>>>
+    pub top_level_frame: Rc<RefCell<Frame>>,
+    pub current_frame: Rc<RefCell<Frame>>,
+    pub output: Box<dyn Write>,
+    pub random: Random,
+    /// The command line arguments of the script, bound to the parameters of `main`.
+    pub arguments: Vec<String>,
+    pub evaluator: Evaluator,
+    functions: HashMap<String, FunctionDefinition>,
+    host: Host,
+    pub sandbox: Sandbox,
+    usage: Usage,
+    pub cancellation: CancellationToken,
+    pub debugger: Option<Debugger>,
+    /// The running calls of script functions, outermost first, kept while a debugger is attached.
+    pub(crate) debug_calls: Vec<DebugFrame>,
+    /// The bytecode of the program, compiled when it first runs on the bytecode engine, or loaded
+    /// from a compiled image, which has no AST.
+    compiled: Option<Rc<CompiledProgram>>,
+}
+
+/// How [`Program::execute`] runs the program. Both give the same results.
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum Evaluator {
+    /// Evaluates the AST directly.
+    #[default]
+    TreeWalker,
+    /// Compiles the program to bytecode first, which runs faster.
+    Bytecode,
 }
 
 
-type RuntimeReturn = Option<RuntimeError>;
+pub(crate) type EvalReturn = Result<Value, RuntimeError>;
+type StatementReturn = Result<Completion, RuntimeError>;
 
+/// How a statement finished: either normally, or by a `return` unwinding to the enclosing function.
+enum Completion {
+    Normal,
+    Return(Value),
+}
 
 
-impl<'a> Program<'a> {
+impl Program {
     pub fn new(ast: Node) -> Self {
         let frame = Rc::new(RefCell::new(Frame::new(None)));
 
-        
+        let mut functions = HashMap::new();
+        if let NodeType::Program(nodes) = ast.node_type.as_ref() {
+            for node in nodes {
+                if let NodeType::FunctionDefinition(definition) = node.node_type.as_ref() {
+                    functions.insert(definition.name.clone(), definition.clone());
+                }
+            }
+        }
+
+
         Self {
             started_at: Instant::now(),
             ast,
             top_level_frame: Rc::clone(&frame),
-            current_frame: frame
+            current_frame: frame,
+            output: Box::new(std::io::stdout()),
+            random: Random::from_time(),
+            arguments: Vec::new(),
+            evaluator: Evaluator::default(),
+            functions,
+            host: Host::default(),
+            sandbox: Sandbox::default(),
+            usage: Usage::default(),
+            cancellation: CancellationToken::new(),
+            debugger: None,
+            debug_calls: Vec::new(),
+            compiled: None,
         }
     }
 
-    pub fn start(&self) -> RuntimeReturn {
+    /// A program that runs already compiled bytecode, e.g. from an image. It only runs on the
+    /// bytecode engine.
+    pub fn from_compiled(compiled: CompiledProgram) -> Self {
+        let mut program = Self::new(Node::new(NodeType::Program(Vec::new()), Span::default())).with_evaluator(Evaluator::Bytecode);
+        program.compiled = Some(Rc::new(compiled));
 
-        self.execute_entrypoint(&self.ast)?;
+        program
+    }
 
-        None
+    /// Replaces the sink `println` writes to, e.g. to capture the output of a script.
+    pub fn with_output(mut self, output: Box<dyn Write>) -> Self {
+        self.output = output;
+        self
     }
 
+    pub fn with_arguments(mut self, arguments: Vec<String>) -> Self {
+        self.arguments = arguments;
+        self
+    }
 
-    fn execute_entrypoint(&self, node: &Node) -> RuntimeReturn {
-        match node.node_type.as_ref() {
-            NodeType::FunctionDefinition(def) => {
-                self.execute_node(&def.body)
-            },
-            _ => Some(RuntimeError::expected_node_type("function definition", node.node_type.as_ref()))
+    pub fn with_evaluator(mut self, evaluator: Evaluator) -> Self {
+        self.evaluator = evaluator;
+        self
+    }
+
+    /// Provides the functions and globals of an embedding application to the program. Host
+    /// functions have to be there before the program first runs on the bytecode engine, which
+    /// resolves calls when it compiles the program.
+    pub fn with_host(mut self, host: Host) -> Self {
+        for (name, value) in host.globals.iter() {
+            self.top_level_frame.borrow_mut().declare_variable(name.clone(), value.clone());
+        }
+
+        self.host = host;
+        self
+    }
+
+    pub fn with_sandbox(mut self, sandbox: Sandbox) -> Self {
+        self.sandbox = sandbox;
+        self
+    }
+
+    /// Lets the token stop the program, see [`CancellationToken`].
+    pub fn with_cancellation(mut self, cancellation: CancellationToken) -> Self {
+        self.cancellation = cancellation;
+        self
+    }
+
+    /// Lets the debugger pause the program before its statements. Debugging runs on the
+    /// tree-walking evaluator.
+    pub fn with_debugger(mut self, debugger: Debugger) -> Self {
+        self.debugger = Some(debugger);
+        self.evaluator = Evaluator::TreeWalker;
+        self
+    }
+
+    pub fn host(&self) -> &Host {
+        &self.host
+    }
+
+    /// Defines (or redefines) a function of the host, see [`Program::with_host`].
+    pub fn define_host_function(&mut self, name: String, function: Rc<dyn NativeFunction>) {
+        self.host.functions.insert(name, function);
+    }
+
+    /// Defines (or redefines) a global, which scripts see from then on.
+    pub fn define_global(&mut self, name: String, value: Value) {
+        self.top_level_frame.borrow_mut().declare_variable(name.clone(), value.clone());
+        self.host.globals.insert(name, value);
+    }
+
+    /// Defines (or redefines) a function callable from any later code.
+    pub fn define_function(&mut self, definition: FunctionDefinition) {
+        self.functions.insert(definition.name.clone(), definition);
+    }
+
+    pub fn function(&self, name: &str) -> Option<&FunctionDefinition> {
+        self.functions.get(name)
+    }
+
+    pub(crate) fn functions(&self) -> &HashMap<String, FunctionDefinition> {
+        &self.functions
+    }
+
+    /// Runs one interactive entry in the top-level frame, so its variables and functions stay
+    /// visible to later entries. Returns the value of the entry if it ends in an expression.
+    pub fn evaluate_entry(&mut self, nodes: &[Node]) -> Result<Option<Value>, RuntimeError> {
+        let mut last_value = None;
+
+        for node in nodes {
+            last_value = None;
+
+            match node.node_type.as_ref() {
+                NodeType::FunctionDefinition(definition) => self.define_function(definition.clone()),
+                NodeType::ReturnExpression(_) => {
+                    return Err(RuntimeError::of_kind(RuntimeErrorKind::InvalidProgram, "'return' is only allowed inside a function.".to_string()));
+                }
+                NodeType::Import(_) => {
+                    return Err(RuntimeError::of_kind(RuntimeErrorKind::InvalidProgram, "Modules can only be imported by scripts.".to_string()));
+                }
+                NodeType::VariableDeclaration(declaration) => {
+                    self.execute_variable_declaration(declaration)?;
+                }
+                NodeType::Block(_) | NodeType::TryCatch(_) => {
+                    if let Completion::Return(_) = self.execute_node(node)? {
+                        return Err(RuntimeError::of_kind(RuntimeErrorKind::InvalidProgram, "'return' is only allowed inside a function.".to_string()));
+                    }
+                }
+                _ => last_value = Some(self.evaluate(node)?)
+            }
+        }
+
+        Ok(last_value)
+    }
+
+    /// Runs an entry like [`Program::evaluate_entry`] in the frame, e.g. of a paused function.
+    pub(crate) fn evaluate_entry_in(&mut self, frame: Rc<RefCell<Frame>>, nodes: &[Node]) -> Result<Option<Value>, RuntimeError> {
+        self.with_frame(frame, |program| program.evaluate_entry(nodes))
+    }
+
+    fn start(&mut self, main_method: &FunctionDefinition) -> Result<Value, RuntimeError> {
+        let arguments = main_arguments::bind(main_method, &self.arguments)?;
+
+        match self.evaluator {
+            Evaluator::TreeWalker => self.call_function(main_method, arguments, None),
+            Evaluator::Bytecode => {
+                let compiled = self.compiled();
+                let main = compiled.main.expect("the program has a main method");
+
+                vm::call(self, &compiled, main, arguments)
+            }
         }
     }
 
-    fn execute_node(&self, node: &Node) -> RuntimeReturn {
+    fn compiled(&mut self) -> Rc<CompiledProgram> {
+        let compiled = match self.compiled.take() {
+            Some(compiled) => compiled,
+            None => Rc::new(compiler::compile_with(&self.ast, &self.host)),
+        };
+
+        self.compiled = Some(Rc::clone(&compiled));
+        compiled
+    }
+
+    /// Calls a function of the script with arguments for its parameters in order, e.g. from an
+    /// application embedding the interpreter. A call of `exit(code)` fails with the exit code set.
+    pub fn call(&mut self, name: &str, arguments: Vec<Value>) -> Result<Value, RuntimeError> {
+        self.usage = Usage::default();
+
+        let compiled = match self.evaluator {
+            Evaluator::TreeWalker => None,
+            Evaluator::Bytecode => Some(self.compiled()),
+        };
+
+        let definition = match &compiled {
+            Some(compiled) => compiled.function_indices.get(name).map(|index| compiled.functions[*index].declaration()),
+            None => self.functions.get(name).cloned(),
+        };
+        let definition = definition.ok_or_else(|| RuntimeError::unknown_function(name))?;
+
+        if arguments.len() != definition.signature.len() {
+            return Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!(
+                "Function '{name}' takes {} argument(s) but got {}.", definition.signature.len(), arguments.len()
+            )));
+        }
+
+        let mut values = HashMap::new();
+        for (parameter, argument) in definition.signature.iter().zip(arguments) {
+            values.insert(parameter.param_name.clone(), argument.coerce(&parameter.param_type)?);
+        }
+
+        match compiled {
+            Some(compiled) => vm::call(self, &compiled, compiled.function_indices[name], values),
+            None => self.call_function(&definition, values, None),
+        }
+    }
+
+    /// Calls a builtin, or the host function named like the call if there is no builtin.
+    pub(crate) fn call_builtin(&mut self, builtin: Option<BuiltinFunction>, mut arguments: Arguments) -> EvalReturn {
+        if !self.sandbox.system_access && is_system_builtin(&arguments.function) {
+            return Err(RuntimeError::capability_denied(&arguments.function));
+        }
+
+        // a builtin takes time for every value it goes through
+        self.usage.work(&self.sandbox, arguments.value_count() as u64)?;
+
+        if let Some(builtin) = builtin {
+            let result = builtin(self, &mut arguments)?;
+            arguments.finish()?;
+
+            return Ok(result);
+        }
+
+        let function = self.host.function(&arguments.function)
+            .ok_or_else(|| RuntimeError::unknown_builtin(&arguments.function))?;
+
+        // the arguments are checked against the signature up front, so a native function does
+        // not have to read optional ones
+        let signature = function.signature();
+        arguments.bind(&signature)?;
+
+        function.call(&mut arguments)?.coerce(&signature.return_type)
+    }
+
+    fn call_function(&mut self, definition: &FunctionDefinition, arguments: HashMap<String, Value>, call_site: Option<Span>) -> EvalReturn {
+        let frame = Rc::new(RefCell::new(Frame::new(Some(Rc::clone(&self.top_level_frame)))));
+
+        for (name, value) in arguments {
+            frame.borrow_mut().declare_variable(name, value);
+        }
+
+        self.usage.enter(&self.sandbox)?;
+
+        // a debugger shows where each caller is, and in which block
+        let debugged = self.debugger.is_some();
+        if debugged {
+            if let Some(caller) = self.debug_calls.last_mut() {
+                caller.frame = Rc::clone(&self.current_frame);
+            }
+            self.debug_calls.push(DebugFrame {
+                function: definition.name.clone(),
+                module: definition.module.clone(),
+                line: definition.body.span.line,
+                frame: Rc::clone(&frame),
+            });
+        }
+
+        let completion = self.with_frame(Rc::clone(&frame), |program| program.execute_function_body(&definition.body));
+
+        if debugged {
+            self.debug_calls.pop();
+        }
+        self.usage.leave();
+
+        let result = match completion {
+            Ok(Completion::Normal) => Ok(Value::Nothing),
+            Ok(Completion::Return(value)) => value.coerce(&definition.return_type),
+            Err(e) => Err(e),
+        };
+
+        result.map_err(|e| {
+            let arguments = definition.signature.iter()
+                .map(|parameter| (parameter.param_name.clone(), frame.borrow().find_variable(&parameter.param_name).unwrap_or(Value::Nothing)))
+                .collect();
+
+            e.in_function(&definition.name, call_site, arguments)
+        })
+    }
+
+    fn execute_function_body(&mut self, body: &Node) -> StatementReturn {
+        match body.node_type.as_ref() {
+            NodeType::Block(nodes) => self.execute_statements(nodes),
+            node_type => Err(RuntimeError::expected_node_type("block", node_type))
+        }
+    }
+
+    fn execute_node(&mut self, node: &Node) -> StatementReturn {
+        if let Some(mut debugger) = self.debugger.take() {
+            if let Some(call) = self.debug_calls.last_mut() {
+                call.line = node.span.line;
+                call.frame = Rc::clone(&self.current_frame);
+            }
+
+            let result = debugger.before_statement(self, node.span);
+            self.debugger = Some(debugger);
+            result?;
+        }
+
+        let completion = match node.node_type.as_ref() {
+            NodeType::Block(nodes) => self.execute_block(nodes),
+            NodeType::VariableDeclaration(declaration) => self.execute_variable_declaration(declaration),
+            NodeType::ReturnExpression(value) => self.evaluate(value).map(Completion::Return),
+            NodeType::TryCatch(try_catch) => self.execute_try_catch(try_catch),
+            _ => self.evaluate(node).map(|_| Completion::Normal),
+        };
+
+        completion.map_err(|e| e.at(node.span))
+    }
+
+    fn execute_try_catch(&mut self, try_catch: &TryCatch) -> StatementReturn {
+        let error = match self.execute_node(&try_catch.body) {
+            Err(e) if e.kind.is_catchable() => e,
+            completion => return completion,
+        };
+
+        let frame = Rc::new(RefCell::new(Frame::new(Some(Rc::clone(&self.current_frame)))));
+        frame.borrow_mut().declare_variable(try_catch.variable.clone(), Value::Record(error.to_record()));
+
+        self.with_frame(frame, |program| program.execute_node(&try_catch.handler))
+    }
+
+    fn execute_block(&mut self, inner_nodes: &[Node]) -> StatementReturn {
+        let frame = Rc::new(RefCell::new(Frame::new(Some(Rc::clone(&self.current_frame)))));
+
+        self.with_frame(frame, |program| program.execute_statements(inner_nodes))
+    }
+
+    fn execute_statements(&mut self, nodes: &[Node]) -> StatementReturn {
+        for node in nodes {
+            if let Completion::Return(value) = self.execute_node(node)? {
+                return Ok(Completion::Return(value));
+            }
+        }
+
+        Ok(Completion::Normal)
+    }
+
+    fn execute_variable_declaration(&mut self, declaration: &VariableDeclaration) -> StatementReturn {
+        let mut value = self.evaluate(&declaration.value)?;
+
+        if let Some(variable_type) = &declaration.variable_type {
+            value = value.coerce(variable_type)?;
+        }
+
+        self.current_frame.borrow_mut().declare_variable(declaration.name.clone(), value);
+
+        Ok(Completion::Normal)
+    }
+
+    /// Runs `f` with `frame` as the current frame and restores the previous frame afterwards.
+    fn with_frame<T>(&mut self, frame: Rc<RefCell<Frame>>, f: impl FnOnce(&mut Self) -> T) -> T {
+        let previous_frame = std::mem::replace(&mut self.current_frame, frame);
+        let result = f(self);
+        self.current_frame = previous_frame;
+
+        result
+    }
+
+    fn evaluate(&mut self, node: &Node) -> EvalReturn {
+        self.step()?;
+        let value = self.evaluate_node(node)?;
+
+        // literals are part of the program, the values computed from them are new
+        let created = match node.node_type.as_ref() {
+            NodeType::FunctionCall(call) => call.builtin,
+            node_type => matches!(node_type, NodeType::ListLiteral(_) | NodeType::FieldAccess(..) | NodeType::UnaryNode(..) | NodeType::BinaryNode(..)),
+        };
+        if created {
+            self.allocate(&value)?;
+        }
+
+        Ok(value)
+    }
+
+    /// Counts a step against the sandbox, and stops if the program was cancelled.
+    pub(crate) fn step(&mut self) -> Result<(), RuntimeError> {
+        if self.cancellation.is_cancelled() {
+            return Err(RuntimeError::interrupted());
+        }
+
+        self.usage.step(&self.sandbox)
+    }
+
+    pub(crate) fn enter(&mut self) -> Result<(), RuntimeError> {
+        self.usage.enter(&self.sandbox)
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.usage.leave();
+    }
+
+    pub(crate) fn allocate(&mut self, value: &Value) -> Result<(), RuntimeError> {
+        self.usage.allocate(&self.sandbox, value)
+    }
+
+    /// Charges a builtin for `count` values it is about to create: the work to the step limit,
+    /// and their bytes to what is left of the memory limit.
+    pub(crate) fn reserve(&mut self, count: usize) -> Result<(), RuntimeError> {
+        self.usage.work(&self.sandbox, count as u64)?;
+        self.usage.reserve(&self.sandbox, count.saturating_mul(std::mem::size_of::<Value>()))
+    }
+
+    fn evaluate_node(&mut self, node: &Node) -> EvalReturn {
         match node.node_type.as_ref() {
-            NodeType::Block(nodes ) => self.execute_block(nodes),
-            _ => todo!("Node type not implemented in runtime")
+            NodeType::StringLiteral(s) => Ok(Value::String(s.clone())),
+            NodeType::Int8Literal(i) => Ok(Value::Integer(*i as i128)),
+            NodeType::Int16Literal(i) => Ok(Value::Integer(*i as i128)),
+            NodeType::Int32Literal(i) => Ok(Value::Integer(*i as i128)),
+            NodeType::Int64Literal(i) => Ok(Value::Integer(*i as i128)),
+            NodeType::Int128Literal(i) => i128::try_from(*i)
+                .map(Value::Integer)
+                .map_err(|_| RuntimeError::of_kind(RuntimeErrorKind::Overflow, format!("Integer literal {i} is out of range."))),
+            NodeType::DoubleLiteral(d) => Ok(Value::Double(*d)),
+            NodeType::BooleanLiteral(b) => Ok(Value::Boolean(*b)),
+            NodeType::NaLiteral => Ok(Value::NA),
+            NodeType::ListLiteral(elements) => elements.iter()
+                .map(|e| self.evaluate(e))
+                .collect::<Result<Vec<Value>, RuntimeError>>()
+                .map(Value::List),
+            NodeType::VariableReference(name) => self.current_frame.borrow()
+                .find_variable(name)
+                .ok_or_else(|| RuntimeError::undefined_variable(name)),
+            NodeType::FieldAccess(target, field) => self.evaluate(target)?.field(field),
+            NodeType::UnaryNode(unary_type, operand) => self.evaluate(operand)?.unary(unary_type),
+            NodeType::BinaryNode(binary_type, left, right) => self.evaluate_binary(*binary_type, left, right),
+            NodeType::FunctionCall(call) => self.evaluate_function_call(call, node.span),
+            NodeType::Block(nodes) => match self.execute_block(nodes)? {
+                Completion::Normal => Ok(Value::Nothing),
+                Completion::Return(value) => Ok(value)
+            },
+            node_type => Err(RuntimeError::expected_node_type("expression", node_type))
         }
     }
 
-    fn execute_block(&self, inner_nodes: &Vec<Node>) -> RuntimeReturn {
-        for node in inner_nodes {
-            self.execute_node(node)?;
+    fn evaluate_binary(&mut self, binary_type: BinaryType, left: &Node, right: &Node) -> EvalReturn {
+        let left = self.evaluate(left)?;
+
+        // short-circuit where the left operand alone decides the result
+        match (binary_type, &left) {
+            (BinaryType::And, Value::Boolean(false)) => return Ok(left),
+            (BinaryType::Or, Value::Boolean(true)) => return Ok(left),
+            _ => {}
         }
 
-        None
+        let right = self.evaluate(right)?;
+        left.binary(binary_type, &right)
     }
 
-    fn start_new_frame(&self, inner_ast: &Node) {
-        let frame = Rc::new(RefCell::new(Frame::new(Some(self.current_frame.borrow()))));
+    fn evaluate_function_call(&mut self, call: &FunctionCall, span: Span) -> EvalReturn {
+        if !call.builtin {
+            return self.evaluate_user_function_call(call, span);
+        }
+
+        let builtin = find_builtin(&call.name);
+        if builtin.is_none() && self.host.function(&call.name).is_none() {
+            return Err(RuntimeError::unknown_builtin(&call.name));
+        }
+
+        let mut values = HashMap::new();
+        for (name, node) in call.parameters.iter() {
+            values.insert(name.clone(), self.evaluate(node)?);
+        }
+
+        self.call_builtin(builtin, Arguments::new(call.name.clone(), values))
     }
-    
 
-    pub fn execute(&self) -> RuntimeReturn {
-        let main_method = self.find_main_method();
+    fn evaluate_user_function_call(&mut self, call: &FunctionCall, span: Span) -> EvalReturn {
+        let definition = self.functions.get(&call.name)
+            .cloned()
+            .ok_or_else(|| RuntimeError::unknown_function(&call.name))?;
+
+        if let Some(unknown) = call.parameters.iter().map(|(name, _)| name).find(|name| !definition.signature.iter().any(|p| &p.param_name == *name)) {
+            return Err(RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Function '{}' has no parameter '{unknown}'.", call.name)));
+        }
+
+        let mut values = HashMap::new();
+        for parameter in definition.signature.iter() {
+            let (_, node) = call.parameters.iter()
+                .find(|(name, _)| name == &parameter.param_name)
+                .ok_or_else(|| RuntimeError::of_kind(RuntimeErrorKind::ArgumentMismatch, format!("Missing argument '{}' for function '{}'.", parameter.param_name, call.name)))?;
 
-        if main_method.is_none() {
-            return Some(RuntimeError::new("No main method present in top-level context.".to_string()));
+            values.insert(parameter.param_name.clone(), self.evaluate(node)?.coerce(&parameter.param_type)?);
         }
 
+        self.call_function(&definition, values, Some(span))
+    }
+
+
+    /// Runs `main` and returns the exit status of the program: the value returned by `main` if it
+    /// is an integer, the code passed to `exit(code)`, and 0 otherwise.
+    pub fn execute(&mut self) -> Result<i32, RuntimeError> {
+        self.usage = Usage::default();
+
+        let main_method = match self.find_main_method() {
+            Some(main_method) => main_method,
+            None => return Err(RuntimeError::of_kind(RuntimeErrorKind::InvalidProgram, "No main method present in top-level context.".to_string()))
+        };
 
-        self.start()
+        let result = self.start(&main_method);
+        // output the script wrote before it failed or was interrupted is not lost
+        self.output.flush().ok();
+
+        match result {
+            Ok(Value::Integer(code)) => i32::try_from(code)
+                .map_err(|_| RuntimeError::of_kind(RuntimeErrorKind::Overflow, format!("The exit status {code} returned by main is out of range."))),
+            Ok(_) => Ok(0),
+            Err(RuntimeError { exit_code: Some(code), .. }) => Ok(code),
+            Err(e) => Err(e)
+        }
+    }
+
+    /// Binds the command line arguments to the parameters of `main`, to report usage errors before
+    /// the script starts.
+    pub fn main_arguments(&self) -> Result<HashMap<String, Value>, RuntimeError> {
+        match self.find_main_method() {
+            Some(main_method) => main_arguments::bind(&main_method, &self.arguments),
+            None => Ok(HashMap::new())
+        }
     }
 
-    fn find_main_method(&self) -> Option<&Node> {
+    pub fn find_main_method(&self) -> Option<FunctionDefinition> {
+        if let Some(compiled) = &self.compiled {
+            return compiled.main.map(|main| compiled.functions[main].declaration());
+        }
+
         match self.ast.node_type.as_ref() {
             NodeType::Program(nodes) => {
                 for node in nodes.iter() {
-                    return match node.node_type.as_ref() {
-                        NodeType::FunctionDefinition(definition) => {
-                            if definition.name != "main" {
-                                return None;
-                            }
-
-                            Some(&definition.body)
+                    match node.node_type.as_ref() {
+                        NodeType::FunctionDefinition(definition) if definition.name == "main" => {
+                            return Some(definition.clone());
                         }
                         _ => continue
                     };
@@ -99,4 +608,76 @@ impl<'a> Program<'a> {
             _ => None
         }
     }
-}
\ No newline at end of file
+}
+
+#[cfg(test)]
+mod program_tests {
+    use crate::error::runtime::RuntimeErrorKind;
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::parse::parser::StatParser;
+    use crate::runtime::program::{Evaluator, Program};
+
+    /// Runs the source with both engines, which must agree.
+    fn execute(source: &str) -> Result<i32, RuntimeErrorKind> {
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+
+        let [tree_walker, bytecode] = [Evaluator::TreeWalker, Evaluator::Bytecode].map(|engine| {
+            Program::new(ast.clone())
+                .with_output(Box::new(std::io::sink()))
+                .with_evaluator(engine)
+                .execute()
+                .map_err(|e| (e.kind, e.message))
+        });
+
+        assert_eq!(tree_walker, bytecode);
+        tree_walker.map_err(|(kind, _)| kind)
+    }
+
+    #[test]
+    fn test_exit_status() {
+        assert_eq!(execute("func main() @i32 { return 7 }"), Ok(7));
+        assert_eq!(execute("func main() { builtin println(template = 7) }"), Ok(0));
+        assert_eq!(execute("func main() @i32 { return 1 / 2 }"), Err(RuntimeErrorKind::TypeMismatch));
+        assert_eq!(execute("func main() { builtin println(template = 1 / 0) }"), Err(RuntimeErrorKind::DivisionByZero));
+        assert_eq!(execute("func main() { builtin println(template = 1.5 % 0.0) }"), Err(RuntimeErrorKind::DivisionByZero));
+        assert_eq!(execute("func main() @i32 { return 2147483648 }"), Err(RuntimeErrorKind::Overflow));
+        assert_eq!(execute("func main() { builtin random_int(lo = -(2 ^ 126), hi = 2 ^ 126) }"), Err(RuntimeErrorKind::InvalidArgument));
+        assert_eq!(execute("func main() { missing(x = 1) }"), Err(RuntimeErrorKind::UnknownFunction));
+        assert_eq!(execute("func main() { f(y = 1) } func f(x @int) {}"), Err(RuntimeErrorKind::ArgumentMismatch));
+    }
+
+    #[test]
+    fn test_exit_unwinds_nested_calls() {
+        let source = "func stop(code @int) @int {
+                { builtin exit(code = code) }
+                return 1
+            }
+            func main() @i32 {
+                stop(code = 3)
+                return 0
+            }";
+
+        assert_eq!(execute(source), Ok(3));
+    }
+
+    #[test]
+    fn test_stack_traces() {
+        let source = "func ratio(a @int, b @list) @f64 {
+                return a / builtin sd(values = b)
+            }
+            func summarize(values @list) @f64 {
+                set<f64> r <- ratio(a = 10, b = values)
+                return r
+            }
+            func main() { summarize(values = [1, \"x\"]) }";
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+
+        let [tree_walker, bytecode] = [Evaluator::TreeWalker, Evaluator::Bytecode].map(|engine| {
+            let error = Program::new(ast.clone()).with_evaluator(engine).execute().unwrap_err();
+            error.stack.iter().map(ToString::to_string).collect::<Vec<_>>()
+        });
+
+        assert_eq!(tree_walker, bytecode);
+        assert_eq!(tree_walker, ["ratio(a = 10, b = [1, x]) called at line 5", "summarize(values = [1, x]) called at line 8", "main()"]);
+    }
+}
@@ -0,0 +1,91 @@
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The random number generator of a program, xoshiro256** seeded through SplitMix64.
+///
+/// The generator only uses integer arithmetic, so the same seed produces the same sequence on
+/// every platform.
+#[derive(Debug, Clone)]
+pub struct Random {
+    state: [u64; 4],
+}
+
+impl Random {
+    pub fn new(seed: u64) -> Self {
+        let mut splitmix = seed;
+        let mut next = || {
+            splitmix = splitmix.wrapping_add(0x9e37_79b9_7f4a_7c15);
+            let mut z = splitmix;
+            z = (z ^ (z >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+            z = (z ^ (z >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+            z ^ (z >> 31)
+        };
+
+        Self {
+            state: [next(), next(), next(), next()]
+        }
+    }
+
+    /// Seeds the generator from the system clock.
+    pub fn from_time() -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+
+        Self::new(nanos)
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        let result = self.state[1].wrapping_mul(5).rotate_left(7).wrapping_mul(9);
+        let t = self.state[1] << 17;
+
+        self.state[2] ^= self.state[0];
+        self.state[3] ^= self.state[1];
+        self.state[1] ^= self.state[2];
+        self.state[0] ^= self.state[3];
+        self.state[2] ^= t;
+        self.state[3] = self.state[3].rotate_left(45);
+
+        result
+    }
+
+    /// A uniformly distributed integer in `[0, bound)`, using rejection sampling so that no value
+    /// is favoured.
+    pub fn next_below(&mut self, bound: u64) -> u64 {
+        assert!(bound > 0, "bound must be positive");
+
+        let zone = u64::MAX - (u64::MAX % bound);
+        loop {
+            let value = self.next_u64();
+            if value < zone {
+                return value % bound;
+            }
+        }
+    }
+
+    /// A uniformly distributed double in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        (self.next_u64() >> 11) as f64 * (1.0 / (1u64 << 53) as f64)
+    }
+}
+
+#[cfg(test)]
+mod random_tests {
+    use crate::runtime::random::Random;
+
+    #[test]
+    fn test_fixed_seed_sequence() {
+        let mut random = Random::new(42);
+        let values: Vec<u64> = (0..3).map(|_| random.next_u64()).collect();
+
+        // reference output of xoshiro256** seeded through SplitMix64 with 42
+        assert_eq!(values, vec![1_546_998_764_402_558_742, 6_990_951_692_964_543_102, 12_544_586_762_248_559_009]);
+    }
+
+    #[test]
+    fn test_next_below_stays_in_range() {
+        let mut random = Random::new(7);
+
+        assert!((0..1_000).all(|_| random.next_below(6) < 6));
+    }
+}
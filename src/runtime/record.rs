@@ -0,0 +1,56 @@
+use std::fmt::{Display, Formatter};
+use crate::runtime::value::Value;
+
+/// Lists longer than this are abbreviated when a record is printed, e.g. the residuals of a model.
+const PRINTED_LIST_LIMIT: usize = 6;
+
+/// A named collection of fields, e.g. the result of a hypothesis test. Fields keep the order they
+/// were added in and can be read from scripts with `record.field`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Record {
+    pub name: String,
+    pub fields: Vec<(String, Value)>,
+}
+
+impl Record {
+    pub fn new(name: impl Into<String>) -> Self {
+        Self {
+            name: name.into(),
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn with(mut self, field: &str, value: Value) -> Self {
+        self.fields.push((field.to_string(), value));
+        self
+    }
+
+    pub fn field(&self, name: &str) -> Option<&Value> {
+        self.fields.iter().find(|(n, _)| n == name).map(|(_, v)| v)
+    }
+}
+
+impl Display for Record {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.name)?;
+
+        for (name, value) in self.fields.iter() {
+            let value = match value {
+                Value::List(values) if values.len() > PRINTED_LIST_LIMIT => {
+                    let head: Vec<String> = values.iter().take(PRINTED_LIST_LIMIT).map(|v| v.to_string()).collect();
+                    format!("[{}, ... ({} values)]", head.join(", "), values.len())
+                }
+                v => v.to_string()
+            };
+
+            if value.contains('\n') {
+                // nested tables and records start on their own line, indented under the field name
+                write!(f, "\n  {name}:\n    {}", value.replace('\n', "\n    "))?;
+            } else {
+                write!(f, "\n  {name}: {value}")?;
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,250 @@
+//! Bounds on what a script may do, for running scripts that are not trusted.
+
+use std::time::{Duration, Instant};
+
+use crate::error::runtime::RuntimeError;
+use crate::runtime::value::Value;
+
+/// How often the wall-clock time is checked, in steps.
+const TIME_CHECK_INTERVAL: u64 = 256;
+
+/// The calls of script functions a run nests at most by default, so that runaway recursion fails
+/// with an error instead of overflowing the native stack.
+pub const DEFAULT_MAX_DEPTH: usize = 1000;
+
+/// The native stack a thread running scripts needs for [`DEFAULT_MAX_DEPTH`] nested calls on the
+/// tree-walking evaluator, which recurses for every call, and for sources nested
+/// [`MAX_NESTING`](crate::parse::parser::MAX_NESTING) levels deep. The command line runs scripts
+/// on a thread of this size.
+pub const STACK_SIZE: usize = 64 * 1024 * 1024;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Limit {
+    Steps,
+    Time,
+    Depth,
+    Memory,
+}
+
+/// The limits of a run of a script, i.e. of [`Program::execute`](crate::runtime::program::Program::execute)
+/// or [`Program::call`](crate::runtime::program::Program::call). The default limits only the
+/// depth of calls, to [`DEFAULT_MAX_DEPTH`].
+#[derive(Debug, Clone, Copy)]
+pub struct Sandbox {
+    /// Steps are the nodes the tree-walker evaluates, or the instructions the VM runs, and the
+    /// values builtins go through or create.
+    pub max_steps: Option<u64>,
+    pub max_time: Option<Duration>,
+    /// Calls of script functions active at once.
+    pub max_depth: Option<usize>,
+    /// Bytes of the values the script creates, counted when they are created.
+    pub max_memory: Option<usize>,
+    /// Whether the builtins reading files, the environment or the process may run.
+    pub system_access: bool,
+}
+
+impl Default for Sandbox {
+    fn default() -> Self {
+        Self {
+            max_steps: None,
+            max_time: None,
+            max_depth: Some(DEFAULT_MAX_DEPTH),
+            max_memory: None,
+            system_access: true,
+        }
+    }
+}
+
+/// What a run of a script has used up so far.
+#[derive(Debug)]
+pub(crate) struct Usage {
+    steps: u64,
+    depth: usize,
+    memory: usize,
+    started_at: Instant,
+}
+
+impl Default for Usage {
+    fn default() -> Self {
+        Self {
+            steps: 0,
+            depth: 0,
+            memory: 0,
+            started_at: Instant::now(),
+        }
+    }
+}
+
+impl Usage {
+    pub(crate) fn step(&mut self, sandbox: &Sandbox) -> Result<(), RuntimeError> {
+        self.work(sandbox, 1)
+    }
+
+    /// Counts several steps at once, e.g. for the values a builtin goes through.
+    pub(crate) fn work(&mut self, sandbox: &Sandbox, steps: u64) -> Result<(), RuntimeError> {
+        let before = self.steps;
+        self.steps = self.steps.saturating_add(steps);
+
+        if let Some(max_steps) = sandbox.max_steps.filter(|max_steps| self.steps > *max_steps) {
+            return Err(RuntimeError::limit_exceeded(Limit::Steps, format!("The script exceeded the limit of {max_steps} steps.")));
+        }
+
+        if let Some(max_time) = sandbox.max_time.filter(|_| self.steps / TIME_CHECK_INTERVAL != before / TIME_CHECK_INTERVAL) {
+            if self.started_at.elapsed() > max_time {
+                return Err(RuntimeError::limit_exceeded(Limit::Time, format!("The script ran longer than the limit of {max_time:?}.")));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Enters a call of a script function.
+    pub(crate) fn enter(&mut self, sandbox: &Sandbox) -> Result<(), RuntimeError> {
+        self.depth += 1;
+
+        match sandbox.max_depth.filter(|max_depth| self.depth > *max_depth) {
+            Some(max_depth) => Err(RuntimeError::limit_exceeded(
+                Limit::Depth, format!("The script exceeded the limit of {max_depth} nested function calls.")
+            )),
+            None => Ok(()),
+        }
+    }
+
+    pub(crate) fn leave(&mut self) {
+        self.depth = self.depth.saturating_sub(1);
+    }
+
+    /// Counts a value the script created.
+    pub(crate) fn allocate(&mut self, sandbox: &Sandbox, value: &Value) -> Result<(), RuntimeError> {
+        let Some(max_memory) = sandbox.max_memory else {
+            return Ok(());
+        };
+
+        self.memory += value.size();
+
+        if self.memory > max_memory {
+            return Err(memory_exceeded(max_memory));
+        }
+
+        Ok(())
+    }
+
+    /// Checks that `bytes` more fit in the memory limit, before a builtin allocates them.
+    pub(crate) fn reserve(&self, sandbox: &Sandbox, bytes: usize) -> Result<(), RuntimeError> {
+        match sandbox.max_memory.filter(|max_memory| self.memory.saturating_add(bytes) > *max_memory) {
+            Some(max_memory) => Err(memory_exceeded(max_memory)),
+            None => Ok(()),
+        }
+    }
+}
+
+fn memory_exceeded(max_memory: usize) -> RuntimeError {
+    RuntimeError::limit_exceeded(Limit::Memory, format!("The script allocated more than the limit of {max_memory} bytes."))
+}
+
+#[cfg(test)]
+mod sandbox_tests {
+    use std::time::Duration;
+
+    use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::parse::parser::StatParser;
+    use crate::runtime::program::{Evaluator, Program};
+    use crate::runtime::sandbox::{Limit, Sandbox, DEFAULT_MAX_DEPTH, STACK_SIZE};
+
+    /// Runs the source with both engines, which must fail the same way.
+    fn execute(source: &str, sandbox: Sandbox) -> RuntimeError {
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+
+        let [tree_walker, bytecode] = [Evaluator::TreeWalker, Evaluator::Bytecode].map(|evaluator| {
+            Program::new(ast.clone())
+                .with_output(Box::new(std::io::sink()))
+                .with_evaluator(evaluator)
+                .with_sandbox(sandbox)
+                .execute()
+                .unwrap_err()
+        });
+
+        assert_eq!(tree_walker.kind, bytecode.kind);
+        assert_eq!(tree_walker.message, bytecode.message);
+        tree_walker
+    }
+
+    /// Functions calling the next one twice, 2^levels calls in all.
+    fn call_tree(levels: usize) -> String {
+        let mut source: String = (0..levels)
+            .map(|level| format!("func f{level}() {{ f{next}() f{next}() }}\n", next = level + 1))
+            .collect();
+        source.push_str(&format!("func f{levels}() {{}}\nfunc main() @i32 {{ f0() return 1 }}"));
+
+        source
+    }
+
+    #[test]
+    fn test_limits() {
+        let endless = "func down(n @int) @int { return down(n = n + 1) }
+            func main() { down(n = 0) }";
+
+        let error = execute(endless, Sandbox { max_depth: Some(50), ..Sandbox::default() });
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Depth));
+        assert_eq!(error.stack.len(), 50);
+
+        let error = execute(endless, Sandbox { max_steps: Some(200), max_depth: Some(100), ..Sandbox::default() });
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Steps));
+
+        let error = execute(&call_tree(12), Sandbox { max_time: Some(Duration::ZERO), ..Sandbox::default() });
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Time));
+
+        let nested = "func main() {
+                set<list> a <- [1, 2, 3, 4]
+                set<list> b <- [a, a, a, a]
+                set<list> c <- [b, b, b, b]
+            }";
+        let error = execute(nested, Sandbox { max_memory: Some(2000), ..Sandbox::default() });
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Memory));
+    }
+
+    #[test]
+    fn test_limits_recursion_by_default() {
+        let endless = "func down(n @int) @int { return down(n = n + 1) }
+            func main() { down(n = 0) }";
+
+        let thread = std::thread::Builder::new().stack_size(STACK_SIZE).spawn(|| {
+            let ast = StatParser::new(Tokenizer::new(endless.into())).parse().unwrap();
+            [Evaluator::TreeWalker, Evaluator::Bytecode].map(|evaluator| {
+                Program::new(ast.clone()).with_evaluator(evaluator).execute().unwrap_err().kind
+            })
+        });
+
+        let kinds = thread.unwrap().join().unwrap();
+        assert_eq!(kinds, [RuntimeErrorKind::LimitExceeded(Limit::Depth); 2]);
+        assert_eq!(Sandbox::default().max_depth, Some(DEFAULT_MAX_DEPTH));
+    }
+
+    #[test]
+    fn test_limits_builtins() {
+        let memory = Sandbox { max_memory: Some(100_000), ..Sandbox::default() };
+        let error = execute("func main() { builtin normal_sample(n = 1000000) }", memory);
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Memory));
+
+        let error = execute("func main() { builtin sample(values = [1], n = 1000000, replace = true) }", memory);
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Memory));
+
+        let steps = Sandbox { max_steps: Some(1000), ..Sandbox::default() };
+        let error = execute("func main() { builtin normal_sample(n = 5000) }", steps);
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Steps));
+
+        let error = execute("func main() {
+                set<list> values <- builtin sample(values = [1, 2, 3], n = 900, replace = true)
+                builtin mean(values = values)
+            }", steps);
+        assert_eq!(error.kind, RuntimeErrorKind::LimitExceeded(Limit::Steps));
+    }
+
+    #[test]
+    fn test_denies_system_builtins() {
+        let error = execute("func main() { builtin env(name = \"HOME\") }", Sandbox { system_access: false, ..Sandbox::default() });
+
+        assert_eq!(error.kind, RuntimeErrorKind::CapabilityDenied);
+    }
+}
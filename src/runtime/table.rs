@@ -0,0 +1,68 @@
+use std::fmt::{Display, Formatter};
+use crate::runtime::value::Value;
+
+/// A column oriented table, as produced by `read_csv`.
+#[derive(Debug, Clone, PartialEq, Default)]
+pub struct Table {
+    pub columns: Vec<Column>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Column {
+    pub name: String,
+    pub values: Vec<Value>,
+}
+
+impl Table {
+    pub fn row_count(&self) -> usize {
+        self.columns.first().map(|c| c.values.len()).unwrap_or(0)
+    }
+
+    pub fn column(&self, name: &str) -> Option<&Column> {
+        self.columns.iter().find(|c| c.name == name)
+    }
+
+    /// Returns a copy of the table that only keeps the rows for which `keep` returns `true`.
+    pub fn filter_rows(&self, keep: impl Fn(usize) -> bool) -> Table {
+        Table {
+            columns: self.columns.iter()
+                .map(|c| Column {
+                    name: c.name.clone(),
+                    values: c.values.iter()
+                        .enumerate()
+                        .filter(|(row, _)| keep(*row))
+                        .map(|(_, v)| v.clone())
+                        .collect(),
+                })
+                .collect()
+        }
+    }
+}
+
+impl Display for Table {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        let widths: Vec<usize> = self.columns.iter()
+            .map(|c| c.values.iter()
+                .map(|v| v.to_string().len())
+                .chain(std::iter::once(c.name.len()))
+                .max()
+                .unwrap_or(0))
+            .collect();
+
+        let header: Vec<String> = self.columns.iter()
+            .zip(widths.iter())
+            .map(|(c, w)| format!("{:>w$}", c.name, w = w))
+            .collect();
+        write!(f, "{}", header.join("  "))?;
+
+        for row in 0..self.row_count() {
+            let cells: Vec<String> = self.columns.iter()
+                .zip(widths.iter())
+                .map(|(c, w)| format!("{:>w$}", c.values[row].to_string(), w = w))
+                .collect();
+            write!(f, "\n{}", cells.join("  "))?;
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,392 @@
+use std::fmt::{Display, Formatter};
+use crate::error::runtime::{RuntimeError, RuntimeErrorKind};
+use crate::parse::parser::{BinaryType, UnaryType};
+use crate::runtime::record::Record;
+use crate::runtime::table::Table;
+
+/// A value living in the runtime.
+///
+/// `NA` marks a missing value. It is accepted by every declared type and propagates through
+/// arithmetic and comparisons, so `NA + 1` and `NA < 2` are both `NA`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Value {
+    Nothing,
+    NA,
+    Boolean(bool),
+    Integer(i128),
+    Double(f64),
+    String(String),
+    Character(char),
+    List(Vec<Value>),
+    Table(Table),
+    Record(Record),
+}
+
+impl Value {
+    pub fn is_na(&self) -> bool {
+        matches!(self, Value::NA)
+    }
+
+    pub fn type_name(&self) -> &'static str {
+        match self {
+            Value::Nothing => "Nothing",
+            Value::NA => "NA",
+            Value::Boolean(_) => "bool",
+            Value::Integer(_) => "int",
+            Value::Double(_) => "f64",
+            Value::String(_) => "string",
+            Value::Character(_) => "char",
+            Value::List(_) => "list",
+            Value::Table(_) => "table",
+            Value::Record(_) => "record",
+        }
+    }
+
+    /// Returns the numeric value as `f64`, or `None` for non-numeric values (including `NA`).
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            Value::Integer(i) => Some(*i as f64),
+            Value::Double(d) => Some(*d),
+            _ => None
+        }
+    }
+
+    /// An estimate of the bytes the value takes, for the memory limit of a sandbox.
+    pub fn size(&self) -> usize {
+        let contents = match self {
+            Value::String(s) => s.len(),
+            Value::List(values) => values.iter().map(Value::size).sum(),
+            Value::Table(table) => table.columns.iter()
+                .map(|column| column.name.len() + column.values.iter().map(Value::size).sum::<usize>())
+                .sum(),
+            Value::Record(record) => record.name.len() + record.fields.iter()
+                .map(|(name, value)| name.len() + value.size())
+                .sum::<usize>(),
+            _ => 0,
+        };
+
+        std::mem::size_of::<Value>() + contents
+    }
+
+    /// The number of values the value consists of, itself included.
+    pub fn count(&self) -> usize {
+        let contents = match self {
+            Value::List(values) => values.iter().map(Value::count).sum(),
+            Value::Table(table) => table.columns.iter()
+                .map(|column| column.values.iter().map(Value::count).sum::<usize>())
+                .sum(),
+            Value::Record(record) => record.fields.iter().map(|(_, value)| value.count()).sum(),
+            _ => 0,
+        };
+
+        1 + contents
+    }
+
+    /// Converts the value into the declared type of a variable or parameter. Integers are widened
+    /// to doubles where a floating point type is declared, and must lie in the range of a sized
+    /// integer type. `NA` fits every type.
+    pub fn coerce(self, type_name: &str) -> Result<Value, RuntimeError> {
+        if self.is_na() {
+            return Ok(self);
+        }
+
+        let matches = match type_name {
+            "any" => true,
+            "Nothing" => matches!(self, Value::Nothing),
+            "i8" | "i16" | "i32" | "i64" | "i128" | "u8" | "u16" | "u32" | "u64" | "u128" | "int" => match self {
+                Value::Integer(i) if !integer_range(type_name).contains(&i) => {
+                    return Err(RuntimeError::of_kind(RuntimeErrorKind::Overflow, format!("{i} is out of range for '{type_name}'.")));
+                }
+                Value::Integer(_) => true,
+                _ => false,
+            },
+            "f32" | "f64" | "double" => {
+                if let Value::Integer(i) = self {
+                    return Ok(Value::Double(i as f64));
+                }
+
+                matches!(self, Value::Double(_))
+            }
+            "bool" => matches!(self, Value::Boolean(_)),
+            "string" => matches!(self, Value::String(_)),
+            "char" => matches!(self, Value::Character(_)),
+            "list" => matches!(self, Value::List(_)),
+            "table" => matches!(self, Value::Table(_)),
+            "record" => matches!(self, Value::Record(_)),
+            _ => return Err(RuntimeError::of_kind(RuntimeErrorKind::UnknownType, format!("Unknown type '{type_name}'.")))
+        };
+
+        if !matches {
+            return Err(RuntimeError::type_mismatch(type_name, self.type_name()));
+        }
+
+        Ok(self)
+    }
+
+    /// Reads a field of a record, or a column of a table as a list.
+    pub fn field(&self, name: &str) -> Result<Value, RuntimeError> {
+        match self {
+            Value::Record(record) => record.field(name)
+                .cloned()
+                .ok_or_else(|| RuntimeError::of_kind(RuntimeErrorKind::UnknownField, format!("Record '{}' has no field '{name}'.", record.name))),
+            Value::Table(table) => table.column(name)
+                .map(|c| Value::List(c.values.clone()))
+                .ok_or_else(|| RuntimeError::of_kind(RuntimeErrorKind::UnknownField, format!("Table has no column '{name}'."))),
+            v => Err(RuntimeError::of_kind(RuntimeErrorKind::UnknownField, format!("Cannot access field '{name}' on '{}'.", v.type_name())))
+        }
+    }
+
+    pub fn unary(&self, unary_type: &UnaryType) -> Result<Value, RuntimeError> {
+        match (unary_type, self) {
+            (_, Value::NA) => Ok(Value::NA),
+            (_, Value::List(values)) => {
+                values.iter()
+                    .map(|v| v.unary(unary_type))
+                    .collect::<Result<Vec<Value>, RuntimeError>>()
+                    .map(Value::List)
+            }
+            (UnaryType::Not, Value::Boolean(b)) => Ok(Value::Boolean(!b)),
+            (UnaryType::Negate, Value::Integer(i)) => i.checked_neg().map(Value::Integer).ok_or_else(|| {
+                RuntimeError::of_kind(RuntimeErrorKind::Overflow, format!("Integer overflow in -({i})."))
+            }),
+            (UnaryType::Negate, Value::Double(d)) => Ok(Value::Double(-d)),
+            (UnaryType::Not, v) => Err(RuntimeError::type_mismatch("bool", v.type_name())),
+            (UnaryType::Negate, v) => Err(RuntimeError::type_mismatch("number", v.type_name())),
+        }
+    }
+
+    /// Applies a binary operator. Lists are combined element-wise, a scalar operand is applied to
+    /// every element of a list.
+    pub fn binary(&self, binary_type: BinaryType, other: &Value) -> Result<Value, RuntimeError> {
+        match (self, other) {
+            (Value::List(left), Value::List(right)) => {
+                if left.len() != right.len() {
+                    return Err(RuntimeError::new(format!(
+                        "Cannot combine lists of different length ({} and {}).", left.len(), right.len()
+                    )));
+                }
+
+                left.iter().zip(right.iter())
+                    .map(|(l, r)| l.binary(binary_type, r))
+                    .collect::<Result<Vec<Value>, RuntimeError>>()
+                    .map(Value::List)
+            }
+            (Value::List(left), right) => {
+                left.iter()
+                    .map(|l| l.binary(binary_type, right))
+                    .collect::<Result<Vec<Value>, RuntimeError>>()
+                    .map(Value::List)
+            }
+            (left, Value::List(right)) => {
+                right.iter()
+                    .map(|r| left.binary(binary_type, r))
+                    .collect::<Result<Vec<Value>, RuntimeError>>()
+                    .map(Value::List)
+            }
+            (left, right) => match binary_type {
+                BinaryType::And | BinaryType::Or => Self::logical(binary_type, left, right),
+                _ if left.is_na() || right.is_na() => Ok(Value::NA),
+                BinaryType::Equal => Ok(Value::Boolean(Self::scalar_equals(left, right))),
+                BinaryType::NotEqual => Ok(Value::Boolean(!Self::scalar_equals(left, right))),
+                BinaryType::Less | BinaryType::LessEqual | BinaryType::Greater | BinaryType::GreaterEqual => {
+                    Self::compare(binary_type, left, right)
+                }
+                _ => Self::arithmetic(binary_type, left, right)
+            }
+        }
+    }
+
+    /// Three-valued logic: `NA & false` is `false` and `NA | true` is `true`, since the result
+    /// does not depend on the missing value.
+    fn logical(binary_type: BinaryType, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+        let as_bool = |v: &Value| match v {
+            Value::Boolean(b) => Ok(Some(*b)),
+            Value::NA => Ok(None),
+            v => Err(RuntimeError::type_mismatch("bool", v.type_name()))
+        };
+
+        let (l, r) = (as_bool(left)?, as_bool(right)?);
+        let dominant = binary_type == BinaryType::Or;
+
+        if l == Some(dominant) || r == Some(dominant) {
+            return Ok(Value::Boolean(dominant));
+        }
+
+        match (l, r) {
+            (Some(_), Some(_)) => Ok(Value::Boolean(!dominant)),
+            _ => Ok(Value::NA)
+        }
+    }
+
+    fn scalar_equals(left: &Value, right: &Value) -> bool {
+        match (left.as_f64(), right.as_f64()) {
+            (Some(l), Some(r)) => l == r,
+            _ => left == right
+        }
+    }
+
+    fn compare(binary_type: BinaryType, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+        let ordering = match (left, right) {
+            (Value::String(l), Value::String(r)) => l.partial_cmp(r),
+            (Value::Character(l), Value::Character(r)) => l.partial_cmp(r),
+            (l, r) => match (l.as_f64(), r.as_f64()) {
+                (Some(l), Some(r)) => l.partial_cmp(&r),
+                _ => return Err(RuntimeError::of_kind(RuntimeErrorKind::TypeMismatch, format!(
+                    "Cannot compare '{}' with '{}'.", l.type_name(), r.type_name()
+                )))
+            }
+        };
+
+        let ordering = match ordering {
+            Some(o) => o,
+            None => return Ok(Value::NA)
+        };
+
+        Ok(Value::Boolean(match binary_type {
+            BinaryType::Less => ordering.is_lt(),
+            BinaryType::LessEqual => ordering.is_le(),
+            BinaryType::Greater => ordering.is_gt(),
+            _ => ordering.is_ge(),
+        }))
+    }
+
+    fn arithmetic(binary_type: BinaryType, left: &Value, right: &Value) -> Result<Value, RuntimeError> {
+        if let (Value::String(l), Value::String(r), BinaryType::Add) = (left, right, binary_type) {
+            return Ok(Value::String(format!("{l}{r}")));
+        }
+
+        if let (Value::Integer(l), Value::Integer(r)) = (left, right) {
+            let overflow = || RuntimeError::of_kind(RuntimeErrorKind::Overflow, format!("Integer overflow in {l} {binary_type:?} {r}."));
+
+            return match binary_type {
+                BinaryType::Add => l.checked_add(*r).map(Value::Integer).ok_or_else(overflow),
+                BinaryType::Subtract => l.checked_sub(*r).map(Value::Integer).ok_or_else(overflow),
+                BinaryType::Multiply => l.checked_mul(*r).map(Value::Integer).ok_or_else(overflow),
+                BinaryType::Divide | BinaryType::Modulo if *r == 0 => Err(RuntimeError::division_by_zero()),
+                BinaryType::Divide => Ok(Value::Double(*l as f64 / *r as f64)),
+                BinaryType::Modulo => Ok(Value::Integer(l.rem_euclid(*r))),
+                BinaryType::Power => match u32::try_from(*r) {
+                    Ok(exponent) => l.checked_pow(exponent).map(Value::Integer).ok_or_else(overflow),
+                    Err(_) => Ok(Value::Double((*l as f64).powf(*r as f64)))
+                },
+                _ => unreachable!("non-arithmetic operator {binary_type:?}")
+            };
+        }
+
+        let (l, r) = match (left.as_f64(), right.as_f64()) {
+            (Some(l), Some(r)) => (l, r),
+            _ => return Err(RuntimeError::of_kind(RuntimeErrorKind::TypeMismatch, format!(
+                "Operator {binary_type:?} is not defined for '{}' and '{}'.", left.type_name(), right.type_name()
+            )))
+        };
+
+        if matches!(binary_type, BinaryType::Divide | BinaryType::Modulo) && r == 0.0 {
+            return Err(RuntimeError::division_by_zero());
+        }
+
+        Ok(Value::Double(match binary_type {
+            BinaryType::Add => l + r,
+            BinaryType::Subtract => l - r,
+            BinaryType::Multiply => l * r,
+            BinaryType::Divide => l / r,
+            BinaryType::Modulo => l.rem_euclid(r),
+            BinaryType::Power => l.powf(r),
+            _ => unreachable!("non-arithmetic operator {binary_type:?}")
+        }))
+    }
+}
+
+impl Display for Value {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Value::Nothing => write!(f, "Nothing"),
+            Value::NA => write!(f, "NA"),
+            Value::Boolean(b) => write!(f, "{b}"),
+            Value::Integer(i) => write!(f, "{i}"),
+            Value::Double(d) if *d != 0.0 && (d.abs() < 1e-5 || d.abs() >= 1e16) => write!(f, "{d:e}"),
+            Value::Double(d) => write!(f, "{d}"),
+            Value::String(s) => write!(f, "{s}"),
+            Value::Character(c) => write!(f, "{c}"),
+            Value::List(values) => {
+                write!(f, "[")?;
+                for (i, value) in values.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{value}")?;
+                }
+                write!(f, "]")
+            }
+            Value::Table(table) => write!(f, "{table}"),
+            Value::Record(record) => write!(f, "{record}"),
+        }
+    }
+}
+
+/// The values of an integer type. Integers end at `i128::MAX`, which bounds `u128` too, and `int`
+/// holds all of them.
+fn integer_range(type_name: &str) -> std::ops::RangeInclusive<i128> {
+    match type_name {
+        "i8" => i8::MIN as i128..=i8::MAX as i128,
+        "i16" => i16::MIN as i128..=i16::MAX as i128,
+        "i32" => i32::MIN as i128..=i32::MAX as i128,
+        "i64" => i64::MIN as i128..=i64::MAX as i128,
+        "u8" => 0..=u8::MAX as i128,
+        "u16" => 0..=u16::MAX as i128,
+        "u32" => 0..=u32::MAX as i128,
+        "u64" => 0..=u64::MAX as i128,
+        "u128" => 0..=i128::MAX,
+        _ => i128::MIN..=i128::MAX,
+    }
+}
+
+#[cfg(test)]
+mod value_tests {
+    use crate::error::runtime::RuntimeErrorKind;
+    use crate::parse::parser::{BinaryType, UnaryType};
+    use crate::runtime::value::Value;
+
+    #[test]
+    fn test_na_propagation() {
+        assert_eq!(Value::NA.binary(BinaryType::Add, &Value::Integer(1)).unwrap(), Value::NA);
+        assert_eq!(Value::Double(2.0).binary(BinaryType::Less, &Value::NA).unwrap(), Value::NA);
+        assert_eq!(Value::NA.binary(BinaryType::Equal, &Value::NA).unwrap(), Value::NA);
+        assert_eq!(Value::NA.unary(&UnaryType::Negate).unwrap(), Value::NA);
+
+        let list = Value::List(vec![Value::Integer(1), Value::NA, Value::Integer(3)]);
+        assert_eq!(
+            list.binary(BinaryType::Multiply, &Value::Integer(2)).unwrap(),
+            Value::List(vec![Value::Integer(2), Value::NA, Value::Integer(6)])
+        );
+    }
+
+    #[test]
+    fn test_three_valued_logic() {
+        assert_eq!(Value::NA.binary(BinaryType::And, &Value::Boolean(false)).unwrap(), Value::Boolean(false));
+        assert_eq!(Value::NA.binary(BinaryType::And, &Value::Boolean(true)).unwrap(), Value::NA);
+        assert_eq!(Value::NA.binary(BinaryType::Or, &Value::Boolean(true)).unwrap(), Value::Boolean(true));
+        assert_eq!(Value::Boolean(false).binary(BinaryType::Or, &Value::NA).unwrap(), Value::NA);
+    }
+
+    #[test]
+    fn test_division_by_zero_and_overflow() {
+        for divide in [BinaryType::Divide, BinaryType::Modulo] {
+            assert_eq!(Value::Integer(1).binary(divide, &Value::Integer(0)).unwrap_err().kind, RuntimeErrorKind::DivisionByZero);
+            assert_eq!(Value::Double(1.0).binary(divide, &Value::Integer(0)).unwrap_err().kind, RuntimeErrorKind::DivisionByZero);
+        }
+        assert_eq!(Value::Integer(i128::MIN).unary(&UnaryType::Negate).unwrap_err().kind, RuntimeErrorKind::Overflow);
+    }
+
+    #[test]
+    fn test_coerce() {
+        assert_eq!(Value::NA.coerce("i32").unwrap(), Value::NA);
+        assert_eq!(Value::Integer(3).coerce("f64").unwrap(), Value::Double(3.0));
+        assert!(Value::String("a".into()).coerce("i32").is_err());
+
+        assert_eq!(Value::Integer(255).coerce("u8").unwrap(), Value::Integer(255));
+        assert_eq!(Value::Integer(-128).coerce("i8").unwrap(), Value::Integer(-128));
+        for (value, type_name) in [(-300, "u8"), (256, "u8"), (-1, "u128"), (128, "i8"), (-129, "i8")] {
+            assert_eq!(Value::Integer(value).coerce(type_name).unwrap_err().kind, RuntimeErrorKind::Overflow);
+        }
+    }
+}
@@ -0,0 +1,317 @@
+//! Runs [`bytecode`](crate::runtime::bytecode) on a stack machine.
+//!
+//! All frames share one value stack: a frame's slots start at its base, and the temporary values
+//! of its expressions lie above them. Builtins get the [`Program`] the VM runs for, like they do
+//! from the tree-walking evaluator.
+
+use std::collections::HashMap;
+
+use crate::error::runtime::RuntimeError;
+use crate::parse::parser::BinaryType;
+use crate::runtime::builtins::Arguments;
+use crate::runtime::bytecode::{CompiledProgram, Instruction};
+use crate::runtime::program::{EvalReturn, Program};
+use crate::runtime::value::Value;
+
+struct CallFrame {
+    function: usize,
+    /// The next instruction.
+    ip: usize,
+    /// The stack index of the frame's first slot.
+    base: usize,
+}
+
+/// A `try` whose body is running.
+struct Handler {
+    /// The number of callers of the frame the `try` is in.
+    depth: usize,
+    /// The first instruction of the `catch` block.
+    target: usize,
+    /// The height of the stack when the `try` started.
+    stack_len: usize,
+}
+
+/// Calls the function with the index, with arguments bound to its parameters by name.
+pub(crate) fn call(program: &mut Program, compiled: &CompiledProgram, function: usize, mut arguments: HashMap<String, Value>) -> EvalReturn {
+    let mut stack: Vec<Value> = compiled.functions[function].parameters.iter()
+        .map(|parameter| arguments.remove(&parameter.param_name).unwrap_or(Value::Nothing))
+        .collect();
+    stack.resize(compiled.functions[function].slot_count, Value::Nothing);
+
+    program.enter()?;
+    let mut frame = CallFrame { function, ip: 0, base: 0 };
+    let mut callers: Vec<CallFrame> = Vec::new();
+
+    run(program, compiled, &mut stack, &mut frame, &mut callers).map_err(|e| {
+        // the frames that are left are the functions the error unwinds, each called by the next
+        let active: Vec<&CallFrame> = std::iter::once(&frame).chain(callers.iter().rev()).collect();
+
+        active.iter().enumerate().fold(e, |e, (depth, active_frame)| {
+            let function = &compiled.functions[active_frame.function];
+            let call_site = active.get(depth + 1)
+                .and_then(|caller| compiled.functions[caller.function].chunk.call_site(caller.ip - 1));
+            let arguments = function.parameters.iter().enumerate()
+                .map(|(slot, parameter)| (parameter.param_name.clone(), stack.get(active_frame.base + slot).cloned().unwrap_or(Value::Nothing)))
+                .collect();
+
+            e.in_function(&function.name, call_site, arguments)
+        })
+    })
+}
+
+fn run(program: &mut Program, compiled: &CompiledProgram, stack: &mut Vec<Value>, frame: &mut CallFrame, callers: &mut Vec<CallFrame>) -> EvalReturn {
+    let mut handlers: Vec<Handler> = Vec::new();
+
+    loop {
+        let error = match execute(program, compiled, stack, frame, callers, &mut handlers) {
+            Ok(None) => continue,
+            Ok(Some(value)) => return Ok(value),
+            Err(e) => located(e, compiled, frame),
+        };
+
+        let handler = match handlers.pop() {
+            Some(handler) if error.kind.is_catchable() => handler,
+            _ => return Err(error),
+        };
+
+        // leave the functions the error unwinds, back to the frame of the `try`
+        while callers.len() > handler.depth {
+            *frame = callers.pop().expect("the handler is in a caller");
+            program.leave();
+        }
+        stack.truncate(handler.stack_len);
+        stack.push(Value::Record(error.to_record()));
+        frame.ip = handler.target;
+    }
+}
+
+/// Runs the next instruction, and returns the result of the function the VM was called with
+/// once it returns.
+fn execute(program: &mut Program, compiled: &CompiledProgram, stack: &mut Vec<Value>, frame: &mut CallFrame, callers: &mut Vec<CallFrame>, handlers: &mut Vec<Handler>) -> Result<Option<Value>, RuntimeError> {
+    let function = &compiled.functions[frame.function];
+    let chunk = &function.chunk;
+    let instruction = chunk.code[frame.ip];
+    frame.ip += 1;
+    program.step()?;
+
+    match instruction {
+        Instruction::Constant(index) => stack.push(chunk.constants[index].clone()),
+        Instruction::Nothing => stack.push(Value::Nothing),
+        Instruction::Load(slot) => stack.push(stack[frame.base + slot].clone()),
+        Instruction::Store(slot) => {
+            let value = pop(stack);
+            stack[frame.base + slot] = value;
+        }
+        Instruction::Global(name) => {
+            let name = &chunk.names[name];
+            let value = program.top_level_frame.borrow().find_variable(name).ok_or_else(|| RuntimeError::undefined_variable(name))?;
+            stack.push(value);
+        }
+        Instruction::Pop => {
+            pop(stack);
+        }
+        Instruction::Coerce(type_name) => {
+            let value = pop(stack).coerce(&chunk.names[type_name])?;
+            stack.push(value);
+        }
+        Instruction::List(count) => {
+            let elements = stack.split_off(stack.len() - count);
+            push_created(program, stack, Value::List(elements))?;
+        }
+        Instruction::Field(field) => {
+            let value = pop(stack).field(&chunk.names[field])?;
+            push_created(program, stack, value)?;
+        }
+        Instruction::Unary(unary_type) => {
+            let value = pop(stack).unary(&unary_type)?;
+            push_created(program, stack, value)?;
+        }
+        Instruction::Binary(binary_type) => {
+            let right = pop(stack);
+            let left = pop(stack);
+            push_created(program, stack, left.binary(binary_type, &right)?)?;
+        }
+        Instruction::ShortCircuit(binary_type, target) => {
+            // the left operand alone decides the result, like in the tree-walker
+            let decided = match binary_type {
+                BinaryType::And => stack.last() == Some(&Value::Boolean(false)),
+                BinaryType::Or => stack.last() == Some(&Value::Boolean(true)),
+                _ => false,
+            };
+
+            if decided {
+                frame.ip = target;
+            }
+        }
+        Instruction::Jump(target) => frame.ip = target,
+        Instruction::Call(index) => {
+            let callee = &compiled.functions[index];
+            let base = stack.len() - callee.parameters.len();
+            stack.resize(base + callee.slot_count, Value::Nothing);
+
+            program.enter()?;
+            callers.push(std::mem::replace(frame, CallFrame { function: index, ip: 0, base }));
+        }
+        Instruction::CallBuiltin(index) => {
+            let call = &chunk.builtin_calls[index];
+            let values = stack.split_off(stack.len() - call.arguments.len());
+
+            let arguments = Arguments::new(call.name.clone(), call.arguments.iter().cloned().zip(values).collect());
+            let result = program.call_builtin(call.function, arguments)?;
+            push_created(program, stack, result)?;
+        }
+        Instruction::Return | Instruction::ReturnNothing => {
+            let value = match instruction {
+                // a returned value of the wrong type is an error of the call, like in the
+                // tree-walker
+                Instruction::Return => pop(stack).coerce(&function.return_type).map_err(|e| match callers.last() {
+                    Some(caller) => located(e, compiled, caller),
+                    None => e,
+                })?,
+                _ => Value::Nothing,
+            };
+            stack.truncate(frame.base);
+            program.leave();
+
+            match callers.pop() {
+                Some(caller) => {
+                    *frame = caller;
+                    stack.push(value);
+                }
+                None => return Ok(Some(value)),
+            }
+        }
+        Instruction::Fail(message, kind) => return Err(RuntimeError::of_kind(kind, chunk.names[message].clone())),
+        Instruction::Try(target) => handlers.push(Handler { depth: callers.len(), target, stack_len: stack.len() }),
+        Instruction::EndTry => {
+            handlers.pop();
+        }
+    }
+
+    Ok(None)
+}
+
+/// Sets where the error occurred to the statement of the instruction the frame ran last.
+fn located(error: RuntimeError, compiled: &CompiledProgram, frame: &CallFrame) -> RuntimeError {
+    match compiled.functions[frame.function].chunk.statement(frame.ip - 1) {
+        Some(span) => error.at(span),
+        None => error,
+    }
+}
+
+/// Pushes a value the instruction created, counting it against the memory limit.
+fn push_created(program: &mut Program, stack: &mut Vec<Value>, value: Value) -> Result<(), RuntimeError> {
+    program.allocate(&value)?;
+    stack.push(value);
+
+    Ok(())
+}
+
+fn pop(stack: &mut Vec<Value>) -> Value {
+    stack.pop().expect("the compiler balances the stack")
+}
+
+#[cfg(test)]
+mod vm_tests {
+    use std::fs;
+
+    use crate::lexer::tokenizer::Tokenizer;
+    use crate::parse::parser::StatParser;
+    use crate::runtime::program::{Evaluator, Program};
+    use crate::test_support::SharedOutput;
+
+    fn run(source: &str, engine: Evaluator) -> (Result<i32, String>, String) {
+        let ast = StatParser::new(Tokenizer::new(source.into())).parse().unwrap();
+        let output = SharedOutput::default();
+
+        let result = Program::new(ast)
+            .with_output(Box::new(output.clone()))
+            .with_arguments(vec!["world".into()])
+            .with_evaluator(engine)
+            .execute()
+            .map_err(|e| e.message);
+
+        let text = output.text();
+        (result, text)
+    }
+
+    fn assert_same(source: &str) {
+        assert_eq!(run(source, Evaluator::Bytecode), run(source, Evaluator::TreeWalker), "{source}");
+    }
+
+    #[test]
+    fn test_matches_tree_walker() {
+        for entry in fs::read_dir("grammar").unwrap() {
+            let source = fs::read_to_string(entry.unwrap().path()).unwrap();
+
+            if StatParser::new(Tokenizer::new(source.clone())).parse().is_ok() {
+                assert_same(&source);
+            }
+        }
+
+        const SOURCES: [&str; 12] = [
+            // a block used as an expression ends at its `return`, a nested block statement too
+            "func main() @i32 {
+                set<int> a <- { { return 4 } return 5 }
+                set<int> b <- 1 + { set<int> a <- a * 2 return a }
+                builtin println(template = [a, b])
+                { return a }
+            }",
+            "func main() {
+                set<int> a <- 1
+                { set<int> a <- a + 1 builtin println(template = a) }
+                set<int> a <- a + 10
+                builtin println(template = a)
+            }",
+            "func main() { builtin println(template = false & undefined) builtin println(template = true | 1 / 0) }",
+            "func main() { builtin println(template = true & undefined) }",
+            "func main() { builtin println(template = 1) builtin println(template = [2, missing]) }",
+            "func twice(x @f64) @f64 { return x * 2 }
+            func main() { builtin println(template = twice(x = 2)) builtin println(template = twice(y = 2)) }",
+            "func pair(a @int, b @int) @list { return [a, b] }
+            func main() { builtin println(template = pair(b = builtin println(template = \"b\"), a = 1)) }",
+            "func f(a @int) @int { return a }
+            func main() { builtin println(template = \"before\") f() }",
+            "func main() { builtin println(template = nope(a = 1)) }",
+            "func main() { builtin nope(a = builtin println(template = 1)) }",
+            "func main() { builtin println(template = 1, extra = 2) }",
+            "func roll(n @int) @list { builtin set_seed(seed = n) return builtin sample(values = [1, 2, 3, 4], n = 3) }
+            func main() @i32 {
+                builtin println(template = roll(n = 7) + builtin random_int(lo = 0, hi = 10))
+                builtin exit(code = builtin random_int(lo = 2, hi = 5))
+                return 0
+            }",
+        ];
+
+        for source in SOURCES {
+            assert_same(source);
+        }
+
+        assert_eq!(run(SOURCES[0], Evaluator::Bytecode), (Ok(4), "[4, 9]\n".into()));
+        assert_eq!(run(SOURCES[1], Evaluator::Bytecode).1, "2\n11\n");
+        assert_eq!(run(SOURCES[11], Evaluator::Bytecode), (Ok(2), "[11, 12, 9]\n".into()));
+    }
+
+    #[test]
+    fn test_catches_errors() {
+        let source = "func fail(reason @string) @int {
+                { builtin error(message = reason) }
+                return 1
+            }
+            func main() @i32 {
+                try {
+                    set<int> x <- fail(reason = \"no data\")
+                    builtin println(template = \"not reached\")
+                } catch e {
+                    builtin println(template = [e.kind, e.code, e.message, e.line])
+                }
+                set<list> values <- [1, { try { return 2 + builtin mean(values = [\"a\"]) } catch e { return e.kind } }]
+                builtin println(template = values)
+                try { builtin exit(code = 3) } catch e { builtin println(template = \"not caught\") }
+                return 0
+            }";
+
+        assert_same(source);
+        assert_eq!(run(source, Evaluator::Bytecode), (Ok(3), "[Thrown, E0319, no data, 2]\n[1, InvalidArgument]\n".into()));
+    }
+}